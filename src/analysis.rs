@@ -0,0 +1,170 @@
+use crate::eval::evaluate;
+use crate::gui::WordSize;
+use crate::parser::{Diagnostic, Program, Severity};
+use crate::source_map::{SourceMap, Span};
+use crate::symbols::ConstTable;
+use crate::types::{Expr, Operator};
+use rust_i18n::t;
+use std::collections::HashMap;
+
+/// Semantic analysis between parsing and code generation: checks how the constants are declared
+/// and used, and finds divisions by something that's always zero.
+///
+/// Errors keep the program from compiling, warnings are only shown in the editor.
+pub fn analyze(input: &str, program: &Program, map: &SourceMap) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut report = |span: &Span, message: &str, severity| {
+        diagnostics.push(Diagnostic::new(input, span.clone(), message, severity));
+    };
+
+    let declared: Vec<&str> = program.constants.iter().map(|d| d.name.as_str()).collect();
+    let mut used = program.body.variables();
+    for (i, decl) in program.constants.iter().enumerate() {
+        if declared[..i].contains(&decl.name.as_str()) {
+            report(
+                &decl.span,
+                &t!("symbols.redefined", name = decl.name),
+                Severity::Error,
+            );
+        }
+
+        let mut vars: Vec<_> = decl.value.variables().into_iter().collect();
+        vars.sort();
+        for var in vars {
+            let message = if declared[..i].contains(&var.as_str()) {
+                used.insert(var);
+                continue;
+            } else if declared[i..].contains(&var.as_str()) {
+                t!("analysis.declared_later", name = var)
+            } else {
+                t!("analysis.input_in_constant", name = var)
+            };
+            for span in decl.map.spans(&Expr::Var(var.as_str().into())) {
+                report(span, &message, Severity::Error);
+            }
+            used.insert(var);
+        }
+    }
+
+    for decl in &program.constants {
+        if !used.contains(&decl.name) {
+            report(
+                &decl.span,
+                &t!("analysis.unused", name = decl.name),
+                Severity::Warning,
+            );
+        }
+    }
+
+    // constants can't be computed if they divide by zero, the program only fails when it runs
+    let table = ConstTable::partial(&program.constants);
+    for decl in &program.constants {
+        for (division, divisor) in zero_divisors(&decl.value, &table) {
+            for span in divisor_spans(&decl.map, &division, &divisor) {
+                report(&span, &t!("analysis.divzero"), Severity::Error);
+            }
+        }
+    }
+    for (division, divisor) in zero_divisors(&program.body, &table) {
+        for span in divisor_spans(map, &division, &divisor) {
+            report(&span, &t!("analysis.divzero"), Severity::Warning);
+        }
+    }
+
+    diagnostics.sort_by_key(|d| (d.span.start, d.span.end));
+    diagnostics.dedup();
+    diagnostics
+}
+
+/// Where `divisor` is the right-hand side of `division`. The map has the spans of all equal
+/// expressions, so the divisor's spans may include ones outside of this division.
+fn divisor_spans(map: &SourceMap, division: &Expr, divisor: &Expr) -> Vec<Span> {
+    map.spans(division)
+        .iter()
+        .filter_map(|d| {
+            map.spans(divisor)
+                .iter()
+                .filter(|s| d.start < s.start && s.end <= d.end)
+                .max_by_key(|s| s.end)
+                .cloned()
+        })
+        .collect()
+}
+
+/// Divisions in `expr` with their right-hand sides, where these are zero no matter the inputs.
+fn zero_divisors(expr: &Expr, constants: &ConstTable) -> Vec<(Expr, Expr)> {
+    let mut divisors = Vec::new();
+    collect_zero_divisors(expr, constants, &mut divisors);
+    divisors
+}
+
+fn collect_zero_divisors(expr: &Expr, constants: &ConstTable, divisors: &mut Vec<(Expr, Expr)>) {
+    match expr {
+        Expr::Num(_) | Expr::Var(_) => {}
+        Expr::UnaryOp(_, e) => collect_zero_divisors(&e.get(), constants, divisors),
+        Expr::BinaryOp(l, op, r) => {
+            if op.base() == Operator::Div {
+                let value = constants.resolve(r.get());
+                if value.variables().is_empty()
+                    && matches!(evaluate(&value, &HashMap::new(), WordSize::W32), Ok(0))
+                {
+                    divisors.push((*expr, r.get()));
+                }
+            }
+            collect_zero_divisors(&l.get(), constants, divisors);
+            collect_zero_divisors(&r.get(), constants, divisors);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{DEFAULT_MAX_NESTING, DEFAULT_MAX_TOKENS, diagnose, run_program_parser};
+    use crate::types::LpErr;
+
+    fn analyze_str(input: &str) -> Result<Vec<(Span, Severity)>, LpErr> {
+        let (program, map) = run_program_parser(input, DEFAULT_MAX_NESTING)?;
+        Ok(analyze(input, &program, &map)
+            .into_iter()
+            .map(|d| (d.span, d.severity))
+            .collect())
+    }
+
+    #[test]
+    fn constants_used_before_their_declaration() -> Result<(), LpErr> {
+        let found = analyze_str("const A = B + x; const B = 2; A")?;
+        assert_eq!(
+            found,
+            [(10..11, Severity::Error), (14..15, Severity::Error)]
+        );
+        assert!(analyze_str("const B = 2; const A = B + 1; A")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn unused_constants_are_warnings() -> Result<(), LpErr> {
+        let found = analyze_str("const A = 1;\nx + 1")?;
+        assert_eq!(found, [(0..12, Severity::Warning)]);
+        Ok(())
+    }
+
+    #[test]
+    fn division_by_zero() -> Result<(), LpErr> {
+        let found = analyze_str("const Z = 3 - 3; x / Z + x / (2 * Z) + Z / x")?;
+        assert_eq!(
+            found,
+            [(21..22, Severity::Warning), (30..35, Severity::Warning)]
+        );
+        assert_eq!(
+            analyze_str("const A = 1 / 0; A")?,
+            [(14..15, Severity::Error)]
+        );
+        // parse errors come first, the analysis needs a complete program
+        assert_eq!(
+            diagnose("1 / 0 +", DEFAULT_MAX_NESTING, DEFAULT_MAX_TOKENS).len(),
+            1
+        );
+        Ok(())
+    }
+}
@@ -1,15 +1,25 @@
 use std::collections::BTreeSet;
 
-use crate::compiler::CompileOptions;
-use crate::gui::{AssemblyOutput, CodeEditor, EditorAction, Examples, InterpreterOptions, Window};
+use crate::assembler;
+use crate::compile_service::{CompileJob, CompileService, Compiled};
+use crate::compiler::{CompileOptions, OptLevel, RegNaming};
+use crate::gui::{
+    AssemblyOutput, Autopilot, BatchProgram, BatchRun, BookmarkKeys, ChallengeView, CodeEditor,
+    CompileLogView, ConformanceView, CostWeights, DagView, DemoStep, Dock, EditorAction, Examples,
+    Explanations, HintTarget, History, InterpreterOptions, IsaReference, Leaderboard, Lockstep,
+    LockstepProgram, PassMatrix, PipelineView, PresenterNotes, Profiler, Quiz, Repl, RewriteRules,
+    Situation, TargetOutput, Theme, Tutorial, Window,
+};
+use crate::sound;
 use eframe::egui::{self, FontData, FontFamily, Modifiers, Ui, ViewportCommand};
 use eframe::epaint::text::{FontInsert, InsertFontFamily};
 use rust_i18n::t;
+use std::time::Instant;
 
 macro_rules! add_sidebar_item {
     ($ui: expr, $open: expr, $item: expr) => {
         let mut is_open = $open.contains(&$item.name());
-        $ui.toggle_value(&mut is_open, t!($item.name()));
+        $ui.toggle_value(&mut is_open, $item.title());
         set_open(&mut $open, &$item.name(), is_open);
     };
 }
@@ -38,9 +48,46 @@ pub struct LndwApp {
     interpreter_options: InterpreterOptions,
     asm_unoptimized: AssemblyOutput,
     asm_optimized: AssemblyOutput,
+    /// Extra outputs with their own compile options, to compare more than two versions.
+    comparisons: Vec<AssemblyOutput>,
+    asm_riscv: TargetOutput,
+    asm_wat: TargetOutput,
+    /// The optimized program written as source code again.
+    source_output: TargetOutput,
+    pipeline: PipelineView,
+    explanations: Explanations,
+    compile_log: CompileLogView,
+    dag: DagView,
+    batch: BatchRun,
+    lockstep: Lockstep,
+    pass_matrix: PassMatrix,
     examples: Examples,
+    quiz: Quiz,
+    repl: Repl,
+    history: History,
+    isa: IsaReference,
+    leaderboard: Leaderboard,
+    challenges: ChallengeView,
+    rewrite_rules: RewriteRules,
+    cost_weights: CostWeights,
+    tutorial: Tutorial,
+    presenter: PresenterNotes,
     result: Option<String>,
     language: String,
+    theme: Theme,
+    dock: Dock,
+    /// Compiles on a worker thread. Compiling again cancels the running job, the other actions
+    /// wait for the latest result, see [`LndwApp::handle_actions`].
+    compile_service: CompileService,
+    /// Whether the outputs are opened once the compilation finishes, because it was asked for
+    /// and not just a live one.
+    reveal_outputs: bool,
+    profiler: Profiler,
+    conformance: ConformanceView,
+    /// Plays a demo when nobody uses the app for a while.
+    autopilot: Autopilot,
+    /// A program file dropped onto the window, loaded once replacing the output is confirmed.
+    dropped: Option<egui::DroppedFile>,
 
     /// List of open windows
     open: BTreeSet<String>,
@@ -62,19 +109,354 @@ impl LndwApp {
         let mut res = Self {
             asm_unoptimized: AssemblyOutput::empty("output.unopt".to_string()),
             asm_optimized: AssemblyOutput::empty("output.opt".to_string()),
+            asm_riscv: TargetOutput::empty("output.riscv".to_string()),
+            asm_wat: TargetOutput::empty("output.wat".to_string()).downloadable("program.wat"),
+            source_output: TargetOutput::empty("output.source".to_string()),
             language: "en".to_string(),
             examples: Examples::preloaded(),
+            leaderboard: Leaderboard::load(),
+            theme: Theme::load(),
             ..Self::default()
         };
 
+        res.rewrite_rules.load_peephole();
         set_open(&mut res.open, &res.code_editor.name(), true);
+        res.theme.apply(&cc.egui_ctx);
 
         res
     }
+
+    /// Everything needed to compile the code for all outputs, taken from the current settings.
+    fn compile_job(&self) -> CompileJob {
+        let rules = self.rewrite_rules.active();
+        let peephole = self.rewrite_rules.peephole().to_vec();
+        let optimize =
+            self.code_editor.compile_options.any() || !rules.is_empty() || !peephole.is_empty();
+        // the size limits are about the source, all outputs share the editor's
+        let limits = self.code_editor.compile_options;
+        let with_limit = |options| CompileOptions {
+            max_nesting: limits.max_nesting,
+            max_tokens: limits.max_tokens,
+            max_nodes: limits.max_nodes,
+            ..options
+        };
+        let mut outputs = vec![(
+            with_limit(CompileOptions::default()),
+            Vec::new(),
+            Vec::new(),
+        )];
+        if optimize {
+            let options = self.code_editor.compile_options;
+            outputs.push((options, rules.clone(), peephole.clone()));
+        }
+        for output in &self.comparisons {
+            let options = with_limit(output.own_options().unwrap_or_default());
+            outputs.push((options, rules.clone(), peephole.clone()));
+        }
+        CompileJob {
+            code: self.code_editor.code.clone(),
+            hw: self.interpreter_options,
+            optimize,
+            outputs,
+            target_options: self.code_editor.compile_options,
+            hints: self.code_editor.hints.clone(),
+            costs: self.cost_weights.costs().clone(),
+        }
+    }
+
+    /// Carries out a step of the demo like a visitor would.
+    fn play_demo_step(&mut self, step: DemoStep) {
+        let editor = &mut self.code_editor;
+        match step {
+            DemoStep::Source(code) => editor.code = code,
+            DemoStep::Toggle(opt, on) => *opt.flag(&mut editor.compile_options) = on,
+            DemoStep::Level(level) => {
+                editor.compile_options = editor.compile_options.with_level(level)
+            }
+            DemoStep::Var(var, value) => {
+                editor.input_variables.insert(var, value);
+            }
+            DemoStep::Compile => editor.actions.push(EditorAction::Compile),
+            DemoStep::Run => editor.actions.push(EditorAction::Run(false)),
+            DemoStep::Wait(_) => {}
+        }
+        set_open(&mut self.open, &self.code_editor.name(), true);
+    }
+
+    /// Loads a hand-written program dropped onto the window into the first output, bypassing the
+    /// compiler.
+    fn load_dropped(&mut self, file: &egui::DroppedFile) {
+        let contents = match (&file.path, &file.bytes) {
+            (Some(path), _) => std::fs::read_to_string(path).map_err(|e| e.to_string()),
+            (None, Some(bytes)) => String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string()),
+            (None, None) => return,
+        };
+        match contents
+            .map_err(|e| t!("output.open_failed", file = file.name, e = e).to_string())
+            .and_then(|text| assembler::parse_program(&text).map_err(|e| e.to_string()))
+        {
+            Ok(instructions) => {
+                let vars = self.asm_unoptimized.load_instructions(
+                    instructions,
+                    self.interpreter_options,
+                    &file.name,
+                );
+                self.code_editor.set_variables(vars);
+            }
+            Err(e) => self.asm_unoptimized.set_error(e),
+        }
+        set_open(&mut self.open, &self.asm_unoptimized.name(), true);
+    }
+
+    /// Asks whether the dropped file should replace the program shown in the first output.
+    fn confirm_drop(&mut self, ctx: &egui::Context) {
+        let Some(file) = &self.dropped else {
+            return;
+        };
+        let mut answer = None;
+        egui::Window::new(t!("output.dropped.title"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(t!("output.dropped.replace", file = file.name));
+                ui.horizontal(|ui| {
+                    if ui.button(t!("output.dropped.confirm")).clicked() {
+                        answer = Some(true);
+                    }
+                    if ui.button(t!("output.dropped.cancel")).clicked() {
+                        answer = Some(false);
+                    }
+                });
+            });
+        match answer {
+            Some(true) => {
+                let file = self.dropped.take().expect("checked above");
+                self.load_dropped(&file);
+            }
+            Some(false) => self.dropped = None,
+            None => {}
+        }
+    }
+
+    /// Shows the results of a finished [`CompileJob`] in all outputs.
+    fn show_compiled(&mut self, compiled: Compiled) {
+        let (hw, optimize) = (compiled.hw, compiled.optimize);
+        self.code_editor.diagnostics = compiled.diagnostics;
+        self.code_editor.stats = compiled.stats;
+        let logs = compiled
+            .outputs
+            .iter()
+            .flat_map(|(r, _)| r.as_ref().map(|ir| &ir.log));
+        self.profiler.compiled(logs, compiled.elapsed);
+        let mut results = compiled.outputs.into_iter();
+
+        let (unoptimized, _) = results
+            .next()
+            .expect("the unoptimized output is always compiled");
+        self.code_editor.constants = unoptimized
+            .as_ref()
+            .map(|ir| ir.constants.clone())
+            .unwrap_or_default();
+        if let Ok(vars) = self.asm_unoptimized.load(unoptimized, hw, false) {
+            self.code_editor.set_variables(vars);
+            self.code_editor.divisors = self.asm_unoptimized.divisors().clone();
+            self.presenter.compiled(true);
+        } else {
+            self.code_editor.set_variables(Vec::new());
+            self.presenter.compiled(false);
+        }
+
+        let baseline = self.asm_unoptimized.baseline();
+        if optimize && let Some((result, _)) = results.next() {
+            // TODO: consider what to do with vars & any errors.
+            let _ = self.asm_optimized.load(result, hw, true);
+            self.asm_optimized.baseline = baseline;
+            if self.reveal_outputs {
+                set_open(&mut self.open, &self.asm_optimized.name(), true);
+            }
+        }
+
+        if std::mem::take(&mut self.reveal_outputs) {
+            set_open(&mut self.open, &self.asm_unoptimized.name(), true);
+        }
+
+        for (output, (result, optimized)) in self.comparisons.iter_mut().zip(results) {
+            let _ = output.load(result, hw, optimized);
+            output.baseline = baseline;
+        }
+
+        let best = if optimize {
+            &self.asm_optimized
+        } else {
+            &self.asm_unoptimized
+        };
+        self.tutorial.compiled(self.code_editor.compile_options);
+        self.pipeline.set(best.stages());
+        self.explanations.set(best.explanations());
+        self.compile_log.set(best.log());
+        self.dag
+            .set(best.ast(), self.code_editor.hints.clone(), hw.word_size);
+        self.cost_weights.set(
+            self.asm_unoptimized.ast(),
+            self.interpreter_options.word_size,
+        );
+        match best.source() {
+            Some(source) => self.source_output.set(vec![source.to_string()]),
+            None => self.source_output.clear(),
+        }
+
+        let mut outputs = vec![&self.asm_unoptimized];
+        if optimize {
+            outputs.push(&self.asm_optimized);
+        }
+        let programs = outputs
+            .into_iter()
+            .chain(&self.comparisons)
+            .filter(|output| !output.instructions().is_empty())
+            .map(|output| BatchProgram {
+                title: output.title(),
+                instructions: output.instructions(),
+            })
+            .collect();
+        self.batch
+            .set(programs, hw, &self.code_editor.input_variables);
+
+        self.pass_matrix.set(
+            &self.code_editor.code,
+            self.code_editor.compile_options,
+            hw,
+            self.code_editor.hints.clone(),
+            &self.code_editor.input_variables,
+        );
+
+        if optimize {
+            let program = |output: &AssemblyOutput| LockstepProgram {
+                title: output.title(),
+                instructions: output.instructions(),
+                debug_info: output.debug_info().to_vec(),
+            };
+            self.lockstep.set(
+                &self.code_editor.code,
+                [program(&self.asm_unoptimized), program(&self.asm_optimized)],
+                hw,
+                &self.code_editor.input_variables,
+            );
+        } else {
+            self.lockstep.clear();
+        }
+
+        // show the real-world translations of the best program we have
+        for (lines, output) in compiled
+            .targets
+            .into_iter()
+            .zip([&mut self.asm_riscv, &mut self.asm_wat])
+        {
+            match lines {
+                Some(lines) => output.set(lines),
+                None => output.clear(),
+            }
+        }
+    }
+
+    /// Handle the actions requested in the code editor.
+    fn handle_actions(&mut self) {
+        if let Some(compiled) = self.compile_service.poll() {
+            self.show_compiled(compiled);
+        }
+
+        // actions after a compilation wait for it, e.g. running after compiling with Ctrl+Enter,
+        // while compiling again cancels it
+        while let Some(action) = self.code_editor.actions.first()
+            && (matches!(action, EditorAction::Compile | EditorAction::Recompile)
+                || !self.compile_service.is_busy())
+        {
+            match self.code_editor.actions.remove(0) {
+                action @ (EditorAction::Compile | EditorAction::Recompile) => {
+                    // a live compile cancelling an asked for one still opens the outputs
+                    self.reveal_outputs |= matches!(action, EditorAction::Compile);
+                    let job = self.compile_job();
+                    self.compile_service.submit(job);
+                }
+                EditorAction::Run(stepwise) => {
+                    set_open(&mut self.open, &self.asm_unoptimized.name(), true);
+                    self.asm_unoptimized
+                        .run(&self.code_editor.input_variables, stepwise);
+                    let optimized = self.code_editor.compile_options.any()
+                        || !self.rewrite_rules.active().is_empty()
+                        || !self.rewrite_rules.peephole().is_empty();
+                    if optimized {
+                        set_open(&mut self.open, &self.asm_optimized.name(), true);
+                        self.asm_optimized
+                            .run(&self.code_editor.input_variables, stepwise);
+                    }
+                    for output in &mut self.comparisons {
+                        output.run(&self.code_editor.input_variables, stepwise);
+                    }
+                    let outputs = [&self.asm_unoptimized, &self.asm_optimized];
+                    self.profiler.ran(
+                        (outputs.into_iter().chain(&self.comparisons)).map(AssemblyOutput::run_log),
+                    );
+                    self.lockstep
+                        .set_variables(&self.code_editor.input_variables);
+                    self.tutorial.ran(optimized);
+                    self.presenter.set_situation(Situation::Ran);
+                    // the demo doesn't take places on the leaderboard from visitors, nor fills
+                    // their history, and a dropped program isn't the code in the editor
+                    if !self.autopilot.is_playing() && self.asm_unoptimized.loaded_from().is_none()
+                    {
+                        let best = if optimized {
+                            &self.asm_optimized
+                        } else {
+                            &self.asm_unoptimized
+                        };
+                        self.history.record(
+                            &self.code_editor.code,
+                            self.code_editor.compile_options,
+                            &self.code_editor.input_variables,
+                            best.result(),
+                            &best.instructions(),
+                            best.estimated_cycles().unwrap_or_default(),
+                        );
+                        self.leaderboard.submit(
+                            &self.code_editor.code,
+                            self.code_editor.compile_options,
+                            self.rewrite_rules.active(),
+                        );
+                    }
+                }
+                EditorAction::Clear => {
+                    self.asm_unoptimized.clear();
+                    self.asm_optimized.clear();
+                    self.comparisons.iter_mut().for_each(AssemblyOutput::clear);
+                    self.asm_riscv.clear();
+                    self.asm_wat.clear();
+                    self.source_output.clear();
+                    self.pipeline.clear();
+                    self.explanations.clear();
+                    self.compile_log.clear();
+                    self.dag.clear();
+                    self.cost_weights.clear();
+                    self.batch.clear();
+                    self.lockstep.clear();
+                    self.pass_matrix.clear();
+                    self.code_editor.diagnostics.clear();
+                    self.code_editor.stats = Default::default();
+                    self.code_editor.constants = Default::default();
+                    self.result = None;
+                }
+            }
+        }
+    }
 }
 
 impl eframe::App for LndwApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let frame_start = Instant::now();
+        self.profiler.handle_shortcut(ctx);
+        self.conformance.handle_shortcut(ctx);
+        self.autopilot.watch_input(ctx);
+
         egui::SidePanel::right("window_selector")
             .resizable(false)
             .default_width(160.0)
@@ -88,15 +470,55 @@ impl eframe::App for LndwApp {
                 // window list
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
+                        add_sidebar_item!(ui, self.open, self.tutorial);
                         add_sidebar_item!(ui, self.open, self.code_editor);
                         add_sidebar_item!(ui, self.open, self.asm_unoptimized);
+                        // the tutorial unlocks the other tools as it gets to them
+                        if self.tutorial.is_active() {
+                            if self.tutorial.options_unlocked() {
+                                add_sidebar_item!(ui, self.open, self.asm_optimized);
+                            }
+                            return;
+                        }
                         add_sidebar_item!(ui, self.open, self.asm_optimized);
+                        for output in &self.comparisons {
+                            add_sidebar_item!(ui, self.open, output);
+                        }
+                        if ui
+                            .button(t!("output.add_comparison"))
+                            .on_hover_text(t!("output.add_comparison.hint"))
+                            .clicked()
+                        {
+                            let output = AssemblyOutput::comparison(self.comparisons.len() + 1);
+                            set_open(&mut self.open, &output.name(), true);
+                            self.comparisons.push(output);
+                        }
+                        add_sidebar_item!(ui, self.open, self.asm_riscv);
+                        add_sidebar_item!(ui, self.open, self.asm_wat);
+                        add_sidebar_item!(ui, self.open, self.source_output);
+                        add_sidebar_item!(ui, self.open, self.pipeline);
+                        add_sidebar_item!(ui, self.open, self.explanations);
+                        add_sidebar_item!(ui, self.open, self.compile_log);
+                        add_sidebar_item!(ui, self.open, self.dag);
+                        add_sidebar_item!(ui, self.open, self.batch);
+                        add_sidebar_item!(ui, self.open, self.lockstep);
+                        add_sidebar_item!(ui, self.open, self.pass_matrix);
                         add_sidebar_item!(ui, self.open, self.interpreter_options);
                         add_sidebar_item!(ui, self.open, self.examples);
+                        add_sidebar_item!(ui, self.open, self.quiz);
+                        add_sidebar_item!(ui, self.open, self.repl);
+                        add_sidebar_item!(ui, self.open, self.history);
+                        add_sidebar_item!(ui, self.open, self.isa);
+                        add_sidebar_item!(ui, self.open, self.leaderboard);
+                        add_sidebar_item!(ui, self.open, self.challenges);
+                        add_sidebar_item!(ui, self.open, self.rewrite_rules);
+                        add_sidebar_item!(ui, self.open, self.cost_weights);
+                        add_sidebar_item!(ui, self.open, self.presenter);
 
                         ui.separator();
                         if ui.button(t!("app.organize")).clicked() {
                             ui.ctx().memory_mut(|mem| mem.reset_areas());
+                            self.dock.reset();
                         }
                     });
                 });
@@ -104,80 +526,201 @@ impl eframe::App for LndwApp {
 
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
-                file_menu_button(ui, &mut self.language);
+                file_menu_button(
+                    ui,
+                    &mut self.language,
+                    &mut self.theme,
+                    &mut self.dock,
+                    &mut self.autopilot,
+                );
             });
         });
 
         // STATE
-        self.code_editor.disable_run =
-            self.asm_unoptimized.is_running() || self.asm_optimized.is_running();
-
-        add_window!(ctx, self.open, self.code_editor);
-
-        // code actions?
-        for action in self.code_editor.actions.drain(..) {
-            match action {
-                EditorAction::Compile => {
-                    if let Ok(vars) = self.asm_unoptimized.compile(
-                        &self.code_editor.code,
-                        CompileOptions::default(),
-                        self.interpreter_options,
-                    ) {
-                        self.code_editor.input_variables =
-                            vars.iter().map(|s| (s.clone(), String::new())).collect();
-                    } else {
-                        self.code_editor.input_variables.clear();
-                    }
+        if RegNaming::global() != self.interpreter_options.reg_naming {
+            RegNaming::set_global(self.interpreter_options.reg_naming);
+        }
+        self.quiz.hw = self.interpreter_options;
+        self.repl.hw = self.interpreter_options;
+        self.repl.options = self.code_editor.compile_options;
+        self.code_editor.disable_run = self.asm_unoptimized.is_running()
+            || self.asm_optimized.is_running()
+            || self.comparisons.iter().any(AssemblyOutput::is_running);
+
+        // decided from the last compilation, parsing every frame would freeze on long programs
+        self.tutorial
+            .update(!self.code_editor.input_variables.is_empty());
+        self.presenter
+            .watch_options(self.code_editor.compile_options);
+        self.code_editor.hide_options = !self.tutorial.options_unlocked();
+
+        self.code_editor.handle_shortcuts(ctx);
+
+        // link the instructions to the code they came from, in both directions
+        self.code_editor.highlight = [&self.asm_unoptimized, &self.asm_optimized]
+            .into_iter()
+            .chain(&self.comparisons)
+            .map(|asm| asm.hovered_source())
+            .find(|spans| !spans.is_empty())
+            .unwrap_or_default()
+            .to_vec();
+        let bookmark_keys = BookmarkKeys::consume(ctx);
+        for asm in [&mut self.asm_unoptimized, &mut self.asm_optimized]
+            .into_iter()
+            .chain(&mut self.comparisons)
+        {
+            asm.highlight_source(self.code_editor.hovered);
+            asm.press_bookmark_keys(bookmark_keys);
+        }
 
-                    if self.code_editor.compile_options.any() {
-                        // TODO: consider what to do with vars & any errors.
-                        let _ = self.asm_optimized.compile(
-                            &self.code_editor.code,
-                            self.code_editor.compile_options,
-                            self.interpreter_options,
-                        );
+        let busy = !self.code_editor.actions.is_empty()
+            || self.compile_service.is_busy()
+            || self.code_editor.disable_run;
+        if let Some(step) = self.autopilot.next_step(Instant::now(), busy) {
+            self.play_demo_step(step);
+        }
 
-                        set_open(&mut self.open, &self.asm_optimized.name(), true);
-                    }
+        self.handle_actions();
 
-                    set_open(&mut self.open, &self.asm_unoptimized.name(), true);
-                }
-                EditorAction::Run(stepwise) => {
-                    set_open(&mut self.open, &self.asm_unoptimized.name(), true);
-                    self.asm_unoptimized
-                        .run(&self.code_editor.input_variables, stepwise);
-                    if self.code_editor.compile_options.any() {
-                        set_open(&mut self.open, &self.asm_optimized.name(), true);
-                        self.asm_optimized
-                            .run(&self.code_editor.input_variables, stepwise);
-                    }
-                }
-                EditorAction::Clear => {
-                    self.asm_unoptimized.clear();
-                    self.asm_optimized.clear();
-                    self.result = None;
-                }
+        let dropped = ctx.input_mut(|i| std::mem::take(&mut i.raw.dropped_files));
+        if let Some(file) = dropped.last() {
+            if self.asm_unoptimized.instructions().is_empty() {
+                self.load_dropped(file);
+            } else {
+                self.dropped = Some(file.clone());
+            }
+        }
+        self.confirm_drop(ctx);
+
+        let mut windows: Vec<&mut dyn Window> = vec![
+            &mut self.code_editor,
+            &mut self.asm_unoptimized,
+            &mut self.asm_optimized,
+            &mut self.asm_riscv,
+            &mut self.asm_wat,
+            &mut self.source_output,
+            &mut self.pipeline,
+            &mut self.explanations,
+            &mut self.compile_log,
+            &mut self.dag,
+            &mut self.batch,
+            &mut self.lockstep,
+            &mut self.pass_matrix,
+            &mut self.interpreter_options,
+            &mut self.examples,
+            &mut self.quiz,
+            &mut self.repl,
+            &mut self.history,
+            &mut self.isa,
+            &mut self.leaderboard,
+            &mut self.challenges,
+            &mut self.rewrite_rules,
+            &mut self.cost_weights,
+            &mut self.tutorial,
+            &mut self.presenter,
+        ];
+        windows.extend(
+            self.comparisons
+                .iter_mut()
+                .map(|output| output as &mut dyn Window),
+        );
+        if self.dock.enabled {
+            self.dock.show(ctx, &mut self.open, &mut windows);
+        } else {
+            for window in windows {
+                add_window!(ctx, self.open, window);
+            }
+            egui::CentralPanel::default().show(ctx, |_| {});
+        }
+
+        // actions from this frame are handled at the start of the next one
+        if !self.code_editor.actions.is_empty() || self.compile_service.is_busy() {
+            ctx.request_repaint();
+        }
+
+        for asm in [&mut self.asm_unoptimized, &mut self.asm_optimized]
+            .into_iter()
+            .chain(&mut self.comparisons)
+        {
+            if asm.rerun_requested {
+                asm.run(&self.code_editor.input_variables, false);
             }
         }
 
-        add_window!(ctx, self.open, self.asm_unoptimized);
-        add_window!(ctx, self.open, self.asm_optimized);
-        add_window!(ctx, self.open, self.interpreter_options);
-        add_window!(ctx, self.open, self.examples);
+        if self.challenges.check_requested {
+            self.challenges.check(
+                &self.code_editor.code,
+                self.code_editor.compile_options,
+                self.interpreter_options,
+                self.rewrite_rules.active(),
+            );
+        }
+
+        if self.pipeline.export_requested {
+            self.pipeline.export(
+                &self.code_editor.code,
+                self.code_editor.compile_options,
+                self.interpreter_options,
+                self.rewrite_rules.active(),
+            );
+        }
+
+        if self.tutorial.start_requested {
+            self.tutorial.start();
+            self.code_editor.code.clear();
+            self.code_editor.compile_options =
+                self.code_editor.compile_options.with_level(OptLevel::O0);
+            set_open(&mut self.open, &self.code_editor.name(), true);
+        }
+
+        if let Some(entry) = self.history.restore.take() {
+            self.code_editor.code = entry.source;
+            self.code_editor.compile_options = entry.options;
+            self.code_editor.input_variables = entry.variables;
+            self.code_editor.actions.push(EditorAction::Compile);
+            set_open(&mut self.open, &self.code_editor.name(), true);
+        }
+
+        if std::mem::take(&mut self.rewrite_rules.changed)
+            | std::mem::take(&mut self.cost_weights.changed)
+        {
+            self.code_editor.actions.push(EditorAction::Recompile);
+        }
 
         if let Some(choice) = self.examples.chosen {
-            self.code_editor.input_variables.clear();
+            self.code_editor.set_variables(Vec::new());
             self.code_editor.code = self.examples.examples[choice].input.into();
             self.code_editor.compile_options = self.examples.examples[choice].options;
 
             self.examples.chosen = None;
         }
 
-        egui::CentralPanel::default().show(ctx, |_| {});
+        self.autopilot.show_hint(ctx, |target| {
+            let name = match target {
+                HintTarget::Editor => self.code_editor.name(),
+                HintTarget::Output => self.asm_unoptimized.name(),
+            };
+            ctx.memory(|mem| mem.area_rect(egui::Id::new(name)))
+        });
+        self.conformance.show(ctx, &self.interpreter_options);
+        if self.profiler.is_enabled() {
+            let outputs = [&self.asm_unoptimized, &self.asm_optimized];
+            self.profiler.stepped(
+                (outputs.into_iter().chain(&self.comparisons)).map(AssemblyOutput::step_log),
+            );
+        }
+        self.profiler.show(ctx);
+        self.profiler.frame(frame_start.elapsed());
     }
 }
 
-fn file_menu_button(ui: &mut Ui, lang: &mut String) {
+fn file_menu_button(
+    ui: &mut Ui,
+    lang: &mut String,
+    theme: &mut Theme,
+    dock: &mut Dock,
+    autopilot: &mut Autopilot,
+) {
     let organize_shortcut =
         egui::KeyboardShortcut::new(Modifiers::CTRL | Modifiers::SHIFT, egui::Key::O);
     let reset_shortcut =
@@ -188,13 +731,33 @@ fn file_menu_button(ui: &mut Ui, lang: &mut String) {
 
     if ui.input_mut(|i| i.consume_shortcut(&organize_shortcut)) {
         ui.ctx().memory_mut(|mem| mem.reset_areas());
+        dock.reset();
     }
 
     if ui.input_mut(|i| i.consume_shortcut(&reset_shortcut)) {
         ui.ctx().memory_mut(|mem| *mem = Default::default());
     }
 
-    egui::widgets::global_theme_preference_switch(ui);
+    egui::ComboBox::from_id_salt("theme")
+        .selected_text(t!(theme.key()))
+        .show_ui(ui, |ui| {
+            for t in Theme::ALL {
+                if ui.selectable_value(theme, t, t!(t.key())).clicked() {
+                    t.apply(ui.ctx());
+                    t.save();
+                }
+            }
+        });
+
+    let mut sound_on = sound::enabled();
+    if ui
+        .selectable_label(sound_on, if sound_on { "🔊" } else { "🔇" })
+        .on_hover_text(t!("app.sound"))
+        .clicked()
+    {
+        sound_on = !sound_on;
+        sound::set_enabled(sound_on);
+    }
 
     ui.separator();
 
@@ -217,6 +780,26 @@ fn file_menu_button(ui: &mut Ui, lang: &mut String) {
             .clicked()
         {
             ui.ctx().memory_mut(|mem| mem.reset_areas());
+            dock.reset();
+        }
+
+        ui.checkbox(&mut dock.enabled, t!("app.dock"));
+
+        let demo = if autopilot.is_playing() {
+            ui.button(t!("autopilot.stop"))
+        } else {
+            ui.button(t!("autopilot.start"))
+        };
+        let demo = match &autopilot.error {
+            Some(error) => demo.on_hover_text(error),
+            None => demo.on_hover_text(t!("autopilot.desc")),
+        };
+        if demo.clicked() {
+            if autopilot.is_playing() {
+                autopilot.stop();
+            } else {
+                autopilot.start();
+            }
         }
 
         if ui
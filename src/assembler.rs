@@ -0,0 +1,238 @@
+use crate::compiler::{ISA, Inst, LpErr, Operand, OperandValue, Reg};
+use crate::gui::InterpreterOptions;
+use rust_i18n::t;
+
+/// Reads a hand-written program, so it can run without going through the compiler.
+///
+/// JSON is either a list of instructions or a document exported with `--emit json`, anything
+/// else is read as assembly text with one instruction per line, see [`to_text`].
+pub fn parse_program(text: &str) -> Result<Vec<Inst>, LpErr> {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        parse_json(trimmed)
+    } else {
+        text.lines()
+            .enumerate()
+            .filter_map(|(i, line)| parse_line(line).transpose().map(|r| (i, r)))
+            .map(|(i, result)| {
+                result.map_err(|msg| {
+                    LpErr::Parse(t!("assembler.at", line = i + 1, msg = msg).to_string())
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads assembly text like [`parse_program`], and also checks that every instruction fits the
+/// hardware and that nothing comes after the end of the program. Gives all problems with the
+/// index of their line otherwise.
+pub fn parse_for(text: &str, hw: &InterpreterOptions) -> Result<Vec<Inst>, Vec<(usize, String)>> {
+    let mut instructions = Vec::new();
+    let mut errors = Vec::new();
+    // the line that ends the program, see `Inst::terminates`
+    let mut end = None;
+    for (i, line) in text.lines().enumerate() {
+        match parse_line(line).and_then(|inst| inst.map(|inst| validate(inst, hw)).transpose()) {
+            Ok(Some(_)) if let Some(end) = end => {
+                errors.push((i, t!("assembler.after_end", line = end + 1).to_string()));
+            }
+            Ok(Some(inst)) => {
+                if inst.terminates() {
+                    end = Some(i);
+                }
+                instructions.push(inst);
+            }
+            Ok(None) => {}
+            Err(msg) => errors.push((i, msg)),
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(instructions),
+        false => Err(errors),
+    }
+}
+
+/// The instruction, if its registers and RAM addresses exist on the hardware.
+fn validate(inst: Inst, hw: &InterpreterOptions) -> Result<Inst, String> {
+    for operand in inst.operands() {
+        match operand {
+            OperandValue::Reg(r) if r.0 >= hw.num_registers => {
+                let n = hw.num_registers;
+                return Err(t!("assembler.register_range", r = r, n = n).to_string());
+            }
+            OperandValue::Addr(addr) if addr >= hw.num_cachelines => {
+                let n = hw.num_cachelines;
+                return Err(t!("assembler.address_range", addr = addr, n = n).to_string());
+            }
+            _ => {}
+        }
+    }
+    Ok(inst)
+}
+
+fn parse_json(text: &str) -> Result<Vec<Inst>, LpErr> {
+    let err = |e: serde_json::Error| LpErr::Parse(t!("assembler.json", e = e).to_string());
+    let mut value: serde_json::Value = serde_json::from_str(text).map_err(err)?;
+    // the instructions of an exported pipeline run
+    if let Some(instructions) = value.pointer_mut("/program/instructions") {
+        value = instructions.take();
+    }
+    serde_json::from_value(value).map_err(err)
+}
+
+/// The instruction on this line, if there's one. Everything after `;` or `#` is a comment.
+fn parse_line(line: &str) -> Result<Option<Inst>, String> {
+    let code = line.split([';', '#']).next().unwrap_or_default();
+    let mut words = code
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|w| !w.is_empty());
+    let Some(name) = words.next() else {
+        return Ok(None);
+    };
+    let operands: Vec<_> = words.collect();
+
+    let def = ISA
+        .iter()
+        .find(|def| def.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| t!("assembler.unknown", name = name).to_string())?;
+    if operands.len() != def.operands.len() {
+        let n = def.operands.len();
+        return Err(t!("assembler.operands", name = name, n = n).to_string());
+    }
+    let values = def
+        .operands
+        .iter()
+        .zip(operands)
+        .map(|(operand, s)| parse_operand(operand.kind, s))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Inst::from_operands(def, values))
+}
+
+/// An operand of this kind written like in [`to_text`], e.g. `b` for a register.
+pub fn parse_operand(kind: Operand, s: &str) -> Result<OperandValue, String> {
+    match kind {
+        Operand::Reg => s.parse::<Reg>().map(OperandValue::Reg),
+        Operand::Num => s
+            .parse()
+            .map(OperandValue::Num)
+            .map_err(|_| t!("assembler.number", s = s).to_string()),
+        Operand::Var => Ok(OperandValue::Var(s.into())),
+        Operand::Addr => s
+            .parse()
+            .map(OperandValue::Addr)
+            .map_err(|_| t!("assembler.address", s = s).to_string()),
+    }
+}
+
+/// The instruction as a line of assembly text that [`parse_program`] reads back.
+pub fn to_text(inst: &Inst) -> String {
+    let operands: Vec<_> = inst.operands().iter().map(ToString::to_string).collect();
+    match operands.is_empty() {
+        true => inst.name().into(),
+        false => format!("{} {}", inst.name(), operands.join(", ")),
+    }
+}
+
+/// Input variables the program reads, in the order they're first read, with how often.
+pub fn variables(instructions: &[Inst]) -> Vec<(String, usize)> {
+    let mut variables: Vec<(String, usize)> = Vec::new();
+    for inst in instructions {
+        if let Inst::Transfer(v, _) = inst {
+            match variables.iter_mut().find(|(name, _)| name == v) {
+                Some((_, n)) => *n += 1,
+                None => variables.push((v.clone(), 1)),
+            }
+        }
+    }
+    variables
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::{CompileOptions, Compiler, OptLevel};
+    use crate::export::Artifact;
+
+    fn text(instructions: &[Inst]) -> Vec<String> {
+        instructions.iter().map(to_text).collect()
+    }
+
+    #[test]
+    fn assembly_text_round_trips() -> Result<(), LpErr> {
+        let options = CompileOptions::default().with_level(OptLevel::O2);
+        let ir = Compiler::with(options).compile("a * 4 + b / a")?;
+        let assembly = text(&ir.instructions).join("\n");
+        assert_eq!(text(&parse_program(&assembly)?), text(&ir.instructions));
+        assert_eq!(variables(&ir.instructions), ir.variables);
+        Ok(())
+    }
+
+    #[test]
+    fn hand_written_assembly_is_read() -> Result<(), LpErr> {
+        let program = parse_program(
+            "; doubles the input\n\
+             transfer n, a\n\
+             \n\
+             STORE 1, R1   # shift amount\n\
+             shl a b\n\
+             result a\n",
+        )?;
+        assert_eq!(
+            text(&program),
+            ["transfer n, a", "store 1, b", "shl a, b", "result a"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_lines_are_reported() {
+        for (source, line) in [
+            ("store 1, a\njump a", 2),
+            ("add a", 1),
+            ("result a\n\nstore x, a", 3),
+            ("load a, b", 1),
+        ] {
+            let Err(LpErr::Parse(msg)) = parse_program(source) else {
+                panic!("`{source}` should not be accepted");
+            };
+            assert!(msg.contains(&line.to_string()), "{msg}");
+        }
+    }
+
+    #[test]
+    fn edited_lines_are_checked_against_the_hardware() {
+        let hw = InterpreterOptions {
+            num_registers: 2,
+            num_cachelines: 4,
+            ..Default::default()
+        };
+        let program = parse_for("store 1, a\nwrite a, 3\n\nresult b", &hw).unwrap();
+        assert_eq!(text(&program), ["store 1, a", "write a, 3", "result b"]);
+
+        let errors = parse_for("store 1, c\nload 4, a\nadd a, b\njump a", &hw).unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [0, 1, 3]);
+
+        let program = parse_for("store 1, a\nhalt", &hw).unwrap();
+        assert_eq!(text(&program), ["store 1, a", "halt"]);
+        let errors = parse_for("result a\n\nstore 1, a\nhalt", &hw).unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2, 3]);
+    }
+
+    #[test]
+    fn json_is_read_from_lists_and_exports() -> Result<(), LpErr> {
+        let program = parse_program(r#"[{"Store": [7, 0]}, {"Result": 0}]"#)?;
+        assert_eq!(text(&program), ["store 7, a", "result a"]);
+
+        let artifact = Artifact::new("a + 1", Default::default(), Default::default(), Vec::new());
+        let exported = artifact.program.as_ref().unwrap();
+        assert_eq!(
+            text(&parse_program(&artifact.to_json())?),
+            text(&exported.instructions)
+        );
+
+        assert!(parse_program("[{\"Jump\": 0}]").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use crate::compiler::Ir;
+use crate::interpreter::alu;
+use crate::types::{Inst, OperandValue, Reg, Unit};
+use std::collections::HashSet;
+
+mod riscv;
+mod wat;
+
+pub use riscv::RiscV;
+pub use wat::Wat;
+
+/// Result of lowering the IR for a target.
+pub enum BackendOutput {
+    /// Instructions that can be run by our interpreter.
+    Instructions(Vec<Inst>),
+    /// Assembly text for a real-world target, one line per entry. Only meant for display.
+    Text(Vec<String>),
+}
+
+/// A compilation target, turning the register-allocated IR into something a machine understands.
+pub trait Backend {
+    fn lower(&self, ir: &Ir) -> BackendOutput;
+}
+
+/// Our own toy ISA, which the IR already is.
+pub struct ToyIsa;
+
+impl Backend for ToyIsa {
+    fn lower(&self, ir: &Ir) -> BackendOutput {
+        BackendOutput::Instructions(ir.instructions.clone())
+    }
+}
+
+/// Selects the backend used by the compiler.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub enum Target {
+    #[default]
+    ToyIsa,
+    RiscV,
+    Wat,
+}
+
+impl Target {
+    /// Key of the localized name, for messages to visitors.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Target::ToyIsa => "compiler.target.toy_isa",
+            Target::RiscV => "compiler.target.riscv",
+            Target::Wat => "compiler.target.wat",
+        }
+    }
+
+    /// Number of registers the target can map ours onto, if it's limited.
+    pub fn max_registers(&self) -> Option<u8> {
+        match self {
+            Target::RiscV => Some(riscv::NUM_REGS),
+            Target::ToyIsa | Target::Wat => None,
+        }
+    }
+
+    pub fn backend(&self) -> &'static dyn Backend {
+        match self {
+            Target::ToyIsa => &ToyIsa,
+            Target::RiscV => &RiscV,
+            Target::Wat => &Wat,
+        }
+    }
+}
+
+/// How an arithmetic instruction can overflow, see [`Inst::Sat`] and [`Inst::Chk`]. The targets
+/// have no overflow flag and compute it from the operands instead, at 32 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overflow {
+    Add,
+    Sub,
+    Mul,
+    /// Only the smallest number divided by -1.
+    Div,
+    Never,
+}
+
+/// The arithmetic of an instruction as `dest = x op y`, negation is `0 - r`.
+fn arithmetic(inst: &Inst) -> Option<(Overflow, [OperandValue; 2], Reg)> {
+    let (_, operands, dest) = alu(inst)?;
+    let Unit::Alu { symbol, .. } = inst.def().unit else {
+        return None;
+    };
+    let overflow = match symbol {
+        "+" => Overflow::Add,
+        "-" => Overflow::Sub,
+        "*" => Overflow::Mul,
+        "/" => Overflow::Div,
+        _ => Overflow::Never,
+    };
+    Some((overflow, operands, dest))
+}
+
+/// Positions of the arithmetic instructions whose overflow flag is read, by the [`Inst::Sat`]
+/// or [`Inst::Chk`] after them. Only these need to compute it.
+fn flags_read(instructions: &[Inst]) -> HashSet<usize> {
+    let mut last = None;
+    let mut read = HashSet::new();
+    for (i, inst) in instructions.iter().enumerate() {
+        match inst {
+            Inst::Sat(_) | Inst::Chk(_) => read.extend(last),
+            _ if arithmetic(inst).is_some() => last = Some(i),
+            _ => {}
+        }
+    }
+    read
+}
@@ -0,0 +1,366 @@
+use crate::backend::{Backend, BackendOutput, Overflow, arithmetic, flags_read};
+use crate::compiler::Ir;
+use crate::gui::InterpreterOptions;
+use crate::types::{Inst, OperandValue, Reg};
+
+/// Number of our registers that can be mapped to RISC-V registers.
+pub const NUM_REGS: u8 = RISCV_REGS.len() as u8;
+
+/// RISC-V registers our toy registers are mapped to, in order.
+///
+/// `a0` is left out since it holds the return value, `sp` since it points to our "RAM".
+const RISCV_REGS: [&str; 26] = [
+    "t0", "t1", "t2", "t3", "t4", "t5", "t6", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8",
+    "s9", "s10", "s11", "a1", "a2", "a3", "a4", "a5", "a6", "a7",
+];
+
+/// Size of a machine word in bytes (RV32).
+const WORD: usize = 4;
+
+fn reg(r: &Reg) -> &'static str {
+    RISCV_REGS[r.0 as usize]
+}
+
+/// Whether the number fits into the 12 bits of an `addi` immediate.
+fn fits_immediate(n: i32) -> bool {
+    (-2048..2048).contains(&n)
+}
+
+/// Lowers our toy instructions to (RV32IM) RISC-V assembly text.
+///
+/// The output is only meant to be looked at, the main memory is modelled as a stack frame and
+/// input variables as global symbols.
+pub struct RiscV;
+
+impl Backend for RiscV {
+    fn lower(&self, ir: &Ir) -> BackendOutput {
+        BackendOutput::Text(lower_to_riscv(&ir.instructions, &ir.hw))
+    }
+}
+
+fn lower_to_riscv(instructions: &[Inst], hw: &InterpreterOptions) -> Vec<String> {
+    // RISC-V has no overflow flag, it's computed into `ra`, which is saved after the RAM cells
+    let uses_flag = (instructions.iter()).any(|i| matches!(i, Inst::Sat(_) | Inst::Chk(_)));
+    let flags = flags_read(instructions);
+    let saved_ra = hw.num_cachelines * WORD;
+    let frame = saved_ra + if uses_flag { WORD } else { 0 };
+    let mut out = vec![
+        ".globl main".to_string(),
+        "main:".to_string(),
+        format!("    addi sp, sp, -{frame}"),
+    ];
+    if uses_flag {
+        out.push(format!("    sw ra, {saved_ra}(sp)"));
+        out.push("    li ra, 0".to_string());
+    }
+
+    // bytes pushed onto the stack, the frame moves up by this much relative to `sp`
+    let mut pushed = 0;
+    for (i, inst) in instructions.iter().enumerate() {
+        let (before, after) = match arithmetic(inst).filter(|_| flags.contains(&i)) {
+            Some((overflow, operands, dest)) => overflow_flag(overflow, &operands, dest),
+            None => (vec![], vec![]),
+        };
+        out.extend(before.into_iter().map(|l| format!("    {l}")));
+        let line = match inst {
+            // the toy ISA accumulates into the second register: b = a op b
+            Inst::Add(a, b) => format!("add {}, {}, {}", reg(b), reg(a), reg(b)),
+            Inst::Sub(a, b) => format!("sub {}, {}, {}", reg(b), reg(a), reg(b)),
+            Inst::Mul(a, b) => format!("mul {}, {}, {}", reg(b), reg(a), reg(b)),
+            Inst::Div(a, b) => format!("div {}, {}, {}", reg(b), reg(a), reg(b)),
+            Inst::Shl(a, b) => format!("sll {}, {}, {}", reg(b), reg(a), reg(b)),
+            Inst::Shr(a, b) => format!("sra {}, {}, {}", reg(b), reg(a), reg(b)),
+            Inst::Add3(a, b, c) => format!("add {}, {}, {}", reg(c), reg(a), reg(b)),
+            Inst::Sub3(a, b, c) => format!("sub {}, {}, {}", reg(c), reg(a), reg(b)),
+            Inst::Mul3(a, b, c) => format!("mul {}, {}, {}", reg(c), reg(a), reg(b)),
+            Inst::Div3(a, b, c) => format!("div {}, {}, {}", reg(c), reg(a), reg(b)),
+            Inst::Shl3(a, b, c) => format!("sll {}, {}, {}", reg(c), reg(a), reg(b)),
+            Inst::Shr3(a, b, c) => format!("sra {}, {}, {}", reg(c), reg(a), reg(b)),
+            // RISC-V only has small immediates for some operations, `a0` is free until the end
+            Inst::AddI(r, n) if fits_immediate(*n) => format!("addi {0}, {0}, {n}", reg(r)),
+            Inst::SubI(r, n) if n.checked_neg().is_some_and(fits_immediate) => {
+                format!("addi {0}, {0}, {1}", reg(r), -n)
+            }
+            Inst::AddI(r, n) | Inst::SubI(r, n) | Inst::MulI(r, n) | Inst::DivI(r, n) => {
+                out.push(format!("    li a0, {n}"));
+                let op = match inst {
+                    Inst::AddI(..) => "add",
+                    Inst::SubI(..) => "sub",
+                    Inst::MulI(..) => "mul",
+                    _ => "div",
+                };
+                format!("{op} {0}, {0}, a0", reg(r))
+            }
+            Inst::ShlI(r, n) => format!("slli {0}, {0}, {1}", reg(r), n & 31),
+            Inst::ShrI(r, n) => format!("srai {0}, {0}, {1}", reg(r), n & 31),
+            Inst::Neg(r) => format!("neg {0}, {0}", reg(r)),
+            // from the Zbb extension
+            Inst::Min(a, b) => format!("min {}, {}, {}", reg(b), reg(a), reg(b)),
+            Inst::Max(a, b) => format!("max {}, {}, {}", reg(b), reg(a), reg(b)),
+            Inst::Min3(a, b, c) => format!("min {}, {}, {}", reg(c), reg(a), reg(b)),
+            Inst::Max3(a, b, c) => format!("max {}, {}, {}", reg(c), reg(a), reg(b)),
+            Inst::Sat(r) => {
+                out.push("    beqz ra, 1f".to_string());
+                // the wrapped result has the wrong sign: -1 turns the smallest number into the
+                // largest one
+                out.push(format!("    srai a0, {}, 31", reg(r)));
+                out.push(format!("    lui {}, 0x80000", reg(r)));
+                out.push(format!("    xor {0}, {0}, a0", reg(r)));
+                "1:".to_string()
+            }
+            Inst::Chk(_) => {
+                out.push("    beqz ra, 1f".to_string());
+                out.push("    ebreak".to_string());
+                "1:".to_string()
+            }
+            Inst::Store(n, r) => format!("li {}, {n}", reg(r)),
+            Inst::Transfer(v, r) => format!("lw {}, {v}", reg(r)),
+            Inst::Mov(a, b) => format!("mv {}, {}", reg(b), reg(a)),
+            Inst::Write(r, addr) => format!("sw {}, {}(sp)", reg(r), addr * WORD + pushed),
+            Inst::Load(addr, r) => format!("lw {}, {}(sp)", reg(r), addr * WORD + pushed),
+            Inst::Push(r) => {
+                pushed += WORD;
+                out.push(format!("    addi sp, sp, -{WORD}"));
+                format!("sw {}, 0(sp)", reg(r))
+            }
+            Inst::Pop(r) => {
+                pushed -= WORD;
+                out.push(format!("    lw {}, 0(sp)", reg(r)));
+                format!("addi sp, sp, {WORD}")
+            }
+            // there's no result to return, the program fails
+            Inst::Halt => "ebreak".to_string(),
+            Inst::Result(r) => {
+                out.push(format!("    mv a0, {}", reg(r)));
+                if uses_flag {
+                    out.push(format!("    lw ra, {}(sp)", saved_ra + pushed));
+                }
+                out.push(format!("    addi sp, sp, {}", frame + pushed));
+                "ret".to_string()
+            }
+        };
+        out.push(format!("    {line}"));
+        out.extend(after.into_iter().map(|l| format!("    {l}")));
+    }
+
+    out
+}
+
+/// The instructions before and after an arithmetic instruction that compute its overflow flag
+/// into `ra`, at 32 bits. Operands the result overwrites are copied to `ra` and `a0` first, the
+/// instruction itself only uses `a0` for immediates, which are loaded again afterwards.
+fn overflow_flag(
+    overflow: Overflow,
+    [x, y]: &[OperandValue; 2],
+    dest: Reg,
+) -> (Vec<String>, Vec<String>) {
+    let (mut before, mut after) = (vec![], vec![]);
+    let r = reg(&dest);
+    match overflow {
+        Overflow::Add | Overflow::Sub => {
+            let x = kept(x, "ra", dest, &mut before, &mut after);
+            let y = kept(y, "a0", dest, &mut before, &mut after);
+            // the sign bit tells: the result has another sign than both operands for additions,
+            // than the first one but not the second one for subtractions
+            after.push(format!("xor a0, {y}, {r}"));
+            if overflow == Overflow::Sub {
+                after.push("not a0, a0".to_string());
+            }
+            after.push(format!("xor ra, {x}, {r}"));
+            after.push("and ra, ra, a0".to_string());
+            after.push("srli ra, ra, 31".to_string());
+        }
+        Overflow::Mul => {
+            let y = match y {
+                OperandValue::Num(n) => {
+                    before.push(format!("li ra, {n}"));
+                    "ra".to_string()
+                }
+                y => operand(y),
+            };
+            before.push(format!("mulh ra, {}, {y}", operand(x)));
+            // the upper half of the product has to be the sign of the lower half
+            after.push(format!("srai a0, {r}, 31"));
+            after.push("xor ra, ra, a0".to_string());
+            after.push("snez ra, ra".to_string());
+        }
+        Overflow::Div => match y {
+            OperandValue::Num(-1) => {
+                before.push("lui ra, 0x80000".to_string());
+                before.push(format!("xor ra, ra, {}", operand(x)));
+                before.push("seqz ra, ra".to_string());
+            }
+            OperandValue::Num(_) => after.push("li ra, 0".to_string()),
+            y => {
+                before.push(format!("addi ra, {}, 1", operand(y)));
+                before.push("seqz ra, ra".to_string());
+                before.push("lui a0, 0x80000".to_string());
+                before.push(format!("xor a0, a0, {}", operand(x)));
+                before.push("seqz a0, a0".to_string());
+                before.push("and ra, ra, a0".to_string());
+            }
+        },
+        Overflow::Never => after.push("li ra, 0".to_string()),
+    }
+    (before, after)
+}
+
+/// Where an operand of an arithmetic instruction is after it ran: registers it overwrote are
+/// copied to `scratch` before, numbers are loaded into it after.
+fn kept(
+    value: &OperandValue,
+    scratch: &str,
+    dest: Reg,
+    before: &mut Vec<String>,
+    after: &mut Vec<String>,
+) -> String {
+    match value {
+        OperandValue::Reg(r) if *r == dest => before.push(format!("mv {scratch}, {}", reg(r))),
+        OperandValue::Num(n) if *n != 0 => after.push(format!("li {scratch}, {n}")),
+        value => return operand(value),
+    }
+    scratch.to_string()
+}
+
+/// A register or zero, the only numbers arithmetic has as its first operand.
+fn operand(value: &OperandValue) -> String {
+    match value {
+        OperandValue::Reg(r) => reg(r).to_string(),
+        OperandValue::Num(0) => "zero".to_string(),
+        value => unreachable!("{value} isn't an operand of arithmetic"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use std::collections::HashMap;
+
+    #[test]
+    fn lower_simple_add() {
+        let hw = InterpreterOptions::default();
+        let asm = lower_to_riscv(
+            &[
+                Inst::Store(1, Reg(0)),
+                Inst::Store(2, Reg(1)),
+                Inst::Add(Reg(0), Reg(1)),
+                Inst::Result(Reg(1)),
+            ],
+            &hw,
+        );
+
+        assert_eq!(asm[3], "    li t0, 1");
+        assert_eq!(asm[5], "    add t1, t0, t1");
+        assert_eq!(asm[6], "    mv a0, t1");
+        assert_eq!(asm.last().unwrap(), "    ret");
+    }
+
+    /// Runs the lowered program, enough of RV32IM for what the overflow checks use. `None` if it
+    /// stopped at an `ebreak`.
+    fn run(asm: &[String]) -> Option<i32> {
+        let mut regs = HashMap::from([("zero", 0), ("sp", 1 << 16)]);
+        let mut memory = HashMap::new();
+        // after the `.globl main` and `main:`
+        let mut lines = asm.iter().skip(2).map(|l| l.trim());
+        while let Some(line) = lines.next() {
+            let Some((op, args)) = line.split_once(' ') else {
+                match line {
+                    "ebreak" => return None,
+                    "ret" => return Some(regs["a0"]),
+                    _ => continue,
+                }
+            };
+            let args: Vec<_> = args.split(", ").collect();
+            let get = |regs: &HashMap<_, i32>, arg: &str| {
+                (regs.get(arg).copied()).unwrap_or_else(|| arg.parse().unwrap_or(0))
+            };
+            let address = |regs: &HashMap<_, i32>, arg: &str| {
+                let (offset, _) = arg.split_once('(').unwrap();
+                regs["sp"] + offset.parse::<i32>().unwrap()
+            };
+            let [a, b] = [1, 2].map(|i| args.get(i).map_or(0, |arg| get(&regs, arg)));
+            let value = match op {
+                "beqz" => {
+                    if get(&regs, args[0]) == 0 {
+                        lines.find(|l| *l == "1:");
+                    }
+                    continue;
+                }
+                "sw" => {
+                    memory.insert(address(&regs, args[1]), get(&regs, args[0]));
+                    continue;
+                }
+                "lw" => memory[&address(&regs, args[1])],
+                "li" => args[1].parse().unwrap(),
+                "lui" => (u32::from_str_radix(&args[1][2..], 16).unwrap() << 12) as i32,
+                "mv" => a,
+                "not" => !a,
+                "neg" => a.wrapping_neg(),
+                "seqz" => i32::from(a == 0),
+                "snez" => i32::from(a != 0),
+                "add" | "addi" => a.wrapping_add(b),
+                "sub" => a.wrapping_sub(b),
+                "mul" => a.wrapping_mul(b),
+                "mulh" => ((i64::from(a) * i64::from(b)) >> 32) as i32,
+                "div" => a.wrapping_div(b),
+                "xor" => a ^ b,
+                "and" => a & b,
+                "srli" | "srl" => (a as u32).wrapping_shr(b as u32) as i32,
+                "srai" | "sra" => a.wrapping_shr(b as u32),
+                "slli" | "sll" => a.wrapping_shl(b as u32),
+                _ => panic!("{op} isn't supported"),
+            };
+            regs.insert(args[0], value);
+        }
+        panic!("the program doesn't return")
+    }
+
+    #[test]
+    fn overflow_is_checked_like_the_interpreter() {
+        let hw = InterpreterOptions::default();
+        let [a, b] = [Reg(0), Reg(1)];
+        let ops = [
+            Inst::Add(a, b),
+            Inst::Sub(a, b),
+            Inst::Mul(a, b),
+            Inst::Div(a, b),
+            Inst::Add(b, b),
+            Inst::Sub3(a, b, a),
+            Inst::AddI(b, 5000),
+            Inst::SubI(b, 1),
+            Inst::MulI(b, 3),
+            Inst::DivI(b, -1),
+            Inst::Neg(b),
+            Inst::Shl(a, b),
+        ];
+        let values = [0, 1, -1, 2, 1 << 16, i32::MAX, i32::MIN, i32::MIN + 1];
+        for op in ops {
+            for check in [Inst::Sat(b), Inst::Chk(b)] {
+                for (x, y) in values
+                    .iter()
+                    .flat_map(|x| values.iter().map(move |y| (*x, *y)))
+                {
+                    if matches!(op, Inst::Div(..)) && y == 0 {
+                        continue;
+                    }
+                    let code = vec![
+                        Inst::Store(x, a),
+                        Inst::Store(y, b),
+                        op.clone(),
+                        Inst::Store(7, Reg(2)),
+                        check.clone(),
+                        Inst::Result(b),
+                    ];
+                    let expected = Interpreter::with_config(&hw)
+                        .load_instructions(code.clone())
+                        .ready()
+                        .run_to_end()
+                        .ok();
+                    let asm = lower_to_riscv(&code, &hw);
+                    assert_eq!(run(&asm), expected, "{op:?} {check:?} of {x} and {y}");
+                }
+            }
+        }
+    }
+}
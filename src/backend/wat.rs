@@ -0,0 +1,332 @@
+use crate::backend::{Backend, BackendOutput, Overflow, arithmetic, flags_read};
+use crate::compiler::Ir;
+use crate::types::{Inst, OperandValue, Reg};
+use std::collections::BTreeSet;
+
+/// Size of a machine word in bytes (i32).
+const WORD: usize = 4;
+
+/// Lowers our toy instructions to a WebAssembly text module exporting a single `main` function.
+///
+/// Input variables become function parameters (in order of first use), registers become locals
+/// prefixed with `r_` to avoid clashing with them, and the main memory is mapped onto linear memory.
+pub struct Wat;
+
+impl Backend for Wat {
+    fn lower(&self, ir: &Ir) -> BackendOutput {
+        BackendOutput::Text(lower_to_wat(&ir.instructions))
+    }
+}
+
+fn lower_to_wat(instructions: &[Inst]) -> Vec<String> {
+    let mut params: Vec<&str> = vec![];
+    let mut regs: BTreeSet<Reg> = BTreeSet::new();
+    for inst in instructions {
+        match inst {
+            Inst::Add(a, b)
+            | Inst::Sub(a, b)
+            | Inst::Mul(a, b)
+            | Inst::Div(a, b)
+            | Inst::Shl(a, b)
+            | Inst::Shr(a, b)
+            | Inst::Min(a, b)
+            | Inst::Max(a, b)
+            | Inst::Mov(a, b) => {
+                regs.insert(*a);
+                regs.insert(*b);
+            }
+            Inst::Add3(a, b, c)
+            | Inst::Sub3(a, b, c)
+            | Inst::Mul3(a, b, c)
+            | Inst::Div3(a, b, c)
+            | Inst::Shl3(a, b, c)
+            | Inst::Shr3(a, b, c)
+            | Inst::Min3(a, b, c)
+            | Inst::Max3(a, b, c) => {
+                regs.extend([*a, *b, *c]);
+            }
+            Inst::Transfer(v, r) => {
+                if !params.contains(&v.as_str()) {
+                    params.push(v);
+                }
+                regs.insert(*r);
+            }
+            Inst::Store(_, r)
+            | Inst::AddI(r, _)
+            | Inst::SubI(r, _)
+            | Inst::MulI(r, _)
+            | Inst::DivI(r, _)
+            | Inst::ShlI(r, _)
+            | Inst::ShrI(r, _)
+            | Inst::Neg(r)
+            | Inst::Sat(r)
+            | Inst::Chk(r)
+            | Inst::Result(r)
+            | Inst::Write(r, _)
+            | Inst::Load(_, r)
+            | Inst::Push(r)
+            | Inst::Pop(r) => {
+                regs.insert(*r);
+            }
+            Inst::Halt => {}
+        }
+    }
+
+    let signature: String = params
+        .iter()
+        .map(|p| format!(" (param ${p} i32)"))
+        .collect();
+
+    let mut out = vec![
+        "(module".to_string(),
+        "  (memory 1)".to_string(),
+        // our stack grows downwards from the end of the memory page
+        "  (global $sp (mut i32) (i32.const 65536))".to_string(),
+        format!("  (func $main (export \"main\"){signature} (result i32)"),
+    ];
+    out.extend(regs.iter().map(|r| format!("    (local $r_{r} i32)")));
+    // WebAssembly has no overflow flag, it's computed from the operands into a local
+    let flags = flags_read(instructions);
+    if (instructions.iter()).any(|i| matches!(i, Inst::Sat(_) | Inst::Chk(_))) {
+        out.push("    (local $overflow i32)".to_string());
+        out.push("    (local $wide i64)".to_string());
+    }
+
+    let mut body = vec![];
+    for (i, inst) in instructions.iter().enumerate() {
+        let flag = arithmetic(inst).filter(|_| flags.contains(&i));
+        if let Some((overflow, operands, _)) = &flag {
+            overflow_before(&mut body, *overflow, operands);
+        }
+        match inst {
+            // the toy ISA accumulates into the second register: b = a op b
+            Inst::Add(a, b) => binop(&mut body, a, b, "i32.add"),
+            Inst::Sub(a, b) => binop(&mut body, a, b, "i32.sub"),
+            Inst::Mul(a, b) => binop(&mut body, a, b, "i32.mul"),
+            Inst::Div(a, b) => binop(&mut body, a, b, "i32.div_s"),
+            Inst::Shl(a, b) => binop(&mut body, a, b, "i32.shl"),
+            Inst::Shr(a, b) => binop(&mut body, a, b, "i32.shr_s"),
+            Inst::Add3(a, b, c) => ternary(&mut body, a, b, c, "i32.add"),
+            Inst::Sub3(a, b, c) => ternary(&mut body, a, b, c, "i32.sub"),
+            Inst::Mul3(a, b, c) => ternary(&mut body, a, b, c, "i32.mul"),
+            Inst::Div3(a, b, c) => ternary(&mut body, a, b, c, "i32.div_s"),
+            Inst::Shl3(a, b, c) => ternary(&mut body, a, b, c, "i32.shl"),
+            Inst::Shr3(a, b, c) => ternary(&mut body, a, b, c, "i32.shr_s"),
+            Inst::AddI(r, n) => immediate(&mut body, r, *n, "i32.add"),
+            Inst::SubI(r, n) => immediate(&mut body, r, *n, "i32.sub"),
+            Inst::MulI(r, n) => immediate(&mut body, r, *n, "i32.mul"),
+            Inst::DivI(r, n) => immediate(&mut body, r, *n, "i32.div_s"),
+            Inst::ShlI(r, n) => immediate(&mut body, r, *n, "i32.shl"),
+            Inst::ShrI(r, n) => immediate(&mut body, r, *n, "i32.shr_s"),
+            Inst::Sat(r) => {
+                body.push("local.get $overflow".to_string());
+                body.push("if".to_string());
+                // the wrapped result has the wrong sign
+                body.push(format!("i32.const {}", i32::MAX));
+                body.push(format!("i32.const {}", i32::MIN));
+                body.push(format!("local.get $r_{r}"));
+                body.push("i32.const 0".to_string());
+                body.push("i32.lt_s".to_string());
+                body.push("select".to_string());
+                body.push(format!("local.set $r_{r}"));
+                body.push("end".to_string());
+            }
+            Inst::Chk(_) => {
+                body.push("local.get $overflow".to_string());
+                body.push("if".to_string());
+                body.push("unreachable".to_string());
+                body.push("end".to_string());
+            }
+            Inst::Min(a, b) | Inst::Max(a, b) | Inst::Min3(a, b, _) | Inst::Max3(a, b, _) => {
+                let (cmp, dest) = match inst {
+                    Inst::Min(..) => ("i32.lt_s", b),
+                    Inst::Max(..) => ("i32.gt_s", b),
+                    Inst::Min3(.., c) => ("i32.lt_s", c),
+                    Inst::Max3(.., c) => ("i32.gt_s", c),
+                    _ => unreachable!("only min and max get here"),
+                };
+                // picks the first value if the condition holds
+                body.push(format!("local.get $r_{a}"));
+                body.push(format!("local.get $r_{b}"));
+                body.push(format!("local.get $r_{a}"));
+                body.push(format!("local.get $r_{b}"));
+                body.push(cmp.to_string());
+                body.push("select".to_string());
+                body.push(format!("local.set $r_{dest}"));
+            }
+            Inst::Neg(r) => {
+                body.push("i32.const 0".to_string());
+                body.push(format!("local.get $r_{r}"));
+                body.push("i32.sub".to_string());
+                body.push(format!("local.set $r_{r}"));
+            }
+            Inst::Store(n, r) => {
+                body.push(format!("i32.const {n}"));
+                body.push(format!("local.set $r_{r}"));
+            }
+            Inst::Transfer(v, r) => {
+                body.push(format!("local.get ${v}"));
+                body.push(format!("local.set $r_{r}"));
+            }
+            Inst::Mov(a, b) => {
+                body.push(format!("local.get $r_{a}"));
+                body.push(format!("local.set $r_{b}"));
+            }
+            Inst::Write(r, addr) => {
+                body.push(format!("i32.const {}", addr * WORD));
+                body.push(format!("local.get $r_{r}"));
+                body.push("i32.store".to_string());
+            }
+            Inst::Load(addr, r) => {
+                body.push(format!("i32.const {}", addr * WORD));
+                body.push("i32.load".to_string());
+                body.push(format!("local.set $r_{r}"));
+            }
+            Inst::Push(r) => {
+                body.push("global.get $sp".to_string());
+                body.push(format!("i32.const {WORD}"));
+                body.push("i32.sub".to_string());
+                body.push("global.set $sp".to_string());
+                body.push("global.get $sp".to_string());
+                body.push(format!("local.get $r_{r}"));
+                body.push("i32.store".to_string());
+            }
+            Inst::Pop(r) => {
+                body.push("global.get $sp".to_string());
+                body.push("i32.load".to_string());
+                body.push(format!("local.set $r_{r}"));
+                body.push("global.get $sp".to_string());
+                body.push(format!("i32.const {WORD}"));
+                body.push("i32.add".to_string());
+                body.push("global.set $sp".to_string());
+            }
+            // the result is the value left on the stack at the end of the function
+            Inst::Result(r) => body.push(format!("local.get $r_{r}")),
+            // there's no result to leave on the stack, the program fails
+            Inst::Halt => body.push("unreachable".to_string()),
+        }
+        if let Some((overflow, _, dest)) = flag {
+            overflow_after(&mut body, overflow, dest);
+        }
+    }
+
+    out.extend(body.into_iter().map(|l| format!("    {l}")));
+    out.push("  )".to_string());
+    out.push(")".to_string());
+    out
+}
+
+/// Starts computing the overflow flag of an arithmetic instruction, at 32 bits: the result is
+/// computed again with 64 bits, for divisions only the one case that overflows is checked.
+fn overflow_before(body: &mut Vec<String>, overflow: Overflow, [x, y]: &[OperandValue; 2]) {
+    let get = |body: &mut Vec<String>, value: &OperandValue| match value {
+        OperandValue::Reg(r) => body.push(format!("local.get $r_{r}")),
+        value => body.push(format!("i32.const {value}")),
+    };
+    let op = match overflow {
+        Overflow::Add => "i64.add",
+        Overflow::Sub => "i64.sub",
+        Overflow::Mul => "i64.mul",
+        Overflow::Div => {
+            get(body, x);
+            body.push(format!("i32.const {}", i32::MIN));
+            body.push("i32.eq".to_string());
+            get(body, y);
+            body.push("i32.const -1".to_string());
+            body.push("i32.eq".to_string());
+            body.push("i32.and".to_string());
+            body.push("local.set $overflow".to_string());
+            return;
+        }
+        Overflow::Never => return,
+    };
+    for value in [x, y] {
+        get(body, value);
+        body.push("i64.extend_i32_s".to_string());
+    }
+    body.push(op.to_string());
+    body.push("local.set $wide".to_string());
+}
+
+/// Finishes the overflow flag once the instruction stored its result in `dest`.
+fn overflow_after(body: &mut Vec<String>, overflow: Overflow, dest: Reg) {
+    match overflow {
+        Overflow::Add | Overflow::Sub | Overflow::Mul => {
+            // the 32 bits of the result don't hold all of it
+            body.push("local.get $wide".to_string());
+            body.push(format!("local.get $r_{dest}"));
+            body.push("i64.extend_i32_s".to_string());
+            body.push("i64.ne".to_string());
+            body.push("local.set $overflow".to_string());
+        }
+        Overflow::Div => {}
+        Overflow::Never => {
+            body.push("i32.const 0".to_string());
+            body.push("local.set $overflow".to_string());
+        }
+    }
+}
+
+fn binop(body: &mut Vec<String>, a: &Reg, b: &Reg, op: &str) {
+    body.push(format!("local.get $r_{a}"));
+    body.push(format!("local.get $r_{b}"));
+    body.push(op.to_string());
+    body.push(format!("local.set $r_{b}"));
+}
+
+fn ternary(body: &mut Vec<String>, a: &Reg, b: &Reg, c: &Reg, op: &str) {
+    body.push(format!("local.get $r_{a}"));
+    body.push(format!("local.get $r_{b}"));
+    body.push(op.to_string());
+    body.push(format!("local.set $r_{c}"));
+}
+
+fn immediate(body: &mut Vec<String>, r: &Reg, n: i32, op: &str) {
+    body.push(format!("local.get $r_{r}"));
+    body.push(format!("i32.const {n}"));
+    body.push(op.to_string());
+    body.push(format!("local.set $r_{r}"));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn variables_become_params() {
+        let wat = lower_to_wat(&[
+            Inst::Transfer("x".into(), Reg(0)),
+            Inst::Transfer("y".into(), Reg(1)),
+            Inst::Sub(Reg(0), Reg(1)),
+            Inst::Result(Reg(1)),
+        ]);
+
+        assert_eq!(
+            wat[3],
+            "  (func $main (export \"main\") (param $x i32) (param $y i32) (result i32)"
+        );
+        assert!(wat.contains(&"    i32.sub".to_string()));
+    }
+
+    #[test]
+    fn overflow_is_computed_for_checks() {
+        let wat = lower_to_wat(&[
+            Inst::Transfer("x".into(), Reg(0)),
+            Inst::AddI(Reg(0), 1),
+            Inst::Chk(Reg(0)),
+            Inst::Result(Reg(0)),
+        ]);
+        let body: Vec<_> = wat.iter().map(|l| l.trim()).collect();
+        let check = [
+            "local.get $r_a",
+            "i64.extend_i32_s",
+            "i32.const 1",
+            "i64.extend_i32_s",
+            "i64.add",
+            "local.set $wide",
+        ];
+        assert!(body.windows(check.len()).any(|w| w == check), "{body:?}");
+        let check = ["local.get $overflow", "if", "unreachable", "end"];
+        assert!(body.windows(check.len()).any(|w| w == check), "{body:?}");
+    }
+}
@@ -0,0 +1,282 @@
+//! A fixed set of programs compiled under several configurations, to check with numbers whether
+//! a change to a pass or to the register allocation helps. Run with `--bench`.
+//!
+//! The allocator itself has one improvement, the cache optimization with the register moves
+//! after it, so it's compared by turning that on alone.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use crate::compiler::{CompileOptions, Compiler, LpErr, OptLevel};
+use crate::gui::{InterpreterOptions, Optimization};
+use crate::interpreter::{Interpreter, Stats};
+
+/// Times each program is compiled, the compile time is the average.
+const REPETITIONS: u32 = 20;
+
+/// A program of the corpus with the inputs it's run with.
+pub struct BenchProgram {
+    pub name: &'static str,
+    pub source: &'static str,
+    pub inputs: &'static [(&'static str, i32)],
+}
+
+/// Programs like the ones visitors write, from small to large, each good for some of the passes.
+pub const CORPUS: &[BenchProgram] = &[
+    BenchProgram {
+        name: "constants",
+        source: "(3 + 4) * (10 - 2) + x",
+        inputs: &[("x", 5)],
+    },
+    BenchProgram {
+        name: "powers",
+        source: "a * 8 + b * 16 - a / 4",
+        inputs: &[("a", 40), ("b", 3)],
+    },
+    BenchProgram {
+        name: "factors",
+        source: "a * b + a * c + a * d",
+        inputs: &[("a", 3), ("b", 4), ("c", 5), ("d", 6)],
+    },
+    BenchProgram {
+        name: "pressure",
+        source: "(a + 1) * (b + 2) - (a * 3 + b) / (a - b * 2) + (c + a) * (c - b)",
+        inputs: &[("a", 7), ("b", 2), ("c", 9)],
+    },
+    BenchProgram {
+        name: "polynomial",
+        source: "x * x * x * 4 + x * x * 2 + x * 16 + 1",
+        inputs: &[("x", 3)],
+    },
+    BenchProgram {
+        name: "clamp",
+        source: "clamp(a * 2 + b, 0, 100) + max(a, b) * 32",
+        inputs: &[("a", 30), ("b", -4)],
+    },
+];
+
+/// The optimizations a program is compiled with.
+pub struct Config {
+    pub name: String,
+    pub options: CompileOptions,
+}
+
+/// The optimization levels, then each optimization on its own.
+pub fn configs() -> Vec<Config> {
+    let levels = OptLevel::ALL.iter().map(|level| Config {
+        name: level.to_string(),
+        options: CompileOptions::default().with_level(*level),
+    });
+    let single = Optimization::all().map(|opt| {
+        let mut options = CompileOptions::default();
+        *opt.flag(&mut options) = true;
+        Config {
+            name: opt.name().to_string(),
+            options,
+        }
+    });
+    levels.chain(single).collect()
+}
+
+/// How a program did with one configuration.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub program: &'static str,
+    pub config: String,
+    pub compile_time: Duration,
+    pub instructions: usize,
+    pub cycles: u64,
+    pub result: i32,
+}
+
+/// Compiles and runs every program of the corpus with every configuration.
+pub fn run(
+    corpus: &[BenchProgram],
+    configs: &[Config],
+    hw: InterpreterOptions,
+) -> Result<Vec<Measurement>, LpErr> {
+    let mut measurements = Vec::new();
+    for program in corpus {
+        let vars: HashMap<_, _> = program
+            .inputs
+            .iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect();
+        for config in configs {
+            let compiler = || Compiler::with(config.options).with_interpreter(hw);
+            let start = Instant::now();
+            for _ in 0..REPETITIONS {
+                compiler().compile(program.source)?;
+            }
+            let compile_time = start.elapsed() / REPETITIONS;
+
+            let run = compile_and_run(compiler(), hw, program.source, &vars)?;
+            measurements.push(Measurement {
+                program: program.name,
+                config: config.name.clone(),
+                compile_time,
+                instructions: run.instructions,
+                cycles: run.stats.cycles,
+                result: run.result,
+            });
+        }
+    }
+    Ok(measurements)
+}
+
+/// What a program did when it was compiled and run once.
+pub struct Run {
+    pub result: i32,
+    pub instructions: usize,
+    pub stats: Stats,
+}
+
+/// Compiles the program for the hardware, with everything else set in the compiler, and runs it
+/// once with the inputs.
+pub fn compile_and_run(
+    compiler: Compiler,
+    hw: InterpreterOptions,
+    source: &str,
+    vars: &HashMap<String, String>,
+) -> Result<Run, LpErr> {
+    let ir = compiler.with_interpreter(hw).compile(source)?;
+    let mut interpreter = Interpreter::with_config(&hw)
+        .load_instructions(ir.instructions.clone())
+        .with_variables(vars.clone())
+        .ready();
+    let result = interpreter.run_to_end()?;
+    Ok(Run {
+        result,
+        instructions: ir.instructions.len(),
+        stats: interpreter.stats().clone(),
+    })
+}
+
+/// One line per program and configuration, the cycles compared to the first configuration of
+/// the program, and the totals per configuration at the end.
+pub fn table(measurements: &[Measurement], configs: &[Config]) -> Vec<String> {
+    let row = |program: &str, config: &str, time: Duration, insts: String, cycles: u64, base| {
+        let change = match base {
+            0 => "–".to_string(),
+            base => format!("{:+.1} %", (cycles as f64 / base as f64 - 1.0) * 100.0),
+        };
+        format!(
+            "{program:<12} {config:<12} {:>10.1} {insts:>6} {cycles:>8} {change:>9}",
+            time.as_secs_f64() * 1e6
+        )
+    };
+    let mut lines = vec![format!(
+        "{:<12} {:<12} {:>10} {:>6} {:>8} {:>9}",
+        "program", "config", "compile µs", "insts", "cycles", "vs first"
+    )];
+    let mut base = 0;
+    for m in measurements {
+        if configs.first().is_some_and(|c| c.name == m.config) {
+            base = m.cycles;
+        }
+        let insts = m.instructions.to_string();
+        lines.push(row(
+            m.program,
+            &m.config,
+            m.compile_time,
+            insts,
+            m.cycles,
+            base,
+        ));
+    }
+
+    lines.push(String::new());
+    let total = |config: &Config| {
+        let of = measurements.iter().filter(|m| m.config == config.name);
+        let time = of.clone().map(|m| m.compile_time).sum::<Duration>();
+        (time, of.map(|m| m.cycles).sum::<u64>())
+    };
+    let base = configs.first().map_or(0, |c| total(c).1);
+    for config in configs {
+        let (time, cycles) = total(config);
+        lines.push(row(
+            "total",
+            &config.name,
+            time,
+            String::new(),
+            cycles,
+            base,
+        ));
+    }
+    lines
+}
+
+/// Handles `--bench`, printing the table for the default hardware. Fails if any program of the
+/// corpus doesn't compile or run, or computes something else with some configuration.
+pub fn cli(args: &[String]) -> Option<ExitCode> {
+    if args.first().is_none_or(|arg| arg != "--bench") {
+        return None;
+    }
+    let configs = configs();
+    let measurements = match run(CORPUS, &configs, InterpreterOptions::default()) {
+        Ok(measurements) => measurements,
+        Err(e) => {
+            eprintln!("benchmark failed: {e}");
+            return Some(ExitCode::FAILURE);
+        }
+    };
+    let mut stdout = std::io::stdout();
+    for line in table(&measurements, &configs) {
+        let _ = writeln!(stdout, "{line}");
+    }
+    let wrong = CORPUS.iter().filter(|program| {
+        let mut results = measurements.iter().filter(|m| m.program == program.name);
+        let first = results.next().map(|m| m.result);
+        results.any(|m| Some(m.result) != first)
+    });
+    let mut ok = true;
+    for program in wrong {
+        eprintln!("`{}` computes different results", program.name);
+        ok = false;
+    }
+    Some(if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::evaluate;
+    use crate::gui::WordSize;
+    use crate::parser::run_parser;
+
+    #[test]
+    fn corpus_runs_with_every_config() -> Result<(), LpErr> {
+        let configs = configs();
+        let measurements = run(CORPUS, &configs, InterpreterOptions::default())?;
+        assert_eq!(measurements.len(), CORPUS.len() * configs.len());
+        for program in CORPUS {
+            let vars = program
+                .inputs
+                .iter()
+                .map(|(var, value)| (var.to_string(), *value))
+                .collect();
+            let expected = evaluate(&run_parser(program.source)?, &vars, WordSize::W32)?;
+            for m in measurements.iter().filter(|m| m.program == program.name) {
+                assert_eq!(m.result, expected, "{} with {}", m.program, m.config);
+            }
+        }
+
+        let lines = table(&measurements, &configs);
+        assert_eq!(lines.len(), 1 + measurements.len() + 1 + configs.len());
+        let total = |name: &str| {
+            measurements
+                .iter()
+                .filter(|m| m.config == name)
+                .map(|m| m.cycles)
+                .sum::<u64>()
+        };
+        assert!(total("-O2") < total("-O0"));
+        Ok(())
+    }
+}
@@ -0,0 +1,430 @@
+//! Pictures of the app for visitors to take home: single frames as PNG, runs of a program as
+//! animated GIF. They are drawn offscreen by [`Offscreen`], so they don't depend on where the
+//! window is or what covers it.
+//!
+//! GIFs have at most 256 colors, so frames are mapped to a fixed palette of evenly spaced
+//! colors. That's enough for the flat colors of the interface, and every frame shares it.
+
+use eframe::egui::{self, Color32, Pos2, Rect, TextureId, epaint};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// One picture of an animation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    /// Red, green, blue and alpha of each pixel, row by row.
+    pub rgba: Vec<u8>,
+    /// How long the frame is shown, in hundredths of a second.
+    pub delay: u16,
+}
+
+/// Levels of red, green and blue in the palette, green has one more since eyes see it best.
+const LEVELS: [usize; 3] = [6, 7, 6];
+
+/// The color of each palette entry, the unused ones at the end are black.
+fn palette() -> Vec<u8> {
+    let level = |i: usize, n: usize| (i * 255 / (n - 1)) as u8;
+    let mut colors = Vec::with_capacity(256 * 3);
+    for r in 0..LEVELS[0] {
+        for g in 0..LEVELS[1] {
+            for b in 0..LEVELS[2] {
+                colors.extend([
+                    level(r, LEVELS[0]),
+                    level(g, LEVELS[1]),
+                    level(b, LEVELS[2]),
+                ]);
+            }
+        }
+    }
+    colors.resize(256 * 3, 0);
+    colors
+}
+
+/// The palette entry closest to a color.
+fn palette_index(r: u8, g: u8, b: u8) -> u8 {
+    let nearest = |c: u8, n: usize| (usize::from(c) * (n - 1) + 127) / 255;
+    let (r, g, b) = (
+        nearest(r, LEVELS[0]),
+        nearest(g, LEVELS[1]),
+        nearest(b, LEVELS[2]),
+    );
+    ((r * LEVELS[1] + g) * LEVELS[2] + b) as u8
+}
+
+fn push_u16(bytes: &mut Vec<u8>, n: u16) {
+    bytes.extend(n.to_le_bytes());
+}
+
+/// An animated GIF, played in a loop. Each frame is turned into palette entries and compressed
+/// as soon as it's added, so a long recording doesn't keep its frames around.
+pub struct GifEncoder {
+    bytes: Vec<u8>,
+    width: usize,
+    height: usize,
+    frames: usize,
+}
+
+impl GifEncoder {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut bytes = b"GIF89a".to_vec();
+        push_u16(&mut bytes, width as u16);
+        push_u16(&mut bytes, height as u16);
+        // a global palette of 256 colors
+        bytes.extend([0xf7, 0, 0]);
+        bytes.extend(palette());
+        // loop forever
+        bytes.extend(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+        Self {
+            bytes,
+            width,
+            height,
+            frames: 0,
+        }
+    }
+
+    /// Adds a frame. A frame of another size is cut off at the size of the animation or filled
+    /// up with black, anchored at the top left.
+    pub fn add(&mut self, frame: &Frame) {
+        let bytes = &mut self.bytes;
+        bytes.extend([0x21, 0xf9, 0x04, 0x04]);
+        push_u16(bytes, frame.delay);
+        bytes.extend([0, 0]);
+
+        bytes.push(0x2c);
+        push_u16(bytes, 0);
+        push_u16(bytes, 0);
+        push_u16(bytes, self.width as u16);
+        push_u16(bytes, self.height as u16);
+        bytes.push(0);
+
+        let mut indices = vec![palette_index(0, 0, 0); self.width * self.height];
+        let (width, height) = (frame.width.min(self.width), frame.height.min(self.height));
+        for y in 0..height {
+            let row = &frame.rgba[y * frame.width * 4..][..width * 4];
+            for (x, p) in row.chunks_exact(4).enumerate() {
+                indices[y * self.width + x] = palette_index(p[0], p[1], p[2]);
+            }
+        }
+        let data = weezl::encode::Encoder::new(weezl::BitOrder::Lsb, 8)
+            .encode(&indices)
+            .expect("8-bit indices can always be encoded");
+        bytes.push(8);
+        for block in data.chunks(255) {
+            bytes.push(block.len() as u8);
+            bytes.extend(block);
+        }
+        bytes.push(0);
+        self.frames += 1;
+    }
+
+    /// How many frames were added.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// The whole file.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.bytes.push(0x3b);
+        self.bytes
+    }
+}
+
+/// The frame as a PNG image.
+pub fn encode_png(frame: &Frame) -> Result<Vec<u8>, png::EncodingError> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, frame.width as u32, frame.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&frame.rgba)?;
+    writer.finish()?;
+    Ok(bytes)
+}
+
+/// Draws a UI into a [`Frame`] without showing it: it gets its own egui context, and the
+/// triangles egui paints are filled in by a simple rasterizer on the CPU.
+pub struct Offscreen {
+    ctx: egui::Context,
+    textures: HashMap<TextureId, Texture>,
+}
+
+struct Texture {
+    size: [usize; 2],
+    pixels: Vec<Color32>,
+}
+
+impl Texture {
+    /// The texel at the texture coordinates, without filtering.
+    fn sample(&self, uv: Pos2) -> Color32 {
+        let [w, h] = self.size;
+        let x = ((uv.x * w as f32) as usize).min(w.saturating_sub(1));
+        let y = ((uv.y * h as f32) as usize).min(h.saturating_sub(1));
+        self.pixels
+            .get(y * w + x)
+            .copied()
+            .unwrap_or(Color32::WHITE)
+    }
+}
+
+impl Offscreen {
+    /// Draws with the style of the app.
+    pub fn new(style: Arc<egui::Style>) -> Self {
+        let ctx = egui::Context::default();
+        ctx.set_style(style);
+        Self {
+            ctx,
+            textures: HashMap::new(),
+        }
+    }
+
+    /// A picture of `width` by `height` pixels of what `draw` shows in a panel.
+    pub fn render(
+        &mut self,
+        [width, height]: [usize; 2],
+        delay: u16,
+        mut draw: impl FnMut(&mut egui::Ui),
+    ) -> Frame {
+        let input = egui::RawInput {
+            screen_rect: Some(Rect::from_min_size(
+                Pos2::ZERO,
+                egui::vec2(width as f32, height as f32),
+            )),
+            ..Default::default()
+        };
+        let output = self.ctx.run(input, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| draw(ui));
+        });
+        for (id, delta) in output.textures_delta.set {
+            self.update_texture(id, delta);
+        }
+        let mut pixels = vec![Color32::TRANSPARENT; width * height];
+        for primitive in self.ctx.tessellate(output.shapes, output.pixels_per_point) {
+            if let epaint::Primitive::Mesh(mesh) = &primitive.primitive {
+                self.fill(&mut pixels, [width, height], primitive.clip_rect, mesh);
+            }
+        }
+        for id in output.textures_delta.free {
+            self.textures.remove(&id);
+        }
+        Frame {
+            width,
+            height,
+            rgba: pixels.iter().flat_map(|c| c.to_array()).collect(),
+            delay,
+        }
+    }
+
+    fn update_texture(&mut self, id: TextureId, delta: epaint::ImageDelta) {
+        let (size, pixels): ([usize; 2], Vec<Color32>) = match &delta.image {
+            epaint::ImageData::Color(image) => (image.size, image.pixels.clone()),
+            epaint::ImageData::Font(image) => (image.size, image.srgba_pixels(None).collect()),
+        };
+        let Some([x, y]) = delta.pos else {
+            self.textures.insert(id, Texture { size, pixels });
+            return;
+        };
+        // a patch of a texture that was set before
+        let Some(texture) = self.textures.get_mut(&id) else {
+            return;
+        };
+        for (row, patch) in pixels.chunks_exact(size[0].max(1)).enumerate() {
+            let start = (y + row) * texture.size[0] + x;
+            if let Some(dest) = texture.pixels.get_mut(start..start + patch.len()) {
+                dest.copy_from_slice(patch);
+            }
+        }
+    }
+
+    /// Blends the triangles of the mesh onto the pixels, colors are premultiplied like egui's.
+    fn fill(
+        &self,
+        pixels: &mut [Color32],
+        [width, height]: [usize; 2],
+        clip: Rect,
+        mesh: &epaint::Mesh,
+    ) {
+        let Some(texture) = self.textures.get(&mesh.texture_id) else {
+            return;
+        };
+        let clip = clip.intersect(Rect::from_min_size(
+            Pos2::ZERO,
+            egui::vec2(width as f32, height as f32),
+        ));
+        if !clip.is_positive() {
+            return;
+        }
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [mut a, mut b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize]);
+            if edge(a.pos, b.pos, c.pos) < 0.0 {
+                std::mem::swap(&mut a, &mut b);
+            }
+            let area = edge(a.pos, b.pos, c.pos);
+            if area <= 0.0 {
+                continue;
+            }
+            let bounds = Rect::from_points(&[a.pos, b.pos, c.pos]).intersect(clip);
+            if !bounds.is_positive() {
+                continue;
+            }
+            let (x0, x1) = (bounds.min.x.floor() as usize, bounds.max.x.ceil() as usize);
+            let (y0, y1) = (bounds.min.y.floor() as usize, bounds.max.y.ceil() as usize);
+            for y in y0..y1.min(height) {
+                for x in x0..x1.min(width) {
+                    let p = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    let weights = [(b, c), (c, a), (a, b)].map(|(from, to)| {
+                        let w = edge(from.pos, to.pos, p);
+                        // pixels on an edge two triangles share belong to one of them
+                        let d = to.pos - from.pos;
+                        let owns = d.y > 0.0 || (d.y == 0.0 && d.x < 0.0);
+                        (w > 0.0 || (w == 0.0 && owns)).then_some(w / area)
+                    });
+                    let [Some(wa), Some(wb), Some(wc)] = weights else {
+                        continue;
+                    };
+                    let uv =
+                        (a.uv.to_vec2() * wa + b.uv.to_vec2() * wb + c.uv.to_vec2() * wc).to_pos2();
+                    let color = [a.color, b.color, c.color].iter().zip([wa, wb, wc]).fold(
+                        [0.0; 4],
+                        |sum, (color, w)| {
+                            let rgba = color.to_array();
+                            [0, 1, 2, 3].map(|i| sum[i] + f32::from(rgba[i]) * w)
+                        },
+                    );
+                    let texel = texture.sample(uv).to_array();
+                    let src = [0, 1, 2, 3].map(|i| color[i] * f32::from(texel[i]) / 255.0);
+                    let dest = pixels[y * width + x].to_array();
+                    let [r, g, b, a] = [0, 1, 2, 3].map(|i| {
+                        let blended = src[i] + f32::from(dest[i]) * (1.0 - src[3] / 255.0);
+                        blended.round().clamp(0.0, 255.0) as u8
+                    });
+                    pixels[y * width + x] = Color32::from_rgba_premultiplied(r, g, b, a);
+                }
+            }
+        }
+    }
+}
+
+/// Twice the signed area of the triangle, positive if `p` is on the inner side of `a` to `b`.
+fn edge(a: Pos2, b: Pos2, p: Pos2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(color: [u8; 4]) -> Frame {
+        Frame {
+            width: 3,
+            height: 2,
+            rgba: color.repeat(6),
+            delay: 10,
+        }
+    }
+
+    #[test]
+    fn palette_has_the_interface_colors() {
+        let palette = palette();
+        for color in [[0, 0, 0], [255, 255, 255], [255, 0, 0], [0, 255, 0]] {
+            let i = usize::from(palette_index(color[0], color[1], color[2]));
+            assert_eq!(palette[i * 3..i * 3 + 3], color);
+        }
+    }
+
+    #[test]
+    fn gif_frames_decode_to_their_colors() {
+        let frames = [frame([255, 255, 255, 255]), frame([255, 0, 0, 255])];
+        let mut encoder = GifEncoder::new(3, 2);
+        for frame in &frames {
+            encoder.add(frame);
+        }
+        assert_eq!(encoder.frames(), 2);
+        let gif = encoder.finish();
+        assert!(gif.starts_with(b"GIF89a"));
+        assert_eq!(gif.last(), Some(&0x3b));
+        assert_eq!(gif[6..10], [3, 0, 2, 0]);
+
+        // after the header, the palette and the loop, each frame has its delay and position
+        let mut i = 13 + 256 * 3 + 19;
+        for (frame, color) in frames.iter().zip([[255, 255, 255], [255, 0, 0]]) {
+            assert_eq!(gif[i..i + 2], [0x21, 0xf9]);
+            assert_eq!(u16::from_le_bytes([gif[i + 4], gif[i + 5]]), frame.delay);
+            i += 8 + 10;
+            assert_eq!(gif[i], 8);
+            i += 1;
+            let mut data = Vec::new();
+            while gif[i] != 0 {
+                let len = usize::from(gif[i]);
+                data.extend(&gif[i + 1..i + 1 + len]);
+                i += 1 + len;
+            }
+            i += 1;
+            let indices = weezl::decode::Decoder::new(weezl::BitOrder::Lsb, 8)
+                .decode(&data)
+                .unwrap();
+            assert_eq!(indices, [palette_index(color[0], color[1], color[2]); 6]);
+        }
+        assert_eq!(i, gif.len() - 1);
+    }
+
+    #[test]
+    fn frames_of_another_size_are_fitted() {
+        let mut encoder = GifEncoder::new(3, 2);
+        let small = Frame {
+            width: 1,
+            height: 1,
+            rgba: vec![255, 0, 0, 255],
+            delay: 10,
+        };
+        encoder.add(&small);
+        encoder.add(&Frame {
+            width: 4,
+            height: 3,
+            rgba: [255; 4].repeat(12),
+            delay: 10,
+        });
+        let gif = encoder.finish();
+        // the frame data starts after the graphic control and image descriptor
+        let data = |start: usize| {
+            let len = usize::from(gif[start]);
+            weezl::decode::Decoder::new(weezl::BitOrder::Lsb, 8)
+                .decode(&gif[start + 1..start + 1 + len])
+                .unwrap()
+        };
+        let first = 13 + 256 * 3 + 19 + 8 + 10 + 1;
+        let [red, black] = [[255, 0, 0], [0, 0, 0]].map(|[r, g, b]| palette_index(r, g, b));
+        assert_eq!(data(first), [red, black, black, black, black, black]);
+    }
+
+    #[test]
+    fn offscreen_ui_is_drawn() {
+        let mut offscreen = Offscreen::new(Arc::new(egui::Style::default()));
+        let square = Rect::from_min_size(Pos2::new(20.0, 20.0), egui::vec2(10.0, 10.0));
+        let frame = offscreen.render([64, 48], 10, |ui| {
+            ui.label("text");
+            ui.painter().rect_filled(square, 0.0, Color32::RED);
+        });
+        assert_eq!((frame.width, frame.height, frame.delay), (64, 48, 10));
+        let pixel = |x: usize, y: usize| &frame.rgba[(y * 64 + x) * 4..][..4];
+        assert_eq!(pixel(25, 25), [255, 0, 0, 255]);
+        assert_eq!(pixel(35, 25), pixel(60, 40));
+        // the panel and the text are painted too
+        let background = pixel(60, 40).to_vec();
+        assert_eq!(background[3], 255);
+        assert!((0..64).any(|x| (0..18).any(|y| pixel(x, y) != background)));
+    }
+
+    #[test]
+    fn png_round_trips() -> Result<(), png::EncodingError> {
+        let frame = frame([10, 20, 30, 255]);
+        let bytes = encode_png(&frame)?;
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut rgba = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut rgba).unwrap();
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(rgba, frame.rgba);
+        Ok(())
+    }
+}
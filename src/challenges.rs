@@ -0,0 +1,170 @@
+use crate::compiler::Ir;
+use crate::interpreter::{Interpreter, InterpreterState};
+use crate::types::LpErr;
+use std::collections::HashMap;
+
+/// Values for the inputs of a challenge (in the order of [`Challenge::inputs`]) and the result a
+/// correct program computes for them.
+pub struct TestVector {
+    pub inputs: &'static [i32],
+    pub expected: i32,
+}
+
+const fn test(inputs: &'static [i32], expected: i32) -> TestVector {
+    TestVector { inputs, expected }
+}
+
+/// A task for visitors: write a program passing all test vectors, in at most `cycle_budget`
+/// cycles.
+pub struct Challenge {
+    /// Key of the localized title.
+    pub title: &'static str,
+    /// Key of the localized task description.
+    pub description: &'static str,
+    /// Input variables the program may use.
+    pub inputs: &'static [&'static str],
+    /// Hidden from the user, who only learns how many of them passed.
+    pub tests: &'static [TestVector],
+    /// Simulated cycles a solution may take at most, see [`crate::compiler::Inst::cycles`].
+    pub cycle_budget: u64,
+    /// Known-good solution within the budget, so we know the challenge can be solved.
+    #[allow(dead_code)]
+    pub reference: &'static str,
+}
+
+/// The challenge on the leaderboard.
+pub const LEADERBOARD_CHALLENGE: Challenge = Challenge {
+    title: "challenge.linear.title",
+    description: "challenge.linear.desc",
+    inputs: &["x"],
+    tests: &[
+        test(&[0], 24),
+        test(&[1], 1024),
+        test(&[-3], -2976),
+        test(&[7], 7024),
+        test(&[100], 100024),
+    ],
+    cycle_budget: 150,
+    reference: "1000 * x + 24",
+};
+
+pub const CHALLENGES: &[Challenge] = &[
+    LEADERBOARD_CHALLENGE,
+    Challenge {
+        title: "challenge.average.title",
+        description: "challenge.average.desc",
+        inputs: &["a", "b"],
+        tests: &[
+            test(&[2, 4], 3),
+            test(&[10, 20], 15),
+            test(&[-4, 8], 2),
+            test(&[7, 8], 7),
+            test(&[0, 0], 0),
+        ],
+        cycle_budget: 210,
+        reference: "(a + b) / 2",
+    },
+    Challenge {
+        title: "challenge.square.title",
+        description: "challenge.square.desc",
+        inputs: &["x"],
+        tests: &[
+            test(&[0], 1),
+            test(&[1], 4),
+            test(&[2], 9),
+            test(&[-1], 0),
+            test(&[5], 36),
+        ],
+        cycle_budget: 200,
+        reference: "(x + 1) * (x + 1)",
+    },
+];
+
+/// Outcome of running a program on all test vectors of a challenge.
+pub struct Report {
+    pub passed: usize,
+    pub total: usize,
+    /// Cycles on the slowest test vector.
+    pub cycles: u64,
+    pub cycle_budget: u64,
+}
+
+impl Report {
+    pub fn all_passed(&self) -> bool {
+        self.passed == self.total
+    }
+
+    pub fn within_budget(&self) -> bool {
+        self.cycles <= self.cycle_budget
+    }
+
+    /// All test vectors passed within the budget.
+    pub fn solved(&self) -> bool {
+        self.all_passed() && self.within_budget()
+    }
+}
+
+impl Challenge {
+    fn run(&self, ir: &Ir, test: &TestVector) -> Result<(i32, u64), LpErr> {
+        let inputs: HashMap<String, String> = self
+            .inputs
+            .iter()
+            .zip(test.inputs)
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let mut interpreter = Interpreter::with_config(&ir.hw)
+            .load_instructions(ir.instructions.clone())
+            .with_variables(inputs)
+            .ready();
+        loop {
+            if let InterpreterState::Finished(res) = interpreter.step()? {
+                return Ok((res, interpreter.stats().cycles));
+            }
+        }
+    }
+
+    /// Runs the compiled program on all test vectors. A program that fails to run (e.g. because
+    /// it divides by zero) fails that test.
+    pub fn check(&self, ir: &Ir) -> Report {
+        let mut report = Report {
+            passed: 0,
+            total: self.tests.len(),
+            cycles: 0,
+            cycle_budget: self.cycle_budget,
+        };
+        for test in self.tests {
+            if let Ok((res, cycles)) = self.run(ir, test) {
+                if res == ir.hw.word_size.wrap(test.expected) {
+                    report.passed += 1;
+                }
+                report.cycles = report.cycles.max(cycles);
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::{CompileOptions, Compiler};
+
+    #[test]
+    fn references_solve_their_challenge() -> Result<(), LpErr> {
+        for challenge in CHALLENGES {
+            let ir = Compiler::with(CompileOptions::default()).compile(challenge.reference)?;
+            assert!(challenge.check(&ir).solved(), "{}", challenge.reference);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_program_fails() -> Result<(), LpErr> {
+        let ir = Compiler::with(CompileOptions::default()).compile("1000 * x")?;
+        let report = LEADERBOARD_CHALLENGE.check(&ir);
+        assert_eq!(report.passed, 0);
+        assert!(!report.solved());
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+//! What the compiler did while compiling one program, for visitors who want to look inside: when
+//! each phase started and ended, which passes ran, and where values were put.
+//!
+//! The compiler reports this with [`tracing`] spans and events. [`record`] collects them for a
+//! single compilation, so compilations on other threads don't mix in. The interpreter reports its
+//! runs the same way, and [`CompileLog::phase_times`] breaks either down for the profiler.
+
+use crate::passes::registry;
+use rust_i18n::t;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// How much of the log is of interest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+    /// When each phase starts and ends, and warnings.
+    #[default]
+    Phases,
+    /// What passes decided and which values were moved to RAM.
+    Decisions,
+    /// The register of every value.
+    Details,
+}
+
+impl Verbosity {
+    pub const ALL: [Verbosity; 3] = [Verbosity::Phases, Verbosity::Decisions, Verbosity::Details];
+
+    fn of(level: Level) -> Self {
+        match level {
+            Level::TRACE => Verbosity::Details,
+            Level::DEBUG => Verbosity::Decisions,
+            _ => Verbosity::Phases,
+        }
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Verbosity::Phases => "log.verbosity.phases",
+            Verbosity::Decisions => "log.verbosity.decisions",
+            Verbosity::Details => "log.verbosity.details",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    /// A phase started, e.g. `pass` named `folding` with the details `round=2`.
+    Enter {
+        phase: &'static str,
+        /// What the phase is about when there are several of its kind, like the pass.
+        name: Option<String>,
+        details: String,
+    },
+    Exit {
+        phase: &'static str,
+        elapsed: Duration,
+    },
+    Event {
+        verbosity: Verbosity,
+        /// Something went wrong in the compiler itself.
+        warning: bool,
+        message: String,
+    },
+}
+
+/// One entry, `depth` phases deep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub depth: usize,
+    pub entry: Entry,
+}
+
+/// Everything reported during one compilation, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompileLog {
+    lines: Vec<Line>,
+}
+
+impl CompileLog {
+    /// The lines of interest at `verbosity`. Phases are always of interest.
+    pub fn filtered(&self, verbosity: Verbosity) -> impl Iterator<Item = &Line> {
+        self.lines.iter().filter(move |line| match line.entry {
+            Entry::Event { verbosity: v, .. } => v <= verbosity,
+            _ => true,
+        })
+    }
+
+    /// Adds what was reported later, like the steps of an animated run.
+    pub fn append(&mut self, later: CompileLog) {
+        self.lines.extend(later.lines);
+    }
+
+    /// How long each innermost phase took, in the order they first ran. Phases with a name are
+    /// told apart by it, and the times of a phase that ran twice, like `folding`, are summed.
+    pub fn phase_times(&self) -> Vec<PhaseTime> {
+        let mut times: Vec<PhaseTime> = Vec::new();
+        // the phases entered, with whether another phase ran inside
+        let mut open: Vec<(&'static str, &Option<String>, bool)> = Vec::new();
+        for line in &self.lines {
+            match &line.entry {
+                Entry::Enter { phase, name, .. } => {
+                    if let Some((_, _, nested)) = open.last_mut() {
+                        *nested = true;
+                    }
+                    open.push((phase, name, false));
+                }
+                Entry::Exit { elapsed, .. } => {
+                    let Some((phase, name, false)) = open.pop() else {
+                        continue;
+                    };
+                    match times
+                        .iter_mut()
+                        .find(|t| t.phase == phase && t.name == *name)
+                    {
+                        Some(time) => time.elapsed += *elapsed,
+                        None => times.push(PhaseTime {
+                            phase,
+                            name: name.clone(),
+                            elapsed: *elapsed,
+                        }),
+                    }
+                }
+                Entry::Event { .. } => (),
+            }
+        }
+        times
+    }
+}
+
+/// How long a phase took, see [`CompileLog::phase_times`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseTime {
+    pub phase: &'static str,
+    pub name: Option<String>,
+    pub elapsed: Duration,
+}
+
+impl PhaseTime {
+    /// The label of the pass for passes, and the name of the phase otherwise.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => match registry().iter().find(|pass| pass.name() == name) {
+                Some(pass) => t!(pass.label()).into(),
+                None => name.clone(),
+            },
+            None => t!(format!("log.phase.{}", self.phase)).into(),
+        }
+    }
+}
+
+/// Turns the fields of a span or event into text, the message and name apart from the others.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    name: Option<String>,
+    others: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            if !self.others.is_empty() {
+                self.others.push(' ');
+            }
+            let _ = write!(self.others, "{}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message.push_str(value),
+            "name" => self.name = Some(value.to_string()),
+            _ => self.record_debug(field, &format_args!("{value}")),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// The phase, name and other fields of each span, its id is the index plus one.
+    spans: Vec<(&'static str, Option<String>, String)>,
+    /// The spans entered and not exited yet, with when they were entered.
+    open: Vec<(u64, Instant)>,
+    log: CompileLog,
+}
+
+impl State {
+    fn push(&mut self, entry: Entry) {
+        let depth = self.open.len();
+        self.log.lines.push(Line { depth, entry });
+    }
+}
+
+/// Collects everything into a [`CompileLog`].
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<State>>);
+
+impl Recorder {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        // a panic while holding the lock would only lose part of the log
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+        let mut state = self.state();
+        state
+            .spans
+            .push((span.metadata().name(), fields.name, fields.others));
+        Id::from_u64(state.spans.len() as u64)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let mut message = fields.message;
+        if !fields.others.is_empty() {
+            let _ = write!(message, " ({})", fields.others);
+        }
+        let level = *event.metadata().level();
+        self.state().push(Entry::Event {
+            verbosity: Verbosity::of(level),
+            warning: level <= Level::WARN,
+            message,
+        });
+    }
+
+    fn enter(&self, id: &Id) {
+        let mut state = self.state();
+        let Some((phase, name, details)) = state.spans.get(id.into_u64() as usize - 1).cloned()
+        else {
+            return;
+        };
+        state.push(Entry::Enter {
+            phase,
+            name,
+            details,
+        });
+        state.open.push((id.into_u64(), Instant::now()));
+    }
+
+    fn exit(&self, id: &Id) {
+        let mut state = self.state();
+        let Some(i) = state
+            .open
+            .iter()
+            .rposition(|(open, _)| *open == id.into_u64())
+        else {
+            return;
+        };
+        let (_, entered) = state.open.remove(i);
+        let phase = state.spans[id.into_u64() as usize - 1].0;
+        state.push(Entry::Exit {
+            phase,
+            elapsed: entered.elapsed(),
+        });
+    }
+}
+
+/// Runs `f`, collecting what it reports on this thread.
+pub fn record<T>(f: impl FnOnce() -> T) -> (T, CompileLog) {
+    let recorder = Recorder::default();
+    let result = tracing::subscriber::with_default(recorder.clone(), f);
+    let log = std::mem::take(&mut recorder.state().log);
+    (result, log)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gui::InterpreterOptions;
+    use crate::interpreter::Interpreter;
+    use crate::types::{Inst, Reg};
+
+    fn describe(line: &Line) -> String {
+        let entry = match &line.entry {
+            Entry::Enter {
+                phase,
+                name,
+                details,
+            } => format!("> {phase} {} {details}", name.as_deref().unwrap_or("-")),
+            Entry::Exit { phase, .. } => format!("< {phase}"),
+            Entry::Event {
+                verbosity,
+                warning,
+                message,
+            } => format!("{verbosity:?} {message}{}", if *warning { "!" } else { "" }),
+        };
+        format!("{} {entry}", line.depth)
+    }
+
+    #[test]
+    fn phases_nest_and_events_are_in_order() {
+        let ((), log) = record(|| {
+            let _outer = tracing::info_span!("outer", n = 3).entered();
+            tracing::debug!("decided");
+            {
+                let _inner = tracing::info_span!("inner").entered();
+                tracing::trace!(reg = 2, "placed");
+            }
+            tracing::warn!("odd");
+        });
+        let lines: Vec<_> = log.filtered(Verbosity::Details).map(describe).collect();
+        assert_eq!(
+            lines,
+            [
+                "0 > outer - n=3",
+                "1 Decisions decided",
+                "1 > inner - ",
+                "2 Details placed (reg=2)",
+                "1 < inner",
+                "1 Phases odd!",
+                "0 < outer",
+            ]
+        );
+        assert_eq!(log.filtered(Verbosity::Phases).count(), 5);
+
+        // nothing is recorded outside
+        tracing::info!("elsewhere");
+        assert_eq!(record(|| ()).1, CompileLog::default());
+    }
+
+    #[test]
+    fn phase_times_are_of_the_innermost_phases() {
+        let ((), log) = record(|| {
+            let _outer = tracing::info_span!("passes").entered();
+            for round in 1..=2 {
+                let _pass = tracing::info_span!("pass", name = "folding", round).entered();
+            }
+            let _pass = tracing::info_span!("pass", name = "cse").entered();
+        });
+        let lines: Vec<_> = log.filtered(Verbosity::Phases).map(describe).collect();
+        assert_eq!(lines[1], "1 > pass folding round=1");
+
+        let times = log.phase_times();
+        let labels: Vec<_> = times.iter().map(PhaseTime::label).collect();
+        assert_eq!(labels, [t!("editor.constant_folding"), "cse".into()]);
+        let folding: Duration = (log.lines.iter())
+            .filter_map(|line| match line.entry {
+                Entry::Exit { elapsed, .. } if line.depth == 1 => Some(elapsed),
+                _ => None,
+            })
+            .take(2)
+            .sum();
+        assert_eq!(times[0].elapsed, folding);
+    }
+
+    #[test]
+    fn runs_are_broken_down() {
+        let program = vec![Inst::Store(2, Reg(0)), Inst::Result(Reg(0))];
+        let interpreter = || {
+            Interpreter::with_config(&InterpreterOptions::default())
+                .load_instructions(program.clone())
+                .ready()
+        };
+        let phases = |log: CompileLog| -> Vec<_> {
+            log.phase_times().into_iter().map(|t| t.phase).collect()
+        };
+        let (_, run) = record(|| interpreter().run_to_end());
+        assert_eq!(phases(run), ["execute", "timing"]);
+
+        // steps outside of a whole run are recorded as well
+        let mut stepped = interpreter();
+        let (_, step) = record(|| stepped.step());
+        assert_eq!(phases(step), ["execute", "timing"]);
+    }
+}
@@ -0,0 +1,481 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use crate::backend::{BackendOutput, Target};
+use crate::compiler::{CompileOptions, Compiler, Ir, LpErr};
+use crate::gui::InterpreterOptions;
+use crate::parser::{self, Diagnostic};
+use crate::passes::{CostModel, PeepholeRule, RewriteRule};
+use crate::ranges::Hints;
+use rust_i18n::t;
+
+/// Number of recent results kept for each kind of output.
+const CACHE_SIZE: usize = 32;
+
+/// Everything needed to compile the code for all outputs, so it can happen off the UI thread.
+pub struct CompileJob {
+    pub code: String,
+    pub hw: InterpreterOptions,
+    /// Whether the optimized output is compiled, it's the second one then.
+    pub optimize: bool,
+    /// Options, rewrite rules and rules for the instructions of each assembly output, the
+    /// unoptimized one comes first.
+    pub outputs: Vec<(CompileOptions, Vec<RewriteRule>, Vec<PeepholeRule>)>,
+    /// Options for the real-world translations.
+    pub target_options: CompileOptions,
+    /// What the user promises about the input variables, for all outputs.
+    pub hints: Hints,
+    /// Cycles of the instructions, for the passes picking the cheapest program.
+    pub costs: CostModel,
+}
+
+/// How large the program is, for a first impression of how complex it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceStats {
+    pub tokens: usize,
+    /// Nodes of the syntax tree and how deeply it's nested, if the program compiles.
+    pub tree: Option<(u64, usize)>,
+    /// Instructions the program compiles to without optimizations, if it compiles.
+    pub instructions: Option<usize>,
+}
+
+impl SourceStats {
+    /// Counts the tokens. The sizes of the program come from its unoptimized output, which
+    /// already built the tree for the node limit.
+    fn of(code: &str, unoptimized: Option<&Ir>) -> Self {
+        SourceStats {
+            tokens: parser::tokens(code).count(),
+            tree: unoptimized.map(|ir| ir.tree),
+            instructions: unoptimized.map(|ir| ir.instructions.len()),
+        }
+    }
+}
+
+/// What a [`CompileJob`] produced, in the same order as it asked for.
+pub struct Compiled {
+    pub hw: InterpreterOptions,
+    pub optimize: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    pub stats: SourceStats,
+    /// Each output's result, and whether it was optimized at all.
+    pub outputs: Vec<(Result<Ir, LpErr>, bool)>,
+    /// RISC-V and WAT lines, if the code compiles for these targets.
+    pub targets: Vec<Option<Vec<String>>>,
+    /// How long the worker took for the whole job, including cache lookups.
+    pub elapsed: Duration,
+}
+
+/// Everything a compilation result depends on.
+#[derive(Clone, PartialEq, Eq)]
+struct Key {
+    code: String,
+    /// Errors and explanations are in the language of the UI.
+    locale: String,
+    options: CompileOptions,
+    hw: InterpreterOptions,
+    rules: Vec<RewriteRule>,
+    peephole: Vec<PeepholeRule>,
+    hints: Hints,
+    costs: CostModel,
+}
+
+/// The `capacity` most recently used entries, the most recent one first.
+struct Lru<K, V> {
+    capacity: usize,
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V: Clone> Lru<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// The value stored for `key`, which becomes the most recently used one.
+    fn get(&mut self, key: &K) -> Option<V> {
+        let i = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(i)?;
+        let value = entry.1.clone();
+        self.entries.push_front(entry);
+        Some(value)
+    }
+
+    /// Stores `value`, dropping the least recently used entry if the cache is full.
+    fn insert(&mut self, key: K, value: V) {
+        self.entries.retain(|(k, _)| *k != key);
+        self.entries.push_front((key, value));
+        self.entries.truncate(self.capacity);
+    }
+
+    /// The value stored for `key`, computing and storing it first if there is none.
+    fn get_or_insert_with(&mut self, key: K, compute: impl FnOnce(&K) -> V) -> V {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let value = compute(&key);
+        self.insert(key, value.clone());
+        value
+    }
+}
+
+struct Cache {
+    outputs: Lru<Key, Result<Ir, LpErr>>,
+    targets: Lru<Key, Option<Vec<String>>>,
+    diagnostics: Lru<(String, String, usize, usize), Vec<Diagnostic>>,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            outputs: Lru::new(CACHE_SIZE),
+            targets: Lru::new(CACHE_SIZE),
+            diagnostics: Lru::new(CACHE_SIZE),
+        }
+    }
+}
+
+impl Cache {
+    /// Compiles the job, reusing recent results. Gives up between outputs once `cancelled`.
+    fn run(&mut self, job: CompileJob, cancelled: impl Fn() -> bool) -> Option<Compiled> {
+        let start = Instant::now();
+        let locale = rust_i18n::locale().to_string();
+        let Cache {
+            outputs,
+            targets,
+            diagnostics,
+        } = self;
+
+        let mut results = Vec::with_capacity(job.outputs.len());
+        for (options, rules, peephole) in job.outputs {
+            if cancelled() {
+                return None;
+            }
+            let optimized = options.any() || !rules.is_empty() || !peephole.is_empty();
+            let key = Key {
+                code: job.code.clone(),
+                locale: locale.clone(),
+                options,
+                hw: job.hw,
+                rules,
+                peephole,
+                hints: job.hints.clone(),
+                costs: job.costs.clone(),
+            };
+            let result = outputs.get_or_insert_with(key, |key| {
+                Compiler::with(key.options)
+                    .with_interpreter(key.hw)
+                    .with_rules(key.rules.clone())
+                    .with_peephole(key.peephole.clone())
+                    .with_hints(key.hints.clone())
+                    .with_costs(key.costs.clone())
+                    .compile(&key.code)
+            });
+            results.push((result, optimized));
+        }
+
+        let mut lines = Vec::new();
+        for target in [Target::RiscV, Target::Wat] {
+            if cancelled() {
+                return None;
+            }
+            let Ok(options) = job
+                .target_options
+                .into_builder()
+                .target(target)
+                .hardware(job.hw)
+                .build()
+            else {
+                lines.push(None);
+                continue;
+            };
+            let key = Key {
+                code: job.code.clone(),
+                locale: locale.clone(),
+                options,
+                hw: job.hw,
+                rules: Vec::new(),
+                peephole: Vec::new(),
+                hints: job.hints.clone(),
+                costs: job.costs.clone(),
+            };
+            lines.push(targets.get_or_insert_with(key, |key| {
+                match Compiler::with(key.options)
+                    .with_interpreter(key.hw)
+                    .with_hints(key.hints.clone())
+                    .with_costs(key.costs.clone())
+                    .emit(&key.code)
+                {
+                    Ok(BackendOutput::Text(lines)) => Some(lines),
+                    _ => None,
+                }
+            }));
+        }
+
+        let source = (
+            job.code,
+            locale,
+            job.target_options.max_nesting,
+            job.target_options.max_tokens,
+        );
+        let unoptimized = (results.iter())
+            .find(|(_, optimized)| !optimized)
+            .and_then(|(result, _)| result.as_ref().ok());
+        let stats = SourceStats::of(&source.0, unoptimized);
+        Some(Compiled {
+            hw: job.hw,
+            optimize: job.optimize,
+            diagnostics: diagnostics.get_or_insert_with(
+                source,
+                |(code, _, max_nesting, max_tokens)| {
+                    parser::diagnose(code, *max_nesting, *max_tokens)
+                },
+            ),
+            stats,
+            outputs: results,
+            targets: lines,
+            elapsed: start.elapsed(),
+        })
+    }
+}
+
+/// Runs the job like [`Cache::run`]. If the compiler panics, every output gets the error instead
+/// of the worker thread ending, which would leave the service busy forever.
+fn run_caught(
+    cache: &mut Cache,
+    job: CompileJob,
+    cancelled: impl Fn() -> bool,
+) -> Option<Compiled> {
+    let start = Instant::now();
+    let (hw, optimize, count) = (job.hw, job.optimize, job.outputs.len());
+    let panic = match panic::catch_unwind(AssertUnwindSafe(|| cache.run(job, cancelled))) {
+        Ok(compiled) => return compiled,
+        Err(panic) => panic,
+    };
+    // the results from before the panic are fine, but it's not worth finding out which
+    *cache = Cache::default();
+    let message = (panic.downcast_ref::<&str>().map(|s| s.to_string()))
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+    let error = LpErr::IR(t!("compiler.error.crashed", e = message).into());
+    Some(Compiled {
+        hw,
+        optimize,
+        diagnostics: Vec::new(),
+        stats: SourceStats::default(),
+        outputs: vec![(Err(error), false); count],
+        targets: vec![None; 2],
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Compiles [`CompileJob`]s on a worker thread, so long compilations don't make the GUI hitch.
+///
+/// Submitting a job cancels the ones before it that haven't finished yet, only the result of the
+/// latest job is ever returned. Recent results are remembered, so switching back and forth between
+/// settings doesn't run the whole pipeline again.
+pub struct CompileService {
+    jobs: Sender<(u64, CompileJob)>,
+    results: Receiver<(u64, Compiled)>,
+    /// Number of the latest submitted job, the worker checks it to notice it was cancelled.
+    latest: Arc<AtomicU64>,
+    /// Number of the latest job whose result was returned.
+    shown: u64,
+}
+
+impl Default for CompileService {
+    fn default() -> Self {
+        let (jobs, queue) = mpsc::channel::<(u64, CompileJob)>();
+        let (done, results) = mpsc::channel();
+        let latest = Arc::new(AtomicU64::new(0));
+
+        let current = latest.clone();
+        let worker = std::thread::Builder::new().stack_size(parser::STACK_SIZE);
+        let spawned = worker.spawn(move || {
+            let mut cache = Cache::default();
+            // ends once the service is dropped
+            while let Ok(mut next) = queue.recv() {
+                // jobs submitted in the meantime replace this one
+                while let Ok(newer) = queue.try_recv() {
+                    next = newer;
+                }
+                let (number, job) = next;
+                let cancelled = || current.load(Ordering::Relaxed) != number;
+                if let Some(compiled) = run_caught(&mut cache, job, cancelled)
+                    && done.send((number, compiled)).is_err()
+                {
+                    break;
+                }
+            }
+        });
+        spawned.expect("failed to start the compile worker");
+
+        Self {
+            jobs,
+            results,
+            latest,
+            shown: 0,
+        }
+    }
+}
+
+impl CompileService {
+    /// Starts compiling the job, cancelling the previous one.
+    pub fn submit(&mut self, job: CompileJob) {
+        let number = self.latest.fetch_add(1, Ordering::Relaxed) + 1;
+        // the worker only stops when the service is dropped
+        let _ = self.jobs.send((number, job));
+    }
+
+    /// The result of the latest job, once it's done.
+    pub fn poll(&mut self) -> Option<Compiled> {
+        let latest = self.latest.load(Ordering::Relaxed);
+        let (number, compiled) = self
+            .results
+            .try_iter()
+            .filter(|(n, _)| *n == latest)
+            .last()?;
+        self.shown = number;
+        Some(compiled)
+    }
+
+    /// Whether the result of the latest job hasn't been returned yet.
+    pub fn is_busy(&self) -> bool {
+        self.latest.load(Ordering::Relaxed) != self.shown
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::{Inst, OptLevel};
+
+    fn job(code: &str, level: OptLevel) -> CompileJob {
+        CompileJob {
+            code: code.into(),
+            hw: InterpreterOptions::default(),
+            optimize: true,
+            outputs: vec![
+                (CompileOptions::default(), Vec::new(), Vec::new()),
+                (
+                    CompileOptions::default().with_level(level),
+                    Vec::new(),
+                    Vec::new(),
+                ),
+            ],
+            target_options: CompileOptions::default(),
+            hints: Hints::new(),
+            costs: CostModel::default(),
+        }
+    }
+
+    #[test]
+    fn job_keeps_the_order_of_outputs() {
+        let compiled = Cache::default()
+            .run(job("a * 4 + 2 * 3", OptLevel::O2), || false)
+            .unwrap();
+
+        assert!(compiled.diagnostics.is_empty());
+        let [(unoptimized, false), (optimized, true)] = &compiled.outputs[..] else {
+            panic!("expected an unoptimized and an optimized output");
+        };
+        let (unoptimized, optimized) = (unoptimized.as_ref().unwrap(), optimized.as_ref().unwrap());
+        assert!(optimized.instructions.len() < unoptimized.instructions.len());
+        assert!(compiled.targets.iter().all(Option::is_some));
+
+        let stats = compiled.stats;
+        assert_eq!((stats.tokens, stats.tree), (7, Some((7, 3))));
+        assert_eq!(stats.instructions, Some(unoptimized.instructions.len()));
+    }
+
+    #[test]
+    fn recompiling_reuses_results() {
+        let mut cache = Cache::default();
+        let mut run = |level| cache.run(job("a * 4 + 2 * 3", level), || false).unwrap();
+        let first = run(OptLevel::O2);
+        run(OptLevel::O1);
+        let again = run(OptLevel::O2);
+
+        // the unoptimized output is shared by all three jobs
+        assert_eq!(cache.outputs.entries.len(), 3);
+        assert_eq!(cache.diagnostics.entries.len(), 1);
+        let instructions = |c: &Compiled| {
+            let ir = c.outputs[1].0.as_ref().unwrap();
+            ir.instructions
+                .iter()
+                .map(Inst::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(instructions(&first), instructions(&again));
+    }
+
+    #[test]
+    fn only_the_latest_job_is_returned() {
+        let mut service = CompileService::default();
+        service.submit(job("1 +", OptLevel::O2));
+        service.submit(job("a * 4 + 2 * 3", OptLevel::O2));
+        assert!(service.is_busy());
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let compiled = loop {
+            if let Some(compiled) = service.poll() {
+                break compiled;
+            }
+            assert!(Instant::now() < deadline, "the job never finished");
+            std::thread::sleep(Duration::from_millis(5));
+        };
+        assert!(!service.is_busy());
+        assert!(compiled.outputs.iter().all(|(result, _)| result.is_ok()));
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let mut cache = Cache::default();
+        let compiled = run_caught(&mut cache, job("a * 4", OptLevel::O2), || panic!("oops"));
+        let compiled = compiled.expect("a panic isn't a cancellation");
+        assert_eq!(compiled.outputs.len(), 2);
+        for (result, _) in &compiled.outputs {
+            assert!(
+                result
+                    .as_ref()
+                    .is_err_and(|e| e.to_string().contains("oops"))
+            );
+        }
+        // it still compiles afterwards
+        let compiled = run_caught(&mut cache, job("a * 4", OptLevel::O2), || false);
+        assert!(
+            compiled
+                .unwrap()
+                .outputs
+                .iter()
+                .all(|(result, _)| result.is_ok())
+        );
+    }
+
+    #[test]
+    fn cancelled_job_gives_up() {
+        assert!(
+            Cache::default()
+                .run(job("a * 4", OptLevel::O2), || true)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_dropped() {
+        let mut lru = Lru::new(2);
+        lru.insert(1, "one");
+        lru.insert(2, "two");
+        assert_eq!(lru.get(&1), Some("one"));
+        lru.insert(3, "three");
+
+        assert_eq!(lru.get(&2), None);
+        assert_eq!(lru.get(&1), Some("one"));
+        assert_eq!(lru.get(&3), Some("three"));
+    }
+}
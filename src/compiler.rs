@@ -1,33 +1,340 @@
-use crate::gui::InterpreterOptions;
-use crate::parser;
+use crate::analysis;
+use crate::backend::{BackendOutput, Target};
+use crate::compile_log::{self, CompileLog};
+use crate::dag::Dag;
+use crate::gui::{InterpreterOptions, IsaFlavor};
+use crate::parser::{self, Severity, Statement};
 use crate::passes::{
-    CommonFactorElimination, ConstantFold, ShiftReplacement, run_cache_optimization,
+    CODE_POSITION, CostModel, Pass, PassContext, PeepholeRule, RewriteRule, TransformationLog,
+    code_stage, registry, run_peephole_rules, run_rewrite_rules, run_three_operand, short,
 };
+use crate::ranges::Hints;
+use crate::source_map::Span;
+use crate::symbols::ConstTable;
 pub use crate::types::*;
 use rust_i18n::t;
 use std::collections::{HashMap, HashSet};
 use std::vec;
+use tracing::{debug, info_span, trace, warn};
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize)]
 pub struct CompileOptions {
     pub do_constant_folding: bool,
     pub run_cache_optimization: bool,
     pub do_common_factor_elimination: bool,
     pub do_shift_replacement: bool,
+    /// Use arithmetic with a number as an operand (`addi r, 3`), instead of storing the number
+    /// in a register first.
+    pub use_immediates: bool,
+    /// Compute `min` and `max` with plain arithmetic instead of their own instructions.
+    pub branchless_min_max: bool,
+    /// Optimize with equality saturation on an e-graph, instead of one pass after the other.
+    pub equality_saturation: bool,
+    /// The backend the IR is lowered to by [`Compiler::emit`].
+    pub target: Target,
+    /// How deeply expressions may be nested, at most [`parser::MAX_NESTING`].
+    pub max_nesting: usize,
+    /// How many tokens the source may have, see [`parser::too_many_tokens`].
+    pub max_tokens: usize,
+    /// How many nodes the syntax tree may have.
+    pub max_nodes: usize,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            do_constant_folding: false,
+            run_cache_optimization: false,
+            do_common_factor_elimination: false,
+            do_shift_replacement: false,
+            use_immediates: false,
+            branchless_min_max: false,
+            equality_saturation: false,
+            target: Target::default(),
+            max_nesting: parser::DEFAULT_MAX_NESTING,
+            max_tokens: parser::DEFAULT_MAX_TOKENS,
+            max_nodes: DEFAULT_MAX_NODES,
+        }
+    }
+}
+
+/// Predefined sets of optimization passes, like the `-O` flags of real compilers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// No optimizations at all.
+    O0,
+    /// Cheap optimizations that never make the program worse.
+    O1,
+    /// All optimizations.
+    O2,
+}
+
+impl OptLevel {
+    pub const ALL: [OptLevel; 3] = [OptLevel::O0, OptLevel::O1, OptLevel::O2];
+}
+
+impl std::fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptLevel::O0 => write!(f, "-O0"),
+            OptLevel::O1 => write!(f, "-O1"),
+            OptLevel::O2 => write!(f, "-O2"),
+        }
+    }
+}
+
+/// How many nodes the syntax tree may have by default. The code computes every node, so larger
+/// programs would take long in every step of the pipeline, and still longer to watch run.
+pub const DEFAULT_MAX_NODES: usize = 20_000;
+
+/// Smallest number of registers the code generator can work with: binary operations need both
+/// operands in registers at the same time.
+pub const MIN_REGISTERS: u8 = 2;
+
+/// Part of the configuration a [`ConfigError`] refers to, so the GUI can show it in the right place.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConfigField {
+    Registers,
+    Ram,
+}
+
+/// An invalid combination of compiler options and hardware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    TooFewRegisters(u8),
+    /// More registers than the naming scheme (second value: its maximum) has names for.
+    TooManyRegisters(u8, u8),
+    /// The target (with its maximum number of registers) can't map all registers.
+    TargetRegisters(Target, u8),
+    NoRam,
+}
+
+impl ConfigError {
+    pub fn field(&self) -> ConfigField {
+        match self {
+            ConfigError::TooFewRegisters(_)
+            | ConfigError::TooManyRegisters(..)
+            | ConfigError::TargetRegisters(..) => ConfigField::Registers,
+            ConfigError::NoRam => ConfigField::Ram,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::TooFewRegisters(n) => f.write_str(&t!(
+                "compiler.config.too_few_regs",
+                n = n,
+                min = MIN_REGISTERS
+            )),
+            ConfigError::TooManyRegisters(n, max) => {
+                f.write_str(&t!("compiler.config.too_many_regs", n = n, max = max))
+            }
+            ConfigError::TargetRegisters(target, max) => f.write_str(&t!(
+                "compiler.config.target_regs",
+                target = t!(target.key()),
+                max = max
+            )),
+            ConfigError::NoRam => f.write_str(&t!("compiler.config.no_ram")),
+        }
+    }
+}
+
+impl From<ConfigError> for LpErr {
+    fn from(value: ConfigError) -> Self {
+        LpErr::Config(value.to_string())
+    }
+}
+
+/// Builds [`CompileOptions`], checking that they're usable on the given hardware.
+#[derive(Default)]
+pub struct CompileOptionsBuilder {
+    options: CompileOptions,
+    hw: InterpreterOptions,
+}
+
+impl CompileOptionsBuilder {
+    pub fn constant_folding(mut self, enabled: bool) -> Self {
+        self.options.do_constant_folding = enabled;
+        self
+    }
+
+    pub fn cache_optimization(mut self, enabled: bool) -> Self {
+        self.options.run_cache_optimization = enabled;
+        self
+    }
+
+    pub fn common_factor_elimination(mut self, enabled: bool) -> Self {
+        self.options.do_common_factor_elimination = enabled;
+        self
+    }
+
+    pub fn shift_replacement(mut self, enabled: bool) -> Self {
+        self.options.do_shift_replacement = enabled;
+        self
+    }
+
+    pub fn target(mut self, target: Target) -> Self {
+        self.options.target = target;
+        self
+    }
+
+    /// The hardware the options are validated against.
+    pub fn hardware(mut self, hw: InterpreterOptions) -> Self {
+        self.hw = hw;
+        self
+    }
+
+    pub fn build(self) -> Result<CompileOptions, ConfigError> {
+        self.options.validate(&self.hw)?;
+        Ok(self.options)
+    }
 }
 
 impl CompileOptions {
+    pub fn builder() -> CompileOptionsBuilder {
+        CompileOptionsBuilder::default()
+    }
+
+    /// Continue building from these options.
+    pub fn into_builder(self) -> CompileOptionsBuilder {
+        CompileOptionsBuilder {
+            options: self,
+            ..Default::default()
+        }
+    }
+
+    /// All problems with the hardware itself, whatever the options. The options window shows
+    /// these while the hardware is changed.
+    pub fn hardware_errors(hw: &InterpreterOptions) -> Vec<ConfigError> {
+        let mut errors = vec![];
+        if hw.num_registers < MIN_REGISTERS {
+            errors.push(ConfigError::TooFewRegisters(hw.num_registers));
+        }
+        let max = hw.reg_naming.max_registers();
+        if hw.num_registers > max {
+            errors.push(ConfigError::TooManyRegisters(hw.num_registers, max));
+        }
+        if hw.num_cachelines == 0 {
+            errors.push(ConfigError::NoRam);
+        }
+        errors
+    }
+
+    /// All problems with using these options on the given hardware.
+    pub fn errors(&self, hw: &InterpreterOptions) -> Vec<ConfigError> {
+        let mut errors = Self::hardware_errors(hw);
+        if let Some(max) = self.target.max_registers()
+            && hw.num_registers > max
+        {
+            errors.push(ConfigError::TargetRegisters(self.target, max));
+        }
+        errors
+    }
+
+    /// Checks whether these options can be used on the given hardware.
+    pub fn validate(&self, hw: &InterpreterOptions) -> Result<(), ConfigError> {
+        match self.errors(hw).into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Enables exactly the passes belonging to the optimization level, keeping the target and
+    /// the passes no level includes.
+    pub fn with_level(mut self, level: OptLevel) -> Self {
+        for pass in registry() {
+            if let Some(lowest) = pass.level() {
+                *pass.flag(&mut self) = lowest <= level;
+            }
+        }
+        self
+    }
+
+    /// The optimization level matching the enabled passes, if any.
+    pub fn level(&self) -> Option<OptLevel> {
+        OptLevel::ALL
+            .into_iter()
+            .find(|level| self.with_level(*level) == *self)
+    }
+
+    /// Whether any pass that makes the program better is enabled.
     pub fn any(&self) -> bool {
-        self.do_constant_folding
-            || self.run_cache_optimization
-            || self.do_common_factor_elimination
-            || self.do_shift_replacement
+        registry()
+            .iter()
+            .any(|pass| pass.level().is_some() && pass.enabled(self))
+    }
+}
+
+/// Snapshot of the program after one step of the compilation pipeline.
+#[derive(Clone, serde::Serialize)]
+pub struct Stage {
+    /// Translation key of the pipeline step's name.
+    pub name: &'static str,
+    pub lines: Vec<String>,
+}
+
+impl Stage {
+    pub fn expr(name: &'static str, ast: &Expr) -> Self {
+        Self {
+            name,
+            lines: vec![ast.to_string()],
+        }
+    }
+
+    pub fn code(name: &'static str, code: &[Inst]) -> Self {
+        Self {
+            name,
+            lines: code.iter().map(Inst::to_string).collect(),
+        }
     }
 }
 
+/// The register-allocated intermediate representation handed to the backends.
+#[derive(Clone)]
+pub struct Ir {
+    pub instructions: Vec<Inst>,
+    /// Input variables in the order they first occur in the source, with how often they occur.
+    pub variables: Vec<(String, usize)>,
+    /// The program after all passes on the syntax tree.
+    pub ast: Expr,
+    /// Input variables used as divisors, see [`Expr::divisor_variables`].
+    pub divisors: HashSet<String>,
+    /// The named constants declared in the source.
+    pub constants: ConstTable,
+    /// The hardware the registers and RAM cells were allocated for.
+    pub hw: InterpreterOptions,
+    /// The program after each step of the pipeline, from source to final instructions.
+    pub stages: Vec<Stage>,
+    /// For each instruction, the parts of the source it was generated from.
+    pub debug_info: Vec<Vec<Span>>,
+    /// The statements of the source, to group the instructions by.
+    pub statements: Vec<Statement>,
+    /// Nodes of the syntax tree as it was parsed, each occurrence on its own, and how deeply
+    /// it's nested.
+    pub tree: (u64, usize),
+    /// Why the passes changed what they did.
+    pub explanations: TransformationLog,
+    /// What the compiler reported while compiling, see [`crate::compile_log`].
+    pub log: CompileLog,
+}
+
+/// Where the user-defined rewrite rules run among the [`crate::passes::Pass`]es, after the first
+/// constant folding round.
+const RULES_POSITION: u32 = 20;
+
+/// A value the generated code computes. Equal sub-expressions are a single [`Expr`], but the code
+/// computes each occurrence separately, so each gets a number of its own.
+type Value = usize;
+
 pub struct Compiler {
     options: CompileOptions,
     hw: InterpreterOptions,
+    rules: Vec<RewriteRule>,
+    peephole: Vec<PeepholeRule>,
+    hints: Hints,
+    costs: CostModel,
 }
 
 impl Compiler {
@@ -35,6 +342,10 @@ impl Compiler {
         Self {
             options,
             hw: Default::default(),
+            rules: Vec::new(),
+            peephole: Vec::new(),
+            hints: Hints::new(),
+            costs: CostModel::default(),
         }
     }
 
@@ -43,252 +354,564 @@ impl Compiler {
         self
     }
 
-    pub fn compile(self, input: &str) -> Result<(Vec<Inst>, HashSet<String>), LpErr> {
-        let mut ast = parser::run_parser(input)?;
-        if self.options.do_constant_folding {
-            ast = ast.run_constant_fold(); // first time
+    /// User-defined rewrite rules, applied after the first constant folding round.
+    pub fn with_rules(mut self, rules: Vec<RewriteRule>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Rules for the instructions, applied after all other passes on them.
+    pub fn with_peephole(mut self, rules: Vec<PeepholeRule>) -> Self {
+        self.peephole = rules;
+        self
+    }
+
+    /// Ranges the user promises the input variables stay in, e.g. that one is never negative.
+    /// These aren't [`CompileOptions`] since they belong to the variables of one program.
+    pub fn with_hints(mut self, hints: Hints) -> Self {
+        self.hints = hints;
+        self
+    }
+
+    /// Cycles of the instructions for passes that pick the cheapest program, when they should
+    /// differ from the ISA.
+    pub fn with_costs(mut self, costs: CostModel) -> Self {
+        self.costs = costs;
+        self
+    }
+
+    /// Compiles the input to instructions our interpreter can run, which requires an executable
+    /// target such as the toy ISA.
+    pub fn compile(self, input: &str) -> Result<Ir, LpErr> {
+        let (ir, output) = self.compile_ir(input)?;
+        match output {
+            BackendOutput::Instructions(instructions) => Ok(Ir { instructions, ..ir }),
+            BackendOutput::Text(_) => Err(LpErr::IR(
+                t!(
+                    "compiler.error.not_executable",
+                    target = t!(self.options.target.key())
+                )
+                .to_string(),
+            )),
         }
+    }
+
+    /// Compiles the input and lowers it with the backend selected in the options.
+    pub fn emit(self, input: &str) -> Result<BackendOutput, LpErr> {
+        Ok(self.compile_ir(input)?.1)
+    }
 
-        if self.options.do_common_factor_elimination {
-            ast = ast.extract_common_factors();
+    /// Runs the pipeline and lowers the result with the backend, recording both in the log.
+    fn compile_ir(&self, input: &str) -> Result<(Ir, BackendOutput), LpErr> {
+        let generation = ExprId::generation();
+        let (result, log) = compile_log::record(|| {
+            let ir = self.run_pipeline(input)?;
+            let output = info_span!("lower").in_scope(|| self.options.target.backend().lower(&ir));
+            Ok((ir, output))
+        });
+        // the arena forgets expressions after two generations, this program's may be among them
+        if ExprId::generation() > generation + 1 {
+            return Err(LpErr::IR(t!("compiler.error.forgotten").into()));
         }
+        result.map(|(ir, output)| (Ir { log, ..ir }, output))
+    }
 
-        if self.options.do_shift_replacement {
-            ast = ast.replace_multiplications_with_bitshifts();
+    fn run_pipeline(&self, input: &str) -> Result<Ir, LpErr> {
+        self.options.validate(&self.hw)?;
+        let mut stages = vec![Stage {
+            name: "pipeline.source",
+            lines: input.lines().map(String::from).collect(),
+        }];
+
+        let parse = info_span!("parse").entered();
+        if let Some(diagnostic) = parser::too_many_tokens(input, self.options.max_tokens) {
+            return Err(LpErr::Parse(diagnostic.message));
+        }
+        let (program, mut source_map) =
+            parser::run_program_parser(input, self.options.max_nesting)?;
+        let errors: Vec<_> = analysis::analyze(input, &program, &source_map)
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message)
+            .collect();
+        if !errors.is_empty() {
+            return Err(LpErr::Parse(errors.join("\n")));
+        }
+        let statements = program.statements(input);
+        let (mut ast, constants) = program.resolve(&mut source_map)?;
+        // counted like the code generator computes them, each occurrence on its own
+        let dag = Dag::new(&[ast]);
+        let nodes = dag.tree_size();
+        let tree = (nodes, dag.depth());
+        if nodes > self.options.max_nodes as u64 {
+            return Err(LpErr::Parse(
+                t!(
+                    "compiler.error.too_many_nodes",
+                    n = nodes,
+                    max = self.options.max_nodes
+                )
+                .into(),
+            ));
+        }
+        parse.exit();
+        stages.push(Stage::expr("pipeline.parsed", &ast));
+        let divisors = ast.divisor_variables();
+        let occurrences = ast.variable_occurrences();
+
+        let rules: Vec<_> = self.rules.iter().map(RewriteRule::reparsed).collect();
+        // runs a pass and keeps track of where the rewritten expressions came from
+        let mut run = |ast: Expr, pass: &mut dyn FnMut(Expr) -> Expr| {
+            let res = pass(ast);
+            source_map.carry(&ast, &res);
+            res
+        };
+
+        let cx = PassContext {
+            hw: self.hw,
+            hints: &self.hints,
+            rules: &rules,
+            costs: &self.costs,
+        };
+        let passes_span = info_span!("passes").entered();
+        let (passes, off): (Vec<&dyn Pass>, Vec<_>) = registry()
+            .iter()
+            .partition(|pass| pass.enabled(&self.options));
+        for pass in off {
+            debug!("{}", t!("log.skipped", pass = pass.name()));
+        }
+        // every run of a pass at its position, including the second ones
+        let mut runs: Vec<_> = passes
+            .into_iter()
+            .flat_map(|pass| {
+                let positions = [Some(pass.position()), pass.rerun()];
+                positions.into_iter().flatten().map(move |at| (at, pass))
+            })
+            .collect();
+        runs.sort_by_key(|(at, _)| *at);
+        let (tree_passes, code_passes): (Vec<_>, Vec<_>) =
+            runs.into_iter().partition(|(at, _)| *at < CODE_POSITION);
+
+        let mut log = TransformationLog::default();
+        let mut rules_done = rules.is_empty();
+        for (at, pass) in tree_passes {
+            if !rules_done && at > RULES_POSITION {
+                let _span = info_span!("rules", count = rules.len()).entered();
+                log.start("pipeline.rewritten");
+                ast = run(ast, &mut |ast| run_rewrite_rules(ast, &rules, &mut log));
+                stages.push(Stage::expr("pipeline.rewritten", &ast));
+                rules_done = true;
+            }
+            let _span = info_span!("pass", name = pass.name()).entered();
+            log.start(pass.label());
+            ast = run(ast, &mut |ast| {
+                let res = pass.run_tree(ast, &cx, &mut stages, &mut log);
+                log_change(ast != res);
+                res
+            });
         }
+        if !rules_done {
+            let _span = info_span!("rules", count = rules.len()).entered();
+            log.start("pipeline.rewritten");
+            ast = run(ast, &mut |ast| run_rewrite_rules(ast, &rules, &mut log));
+            stages.push(Stage::expr("pipeline.rewritten", &ast));
+        }
+
+        passes_span.exit();
 
-        if self.options.do_constant_folding {
-            ast = ast.run_constant_fold(); // second time
+        let codegen = info_span!("codegen").entered();
+        let (instructions, origins) =
+            info_span!("allocation").in_scope(|| self.generate_ir(&ast))?;
+        debug!("{}", t!("log.allocated", n = instructions.len()));
+        let mut code: Vec<_> = instructions
+            .into_iter()
+            .zip(origins.iter().map(|e| source_map.spans(e).to_vec()))
+            .collect();
+        stages.push(Stage::code(
+            "pipeline.allocated",
+            &code.iter().map(|(i, _)| i.clone()).collect::<Vec<_>>(),
+        ));
+
+        for (_, pass) in code_passes {
+            let _span = info_span!("pass", name = pass.name()).entered();
+            log.start(pass.label());
+            let before = code.len();
+            code = pass.run_code(code, &cx, &mut stages, &mut log);
+            debug!(
+                "{}",
+                t!("log.instructions", before = before, after = code.len())
+            );
         }
 
-        let (mut instructions, variables) = self.generate_ir(&ast)?;
+        if !self.peephole.is_empty() {
+            let _span = info_span!("peephole", count = self.peephole.len()).entered();
+            log.start("pipeline.peephole");
+            code = run_peephole_rules(code, &self.peephole, &mut log);
+            stages.push(code_stage("pipeline.peephole", &code));
+        }
 
-        if self.options.run_cache_optimization {
-            instructions = run_cache_optimization(instructions);
+        if self.hw.flavor == IsaFlavor::ThreeOperand {
+            let _span = info_span!("three_operand").entered();
+            log.start("pipeline.three_operand");
+            code = run_three_operand(code, &mut log);
+            stages.push(Stage::code(
+                "pipeline.three_operand",
+                &code.iter().map(|(i, _)| i.clone()).collect::<Vec<_>>(),
+            ));
         }
 
-        Ok((instructions, variables))
+        terminate(&mut code);
+        let (instructions, debug_info) = code.into_iter().unzip();
+        codegen.exit();
+        Ok(Ir {
+            instructions,
+            ast,
+            variables: occurrences,
+            divisors,
+            constants,
+            hw: self.hw,
+            stages,
+            debug_info,
+            statements,
+            tree,
+            explanations: log,
+            log: CompileLog::default(),
+        })
+    }
+
+    fn reg_name(&self, reg: u8) -> String {
+        self.hw.reg_naming.name(Reg(reg))
     }
 
-    fn create_write<'a>(
+    /// Moves the value from its register to the first free cache line.
+    fn create_write(
         &self,
-        exp: &'a Expr,
-        ram_idx: &mut usize,
+        value: Value,
         code: &mut Vec<Inst>,
-        mmap: &mut HashMap<&'a Expr, Location>,
-    ) {
-        if let Some(val) = mmap.get_mut(exp) {
+        mmap: &mut HashMap<Value, Location>,
+    ) -> Result<(), LpErr> {
+        let free = (0..self.hw.num_cachelines).find(|&addr| {
+            !mmap
+                .values()
+                .any(|l| matches!(l, Location::Ram(a) if *a == addr))
+        });
+        if let Some(val) = mmap.get_mut(&value) {
             if let Location::Reg(r) = val {
-                code.push(Inst::Write(u8tochar(*r), *ram_idx));
-                *val = Location::Ram(*ram_idx);
-                *ram_idx = (*ram_idx + 1) % self.hw.num_cachelines;
-                if *ram_idx == 0 {
-                    eprintln!("RAM overrun detected");
-                }
+                let addr = free.ok_or_else(|| {
+                    LpErr::IR(t!("compiler.error.ram_full", n = self.hw.num_cachelines).into())
+                })?;
+                code.push(Inst::Write(Reg(*r), addr));
+                debug!("{}", t!("log.spill", reg = self.reg_name(*r), addr = addr));
+                *val = Location::Ram(addr);
             } else {
-                eprintln!("tried to push RAM to RAM??");
+                warn!("tried to push RAM to RAM??");
             }
         } else {
-            eprintln!("tried to create write for non-existent expression?");
+            warn!("tried to create write for non-existent expression?");
         }
+        Ok(())
     }
 
-    fn create_load<'a>(
+    fn create_load(
         &self,
-        exp: &'a Expr,
+        value: Value,
         target_reg: &mut u8,
         code: &mut Vec<Inst>,
-        mmap: &mut HashMap<&'a Expr, Location>,
+        mmap: &mut HashMap<Value, Location>,
     ) {
-        if let Some(val) = mmap.get_mut(exp) {
+        if let Some(val) = mmap.get_mut(&value) {
             if let Location::Ram(r) = val {
-                code.push(Inst::Load(*r, u8tochar(*target_reg)));
+                code.push(Inst::Load(*r, Reg(*target_reg)));
+                debug!(
+                    "{}",
+                    t!("log.reload", addr = *r, reg = self.reg_name(*target_reg))
+                );
                 *val = Location::Reg(*target_reg);
                 *target_reg = (*target_reg + 1) % self.hw.num_registers;
             } else {
-                eprintln!("tried to load register to register??");
+                warn!("tried to load register to register??");
             }
         } else {
-            eprintln!("tried to create load for non-existent expression?");
+            warn!("tried to create load for non-existent expression?");
+        }
+    }
+
+    /// Makes `reg` free for another value, moving the value in it to RAM if it's still needed.
+    fn evict(
+        &self,
+        reg: u8,
+        value: Value,
+        code: &mut Vec<Inst>,
+        mmap: &mut HashMap<Value, Location>,
+        rmap: &mut HashMap<u8, Value>,
+    ) -> Result<(), LpErr> {
+        if let Some(old) = rmap.insert(reg, value) {
+            self.create_write(old, code, mmap)?;
         }
+        Ok(())
     }
 
-    fn fetch_if_necessary<'a>(
+    fn fetch_if_necessary(
         &self,
         cur_reg: &mut u8,
-        e: &'a Expr,
+        value: Value,
         next_reg: &mut u8,
-        ram_idx: &mut usize,
         code: &mut Vec<Inst>,
-        mmap: &mut HashMap<&'a Expr, Location>,
-        rmap: &mut HashMap<u8, &'a Expr>,
-    ) {
-        if *rmap.get(cur_reg).unwrap() != e {
+        mmap: &mut HashMap<Value, Location>,
+        rmap: &mut HashMap<u8, Value>,
+    ) -> Result<(), LpErr> {
+        if rmap.get(cur_reg) != Some(&value) {
             // the entry was evicted -> need a store (maybe) & load
-            rmap.entry(*next_reg)
-                .and_modify(|expr| {
-                    self.create_write(expr, ram_idx, code, mmap);
-                    *expr = e;
-                })
-                .or_insert(e);
-
+            self.evict(*next_reg, value, code, mmap, rmap)?;
             *cur_reg = *next_reg;
-            self.create_load(e, next_reg, code, mmap);
+            self.create_load(value, next_reg, code, mmap);
         }
+        Ok(())
     }
 
-    fn ast_to_ir<'a>(
+    /// Reserves a register for a number or variable and loads it there with `inst`.
+    fn load_leaf(
         &self,
-        ast: &'a Expr,
+        value: Value,
+        inst: impl FnOnce(Reg) -> Inst,
         next_reg: &mut u8,
-        ram_idx: &mut usize,
         code: &mut Vec<Inst>,
-        variables: &mut HashSet<String>,
-        mmap: &mut HashMap<&'a Expr, Location>,
-        rmap: &mut HashMap<u8, &'a Expr>,
+        mmap: &mut HashMap<Value, Location>,
+        rmap: &mut HashMap<u8, Value>,
     ) -> Result<u8, LpErr> {
-        match ast {
-            Expr::Num(n) => {
-                let reg = *next_reg;
-
-                // reserve a register for the result and (potentially) evict an existing entry to RAM.
-                rmap.entry(reg)
-                    .and_modify(|expr| {
-                        self.create_write(expr, ram_idx, code, mmap);
-                        *expr = ast;
-                    })
-                    .or_insert(ast);
-
-                code.push(Inst::Store(*n, u8tochar(reg)));
-                if mmap.contains_key(ast) {
-                    eprintln!(
-                        "Tried overwriting existing MMAP value -- duplicate expression {ast:?}"
-                    );
-                } else {
-                    mmap.insert(ast, Location::Reg(reg));
-                }
+        let reg = *next_reg; // TODO: avoid duplicate register mapping+transfer
 
-                *next_reg = (*next_reg + 1) % self.hw.num_registers;
-                Ok(reg)
-            }
-            Expr::Var(v) => {
-                let reg = *next_reg; // TODO: avoid duplicate register mapping+transfer
-
-                // reserve a register for the result and (potentially) evict an existing entry to RAM.
-                rmap.entry(reg)
-                    .and_modify(|expr| {
-                        self.create_write(expr, ram_idx, code, mmap);
-                        *expr = ast;
-                    })
-                    .or_insert(ast);
-
-                code.push(Inst::Transfer(v.clone(), u8tochar(reg)));
-                if mmap.contains_key(ast) {
-                    eprintln!(
-                        "[warn] Tried overwriting existing MMAP value -- duplicate expression {ast:?}"
-                    );
-                } else {
-                    mmap.insert(ast, Location::Reg(reg));
+        // reserve a register for the result and (potentially) evict an existing entry to RAM.
+        self.evict(reg, value, code, mmap, rmap)?;
+        code.push(inst(Reg(reg)));
+        mmap.insert(value, Location::Reg(reg));
+
+        *next_reg = (*next_reg + 1) % self.hw.num_registers;
+        Ok(reg)
+    }
+
+    /// Emits the code for `ast`, returning the register holding its value.
+    ///
+    /// Works through the tree with an explicit stack instead of recursion, so long chains of
+    /// operations can't overflow the stack.
+    #[allow(clippy::too_many_arguments)]
+    fn ast_to_ir(
+        &self,
+        ast: &Expr,
+        next_reg: &mut u8,
+        code: &mut Vec<Inst>,
+        mmap: &mut HashMap<Value, Location>,
+        rmap: &mut HashMap<u8, Value>,
+        origins: &mut Vec<Expr>,
+    ) -> Result<u8, LpErr> {
+        /// Code for an expression is emitted in two steps: its operands, then the operation.
+        enum Task {
+            Visit(Expr),
+            Combine(Expr),
+            /// An operation with a number as its second operand, see [`Inst::AddI`].
+            Immediate(Expr),
+            /// Unary minus, see [`Inst::Neg`].
+            Negate(Expr),
+        }
+
+        let mut tasks = vec![Task::Visit(*ast)];
+        // registers holding the values of finished operands, with the values
+        let mut regs: Vec<(u8, Value)> = vec![];
+        let mut values = 0..;
+        while let Some(task) = tasks.pop() {
+            let ast = match task {
+                Task::Visit(ast @ Expr::Num(n)) => {
+                    let inst = |r| Inst::Store(n, r);
+                    let value = values.next().unwrap();
+                    let reg = self.load_leaf(value, inst, next_reg, code, mmap, rmap)?;
+                    regs.push((reg, value));
+                    ast
+                }
+                Task::Visit(ast @ Expr::Var(v)) => {
+                    let inst = |r| Inst::Transfer(v.to_string(), r);
+                    let value = values.next().unwrap();
+                    let reg = self.load_leaf(value, inst, next_reg, code, mmap, rmap)?;
+                    regs.push((reg, value));
+                    ast
                 }
+                Task::Visit(ast @ Expr::UnaryOp(Operator::Sub, e)) => {
+                    tasks.push(Task::Negate(ast));
+                    tasks.push(Task::Visit(e.get()));
+                    continue;
+                }
+                Task::Visit(Expr::UnaryOp(op, _)) => {
+                    return Err(LpErr::IR(
+                        t!("compiler.error.invalid_unary", op = op).to_string(),
+                    ));
+                }
+                Task::Visit(ast @ Expr::BinaryOp(left, op, right))
+                    if self.options.use_immediates
+                        && matches!(right.get(), Expr::Num(_))
+                        && !matches!(op, Operator::Min | Operator::Max) =>
+                {
+                    tasks.push(Task::Immediate(ast));
+                    tasks.push(Task::Visit(left.get()));
+                    continue;
+                }
+                Task::Visit(ast @ Expr::BinaryOp(left, _, right)) => {
+                    tasks.push(Task::Combine(ast));
+                    tasks.push(Task::Visit(right.get()));
+                    tasks.push(Task::Visit(left.get()));
+                    continue;
+                }
+                Task::Immediate(ast) => {
+                    let Expr::BinaryOp(_, op, right) = ast else {
+                        unreachable!("only operations have immediate forms")
+                    };
+                    let Expr::Num(n) = right.get() else {
+                        unreachable!("the second operand is a number")
+                    };
+                    let (mut reg, operand) = regs.pop().expect("operand was visited");
+                    self.fetch_if_necessary(&mut reg, operand, next_reg, code, mmap, rmap)?;
 
-                variables.insert(v.clone());
-                *next_reg = (*next_reg + 1) % self.hw.num_registers;
-                Ok(reg)
-            }
-            Expr::UnaryOp(Operator::Sub, e) => {
-                // TODO: optimization potential -> do the right register first to avoid collisions
-                let mut left_reg = self.ast_to_ir(
-                    &Expr::Num(0),
-                    next_reg,
-                    ram_idx,
-                    code,
-                    variables,
-                    mmap,
-                    rmap,
-                )?;
-                let mut right_reg =
-                    self.ast_to_ir(e, next_reg, ram_idx, code, variables, mmap, rmap)?;
-
-                self.fetch_if_necessary(
-                    &mut left_reg,
-                    &Expr::Num(0),
-                    next_reg,
-                    ram_idx,
-                    code,
-                    mmap,
-                    rmap,
-                );
+                    let r = Reg(reg);
+                    code.push(match op.base() {
+                        Operator::Add => Inst::AddI(r, n),
+                        Operator::Sub => Inst::SubI(r, n),
+                        Operator::Mul => Inst::MulI(r, n),
+                        Operator::Div => Inst::DivI(r, n),
+                        Operator::Shl => Inst::ShlI(r, n),
+                        Operator::Shr => Inst::ShrI(r, n),
+                        _ => unreachable!("builtins are based on plain arithmetic"),
+                    });
+                    code.extend(overflow_check(op, r));
 
-                self.fetch_if_necessary(&mut right_reg, e, next_reg, ram_idx, code, mmap, rmap);
+                    // the operand is overwritten with the result
+                    let value = values.next().unwrap();
+                    mmap.remove(&operand);
+                    rmap.insert(reg, value);
+                    mmap.insert(value, Location::Reg(reg));
+                    regs.push((reg, value));
+                    ast
+                }
+                Task::Negate(ast) => {
+                    let (mut reg, operand) = regs.pop().expect("operand was visited");
+                    self.fetch_if_necessary(&mut reg, operand, next_reg, code, mmap, rmap)?;
+                    code.push(Inst::Neg(Reg(reg)));
 
-                code.push(Inst::Sub(u8tochar(left_reg), u8tochar(right_reg)));
+                    // the operand is overwritten with the result
+                    let value = values.next().unwrap();
+                    mmap.remove(&operand);
+                    rmap.insert(reg, value);
+                    mmap.insert(value, Location::Reg(reg));
+                    regs.push((reg, value));
+                    ast
+                }
+                Task::Combine(ast) => {
+                    let Expr::BinaryOp(_, op, _) = ast else {
+                        unreachable!("leaves are done when visited, unary minus is negated")
+                    };
+                    let (mut right_reg, right) = regs.pop().expect("operand was visited");
+                    let (mut left_reg, left) = regs.pop().expect("operand was visited");
 
-                rmap.entry(right_reg).and_modify(|val| *val = ast);
-                // forced insert here because register is more useful than a potential hit in RAM
-                mmap.insert(ast, Location::Reg(right_reg));
+                    self.fetch_if_necessary(&mut left_reg, left, next_reg, code, mmap, rmap)?;
+                    self.fetch_if_necessary(&mut right_reg, right, next_reg, code, mmap, rmap)?;
 
-                Ok(right_reg)
-            }
-            Expr::UnaryOp(op, _) => Err(LpErr::IR(
-                t!("compiler.error.invalid_unary", op = op).to_string(),
-            )),
-            Expr::BinaryOp(left, op, right) => {
-                let mut left_reg =
-                    self.ast_to_ir(left, next_reg, ram_idx, code, variables, mmap, rmap)?;
-                let mut right_reg =
-                    self.ast_to_ir(right, next_reg, ram_idx, code, variables, mmap, rmap)?;
-
-                self.fetch_if_necessary(&mut left_reg, left, next_reg, ram_idx, code, mmap, rmap);
-                self.fetch_if_necessary(&mut right_reg, right, next_reg, ram_idx, code, mmap, rmap);
-
-                let inst = match op {
-                    Operator::Add => Inst::Add(u8tochar(left_reg), u8tochar(right_reg)),
-                    Operator::Sub => Inst::Sub(u8tochar(left_reg), u8tochar(right_reg)),
-                    Operator::Mul => Inst::Mul(u8tochar(left_reg), u8tochar(right_reg)),
-                    Operator::Div => Inst::Div(u8tochar(left_reg), u8tochar(right_reg)),
-                    Operator::Shl => Inst::Shl(u8tochar(left_reg), u8tochar(right_reg)),
-                    Operator::Shr => Inst::Shr(u8tochar(left_reg), u8tochar(right_reg)),
-                };
+                    let inst = match op.base() {
+                        Operator::Add => Inst::Add(Reg(left_reg), Reg(right_reg)),
+                        Operator::Sub => Inst::Sub(Reg(left_reg), Reg(right_reg)),
+                        Operator::Mul => Inst::Mul(Reg(left_reg), Reg(right_reg)),
+                        Operator::Div => Inst::Div(Reg(left_reg), Reg(right_reg)),
+                        Operator::Shl => Inst::Shl(Reg(left_reg), Reg(right_reg)),
+                        Operator::Shr => Inst::Shr(Reg(left_reg), Reg(right_reg)),
+                        Operator::Min => Inst::Min(Reg(left_reg), Reg(right_reg)),
+                        Operator::Max => Inst::Max(Reg(left_reg), Reg(right_reg)),
+                        _ => unreachable!("builtins are based on plain arithmetic"),
+                    };
 
-                code.push(inst);
+                    code.push(inst);
+                    code.extend(overflow_check(op, Reg(right_reg)));
 
-                rmap.entry(right_reg).and_modify(|val| *val = ast);
-                // forced insert here because register is more useful than a potential hit in RAM
-                mmap.insert(ast, Location::Reg(right_reg));
+                    // the operands aren't needed anymore, the result replaces the right one
+                    let value = values.next().unwrap();
+                    mmap.remove(&left);
+                    mmap.remove(&right);
+                    if left_reg != right_reg {
+                        rmap.remove(&left_reg);
+                    }
+                    rmap.insert(right_reg, value);
+                    mmap.insert(value, Location::Reg(right_reg));
 
-                Ok(right_reg)
+                    regs.push((right_reg, value));
+                    ast
+                }
+            };
+            if let Some(&(reg, _)) = regs.last() {
+                trace!(
+                    "{}",
+                    t!("log.placed", expr = short(ast), reg = self.reg_name(reg))
+                );
             }
+            // everything emitted since the operands were done belongs to this expression
+            origins.resize(code.len(), ast);
         }
+        Ok(regs.pop().expect("the whole tree was visited").0)
     }
 
-    fn generate_ir(&self, ast: &Expr) -> Result<(Vec<Inst>, HashSet<String>), LpErr> {
+    /// Generates the instructions, together with the expression each instruction belongs to.
+    #[allow(clippy::type_complexity)]
+    fn generate_ir(&self, ast: &Expr) -> Result<(Vec<Inst>, Vec<Expr>), LpErr> {
         let mut reg_counter = 0;
-        let mut ram_idx = 0;
         let mut code: Vec<Inst> = vec![];
-        let mut variables = HashSet::new();
 
         let mut mmap = HashMap::new();
         let mut rmap = HashMap::new();
+        let mut origins = vec![];
 
         let result_reg = self.ast_to_ir(
             ast,
             &mut reg_counter,
-            &mut ram_idx,
             &mut code,
-            &mut variables,
             &mut mmap,
             &mut rmap,
+            &mut origins,
         )?;
-        code.push(Inst::Result(u8tochar(result_reg)));
-        Ok((code, variables))
+        code.push(Inst::Result(Reg(result_reg)));
+        origins.push(*ast);
+        Ok((code, origins))
     }
 }
 
-pub fn u8tochar(reg: u8) -> char {
-    // Converts to base 36, i.e. [0..9, a, b, ..z]
-    // +10 means that it maps [0, 1, ..] to [a, b, ..].
-    assert!(reg <= 26, "max 26 registers supported (a..z)");
-    char::from_digit(reg as u32 + 10, 36).unwrap()
+/// Makes sure the program ends with the instruction that ends it, see [`Inst::terminates`], so it
+/// never runs past its last instruction. Code generation always ends with [`Inst::Result`], but
+/// peephole rules aren't checked and can remove it or put instructions after it.
+fn terminate<D: Default>(code: &mut Vec<(Inst, D)>) {
+    match code.iter().position(|(inst, _)| inst.terminates()) {
+        Some(end) if end + 1 < code.len() => {
+            warn!("{}", t!("log.after_end", n = code.len() - end - 1));
+            code.truncate(end + 1);
+        }
+        Some(_) => {}
+        None => {
+            warn!("{}", t!("log.no_end"));
+            code.push((Inst::Halt, D::default()));
+        }
+    }
+}
+
+/// Notes in the compile log whether a pass changed the program.
+fn log_change(changed: bool) {
+    if changed {
+        debug!("{}", t!("log.changed"));
+    } else {
+        debug!("{}", t!("log.unchanged"));
+    }
+}
+
+/// What a builtin does after its arithmetic left the result in `r`, see [`Operator::base`].
+fn overflow_check(op: Operator, r: Reg) -> Option<Inst> {
+    match op {
+        Operator::SatAdd | Operator::SatSub => Some(Inst::Sat(r)),
+        Operator::CheckedAdd
+        | Operator::CheckedSub
+        | Operator::CheckedMul
+        | Operator::CheckedDiv => Some(Inst::Chk(r)),
+        _ => None,
+    }
 }
 
 /// Describes a memory address either as register or RAM address
@@ -296,3 +919,450 @@ pub enum Location {
     Ram(MemAddr),
     Reg(u8),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gui::WordSize;
+    use crate::interpreter::Interpreter;
+    use crate::passes::{ConstantFold, ShiftReplacement};
+    use crate::ranges::ValueRange;
+
+    /// Runs the program on the machine it was compiled for, with the given inputs.
+    fn run(ir: Ir, inputs: &[(&str, i32)]) -> Result<i32, LpErr> {
+        let variables = (inputs.iter())
+            .map(|(v, n)| (v.to_string(), n.to_string()))
+            .collect();
+        Interpreter::with_config(&ir.hw)
+            .load_instructions(ir.instructions)
+            .with_variables(variables)
+            .ready()
+            .run_to_end()
+    }
+
+    #[test]
+    fn builder_rejects_too_few_registers() {
+        let hw = InterpreterOptions {
+            num_registers: 1,
+            ..Default::default()
+        };
+
+        let res = CompileOptions::builder()
+            .constant_folding(true)
+            .hardware(hw)
+            .build();
+        assert_eq!(res.err(), Some(ConfigError::TooFewRegisters(1)));
+    }
+
+    #[test]
+    fn compile_rejects_invalid_hardware() {
+        let hw = InterpreterOptions {
+            num_cachelines: 0,
+            ..Default::default()
+        };
+
+        let res = Compiler::with(CompileOptions::default())
+            .with_interpreter(hw)
+            .compile("1 + 2");
+        assert!(matches!(res, Err(LpErr::Config(_))));
+    }
+
+    #[test]
+    fn targets_are_named_for_visitors() -> Result<(), ConfigError> {
+        let options = CompileOptions::builder().target(Target::RiscV).build()?;
+        let Err(LpErr::IR(message)) = Compiler::with(options).compile("1 + 2") else {
+            panic!("RISC-V can't be run");
+        };
+        assert!(message.contains(&*t!("compiler.target.riscv")), "{message}");
+        assert!(!message.contains("RiscV"), "{message}");
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn debug_info_points_to_source() -> Result<(), LpErr> {
+        let opts = CompileOptions::default().with_level(OptLevel::O1);
+        let ir = Compiler::with(opts).compile("x + 2 * 3")?;
+
+        assert_eq!(ir.debug_info.len(), ir.instructions.len());
+        let store = ir
+            .instructions
+            .iter()
+            .position(|i| matches!(i, Inst::Store(6, _)))
+            .unwrap();
+        assert_eq!(ir.debug_info[store], [4..9]);
+        assert_eq!(ir.debug_info.last().unwrap(), &[0..9]);
+        Ok(())
+    }
+
+    #[test]
+    fn unary_minus_is_negated() -> Result<(), LpErr> {
+        for (source, expected) in [("-a", -3), ("-(a + b)", -7), ("b * -a", -12)] {
+            let ir = Compiler::with(CompileOptions::default()).compile(source)?;
+            assert!(ir.instructions.iter().any(|i| matches!(i, Inst::Neg(_))));
+            assert!(
+                !ir.instructions
+                    .iter()
+                    .any(|i| matches!(i, Inst::Store(0, _)))
+            );
+            assert_eq!(run(ir, &[("a", 3), ("b", 4)])?, expected, "{source}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn builtins_check_the_overflow() -> Result<(), LpErr> {
+        let compute = |source: &str, a: i32, word_size| {
+            let hw = InterpreterOptions {
+                word_size,
+                ..Default::default()
+            };
+            let ir = Compiler::with(CompileOptions::default())
+                .with_interpreter(hw)
+                .compile(source)?;
+            run(ir, &[("a", a)])
+        };
+        for source in [
+            "sat_add(a, 100)",
+            "sat_sub(a, 100)",
+            "checked_add(a, 100)",
+            "checked_mul(a, a)",
+            "checked_div(a, -1)",
+        ] {
+            for a in [i32::MIN, -5, 0, 7, i32::MAX] {
+                let expected = crate::eval::evaluate(
+                    &crate::parser::run_parser(source)?,
+                    &HashMap::from([("a".to_string(), a)]),
+                    WordSize::W32,
+                );
+                let result = compute(source, a, WordSize::W32);
+                assert_eq!(result.ok(), expected.ok(), "{source} with a = {a}");
+            }
+        }
+        // the limits are those of the word size
+        assert_eq!(compute("sat_add(a, 100)", 100, WordSize::W8)?, 127);
+        assert_eq!(compute("sat_sub(a, 100)", -100, WordSize::W8)?, -128);
+        assert_eq!(compute("sat_add(a, 1)", 120, WordSize::W8)?, 121);
+        assert!(compute("checked_add(a, 100)", 100, WordSize::W8).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn folding_computes_like_the_machine() -> Result<(), LpErr> {
+        use crate::generator::{GenOptions, Rng, random_program};
+
+        let compute = |source: &str, level, word_size| {
+            let hw = InterpreterOptions {
+                word_size,
+                ..Default::default()
+            };
+            let ir = Compiler::with(CompileOptions::default().with_level(level))
+                .with_interpreter(hw)
+                .compile(source)?;
+            run(ir, &[])
+        };
+        let mut rng = Rng::with_seed(1146);
+        let mut sources: Vec<String> = [
+            "sat_add(100, 100)",
+            "sat_sub(-100, 100)",
+            "checked_add(100, 100)",
+            "checked_mul(300, 300)",
+            "200 / 2",
+            "-(-128)",
+            "1 << 20",
+            "min(200, 1)",
+        ]
+        .map(String::from)
+        .into();
+        let options = GenOptions {
+            operators: vec![
+                (Operator::Add, 2),
+                (Operator::Mul, 2),
+                (Operator::Div, 1),
+                (Operator::SatAdd, 1),
+                (Operator::CheckedMul, 1),
+            ],
+            variable_chance: 0,
+            max_number: 200,
+            ..Default::default()
+        };
+        sources.extend((0..30).map(|_| random_program(&mut rng, &options).pretty()));
+        for word_size in WordSize::ALL {
+            for source in &sources {
+                let unoptimized = compute(source, OptLevel::O0, word_size);
+                let folded = compute(source, OptLevel::O1, word_size);
+                assert_eq!(folded.ok(), unoptimized.ok(), "{source} at {word_size:?}");
+            }
+        }
+        assert_eq!(
+            compute("sat_add(100, 100)", OptLevel::O1, WordSize::W8)?,
+            127
+        );
+        assert_eq!(compute("200 / 2", OptLevel::O1, WordSize::W8)?, -28);
+        assert!(compute("checked_add(100, 100)", OptLevel::O1, WordSize::W8).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn immediates_compute_the_same() -> Result<(), LpErr> {
+        use crate::generator::{Rng, random_expr};
+
+        let ops = [
+            Operator::Add,
+            Operator::Sub,
+            Operator::Mul,
+            Operator::Div,
+            Operator::Shl,
+            Operator::Shr,
+        ];
+        let mut rng = Rng::with_seed(1143);
+        let (mut with, mut without) = (0, 0);
+        for num_registers in [2, 6] {
+            let hw = InterpreterOptions {
+                num_registers,
+                ..Default::default()
+            };
+            for _ in 0..100 {
+                let expr = random_expr(&mut rng, 4, &ops);
+                let compile = |use_immediates| {
+                    let options = CompileOptions {
+                        use_immediates,
+                        ..Default::default()
+                    };
+                    Compiler::with(options)
+                        .with_interpreter(hw)
+                        .compile(&expr.to_string())
+                };
+                let ir = compile(true)?;
+                with += ir.instructions.len();
+                let expected = crate::eval::evaluate(&expr, &HashMap::new(), hw.word_size);
+                assert_eq!(run(ir, &[]).ok(), expected.ok(), "{expr}");
+                without += compile(false)?.instructions.len();
+            }
+        }
+        assert!(with < without);
+        Ok(())
+    }
+
+    #[test]
+    fn programs_too_large_are_rejected() {
+        // 7 nodes
+        let code = "x + x * 2 - 1";
+        let compile = |options| {
+            Compiler::with(options)
+                .with_interpreter(InterpreterOptions::default())
+                .compile(code)
+        };
+        assert!(compile(CompileOptions::default()).is_ok());
+
+        let options = CompileOptions {
+            max_nodes: 5,
+            ..Default::default()
+        };
+        let too_many_nodes = t!("compiler.error.too_many_nodes", n = 7, max = 5);
+        assert!(matches!(compile(options), Err(LpErr::Parse(e)) if e == too_many_nodes));
+
+        let options = CompileOptions {
+            max_tokens: 6,
+            ..Default::default()
+        };
+        assert!(
+            matches!(compile(options), Err(LpErr::Parse(e)) if e.contains(&*t!("parser.too_many_tokens", max = 6)))
+        );
+    }
+
+    #[test]
+    fn programs_always_end_explicitly() -> Result<(), LpErr> {
+        let compile = |rule: &str| {
+            let rules = vec![PeepholeRule::parse(rule)?];
+            Compiler::with(CompileOptions::default())
+                .with_peephole(rules)
+                .compile("x + 1")
+        };
+        let ends = |ir: &Ir| ir.instructions.iter().position(Inst::terminates);
+
+        // nothing runs after the result
+        let ir = compile("result ?r => result ?r; store 1, ?r")?;
+        assert!(matches!(ir.instructions.last(), Some(Inst::Result(_))));
+        assert_eq!(ends(&ir), Some(ir.instructions.len() - 1));
+
+        // without the result the program stops instead of running past its end
+        let ir = compile("result ?r =>")?;
+        assert!(matches!(ir.instructions.last(), Some(Inst::Halt)));
+        assert_eq!(ends(&ir), Some(ir.instructions.len() - 1));
+        let res = run(ir, &[("x", 2)]);
+        assert!(matches!(res, Err(LpErr::Interpret(e)) if e == t!("compiler.error.no_result")));
+        Ok(())
+    }
+
+    #[test]
+    fn log_has_the_phases_and_spills() -> Result<(), LpErr> {
+        use crate::compile_log::{Entry, Verbosity};
+
+        let hw = InterpreterOptions {
+            num_registers: 2,
+            ..Default::default()
+        };
+        let ir = Compiler::with(CompileOptions::default().with_level(OptLevel::O1))
+            .with_interpreter(hw)
+            .compile("1 + (2 * (3 - x))")?;
+        let phases: Vec<_> = (ir.log.filtered(Verbosity::Phases))
+            .filter_map(|line| match &line.entry {
+                Entry::Enter { phase, .. } if line.depth == 0 => Some(*phase),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(phases, ["parse", "passes", "codegen", "lower"]);
+
+        // the breakdown has each pass that ran and the parts of code generation
+        let times: Vec<_> = ir.log.phase_times().iter().map(|t| t.label()).collect();
+        assert_eq!(times.first().map(String::as_str), Some("Parsing"));
+        assert!(times.contains(&"Constant folding".to_string()));
+        assert!(times.contains(&"Register allocation".to_string()));
+
+        let writes = ir
+            .instructions
+            .iter()
+            .filter(|i| matches!(i, Inst::Write(..)));
+        let spills = (ir.log.filtered(Verbosity::Decisions)).filter(|line| {
+            matches!(&line.entry, Entry::Event { message, .. } if message.contains("cache line"))
+        });
+        assert!(spills.count() >= writes.count());
+        Ok(())
+    }
+
+    #[test]
+    fn generated_programs_compute_the_same() -> Result<(), LpErr> {
+        use crate::generator::{GenOptions, Rng, random_program};
+
+        let mut rng = Rng::with_seed(1176);
+        let variables = [("a", -7), ("b", 3), ("x", 100)];
+        let values: HashMap<String, i32> =
+            variables.iter().map(|(v, n)| (v.to_string(), *n)).collect();
+        for operations in [1, 4, 8, 16] {
+            let options = GenOptions {
+                operations,
+                ..Default::default()
+            };
+            for _ in 0..20 {
+                let expr = random_program(&mut rng, &options);
+                let expected = crate::eval::evaluate(&expr, &values, WordSize::W32);
+                for (level, num_registers) in OptLevel::ALL.into_iter().zip([2, 3, 6]) {
+                    let hw = InterpreterOptions {
+                        num_registers,
+                        ..Default::default()
+                    };
+                    let ir = Compiler::with(CompileOptions::default().with_level(level))
+                        .with_interpreter(hw)
+                        .compile(&expr.pretty())?;
+                    assert_eq!(
+                        run(ir, &variables).ok(),
+                        expected.clone().ok(),
+                        "{expr} at {level} with {num_registers} registers"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn three_operands_compute_the_same() -> Result<(), LpErr> {
+        use crate::generator::{Rng, random_expr};
+
+        let ops = [Operator::Add, Operator::Sub, Operator::Mul, Operator::Shl];
+        let mut rng = Rng::with_seed(1144);
+        for level in OptLevel::ALL {
+            let hw = InterpreterOptions {
+                num_registers: 3,
+                flavor: IsaFlavor::ThreeOperand,
+                ..Default::default()
+            };
+            let options = CompileOptions::default().with_level(level);
+            for _ in 0..50 {
+                let expr = random_expr(&mut rng, 4, &ops);
+                let ir = Compiler::with(options)
+                    .with_interpreter(hw)
+                    .compile(&expr.to_string())?;
+                assert!(!ir.instructions.iter().any(|i| matches!(i, Inst::Add(..))));
+                let expected = crate::eval::evaluate(&expr, &HashMap::new(), hw.word_size);
+                assert_eq!(run(ir, &[]).ok(), expected.ok(), "{expr}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn constants_reach_all_passes() -> Result<(), LpErr> {
+        // the values of constants are put into the body before any pass runs
+        let options = CompileOptions::default().with_level(OptLevel::O2);
+        let ir =
+            Compiler::with(options).compile("const K = 4;\nconst L = K * 2;\nx * L + K * 3")?;
+        assert_eq!(ir.ast, crate::parser::run_parser("(x << 3) + 12")?);
+        Ok(())
+    }
+
+    #[test]
+    fn hints_allow_more_shifts() -> Result<(), LpErr> {
+        let compile = |hints| {
+            let options = CompileOptions::default().with_level(OptLevel::O2);
+            Compiler::with(options)
+                .with_hints(hints)
+                .compile("x / 8 + y / 4")
+        };
+        assert_eq!(compile(Hints::new())?.ast.pretty(), "x / 8 + y / 4");
+        let hints = Hints::from([
+            ("x".to_string(), ValueRange::NON_NEGATIVE),
+            ("y".to_string(), ValueRange { min: -3, max: 9 }),
+        ]);
+        assert_eq!(compile(hints)?.ast.pretty(), "(x >> 3) + y / 4");
+        Ok(())
+    }
+
+    #[test]
+    fn deep_trees_dont_overflow_the_stack() -> Result<(), LpErr> {
+        // far deeper than the parser allows, the passes still shouldn't recurse
+        let ast = (0..100_000).fold(Expr::Num(1), |e, _| {
+            Expr::BinaryOp(ExprId::new(e), Operator::Mul, ExprId::new(Expr::Num(1)))
+        });
+        let handle = std::thread::spawn(move || {
+            let mut log = TransformationLog::default();
+            let shifted =
+                ast.replace_multiplications_with_bitshifts(&Hints::new(), WordSize::W32, &mut log);
+            let compiler = Compiler::with(CompileOptions::default());
+            let (instructions, _) = compiler.generate_ir(&shifted)?;
+            Ok::<_, LpErr>((
+                shifted.run_constant_fold(compiler.hw.word_size, &mut log),
+                instructions.len(),
+            ))
+        });
+        let (folded, instructions) = handle.join().unwrap()?;
+
+        assert_eq!(folded, Expr::Num(1));
+        assert!(instructions > 100_000);
+        Ok(())
+    }
+
+    #[test]
+    fn long_programs_are_rejected_on_a_small_stack() {
+        // a chain is as deep as it is long, and deep parentheses are caught before parsing
+        let sum = format!("x{}", " + x".repeat(50_000));
+        let parentheses = format!("{}1{}", "(1 + ".repeat(1000), ")".repeat(1000));
+        let worker = std::thread::Builder::new().stack_size(2 << 20);
+        let handle = worker.spawn(move || {
+            let options = CompileOptions {
+                max_tokens: 1_000_000,
+                ..CompileOptions::default()
+            };
+            [sum, parentheses].map(|code| Compiler::with(options).compile(&code).map(|_| ()))
+        });
+
+        for result in handle.unwrap().join().unwrap() {
+            let message = result.unwrap_err().to_string();
+            assert!(
+                message.contains(&parser::DEFAULT_MAX_NESTING.to_string()),
+                "{message}"
+            );
+        }
+    }
+}
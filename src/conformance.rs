@@ -0,0 +1,494 @@
+//! A fixed suite of tiny programs pinning down what each instruction does, down to the error
+//! cases, RAM bounds and overflow at every word size. The interpreter is the reference: every
+//! case passes with it, and another way to run programs has to give the same results.
+//!
+//! Each case is a few hand-written instructions, not a compiled program, so instructions the
+//! compiler never emits are covered as well.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::LpErr;
+use crate::gui::{Engine, InterpreterOptions, UninitPolicy, WordSize};
+use crate::interpreter::{Place, StepEffects};
+use crate::types::{Inst, Reg};
+use rust_i18n::t;
+
+/// What a case is about, the report is grouped by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Topic {
+    /// What each instruction computes.
+    Opcodes,
+    /// Programs that have to stop with an error.
+    Errors,
+    /// Cache lines and the stack at the end of them.
+    Ram,
+    /// Wrapping, saturating and checking at each word size.
+    Overflow,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 4] = [Topic::Opcodes, Topic::Errors, Topic::Ram, Topic::Overflow];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Topic::Opcodes => "conformance.topic.opcodes",
+            Topic::Errors => "conformance.topic.errors",
+            Topic::Ram => "conformance.topic.ram",
+            Topic::Overflow => "conformance.topic.overflow",
+        }
+    }
+}
+
+/// How a case has to end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    Result(i32),
+    /// Any error, the messages may differ.
+    Error,
+}
+
+impl Expected {
+    fn matches(self, actual: &Result<i32, String>) -> bool {
+        match (self, actual) {
+            (Expected::Result(expected), Ok(result)) => expected == *result,
+            (Expected::Error, Err(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// One program of the suite.
+#[derive(Clone)]
+pub struct Case {
+    /// Short and unique, e.g. `div by zero`.
+    pub name: String,
+    pub topic: Topic,
+    /// The configuration the suite runs with, changed where the case needs it.
+    pub hw: InterpreterOptions,
+    pub program: Vec<Inst>,
+    pub inputs: HashMap<String, String>,
+    pub expected: Expected,
+}
+
+impl Case {
+    fn new(name: &str, topic: Topic, hw: &InterpreterOptions, program: Vec<Inst>) -> Self {
+        Case {
+            name: name.into(),
+            topic,
+            hw: *hw,
+            program,
+            inputs: HashMap::new(),
+            expected: Expected::Error,
+        }
+    }
+
+    fn gives(self, result: i32) -> Self {
+        Case {
+            expected: Expected::Result(result),
+            ..self
+        }
+    }
+
+    fn input(mut self, name: &str, value: &str) -> Self {
+        self.inputs.insert(name.into(), value.into());
+        self
+    }
+
+    fn word_size(mut self, word_size: WordSize) -> Self {
+        self.hw.word_size = word_size;
+        self
+    }
+
+    fn uninit(mut self, uninit: UninitPolicy) -> Self {
+        self.hw.uninit = uninit;
+        self
+    }
+}
+
+/// How one case went.
+#[derive(Clone)]
+pub struct Outcome {
+    pub case: Case,
+    /// The result, or the message of the error the program stopped with.
+    pub actual: Result<i32, String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.case.expected.matches(&self.actual)
+    }
+}
+
+/// The outcome of every case, in the order of the suite.
+#[derive(Clone, Default)]
+pub struct Report {
+    pub outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.passed()).count()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &Outcome> {
+        self.outcomes.iter().filter(|o| !o.passed())
+    }
+}
+
+const A: Reg = Reg(0);
+const B: Reg = Reg(1);
+const C: Reg = Reg(2);
+
+/// `a` and `b` in registers, then `inst`, and the register it wrote as the result.
+fn binary(a: i32, b: i32, inst: Inst) -> Vec<Inst> {
+    let dest = match inst {
+        Inst::Add3(..)
+        | Inst::Sub3(..)
+        | Inst::Mul3(..)
+        | Inst::Div3(..)
+        | Inst::Shl3(..)
+        | Inst::Shr3(..)
+        | Inst::Min3(..)
+        | Inst::Max3(..) => C,
+        _ => B,
+    };
+    vec![
+        Inst::Store(a, A),
+        Inst::Store(b, B),
+        inst,
+        Inst::Result(dest),
+    ]
+}
+
+/// `a` in a register, then `inst` on it.
+fn unary(a: i32, inst: Inst) -> Vec<Inst> {
+    vec![Inst::Store(a, A), inst, Inst::Result(A)]
+}
+
+fn opcodes(hw: &InterpreterOptions) -> Vec<Case> {
+    let case = |name, program| Case::new(name, Topic::Opcodes, hw, program);
+    vec![
+        case("store", vec![Inst::Store(7, A), Inst::Result(A)]).gives(7),
+        case(
+            "transfer",
+            vec![Inst::Transfer("a".into(), A), Inst::Result(A)],
+        )
+        .input("a", "-4")
+        .gives(-4),
+        case(
+            "mov",
+            vec![Inst::Store(3, A), Inst::Mov(A, B), Inst::Result(B)],
+        )
+        .gives(3),
+        // two operands: the second register gets the result
+        case("add", binary(2, 5, Inst::Add(A, B))).gives(7),
+        case("sub", binary(9, 4, Inst::Sub(A, B))).gives(5),
+        case("mul", binary(6, -7, Inst::Mul(A, B))).gives(-42),
+        case("div", binary(17, 5, Inst::Div(A, B))).gives(3),
+        case("div rounds to zero", binary(-17, 5, Inst::Div(A, B))).gives(-3),
+        case("shl", binary(3, 2, Inst::Shl(A, B))).gives(12),
+        case("shr keeps the sign", binary(-16, 2, Inst::Shr(A, B))).gives(-4),
+        case("min", binary(4, -2, Inst::Min(A, B))).gives(-2),
+        case("max", binary(4, -2, Inst::Max(A, B))).gives(4),
+        // three operands: a register of its own gets the result
+        case("add3", binary(2, 5, Inst::Add3(A, B, C))).gives(7),
+        case("sub3", binary(9, 4, Inst::Sub3(A, B, C))).gives(5),
+        case("mul3", binary(6, -7, Inst::Mul3(A, B, C))).gives(-42),
+        case("div3", binary(17, 5, Inst::Div3(A, B, C))).gives(3),
+        case("shl3", binary(3, 2, Inst::Shl3(A, B, C))).gives(12),
+        case("shr3", binary(-16, 2, Inst::Shr3(A, B, C))).gives(-4),
+        case("min3", binary(4, -2, Inst::Min3(A, B, C))).gives(-2),
+        case("max3", binary(4, -2, Inst::Max3(A, B, C))).gives(4),
+        case(
+            "add3 keeps its operands",
+            vec![
+                Inst::Store(2, A),
+                Inst::Store(5, B),
+                Inst::Add3(A, B, C),
+                Inst::Sub3(A, B, C),
+                Inst::Result(B),
+            ],
+        )
+        .gives(5),
+        case("neg", unary(5, Inst::Neg(A))).gives(-5),
+        // immediates
+        case("addi", unary(5, Inst::AddI(A, 3))).gives(8),
+        case("subi", unary(5, Inst::SubI(A, 3))).gives(2),
+        case("muli", unary(5, Inst::MulI(A, -3))).gives(-15),
+        case("divi", unary(7, Inst::DivI(A, 2))).gives(3),
+        case("shli", unary(5, Inst::ShlI(A, 3))).gives(40),
+        case("shri", unary(40, Inst::ShrI(A, 3))).gives(5),
+        case(
+            "result stops the program",
+            vec![Inst::Store(1, A), Inst::Result(A), Inst::Store(2, A)],
+        )
+        .gives(1),
+    ]
+}
+
+fn errors(hw: &InterpreterOptions) -> Vec<Case> {
+    let case = |name, program| Case::new(name, Topic::Errors, hw, program);
+    let transfer = || vec![Inst::Transfer("a".into(), A), Inst::Result(A)];
+    let zero = |inst| binary(7, 0, inst);
+    vec![
+        case("empty program", vec![]),
+        case("past the end", vec![Inst::Store(1, A)]),
+        case("halt", vec![Inst::Store(1, A), Inst::Halt]),
+        case(
+            "nothing after result",
+            vec![Inst::Store(1, A), Inst::Result(A), Inst::Halt],
+        )
+        .gives(1),
+        case("empty register", vec![Inst::Result(A)]).uninit(UninitPolicy::Error),
+        case("empty register is zero", vec![Inst::Result(A)])
+            .uninit(UninitPolicy::Zero)
+            .gives(0),
+        case("unknown input", transfer()).input("b", "1"),
+        case("empty input", transfer()).input("a", ""),
+        case("input not a number", transfer()).input("a", "seven"),
+        case("div by zero", zero(Inst::Div(A, B))),
+        case("div3 by zero", zero(Inst::Div3(A, B, C))),
+        case("divi by zero", unary(7, Inst::DivI(A, 0))),
+        case("chk without overflow", unary(5, Inst::Chk(A))).gives(5),
+    ]
+}
+
+fn ram(hw: &InterpreterOptions) -> Vec<Case> {
+    let case = |name, program| Case::new(name, Topic::Ram, hw, program);
+    let last = hw.num_cachelines.saturating_sub(1);
+    let write_load = |addr| {
+        vec![
+            Inst::Store(11, A),
+            Inst::Write(A, addr),
+            Inst::Load(addr, B),
+            Inst::Result(B),
+        ]
+    };
+    let push = |n| {
+        let mut program = vec![Inst::Store(1, A)];
+        program.extend((0..n).map(|_| Inst::Push(A)));
+        program.push(Inst::Result(A));
+        program
+    };
+    vec![
+        case("write then load", write_load(0)).gives(11),
+        case(
+            "overwrite then load",
+            vec![
+                Inst::Store(11, A),
+                Inst::Write(A, 0),
+                Inst::Load(0, B),
+                Inst::Store(12, A),
+                Inst::Write(A, 0),
+                Inst::Load(0, B),
+                Inst::Result(B),
+            ],
+        )
+        .gives(12),
+        case("last cache line", write_load(last)).gives(11),
+        case("write past the end", write_load(hw.num_cachelines)),
+        case(
+            "load past the end",
+            vec![Inst::Load(hw.num_cachelines, A), Inst::Result(A)],
+        ),
+        case("ram starts zeroed", vec![Inst::Load(0, A), Inst::Result(A)]).gives(0),
+        case(
+            "stack is last in first out",
+            vec![
+                Inst::Store(1, A),
+                Inst::Store(2, B),
+                Inst::Push(A),
+                Inst::Push(B),
+                Inst::Pop(C),
+                Inst::Result(C),
+            ],
+        )
+        .gives(2),
+        case("stack fills the ram", push(hw.num_cachelines)).gives(1),
+        case("stack overflow", push(hw.num_cachelines + 1)),
+        case("pop from empty stack", vec![Inst::Pop(A), Inst::Result(A)]),
+        case(
+            "stack reuses popped values",
+            vec![
+                Inst::Store(1, A),
+                Inst::Push(A),
+                Inst::Pop(B),
+                Inst::Store(2, A),
+                Inst::Push(A),
+                Inst::Pop(B),
+                Inst::Result(B),
+            ],
+        )
+        .gives(2),
+    ]
+}
+
+fn overflow(hw: &InterpreterOptions) -> Vec<Case> {
+    let case = |name: &str, program| Case::new(name, Topic::Overflow, hw, program);
+    let then =
+        |n: i32, inst: Inst, last: Inst| vec![Inst::Store(n, A), inst, last, Inst::Result(A)];
+    let mut cases = Vec::new();
+    for size in WordSize::ALL {
+        let (max, min) = (size.max(), size.min());
+        let case = |name: &str, program| {
+            case(&format!("{name} ({} bits)", size.bits()), program).word_size(size)
+        };
+        cases.extend([
+            case("add wraps", unary(max, Inst::AddI(A, 1))).gives(min),
+            case("sub wraps", unary(min, Inst::SubI(A, 1))).gives(max),
+            case("neg of min wraps", unary(min, Inst::Neg(A))).gives(min),
+            case("div of min by -1 wraps", unary(min, Inst::DivI(A, -1))).gives(min),
+            case("mul wraps", unary(max, Inst::MulI(A, 2))).gives(-2),
+            case("mul3 wraps", binary(min, -1, Inst::Mul3(A, B, C))).gives(min),
+            case("shl wraps", unary(max, Inst::ShlI(A, 1))).gives(-2),
+            case(
+                "shl3 wraps",
+                binary(1, size.bits() as i32 - 1, Inst::Shl3(A, B, C)),
+            )
+            .gives(min),
+            case("sat up", then(max, Inst::AddI(A, 1), Inst::Sat(A))).gives(max),
+            case("sat down", then(min, Inst::SubI(A, 1), Inst::Sat(A))).gives(min),
+            case("sat", then(max, Inst::SubI(A, 1), Inst::Sat(A))).gives(max - 1),
+            case("sat after mul", then(max, Inst::MulI(A, 2), Inst::Sat(A))).gives(max),
+            case("chk", then(max, Inst::AddI(A, 1), Inst::Chk(A))),
+            case("chk after mul", then(min, Inst::MulI(A, 2), Inst::Chk(A))),
+        ]);
+    }
+    cases.extend([
+        case("store wraps", vec![Inst::Store(200, A), Inst::Result(A)])
+            .word_size(WordSize::W8)
+            .gives(-56),
+        case(
+            "input wraps",
+            vec![Inst::Transfer("a".into(), A), Inst::Result(A)],
+        )
+        .input("a", "300")
+        .word_size(WordSize::W8)
+        .gives(44),
+        case("immediate wraps", unary(1, Inst::AddI(A, 256)))
+            .word_size(WordSize::W8)
+            .gives(1),
+    ]);
+    cases
+}
+
+/// All cases of the suite for the configuration, grouped by [`Topic`].
+pub fn suite(hw: &InterpreterOptions) -> Vec<Case> {
+    let mut cases = opcodes(hw);
+    cases.extend(errors(hw));
+    cases.extend(ram(hw));
+    cases.extend(overflow(hw));
+    cases
+}
+
+/// Runs a case with the engine picked in its hardware options. The engine also has to leave the
+/// registers and the RAM like the reference does, even where the result is the same.
+fn engine(case: &Case) -> Result<i32, LpErr> {
+    let start = |engine: Engine| engine.start(&case.hw, case.program.clone(), case.inputs.clone());
+    let mut engine = start(case.hw.engine);
+    let result = engine.run_to_end();
+    if case.hw.engine == Engine::Reference {
+        return result;
+    }
+
+    let mut reference = start(Engine::Reference);
+    let _ = reference.run_to_end();
+    let registers: HashSet<_> = (case.program.iter())
+        .flat_map(|inst| {
+            let effects = StepEffects::of(inst);
+            effects.reads.into_iter().chain(effects.writes)
+        })
+        .filter_map(|place| match place {
+            Place::Reg(r) => Some(r),
+            Place::Ram(_) => None,
+        })
+        .collect();
+    let same = registers
+        .iter()
+        .all(|r| engine.register(*r) == reference.register(*r));
+    match same && engine.ram() == reference.ram() {
+        true => result,
+        false => Err(LpErr::Interpret(t!("conformance.state").into())),
+    }
+}
+
+/// Runs each case of the suite with `run`, which gets the case and returns the result of its
+/// program.
+pub fn run_with(hw: &InterpreterOptions, run: impl Fn(&Case) -> Result<i32, LpErr>) -> Report {
+    let outcomes = suite(hw)
+        .into_iter()
+        .map(|case| {
+            let actual = run(&case).map_err(|e| e.to_string());
+            Outcome { case, actual }
+        })
+        .collect();
+    Report { outcomes }
+}
+
+/// Runs the suite with the engine picked in `hw`. The reference interpreter passes every case.
+pub fn run_all(hw: &InterpreterOptions) -> Report {
+    run_with(hw, engine)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn failures(report: &Report) -> Vec<&str> {
+        report.failures().map(|o| o.case.name.as_str()).collect()
+    }
+
+    #[test]
+    fn every_engine_passes_every_case() {
+        for engine in Engine::ALL {
+            for hw in [
+                InterpreterOptions {
+                    engine,
+                    ..Default::default()
+                },
+                InterpreterOptions {
+                    num_cachelines: 3,
+                    word_size: WordSize::W8,
+                    uninit: UninitPolicy::Garbage,
+                    engine,
+                    ..Default::default()
+                },
+            ] {
+                let report = run_all(&hw);
+                assert_eq!(failures(&report), Vec::<&str>::new(), "{engine:?}");
+                assert_eq!(report.passed(), suite(&hw).len());
+            }
+        }
+
+        // names are unique, so failures can be told apart
+        let cases = suite(&InterpreterOptions::default());
+        let mut names: Vec<_> = cases.iter().map(|c| &c.name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), cases.len());
+        assert!(
+            Topic::ALL
+                .iter()
+                .all(|t| cases.iter().any(|c| c.topic == *t))
+        );
+    }
+
+    #[test]
+    fn different_semantics_fail() {
+        // an interpreter computing `b - a` instead of `a - b`
+        let swapped = |case: &Case| {
+            let program = (case.program.iter())
+                .map(|inst| match inst {
+                    Inst::Sub(a, b) => Inst::Sub(*b, *a),
+                    inst => inst.clone(),
+                })
+                .collect();
+            engine(&Case {
+                program,
+                ..case.clone()
+            })
+        };
+        let report = run_with(&InterpreterOptions::default(), swapped);
+        assert_eq!(failures(&report), ["sub"]);
+    }
+}
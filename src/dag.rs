@@ -0,0 +1,211 @@
+//! The program as a graph in which equal sub-expressions are a single node, so a value that is
+//! used in several places has several uses.
+//!
+//! The syntax tree repeats such sub-expressions, e.g. `(x * y) + (x * y) / 2` has `x * y` twice.
+//! Each one would only have to be computed once, but the code generator doesn't keep values
+//! around and computes it again at every use. The graph shows what that costs.
+
+use crate::types::Expr;
+use std::collections::HashMap;
+
+/// A distinct sub-expression of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub expr: Expr,
+    /// The operands, in order. An operand used twice, like in `x * x`, is in here twice.
+    pub children: Vec<usize>,
+    /// How often the value is used, by operations or as the result of one of the expressions.
+    /// Nodes used more than once are shared.
+    pub uses: usize,
+}
+
+impl Node {
+    /// The operator, number or variable of the node, without its operands.
+    pub fn label(&self) -> String {
+        match self.expr {
+            Expr::Num(n) => n.to_string(),
+            Expr::Var(v) => v.to_string(),
+            Expr::UnaryOp(op, _) | Expr::BinaryOp(_, op, _) => op.to_string(),
+        }
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.uses > 1
+    }
+}
+
+/// The expressions of a program as one graph, see the [module docs](self).
+///
+/// Operands always come before the operations using them in [`Dag::nodes`].
+#[derive(Debug, Clone, Default)]
+pub struct Dag {
+    nodes: Vec<Node>,
+    /// The node of each expression the graph was built from.
+    roots: Vec<usize>,
+}
+
+fn operands(expr: Expr) -> Vec<Expr> {
+    match expr {
+        Expr::Num(_) | Expr::Var(_) => vec![],
+        Expr::UnaryOp(_, e) => vec![e.get()],
+        Expr::BinaryOp(l, _, r) => vec![l.get(), r.get()],
+    }
+}
+
+impl Dag {
+    /// The graph of all the expressions, sub-expressions they have in common are shared as well.
+    ///
+    /// Works through the trees with an explicit stack, like the code generator, so deep programs
+    /// can't overflow the stack.
+    pub fn new(exprs: &[Expr]) -> Self {
+        let mut dag = Dag::default();
+        let mut index: HashMap<Expr, usize> = HashMap::new();
+        for &root in exprs {
+            // an expression is added once its operands are
+            let mut stack = vec![(root, false)];
+            while let Some((expr, expanded)) = stack.pop() {
+                if index.contains_key(&expr) {
+                    continue;
+                }
+                let operands = operands(expr);
+                if !expanded {
+                    stack.push((expr, true));
+                    stack.extend(operands.into_iter().rev().map(|e| (e, false)));
+                    continue;
+                }
+                let children: Vec<usize> = operands.iter().map(|e| index[e]).collect();
+                for &child in &children {
+                    dag.nodes[child].uses += 1;
+                }
+                index.insert(expr, dag.nodes.len());
+                dag.nodes.push(Node {
+                    expr,
+                    children,
+                    uses: 0,
+                });
+            }
+            dag.nodes[index[&root]].uses += 1;
+            dag.roots.push(index[&root]);
+        }
+        dag
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    pub fn shared(&self) -> usize {
+        self.nodes.iter().filter(|n| n.is_shared()).count()
+    }
+
+    /// How many nodes the syntax trees of the expressions have together, it can be huge when
+    /// shared nodes are nested.
+    pub fn tree_size(&self) -> u64 {
+        let mut sizes: Vec<u64> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let size = node
+                .children
+                .iter()
+                .fold(1u64, |size, &c| size.saturating_add(sizes[c]));
+            sizes.push(size);
+        }
+        self.roots
+            .iter()
+            .fold(0, |total, &r| total.saturating_add(sizes[r]))
+    }
+
+    /// How many operations each node is below the expression it's furthest down in.
+    fn depths(&self) -> Vec<usize> {
+        // operations come after their operands, so going backwards they're placed first
+        let mut depth = vec![0; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate().rev() {
+            for &child in &node.children {
+                depth[child] = depth[child].max(depth[i] + 1);
+            }
+        }
+        depth
+    }
+
+    /// How many levels deep the expressions are nested, a single number is one level.
+    pub fn depth(&self) -> usize {
+        self.depths().into_iter().max().map_or(0, |d| d + 1)
+    }
+
+    /// The nodes row by row for drawing, each one further down than all operations using it.
+    /// Within a row, nodes are in the order they're first reached from the left.
+    pub fn layers(&self) -> Vec<Vec<usize>> {
+        let depth = self.depths();
+
+        let mut order = vec![usize::MAX; self.nodes.len()];
+        let mut reached = 0;
+        for &root in &self.roots {
+            let mut stack = vec![root];
+            while let Some(i) = stack.pop() {
+                if order[i] != usize::MAX {
+                    continue;
+                }
+                order[i] = reached;
+                reached += 1;
+                stack.extend(self.nodes[i].children.iter().rev());
+            }
+        }
+
+        let rows = depth.iter().max().map_or(0, |d| d + 1);
+        let mut layers = vec![Vec::new(); rows];
+        for (i, &d) in depth.iter().enumerate() {
+            layers[d].push(i);
+        }
+        for layer in &mut layers {
+            layer.sort_by_key(|&i| order[i]);
+        }
+        layers
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::run_parser;
+    use crate::types::LpErr;
+
+    fn labels(dag: &Dag, nodes: &[usize]) -> Vec<String> {
+        nodes.iter().map(|&i| dag.nodes()[i].label()).collect()
+    }
+
+    #[test]
+    fn equal_subexpressions_are_one_node() -> Result<(), LpErr> {
+        let dag = Dag::new(&[run_parser("x * y + x * y / 2")?]);
+        // x, y, *, 2, /, +
+        assert_eq!(dag.nodes().len(), 6);
+        assert_eq!(dag.tree_size(), 9);
+        let shared: Vec<_> = (0..dag.nodes().len())
+            .filter(|&i| dag.nodes()[i].is_shared())
+            .collect();
+        assert_eq!(labels(&dag, &shared), ["*"]);
+
+        // expressions share nodes with each other too
+        let dag = Dag::new(&[run_parser("a + b")?, run_parser("(a + b) * a")?]);
+        assert_eq!(dag.nodes().len(), 4);
+        assert_eq!(dag.shared(), 2);
+        assert_eq!(labels(&dag, dag.roots()), ["+", "*"]);
+        Ok(())
+    }
+
+    #[test]
+    fn operations_are_drawn_above_their_operands() -> Result<(), LpErr> {
+        let dag = Dag::new(&[run_parser("(x + 1) * (x - 2)")?]);
+        let layers: Vec<_> = dag.layers().iter().map(|l| labels(&dag, l)).collect();
+        assert_eq!(layers, [vec!["*"], vec!["+", "-"], vec!["x", "1", "2"]]);
+
+        // `x` is used by the root and by `x + 1`, so it's below both
+        let dag = Dag::new(&[run_parser("x * (x + 1)")?]);
+        let layers: Vec<_> = dag.layers().iter().map(|l| labels(&dag, l)).collect();
+        assert_eq!(layers, [vec!["*"], vec!["+"], vec!["x", "1"]]);
+        assert_eq!(dag.depth(), 3);
+        Ok(())
+    }
+}
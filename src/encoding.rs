@@ -0,0 +1,305 @@
+//! Binary machine code of our ISA, what the processor would actually read.
+//!
+//! Every instruction starts with a one-byte opcode, followed by its operands in the order of the
+//! [`ISA`] table: registers are one
+//! byte, numbers four bytes and RAM addresses two bytes, both little-endian. Variable names are
+//! a length byte followed by the name in UTF-8.
+
+use crate::compiler::{ISA, Inst, InstDef, LpErr, Operand, OperandValue, Reg, RegNaming};
+use rust_i18n::t;
+
+/// Why a sequence of bytes isn't a valid program, each with the offset of the offending byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownOpcode(usize, u8),
+    /// The program ends in the middle of an instruction (with this opcode name).
+    Truncated(usize, &'static str),
+    InvalidRegister(usize, u8),
+    /// A variable name that isn't UTF-8.
+    InvalidName(usize),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let byte = |b: &u8| format!("0x{b:02x}");
+        match self {
+            DecodeError::UnknownOpcode(offset, b) => f.write_str(&t!(
+                "encoding.unknown_opcode",
+                offset = offset,
+                byte = byte(b)
+            )),
+            DecodeError::Truncated(offset, name) => {
+                f.write_str(&t!("encoding.truncated", offset = offset, name = name))
+            }
+            DecodeError::InvalidRegister(offset, b) => f.write_str(&t!(
+                "encoding.invalid_register",
+                offset = offset,
+                byte = byte(b)
+            )),
+            DecodeError::InvalidName(offset) => {
+                f.write_str(&t!("encoding.invalid_name", offset = offset))
+            }
+        }
+    }
+}
+
+impl From<DecodeError> for LpErr {
+    fn from(value: DecodeError) -> Self {
+        LpErr::Parse(value.to_string())
+    }
+}
+
+/// The program as machine code, see [`decode`] for the way back.
+///
+/// Fails for RAM addresses and variable names too large for their operand.
+pub fn encode(instructions: &[Inst]) -> Result<Vec<u8>, LpErr> {
+    let mut bytes = Vec::with_capacity(instructions.len() * 3);
+    for inst in instructions {
+        bytes.push(inst.def().opcode);
+        for operand in inst.operands() {
+            match operand {
+                OperandValue::Reg(r) => bytes.push(r.0),
+                OperandValue::Num(n) => bytes.extend(n.to_le_bytes()),
+                OperandValue::Var(var) => {
+                    let len = u8::try_from(var.len())
+                        .map_err(|_| LpErr::IR(t!("encoding.name", var = var).to_string()))?;
+                    bytes.push(len);
+                    bytes.extend(var.as_bytes());
+                }
+                OperandValue::Addr(addr) => {
+                    let addr = u16::try_from(addr)
+                        .map_err(|_| LpErr::IR(t!("encoding.address", addr = addr).to_string()))?;
+                    bytes.extend(addr.to_le_bytes());
+                }
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// Reads bytes of a program one after the other.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    /// The instruction being read, for errors.
+    name: &'static str,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], DecodeError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + n)
+            .ok_or(DecodeError::Truncated(self.bytes.len(), self.name))?;
+        self.offset += n;
+        Ok(bytes)
+    }
+
+    fn reg(&mut self) -> Result<Reg, DecodeError> {
+        let offset = self.offset;
+        let b = self.take(1)?[0];
+        if b >= RegNaming::Numbered.max_registers() {
+            return Err(DecodeError::InvalidRegister(offset, b));
+        }
+        Ok(Reg(b))
+    }
+
+    fn num(&mut self) -> Result<i32, DecodeError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn addr(&mut self) -> Result<usize, DecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]).into())
+    }
+
+    fn name(&mut self) -> Result<String, DecodeError> {
+        let len = self.take(1)?[0];
+        let offset = self.offset;
+        let bytes = self.take(len.into())?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidName(offset))
+    }
+}
+
+/// Reads machine code written by [`encode`] back into instructions.
+pub fn decode(bytes: &[u8]) -> Result<Vec<Inst>, DecodeError> {
+    let mut reader = Reader {
+        bytes,
+        offset: 0,
+        name: "",
+    };
+    let mut instructions = Vec::new();
+    while reader.offset < bytes.len() {
+        let offset = reader.offset;
+        let op = reader.take(1)?[0];
+        let def = ISA
+            .iter()
+            .find(|def| def.opcode == op)
+            .ok_or(DecodeError::UnknownOpcode(offset, op))?;
+        reader.name = def.name;
+        let mut values = Vec::with_capacity(def.operands.len());
+        for operand in def.operands {
+            values.push(match operand.kind {
+                Operand::Reg => OperandValue::Reg(reader.reg()?),
+                Operand::Num => OperandValue::Num(reader.num()?),
+                Operand::Var => OperandValue::Var(reader.name()?),
+                Operand::Addr => OperandValue::Addr(reader.addr()?),
+            });
+        }
+        let inst = Inst::from_operands(def, values).expect("the operands have the right kinds");
+        instructions.push(inst);
+    }
+    Ok(instructions)
+}
+
+/// How an instruction of this kind is laid out in machine code, e.g. `11 ll name… rr` for
+/// `transfer`.
+pub fn layout(def: &InstDef) -> String {
+    let opcode = format!("{:02x}", def.opcode);
+    let mut parts = vec![opcode.as_str()];
+    parts.extend(def.operands.iter().map(|operand| match operand.kind {
+        Operand::Reg => "rr",
+        Operand::Num => "nn nn nn nn",
+        Operand::Var => "ll name…",
+        Operand::Addr => "aa aa",
+    }));
+    parts.join(" ")
+}
+
+/// Rows of 8 bytes in hexadecimal, each starting with its offset.
+pub fn hex_dump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(8)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<_> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+            format!("{:04x}  {}", row * 8, hex.join(" "))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::to_text;
+    use crate::generator::Rng;
+
+    fn random_inst(rng: &mut Rng) -> Inst {
+        let mut reg = || Reg(rng.range(0, 25) as u8);
+        let (a, b) = (reg(), reg());
+        match rng.range(0, 13) {
+            0 => Inst::Add(a, b),
+            1 => Inst::Sub(a, b),
+            2 => Inst::Mul(a, b),
+            3 => Inst::Div(a, b),
+            4 => Inst::Shl(a, b),
+            5 => Inst::Shr(a, b),
+            6 => Inst::Store(rng.range(i32::MIN, i32::MAX), a),
+            7 => Inst::Transfer(rng.choose(&["x", "a1", "länge"]).to_string(), a),
+            8 => Inst::Result(a),
+            9 => Inst::Write(a, rng.range(0, 65535) as usize),
+            10 => Inst::Load(rng.range(0, 65535) as usize, a),
+            11 => Inst::Push(a),
+            12 => Inst::Halt,
+            _ => Inst::Pop(a),
+        }
+    }
+
+    fn text(instructions: &[Inst]) -> Vec<String> {
+        instructions.iter().map(to_text).collect()
+    }
+
+    #[test]
+    fn random_programs_round_trip() -> Result<(), LpErr> {
+        let mut rng = Rng::with_seed(1139);
+        for _ in 0..200 {
+            let len = rng.range(0, 30) as usize;
+            let program: Vec<_> = (0..len).map(|_| random_inst(&mut rng)).collect();
+            let bytes = encode(&program)?;
+            assert_eq!(text(&decode(&bytes)?), text(&program));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn every_bit_is_used() -> Result<(), LpErr> {
+        let mut rng = Rng::with_seed(1139);
+        for _ in 0..20 {
+            let program: Vec<_> = (0..10).map(|_| random_inst(&mut rng)).collect();
+            let bytes = encode(&program)?;
+            for bit in 0..bytes.len() * 8 {
+                let mut flipped = bytes.clone();
+                flipped[bit / 8] ^= 1 << (bit % 8);
+                if let Ok(decoded) = decode(&flipped) {
+                    assert_ne!(text(&decoded), text(&program), "bit {bit}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn corrupted_bytes_are_reported_where_they_are() -> Result<(), LpErr> {
+        let program = [
+            Inst::Transfer("x".into(), Reg(0)),
+            Inst::Store(8, Reg(1)),
+            Inst::Mul(Reg(0), Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        let bytes = encode(&program)?;
+        assert_eq!(bytes.len(), 4 + 6 + 3 + 2);
+
+        let mut unknown = bytes.clone();
+        unknown[10] = 0xff;
+        assert_eq!(
+            decode(&unknown).err(),
+            Some(DecodeError::UnknownOpcode(10, 0xff))
+        );
+
+        let mut register = bytes.clone();
+        register[12] = 200;
+        assert_eq!(
+            decode(&register).err(),
+            Some(DecodeError::InvalidRegister(12, 200))
+        );
+
+        let mut name = bytes.clone();
+        name[2] = 0xc3;
+        assert_eq!(decode(&name).err(), Some(DecodeError::InvalidName(2)));
+
+        let cut = &bytes[..bytes.len() - 1];
+        assert_eq!(
+            decode(cut).err(),
+            Some(DecodeError::Truncated(cut.len(), "result"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn too_large_operands_are_not_encoded() {
+        assert!(encode(&[Inst::Load(70_000, Reg(0))]).is_err());
+        assert!(encode(&[Inst::Transfer("x".repeat(300), Reg(0))]).is_err());
+    }
+
+    #[test]
+    fn layouts_match_the_encoding() -> Result<(), LpErr> {
+        for def in ISA {
+            let bytes = encode(&[def.example()])?;
+            let layout = layout(def);
+            assert!(layout.starts_with(&format!("{:02x}", bytes[0])), "{layout}");
+            if !def.operands.iter().any(|o| o.kind == Operand::Var) {
+                assert_eq!(layout.split(' ').count(), bytes.len(), "{layout}");
+            }
+        }
+        let transfer = ISA.iter().find(|def| def.name == "transfer").unwrap();
+        assert_eq!(layout(transfer), "11 ll name… rr");
+        Ok(())
+    }
+
+    #[test]
+    fn hex_dump_rows() {
+        let dump = hex_dump(&[0x01, 0x00, 0x01, 0x12, 0x01, 0, 0, 0, 0xab]);
+        assert_eq!(dump, ["0000  01 00 01 12 01 00 00 00", "0008  ab"]);
+    }
+}
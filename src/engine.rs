@@ -0,0 +1,254 @@
+//! Ways to run programs of our ISA. The [`Interpreter`] is the reference, the other engines run
+//! programs to the same results but model the hardware differently, which shows in their
+//! [`Stats`]. Which one runs the programs is picked with [`Engine`] in the hardware options, and
+//! [`crate::conformance`] checks that an engine computes what the reference does.
+//!
+//! Pipelining and a second ALU aren't engines of their own, the [`Schedule`](crate::interpreter::Schedule)
+//! of every engine models them.
+
+use std::collections::HashMap;
+
+use tracing::info_span;
+
+use crate::gui::{Engine, InterpreterOptions};
+use crate::interpreter::{Interpreter, InterpreterState, Place, Schedule, Stats};
+use crate::types::{Inst, LpErr, MemAddr, Reg};
+
+/// Something that runs a loaded program one instruction at a time.
+pub trait ExecutionEngine {
+    /// Executes the next instruction.
+    fn step(&mut self) -> Result<InterpreterState, LpErr>;
+
+    /// Index of the instruction executed next.
+    fn program_counter(&self) -> usize;
+
+    /// What the program did so far.
+    fn stats(&self) -> &Stats;
+
+    /// Value of a register as the program sees it, see [`Interpreter::register`].
+    fn register(&self, r: Reg) -> Option<i32>;
+
+    /// The values in RAM, the stack at the end.
+    fn ram(&self) -> &[i32];
+
+    /// Executes instructions until the program finishes or fails.
+    fn run_to_end(&mut self) -> Result<i32, LpErr> {
+        let _span = info_span!("run").entered();
+        loop {
+            if let InterpreterState::Finished(result) = self.step()? {
+                return Ok(result);
+            }
+        }
+    }
+}
+
+impl ExecutionEngine for Interpreter {
+    fn step(&mut self) -> Result<InterpreterState, LpErr> {
+        Interpreter::step(self)
+    }
+
+    fn program_counter(&self) -> usize {
+        Interpreter::program_counter(self)
+    }
+
+    fn stats(&self) -> &Stats {
+        Interpreter::stats(self)
+    }
+
+    fn register(&self, r: Reg) -> Option<i32> {
+        Interpreter::register(self, r)
+    }
+
+    fn ram(&self) -> &[i32] {
+        &self.ram
+    }
+
+    fn run_to_end(&mut self) -> Result<i32, LpErr> {
+        Interpreter::run_to_end(self)
+    }
+}
+
+/// Cycles a load takes when the value is in the cache of [`CacheSim`].
+pub const CACHE_HIT_CYCLES: u32 = 30;
+/// Values the cache of [`CacheSim`] holds.
+const CACHE_SLOTS: usize = 4;
+
+/// The reference interpreter with a small direct-mapped cache in front of the RAM: a value that
+/// was written or loaded recently is loaded from the cache in [`CACHE_HIT_CYCLES`], without
+/// waiting for the RAM port. Address `n` can only be in slot `n % CACHE_SLOTS`, so values can
+/// push each other out.
+pub struct CacheSim {
+    inner: Interpreter,
+    /// The address each slot of the cache holds and its value.
+    slots: [Option<(MemAddr, i32)>; CACHE_SLOTS],
+    /// Places the instructions in time, with the loads from the cache being quicker.
+    schedule: Schedule,
+    /// The stats of the reference with the timing of this engine.
+    stats: Stats,
+}
+
+impl CacheSim {
+    pub fn new(inner: Interpreter, hw: &InterpreterOptions) -> Self {
+        Self {
+            stats: inner.stats().clone(),
+            inner,
+            slots: [None; CACHE_SLOTS],
+            schedule: Schedule::new(hw),
+        }
+    }
+
+    /// The cached value of the address, if it's in the cache.
+    fn cached(&self, addr: MemAddr) -> Option<i32> {
+        match self.slots[addr % CACHE_SLOTS] {
+            Some((a, value)) if a == addr => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl ExecutionEngine for CacheSim {
+    fn step(&mut self) -> Result<InterpreterState, LpErr> {
+        let inst = self.inner.current_instruction().cloned();
+        // the value comes from the cache, so a stale one would show in the result
+        let hit = match &inst {
+            Some(Inst::Load(addr, r)) => self.cached(*addr).map(|value| (*r, value)),
+            Some(Inst::Pop(r)) => (self.cached(self.inner.stack_pointer())).map(|v| (*r, v)),
+            _ => None,
+        };
+        let state = self.inner.step()?;
+        if let Some((r, value)) = hit {
+            self.inner.set_register(r, value);
+        }
+        let loaded = |place: &Place| match place {
+            Place::Ram(addr) => Some(*addr),
+            Place::Reg(_) => None,
+        };
+        let effects = self.inner.last_effects();
+        let places: Vec<_> = (effects.reads.iter().chain(&effects.writes))
+            .filter_map(loaded)
+            .collect();
+        for addr in places {
+            self.slots[addr % CACHE_SLOTS] = Some((addr, self.inner.ram[addr]));
+        }
+
+        let inst = inst.expect("a step ran an instruction");
+        let slot = match hit {
+            Some(_) => self.schedule.place_cached(&inst, CACHE_HIT_CYCLES),
+            None => self.schedule.place(&inst),
+        };
+        self.stats = Stats {
+            cycles: self.stats.cycles + u64::from(slot.added),
+            stalls: self.stats.stalls + u64::from(slot.stall),
+            paired: self.stats.paired + u64::from(slot.lane == 1),
+            cache_hits: self.stats.cache_hits + u64::from(hit.is_some()),
+            ..self.inner.stats().clone()
+        };
+        Ok(state)
+    }
+
+    fn program_counter(&self) -> usize {
+        self.inner.program_counter()
+    }
+
+    fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    fn register(&self, r: Reg) -> Option<i32> {
+        self.inner.register(r)
+    }
+
+    fn ram(&self) -> &[i32] {
+        &self.inner.ram
+    }
+}
+
+impl Engine {
+    /// The engine, ready to run the program with the inputs.
+    pub fn start(
+        self,
+        hw: &InterpreterOptions,
+        instructions: Vec<Inst>,
+        inputs: HashMap<String, String>,
+    ) -> Box<dyn ExecutionEngine> {
+        let interpreter = Interpreter::with_config(hw)
+            .load_instructions(instructions)
+            .with_variables(inputs)
+            .ready();
+        match self {
+            Engine::Reference => Box::new(interpreter),
+            Engine::CacheSim => Box::new(CacheSim::new(interpreter, hw)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::RAM_STALL_CYCLES;
+
+    #[test]
+    fn cache_makes_reloads_cheaper() {
+        let program = vec![
+            Inst::Store(5, Reg(0)),
+            Inst::Write(Reg(0), 1),
+            // was just written, so it's in the cache
+            Inst::Load(1, Reg(1)),
+            // pushes address 1 out of its slot
+            Inst::Write(Reg(0), 1 + CACHE_SLOTS),
+            Inst::Load(1, Reg(2)),
+            Inst::Add(Reg(1), Reg(2)),
+            Inst::Result(Reg(2)),
+        ];
+        let run = |engine: Engine| {
+            let mut engine = engine.start(
+                &InterpreterOptions::default(),
+                program.clone(),
+                HashMap::new(),
+            );
+            let result = engine.run_to_end();
+            (
+                result.ok(),
+                engine.stats().clone(),
+                engine.program_counter(),
+            )
+        };
+        let (reference, cached) = (run(Engine::Reference), run(Engine::CacheSim));
+
+        assert_eq!(reference.0, Some(10));
+        assert_eq!(cached.0, reference.0);
+        assert_eq!(cached.2, reference.2);
+        assert_eq!(cached.1.cache_hits, 1);
+        let saved = u64::from(Inst::Load(1, Reg(1)).cycles() - CACHE_HIT_CYCLES);
+        assert_eq!(cached.1.cycles, reference.1.cycles - saved);
+        assert_eq!(cached.1.steps, reference.1.steps);
+    }
+
+    #[test]
+    fn hits_dont_wait_for_the_ram_port() {
+        let program = vec![
+            Inst::Store(5, Reg(0)),
+            Inst::Write(Reg(0), 1),
+            Inst::Load(1, Reg(1)),
+            Inst::Write(Reg(1), 2),
+            Inst::Result(Reg(1)),
+        ];
+        let hw = InterpreterOptions {
+            ram_ports: 1,
+            ..Default::default()
+        };
+        let run = |engine: Engine| {
+            let mut engine = engine.start(&hw, program.clone(), HashMap::new());
+            assert_eq!(engine.run_to_end().ok(), Some(5));
+            assert_eq!(engine.ram()[..3], [0, 5, 5]);
+            engine.stats().clone()
+        };
+        let (reference, cached) = (run(Engine::Reference), run(Engine::CacheSim));
+
+        // the load waits for the write before it, the second write for the load
+        assert_eq!(reference.stalls, 2 * u64::from(RAM_STALL_CYCLES));
+        assert_eq!(cached.stalls, 0);
+        let saved = u64::from(Inst::Load(1, Reg(1)).cycles() - CACHE_HIT_CYCLES);
+        assert_eq!(cached.cycles, reference.cycles - reference.stalls - saved);
+    }
+}
@@ -0,0 +1,34 @@
+use crate::gui::WordSize;
+use crate::types::{Expr, LpErr, Operator};
+use rust_i18n::t;
+use std::collections::HashMap;
+
+/// Reference evaluator working directly on the AST, with the same semantics as the interpreter
+/// with words of the given size. Used to check that transformations don't change what a program
+/// computes.
+pub fn evaluate(expr: &Expr, vars: &HashMap<String, i32>, word: WordSize) -> Result<i32, LpErr> {
+    match expr {
+        Expr::Num(n) => Ok(word.wrap(*n)),
+        Expr::Var(v) => vars
+            .get(v.as_str())
+            .map(|&n| word.wrap(n))
+            .ok_or_else(|| LpErr::Interpret(t!("compiler.error.unknown_var", v = v).into())),
+        Expr::UnaryOp(Operator::Sub, e) => {
+            Ok(word.wrap(evaluate(&e.get(), vars, word)?.wrapping_neg()))
+        }
+        Expr::UnaryOp(op, _) => Err(LpErr::Interpret(
+            t!("compiler.error.invalid_unary", op = op).into(),
+        )),
+        Expr::BinaryOp(l, op, r) => {
+            let l = evaluate(&l.get(), vars, word)?;
+            let r = evaluate(&r.get(), vars, word)?;
+            op.apply(l, r, word).ok_or_else(|| {
+                let key = match r {
+                    0 => "compiler.error.divzero",
+                    _ => "compiler.error.overflow",
+                };
+                LpErr::Interpret(t!(key).into())
+            })
+        }
+    }
+}
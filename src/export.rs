@@ -0,0 +1,235 @@
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+use crate::assembler;
+use crate::backend::{BackendOutput, Target};
+use crate::compiler::{CompileOptions, Compiler, Expr, Inst, Ir, OptLevel, Stage};
+use crate::gui::InterpreterOptions;
+use crate::parser::{self, Diagnostic};
+use crate::passes::RewriteRule;
+use crate::source_map::Span;
+use crate::symbols::ConstTable;
+use serde::Serialize;
+
+/// File the GUI saves the document to, in the data directory.
+pub const FILE_NAME: &str = "program.json";
+
+/// One document describing a whole pipeline run, for grading scripts and other external tools.
+#[derive(Serialize)]
+pub struct Artifact {
+    pub source: String,
+    pub options: CompileOptions,
+    pub hw: InterpreterOptions,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The compiled program, if there were no errors.
+    pub program: Option<Program>,
+    /// Why the program doesn't compile.
+    pub error: Option<String>,
+    pub riscv: Option<Vec<String>>,
+    pub wat: Option<Vec<String>>,
+}
+
+/// What the compiler made of the source.
+#[derive(Serialize)]
+pub struct Program {
+    /// The program after all passes on the syntax tree.
+    pub ast: Expr,
+    /// Input variables in the order they first occur, with how often they occur.
+    pub variables: Vec<(String, usize)>,
+    pub constants: ConstTable,
+    pub stages: Vec<Stage>,
+    pub instructions: Vec<Inst>,
+    /// The instructions as the GUI shows them.
+    pub listing: Vec<String>,
+    /// The instructions as assembly text, which can be loaded into the GUI again.
+    pub assembly: Vec<String>,
+    /// For each instruction, the byte ranges of the source it was generated from.
+    pub debug_info: Vec<Vec<Span>>,
+    pub cost: Cost,
+}
+
+/// What running the program costs, without running it.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Cost {
+    pub instructions: usize,
+    /// Simulated cycles, see [`Inst::cycles`]. Every instruction runs once, so this is exact.
+    pub cycles: u64,
+    /// Loads from and writes to RAM.
+    pub ram_accesses: usize,
+    /// Registers written to RAM because there weren't enough of them.
+    pub spills: usize,
+}
+
+impl Cost {
+    pub fn of(instructions: &[Inst]) -> Self {
+        Cost {
+            instructions: instructions.len(),
+            cycles: instructions.iter().map(|i| u64::from(i.cycles())).sum(),
+            ram_accesses: instructions
+                .iter()
+                .filter(|i| matches!(i, Inst::Load(..) | Inst::Write(..)))
+                .count(),
+            spills: instructions
+                .iter()
+                .filter(|i| matches!(i, Inst::Write(..)))
+                .count(),
+        }
+    }
+}
+
+impl From<Ir> for Program {
+    fn from(ir: Ir) -> Self {
+        Program {
+            ast: ir.ast,
+            variables: ir.variables,
+            constants: ir.constants,
+            stages: ir.stages,
+            listing: ir.instructions.iter().map(Inst::to_string).collect(),
+            assembly: ir.instructions.iter().map(assembler::to_text).collect(),
+            cost: Cost::of(&ir.instructions),
+            instructions: ir.instructions,
+            debug_info: ir.debug_info,
+        }
+    }
+}
+
+impl Artifact {
+    /// Runs the whole pipeline on `source`, including the real-world targets.
+    pub fn new(
+        source: &str,
+        options: CompileOptions,
+        hw: InterpreterOptions,
+        rules: Vec<RewriteRule>,
+    ) -> Self {
+        let target = |target| {
+            let options = options.into_builder().target(target).hardware(hw).build();
+            match Compiler::with(options.ok()?)
+                .with_interpreter(hw)
+                .emit(source)
+            {
+                Ok(BackendOutput::Text(lines)) => Some(lines),
+                _ => None,
+            }
+        };
+        let (program, error) = match Compiler::with(options)
+            .with_interpreter(hw)
+            .with_rules(rules)
+            .compile(source)
+        {
+            Ok(ir) => (Some(ir.into()), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Artifact {
+            source: source.into(),
+            options,
+            hw,
+            diagnostics: parser::diagnose(source, options.max_nesting, options.max_tokens),
+            program,
+            error,
+            riscv: target(Target::RiscV),
+            wat: target(Target::Wat),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("the artifact only has string keys")
+    }
+}
+
+/// Handles `--emit json [-O0|-O1|-O2] [FILE]`, compiling FILE or standard input and printing
+/// the [`Artifact`]. Without `--emit` there's nothing to do here and the GUI starts.
+pub fn cli(args: &[String]) -> Option<ExitCode> {
+    let [flag, format, rest @ ..] = args else {
+        return None;
+    };
+    if flag != "--emit" {
+        return None;
+    }
+    if format != "json" {
+        eprintln!("unknown format `{format}`, only `json` can be emitted");
+        return Some(ExitCode::FAILURE);
+    }
+
+    let mut options = CompileOptions::default();
+    let mut file = None;
+    for arg in rest {
+        match OptLevel::ALL.iter().find(|l| l.to_string() == *arg) {
+            Some(level) => options = options.with_level(*level),
+            None => file = Some(arg),
+        }
+    }
+    let source = match file {
+        Some(file) => std::fs::read_to_string(file),
+        None => {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source).map(|_| source)
+        }
+    };
+    let source = match source {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("can't read the program: {e}");
+            return Some(ExitCode::FAILURE);
+        }
+    };
+
+    let artifact = Artifact::new(&source, options, InterpreterOptions::default(), Vec::new());
+    // e.g. piping into `head` closes stdout early, that's fine
+    let _ = writeln!(std::io::stdout(), "{}", artifact.to_json());
+    Some(if artifact.error.is_none() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::Reg;
+
+    #[test]
+    fn artifact_describes_the_pipeline() {
+        let options = CompileOptions::default().with_level(OptLevel::O2);
+        let artifact = Artifact::new("a * 4 + 1", options, Default::default(), Vec::new());
+        let json: serde_json::Value = serde_json::from_str(&artifact.to_json()).unwrap();
+
+        let program = &json["program"];
+        assert_eq!(program["variables"][0][0], "a");
+        assert_eq!(json["options"]["do_shift_replacement"], true);
+        let instructions = program["instructions"].as_array().unwrap();
+        assert_eq!(
+            instructions.len(),
+            program["listing"].as_array().unwrap().len()
+        );
+        assert_eq!(program["cost"]["instructions"], instructions.len());
+        assert!(json["riscv"].is_array());
+        assert!(json["error"].is_null());
+    }
+
+    #[test]
+    fn failed_compilation_is_exported_with_its_error() {
+        let artifact = Artifact::new("1 +", Default::default(), Default::default(), Vec::new());
+        assert!(artifact.program.is_none());
+        assert!(artifact.error.is_some());
+        assert!(!artifact.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn cost_counts_ram_accesses() {
+        let code = [
+            Inst::Store(1, Reg(0)),
+            Inst::Write(Reg(0), 0),
+            Inst::Load(0, Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        let cost = Cost::of(&code);
+        assert_eq!(cost.instructions, 4);
+        assert_eq!(cost.ram_accesses, 2);
+        assert_eq!(cost.spills, 1);
+        assert_eq!(
+            cost.cycles,
+            code.iter().map(|i| u64::from(i.cycles())).sum::<u64>()
+        );
+    }
+}
@@ -0,0 +1,44 @@
+//! Where the app keeps the files it writes and reads by itself, like pictures of runs, exports
+//! and the leaderboard. They go into the user's data directory instead of wherever the app was
+//! started from, e.g. `~/.local/share/lndw-compiler` on Linux. `LNDW_DATA_DIR` overrides it, to
+//! keep the files of an event together.
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const APP_DIR: &str = "lndw-compiler";
+
+/// The directory of the app's files. It may not exist yet, see [`data_file`].
+pub fn data_dir() -> PathBuf {
+    let var = |name| std::env::var_os(name).map(PathBuf::from);
+    if let Some(dir) = var("LNDW_DATA_DIR") {
+        return dir;
+    }
+    let base = if cfg!(windows) {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        std::env::home_dir().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        (var("XDG_DATA_HOME").filter(|dir| dir.is_absolute()))
+            .or_else(|| std::env::home_dir().map(|home| home.join(".local").join("share")))
+    };
+    // without a home, the working directory is all there is
+    base.map_or_else(PathBuf::new, |base| base.join(APP_DIR))
+}
+
+/// The path of a file in the data directory, which is created if it doesn't exist yet. Writing
+/// the file reports the error if that failed.
+pub fn data_file(name: &str) -> PathBuf {
+    let dir = data_dir();
+    let _ = std::fs::create_dir_all(&dir);
+    dir.join(name)
+}
+
+/// Like [`data_file`], with the time in the name so earlier files aren't replaced, e.g.
+/// `run-1760000000.gif`.
+pub fn timestamped(name: &str, extension: &str) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    data_file(&format!("{name}-{}.{extension}", now.as_secs()))
+}
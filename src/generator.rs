@@ -0,0 +1,214 @@
+use crate::intern::Symbol;
+use crate::types::{Expr, ExprId, Operator};
+
+/// Small, deterministic pseudo random number generator (xorshift64*).
+///
+/// Good enough to generate programs, and avoids pulling in a dependency.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn with_seed(seed: u64) -> Self {
+        // the state must never be zero
+        Self(seed.max(1))
+    }
+
+    /// Seeded from the current time.
+    pub fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(1, |d| d.as_nanos() as u64);
+        Self::with_seed(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A random number in `[low, high]`.
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        let span = (high as i64 - low as i64 + 1) as u64;
+        (low as i64 + (self.next_u64() % span) as i64) as i32
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.next_u64() as usize % items.len()]
+    }
+}
+
+/// Generates a random expression with up to `depth` levels of binary operations over small
+/// numbers, using only the given operators.
+pub fn random_expr(rng: &mut Rng, depth: u32, ops: &[Operator]) -> Expr {
+    if depth == 0 || rng.range(0, 3) == 0 {
+        return Expr::Num(rng.range(1, 9));
+    }
+
+    Expr::BinaryOp(
+        ExprId::new(random_expr(rng, depth - 1, ops)),
+        *rng.choose(ops),
+        ExprId::new(random_expr(rng, depth - 1, ops)),
+    )
+}
+
+/// What [`random_program`] generates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenOptions {
+    /// How many operations the program has.
+    pub operations: usize,
+    /// The operators to pick from, each with how often it's picked compared to the others.
+    pub operators: Vec<(Operator, u32)>,
+    /// Input variables the program may use.
+    pub variables: Vec<String>,
+    /// Percentage of the operands that are variables instead of numbers.
+    pub variable_chance: i32,
+    /// Numbers are between 1 and this.
+    pub max_number: i32,
+}
+
+impl Default for GenOptions {
+    fn default() -> Self {
+        Self {
+            operations: 5,
+            operators: vec![
+                (Operator::Add, 4),
+                (Operator::Sub, 3),
+                (Operator::Mul, 3),
+                (Operator::Div, 1),
+                (Operator::Shl, 1),
+            ],
+            variables: vec!["a".into(), "b".into(), "x".into()],
+            variable_chance: 40,
+            max_number: 9,
+        }
+    }
+}
+
+impl GenOptions {
+    fn operator(&self, rng: &mut Rng) -> Operator {
+        let total: u32 = self.operators.iter().map(|(_, weight)| weight).sum();
+        let mut pick = rng.next_u64() % u64::from(total.max(1));
+        for &(op, weight) in &self.operators {
+            match pick.checked_sub(u64::from(weight)) {
+                Some(rest) => pick = rest,
+                None => return op,
+            }
+        }
+        Operator::Add
+    }
+
+    fn operand(&self, rng: &mut Rng) -> Expr {
+        if !self.variables.is_empty() && rng.range(1, 100) <= self.variable_chance {
+            let name: &String = rng.choose(&self.variables);
+            Expr::Var(Symbol::new(name))
+        } else {
+            Expr::Num(rng.range(1, self.max_number.max(1)))
+        }
+    }
+}
+
+/// The right side of these has to be a number, so programs can't divide by zero or shift by more
+/// bits than a word has.
+fn needs_number(op: Operator) -> Option<(i32, i32)> {
+    match op {
+        Operator::Div | Operator::CheckedDiv => Some((1, 9)),
+        Operator::Shl | Operator::Shr => Some((1, 4)),
+        _ => None,
+    }
+}
+
+/// A random program with exactly `options.operations` operations, the same for the same state
+/// of `rng`. Divisions are only by numbers, but programs can still overflow.
+pub fn random_program(rng: &mut Rng, options: &GenOptions) -> Expr {
+    if options.operations == 0 {
+        return options.operand(rng);
+    }
+    let op = options.operator(rng);
+    let rest = options.operations - 1;
+    let sized = |operations| GenOptions {
+        operations,
+        ..options.clone()
+    };
+    if let Some((low, high)) = needs_number(op) {
+        let left = random_program(rng, &sized(rest));
+        return Expr::BinaryOp(
+            ExprId::new(left),
+            op,
+            ExprId::new(Expr::Num(rng.range(low, high))),
+        );
+    }
+    let left = rng.range(0, rest as i32) as usize;
+    let l = random_program(rng, &sized(left));
+    let r = random_program(rng, &sized(rest - left));
+    Expr::BinaryOp(ExprId::new(l), op, ExprId::new(r))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::run_parser;
+    use crate::types::LpErr;
+
+    #[test]
+    fn generated_exprs_roundtrip_through_parser() -> Result<(), LpErr> {
+        let mut rng = Rng::with_seed(7);
+        for _ in 0..50 {
+            let expr = random_expr(&mut rng, 3, &[Operator::Add, Operator::Mul]);
+            assert_eq!(run_parser(&expr.to_string())?, expr);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn pretty_exprs_roundtrip_through_parser() -> Result<(), LpErr> {
+        let mut rng = Rng::with_seed(11);
+        let ops = [
+            Operator::Add,
+            Operator::Sub,
+            Operator::Mul,
+            Operator::Div,
+            Operator::Shl,
+            Operator::Shr,
+        ];
+        for _ in 0..200 {
+            let expr = random_expr(&mut rng, 4, &ops);
+            assert_eq!(run_parser(&expr.pretty())?, expr);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn programs_have_the_requested_shape() -> Result<(), LpErr> {
+        let options = GenOptions {
+            operations: 7,
+            operators: vec![(Operator::Mul, 1), (Operator::Div, 1), (Operator::Min, 0)],
+            ..Default::default()
+        };
+        for seed in 0..50 {
+            let program = random_program(&mut Rng::with_seed(seed), &options);
+            assert_eq!(program, random_program(&mut Rng::with_seed(seed), &options));
+            assert_eq!(run_parser(&program.pretty())?, program);
+
+            let mut operations = 0;
+            let mut todo = vec![program];
+            while let Some(expr) = todo.pop() {
+                match expr {
+                    Expr::BinaryOp(l, op, r) => {
+                        operations += 1;
+                        assert!(matches!(op, Operator::Mul | Operator::Div));
+                        if op == Operator::Div {
+                            assert!(matches!(r.get(), Expr::Num(1..=9)));
+                        }
+                        todo.extend([l.get(), r.get()]);
+                    }
+                    Expr::Var(v) => assert!(options.variables.iter().any(|name| v == *name)),
+                    Expr::Num(n) => assert!((1..=options.max_number).contains(&n)),
+                    Expr::UnaryOp(..) => panic!("{program}"),
+                }
+            }
+            assert_eq!(operations, options.operations);
+        }
+        Ok(())
+    }
+}
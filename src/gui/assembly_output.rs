@@ -1,17 +1,79 @@
 use crate::{
-    compiler::{CompileOptions, Compiler, Inst, u8tochar},
-    gui::InterpreterOptions,
-    interpreter::Interpreter,
+    assembler, capture,
+    compile_log::{self, CompileLog},
+    compiler::{CompileOptions, Inst, Ir, LpErr, Reg, Stage},
+    encoding::{self, DecodeError},
+    export::Cost,
+    files,
+    generator::Rng,
+    gui::{InterpreterOptions, Theme, compile_options_ui},
+    interpreter::{Interpreter, LoggedWrite, Place, Schedule, Slot, Snapshot, Stats},
+    parser::Statement,
+    passes::TransformationLog,
+    sound::{self, Sound},
+    source_map::Span,
+    types::Expr,
 };
 use eframe::egui::Id;
 use eframe::egui::{self, Widget};
 use rust_i18n::t;
 use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// How numbers in the register and RAM tables are displayed.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum NumberBase {
+    #[default]
+    Decimal,
+    Hexadecimal,
+    /// Negative numbers are shown in two's complement.
+    Binary,
+}
+
+impl NumberBase {
+    const ALL: [NumberBase; 3] = [
+        NumberBase::Decimal,
+        NumberBase::Hexadecimal,
+        NumberBase::Binary,
+    ];
+
+    /// Formats a number of a machine word with `bits` bits.
+    pub fn format(&self, n: i32, bits: u32) -> String {
+        // only keep the bits of the machine word, this makes negative numbers two's complement
+        let word = n as u32 & (u32::MAX >> (32 - bits));
+        match self {
+            NumberBase::Decimal => n.to_string(),
+            NumberBase::Hexadecimal => format!("0x{word:x}"),
+            NumberBase::Binary => format!("0b{word:b}"),
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            NumberBase::Decimal => "output.base.dec",
+            NumberBase::Hexadecimal => "output.base.hex",
+            NumberBase::Binary => "output.base.bin",
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct AssemblyOutput {
     heading: String,
     asm: Option<Vec<(Inst, f32)>>,
+    stages: Vec<Stage>,
+    /// Why the passes changed the program the way they did.
+    explanations: TransformationLog,
+    /// What the compiler reported while compiling the program.
+    log: CompileLog,
+    /// What the interpreter reported during the last run.
+    run_log: CompileLog,
+    /// What the interpreter reported while animating the steps of the last run.
+    step_log: CompileLog,
+    /// The stats of the last run by the engine picked in the hardware options, the animation
+    /// always uses the reference.
+    run_stats: Option<Stats>,
     error: Option<String>,
     program_result: Option<i32>,
     interpreter: Option<Interpreter>,
@@ -19,7 +81,92 @@ pub struct AssemblyOutput {
     running: bool,
     stepwise: bool,
     step_triggered: bool,
-    total_time: f32,
+    /// Number base used in the register and RAM tables.
+    base: NumberBase,
+    /// For each instruction, the parts of the source it came from.
+    debug_info: Vec<Vec<Span>>,
+    /// The statements of the source, the listing can be grouped by them.
+    statements: Vec<Statement>,
+    /// Input variables used as divisors.
+    divisors: HashSet<String>,
+    /// The compiled program written as source code, after all optimizations.
+    source: Option<String>,
+    /// Whether any optimizations were applied in the last compilation.
+    optimized: bool,
+    /// Source spans of the instruction under the mouse.
+    hovered_source: Vec<Span>,
+    /// Offset in the source code under the mouse, its instructions are highlighted.
+    source_highlight: Option<usize>,
+    /// Set when the user wants to run the program again, with the current inputs.
+    pub rerun_requested: bool,
+    /// Extra outputs added to compare optimizations have a number and their own options.
+    comparison: Option<(usize, CompileOptions)>,
+    /// Instructions that were shown in the last frame, only those are laid out.
+    visible_rows: Range<usize>,
+    /// The unoptimized program, to show how much the optimizations helped.
+    pub baseline: Option<Baseline>,
+    /// The bit of the machine code flipped to show what happens, and what it decodes to.
+    corrupted: Option<(usize, Result<Vec<Inst>, DecodeError>)>,
+    /// Registers and RAM addresses shown in the write log, all of them if empty.
+    log_filter: String,
+    /// The file the program was loaded from, if it didn't come from the compiler.
+    loaded_from: Option<String>,
+    /// Marked states of this program, in the order they happen.
+    bookmarks: Vec<Bookmark>,
+    /// The bookmark shortcuts pressed this frame, see [`BookmarkKeys::consume`].
+    bookmark_keys: BookmarkKeys,
+    /// Values of input variables only this output uses instead of the shared ones, if enabled.
+    own_inputs: Option<HashMap<String, String>>,
+    /// The shared inputs of the last run, to start from when choosing own ones.
+    shared_inputs: HashMap<String, String>,
+    /// The listing as text while it's being edited, runs use it once it's valid.
+    editing: Option<String>,
+    /// Whether each instruction is shown as the micro-operations the processor does for it.
+    micro_ops: bool,
+    /// Whether the listing is grouped by the statement of the source the instructions came from.
+    grouped: bool,
+    /// Words to find in the listing, see [`search_matches`].
+    search: String,
+    /// The instruction of the match that was navigated to last.
+    search_current: Option<usize>,
+    /// Set when the listing should scroll to the current match.
+    search_jump: bool,
+    /// The program after the passes on the syntax tree.
+    ast: Option<Expr>,
+    /// The run being recorded as an animation, if it is.
+    recording: Option<Recording>,
+    /// Set when a screenshot should be taken once the window is drawn.
+    screenshot_requested: bool,
+    /// Where the last picture was saved, or why it couldn't be.
+    capture_status: Option<String>,
+}
+
+/// A run being recorded, it's saved into the data directory once it's done. Frames are drawn
+/// offscreen with [`AssemblyOutput::picture_ui`] and compressed as they come.
+struct Recording {
+    gif: capture::GifEncoder,
+    offscreen: capture::Offscreen,
+    /// When the last frame was drawn.
+    last_frame: Option<Instant>,
+}
+
+/// How often a frame of a recording is taken.
+const FRAME_TIME: Duration = Duration::from_millis(100);
+/// How long the result stays on screen at the end of a recording, in hundredths of a second.
+const LAST_FRAME_DELAY: u16 = 300;
+/// Enough for a minute of animation, longer runs end early.
+const MAX_FRAMES: usize = 600;
+/// Size of screenshots and recordings in pixels, the same however the window is sized.
+const PICTURE_SIZE: [usize; 2] = [640, 480];
+/// How many instructions around the current one pictures show.
+const PICTURE_LINES: usize = 12;
+
+/// A saved state of the machine during the animation, to come back to it.
+struct Bookmark {
+    label: String,
+    snapshot: Snapshot,
+    /// The progress of the animation of each instruction then.
+    progress: Vec<f32>,
 }
 
 impl AssemblyOutput {
@@ -31,6 +178,20 @@ impl AssemblyOutput {
         }
     }
 
+    /// An extra output with its own compile options, to compare it to the others.
+    pub fn comparison(number: usize) -> Self {
+        Self {
+            heading: format!("output.compare.{number}"),
+            comparison: Some((number, CompileOptions::default())),
+            ..Default::default()
+        }
+    }
+
+    /// The compile options of a comparison output.
+    pub fn own_options(&self) -> Option<CompileOptions> {
+        self.comparison.map(|(_, opts)| opts)
+    }
+
     #[allow(dead_code)]
     pub fn new(heading: String, asm: Vec<Inst>) -> Self {
         Self {
@@ -43,14 +204,31 @@ impl AssemblyOutput {
     /// Clear any assembly and error message.
     pub fn clear(&mut self) {
         self.asm = None;
+        self.stages.clear();
+        self.explanations = TransformationLog::default();
+        self.log = CompileLog::default();
+        self.run_log = CompileLog::default();
+        self.step_log = CompileLog::default();
+        self.run_stats = None;
+        self.debug_info.clear();
+        self.divisors.clear();
+        self.source = None;
+        self.ast = None;
+        self.optimized = false;
+        self.hovered_source.clear();
         self.error = None;
         self.program_result = None;
         self.running = false;
-        self.total_time = 0.0;
         self.hw = None;
         self.interpreter = None;
         self.stepwise = false;
         self.step_triggered = false;
+        self.rerun_requested = false;
+        self.corrupted = None;
+        self.bookmarks.clear();
+        self.loaded_from = None;
+        self.editing = None;
+        self.recording = None;
     }
 
     pub fn instructions(&self) -> Vec<Inst> {
@@ -59,67 +237,323 @@ impl AssemblyOutput {
             .map_or(vec![], |v| v.iter().map(|(inst, _)| inst.clone()).collect())
     }
 
+    /// What the last run computed, if it didn't fail.
+    pub fn result(&self) -> Option<i32> {
+        self.program_result
+    }
+
+    /// Cycles the program takes according to the cost model, stalls on its hardware included,
+    /// without running it.
+    pub fn estimated_cycles(&self) -> Option<u64> {
+        let mut schedule = Schedule::new(&self.hw.unwrap_or_default());
+        for (inst, _) in self.asm.as_ref()? {
+            schedule.place(inst);
+        }
+        Some(schedule.end().into())
+    }
+
+    /// The costs other outputs are compared against, see [`Baseline`].
+    pub fn baseline(&self) -> Option<Baseline> {
+        Some(Baseline {
+            cycles: self.estimated_cycles()?,
+            ram_accesses: Cost::of(&self.instructions()).ram_accesses,
+        })
+    }
+
+    /// Whether the execution is still being animated.
     pub fn is_running(&self) -> bool {
         self.running
+            && self
+                .asm
+                .as_ref()
+                .is_some_and(|asm| asm.iter().any(|(_, p)| *p < 1.0))
+    }
+
+    /// The parts of the source the instruction under the mouse was generated from.
+    pub fn hovered_source(&self) -> &[Span] {
+        &self.hovered_source
+    }
+
+    /// Handles the bookmark shortcuts pressed this frame when the window is drawn next.
+    pub fn press_bookmark_keys(&mut self, keys: BookmarkKeys) {
+        self.bookmark_keys = keys;
+    }
+
+    /// Highlights the instructions generated from the source code at this (byte) offset.
+    pub fn highlight_source(&mut self, offset: Option<usize>) {
+        self.source_highlight = offset;
+    }
+
+    /// The program of the last compilation written as source code, after all optimizations.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Input variables of the last compilation that are used as divisors.
+    pub fn divisors(&self) -> &HashSet<String> {
+        &self.divisors
+    }
+
+    /// For each instruction, the parts of the source it came from.
+    pub fn debug_info(&self) -> &[Vec<Span>] {
+        &self.debug_info
+    }
+
+    /// The program after each step of the last compilation.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    pub fn ast(&self) -> Option<Expr> {
+        self.ast
+    }
+
+    pub fn explanations(&self) -> &TransformationLog {
+        &self.explanations
+    }
+
+    pub fn log(&self) -> &CompileLog {
+        &self.log
     }
 
-    pub fn compile(
+    pub fn run_log(&self) -> &CompileLog {
+        &self.run_log
+    }
+
+    pub fn step_log(&self) -> &CompileLog {
+        &self.step_log
+    }
+
+    /// Shows the result of compiling for this output, returning the input variables with their
+    /// number of occurrences.
+    ///
+    /// The compilation itself happens elsewhere, so it doesn't block the UI.
+    pub fn load(
         &mut self,
-        input: &str,
-        opts: CompileOptions,
+        result: Result<Ir, LpErr>,
         hw: InterpreterOptions,
-    ) -> Result<HashSet<String>, ()> {
+        optimized: bool,
+    ) -> Result<Vec<(String, usize)>, ()> {
         self.clear();
-        let r = Compiler::with(opts).with_interpreter(hw).compile(input);
+        self.optimized = optimized;
         self.hw = Some(hw);
 
-        r.map(|(asm, vars)| {
-            self.asm = Some(asm.iter().map(|i| (i.clone(), 0.0)).collect());
-            vars
-        })
-        .map_err(|e| {
-            self.error = Some(format!("Compile error: {e}"));
-        })
+        result
+            .map(|ir| {
+                self.asm = Some(ir.instructions.iter().map(|i| (i.clone(), 0.0)).collect());
+                self.stages = ir.stages;
+                self.explanations = ir.explanations;
+                self.log = ir.log;
+                self.debug_info = ir.debug_info;
+                self.statements = ir.statements;
+                self.divisors = ir.divisors;
+                self.source = Some(ir.ast.pretty());
+                self.ast = Some(ir.ast);
+                ir.variables
+            })
+            .map_err(|e| {
+                self.error = Some(format!("Compile error: {e}"));
+            })
+    }
+
+    /// Shows a program that didn't come from the compiler, e.g. a hand-written one in `file`,
+    /// returning the input variables it reads.
+    pub fn load_instructions(
+        &mut self,
+        instructions: Vec<Inst>,
+        hw: InterpreterOptions,
+        file: &str,
+    ) -> Vec<(String, usize)> {
+        self.clear();
+        self.hw = Some(hw);
+        self.loaded_from = Some(file.into());
+        let variables = assembler::variables(&instructions);
+        self.asm = Some(instructions.into_iter().map(|i| (i, 0.0)).collect());
+        variables
+    }
+
+    /// The file the program was loaded from, see [`Self::load_instructions`].
+    pub fn loaded_from(&self) -> Option<&str> {
+        self.loaded_from.as_deref()
+    }
+
+    /// Shows why a program couldn't be loaded.
+    pub fn set_error(&mut self, error: String) {
+        self.clear();
+        self.error = Some(error);
     }
 
     pub fn run(&mut self, vars: &HashMap<String, String>, stepwise: bool) {
+        if self.editing.is_some() && !self.apply_edit() {
+            // the errors are shown in the editor
+            return;
+        }
+        self.shared_inputs = vars.clone();
+        let vars = &with_own_inputs(vars, self.own_inputs.as_ref());
+        self.rerun_requested = false;
         self.program_result = None;
         self.stepwise = stepwise;
         // fix for the step being falsely triggered
         self.step_triggered = false;
 
-        if self.asm.is_none() {
+        let Some(asm) = self.asm.as_mut() else {
             return;
-        }
+        };
+        // start the animation over, and forget errors from previous inputs
+        asm.iter_mut().for_each(|(_, p)| *p = 0.0);
+        self.error = None;
 
         let hw = self.hw.unwrap();
 
-        match Interpreter::with_config(&hw)
-            .load_instructions(self.instructions())
-            .with_variables(vars.to_owned())
-            .ready()
-            .run_to_end()
-        {
+        let instructions = self.instructions();
+        let count = instructions.len();
+        let ((result, stats, pc), run_log) = compile_log::record(|| {
+            let mut engine = hw.engine.start(&hw, instructions, vars.to_owned());
+            let result = engine.run_to_end();
+            (result, engine.stats().clone(), engine.program_counter())
+        });
+        self.run_log = run_log;
+        self.step_log = CompileLog::default();
+        self.run_stats = Some(stats);
+        match result {
             Ok(r) => {
                 self.program_result = Some(r);
                 self.running = true;
-                // don't overwrite the interpreter
-                if self.interpreter.is_none() {
-                    self.interpreter = Some(
-                        Interpreter::with_config(&hw)
-                            .load_instructions(self.instructions())
-                            .with_variables(vars.to_owned())
-                            .with_tracing()
-                            .ready(),
-                    );
+                match &mut self.interpreter {
+                    Some(interpreter) => {
+                        interpreter.set_variables(vars.to_owned());
+                        interpreter.reset();
+                    }
+                    None => {
+                        self.interpreter = Some(
+                            Interpreter::with_config(&hw)
+                                .load_instructions(self.instructions())
+                                .with_variables(vars.to_owned())
+                                .with_tracing()
+                                .ready(),
+                        )
+                    }
                 }
             }
-            Err(e) => self.error = Some(format!("Runtime error: {e}")),
+            Err(e) if pc < count => {
+                self.error = Some(t!("output.runtime_error.at", n = pc + 1, e = e).into())
+            }
+            Err(e) => self.error = Some(t!("output.runtime_error", e = e).into()),
         }
     }
 
+    /// Replaces the program with the edited one if that's valid, returning whether it was.
+    fn apply_edit(&mut self) -> bool {
+        let (Some(text), Some(hw)) = (&self.editing, &self.hw) else {
+            return false;
+        };
+        let Ok(instructions) = assembler::parse_for(text, hw) else {
+            return false;
+        };
+        let lines = |code: &[Inst]| code.iter().map(assembler::to_text).collect::<Vec<_>>();
+        if lines(&instructions) != lines(&self.instructions()) {
+            self.asm = Some(instructions.into_iter().map(|i| (i, 0.0)).collect());
+            // the instructions no longer match the source or the saved states
+            self.debug_info.clear();
+            self.source = None;
+            self.bookmarks.clear();
+            self.interpreter = None;
+            self.running = false;
+        }
+        true
+    }
+
+    /// Lets this output run with its own values for some variables, e.g. to show a division by
+    /// zero here while the other outputs get the shared inputs.
+    fn own_inputs_ui(&mut self, ui: &mut egui::Ui) {
+        let variables = assembler::variables(&self.instructions());
+        if variables.is_empty() {
+            return;
+        }
+        egui::CollapsingHeader::new(t!("output.own_inputs"))
+            .id_salt(ui.id().with("own_inputs"))
+            .default_open(self.own_inputs.is_some())
+            .show(ui, |ui| {
+                let mut enabled = self.own_inputs.is_some();
+                if ui
+                    .checkbox(&mut enabled, t!("output.own_inputs.enable"))
+                    .on_hover_text(t!("output.own_inputs.desc"))
+                    .changed()
+                {
+                    self.own_inputs = enabled.then(|| self.shared_inputs.clone());
+                    self.rerun_requested = true;
+                }
+                let Some(own) = &mut self.own_inputs else {
+                    return;
+                };
+                egui::Grid::new(ui.id().with("own_inputs_grid"))
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        for (var, _) in &variables {
+                            ui.label(var);
+                            let value = own.entry(var.clone()).or_default();
+                            if ui.text_edit_singleline(value).lost_focus() {
+                                self.rerun_requested = true;
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    /// Goes back to a bookmarked state, stepping on from there.
+    pub fn jump_to(&mut self, index: usize) {
+        let (Some(asm), Some(interpreter), Some(bookmark)) = (
+            self.asm.as_mut(),
+            self.interpreter.as_mut(),
+            self.bookmarks.get(index),
+        ) else {
+            return;
+        };
+        let progress = &bookmark.progress;
+        interpreter.restore(bookmark.snapshot.clone());
+        for ((_, p), saved) in asm.iter_mut().zip(progress) {
+            *p = *saved;
+        }
+        self.running = true;
+        self.stepwise = true;
+        // an instruction that was halfway done finishes its animation
+        self.step_triggered = progress.iter().any(|p| *p > 0.0 && *p < 1.0);
+    }
+
+    /// Executes the rest of the program immediately, without animating the instructions.
+    pub fn fast_forward(&mut self) {
+        let (Some(asm), Some(interpreter)) = (self.asm.as_mut(), self.interpreter.as_mut()) else {
+            return;
+        };
+        // instructions with some progress were already executed by the interpreter
+        let remaining = asm.iter().filter(|(_, p)| *p == 0.0).count();
+        let (_, log) = compile_log::record(|| interpreter.run_for(remaining));
+        self.step_log.append(log);
+        asm.iter_mut().for_each(|(_, p)| *p = 1.0);
+
+        self.running = true;
+        self.stepwise = false;
+        self.step_triggered = false;
+        sound::play(Sound::Fanfare);
+    }
+
     /// Render the assembly output UI. TODO: could use a refactor if you'd ever want to change it.
     pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.hovered_source.clear();
+
+        if let Some((_, opts)) = &mut self.comparison {
+            compile_options_ui(ui, opts);
+            ui.separator();
+        }
+
+        if let Some(file) = &self.loaded_from {
+            ui.label(t!("output.loaded_from", file = file));
+        }
+        if self.asm.is_some() {
+            self.own_inputs_ui(ui);
+        }
+
         if let Some(error) = &self.error {
             ui.colored_label(egui::Color32::RED, "Error:");
             ui.colored_label(egui::Color32::RED, error);
@@ -128,6 +562,7 @@ impl AssemblyOutput {
 
         if self.asm.is_none() {
             ui.label(t!("output.empty"));
+            ui.weak(t!("output.drop_hint"));
             return;
         }
 
@@ -135,38 +570,108 @@ impl AssemblyOutput {
 
         // TODO: this mixed UI-interpreter logic is very bad practice, especially since we have a
         // TODO: great `Interpreter` struct that could handle this for us. We should decouple this.
+        let hw = self.hw.unwrap_or_default();
         let asm = self.asm.as_mut().unwrap();
         let mut done = false;
         if self.running && self.step_triggered {
-            let curr_inst = asm.iter_mut().find(|(_, p)| p < &1.0);
-            if let Some((inst, progress)) = curr_inst {
-                if progress == &0.0 {
-                    // advance the interpreter
-                    let _ = self.interpreter.as_mut().unwrap().step();
+            if let Some(i) = asm.iter().position(|(_, p)| p < &1.0) {
+                let slots = timeline(asm.iter().map(|(inst, _)| inst), &hw);
+                // an instruction on the second ALU runs at the same time as this one
+                let partner = slots.get(i + 1).filter(|s| s.lane == 1).map(|_| i + 1);
+                for j in std::iter::once(i).chain(partner) {
+                    let (inst, progress) = &mut asm[j];
+                    if progress == &0.0 {
+                        // advance the interpreter
+                        let interpreter = self.interpreter.as_mut().unwrap();
+                        let (_, log) = compile_log::record(|| interpreter.step());
+                        self.step_log.append(log);
+                        sound::play(Sound::of(inst));
+                    }
+                    if *progress < 1.0 {
+                        *progress += 1.0 / slots[j].duration() as f32;
+                    }
                 }
-                let progress_increment = match inst {
-                    Inst::Add(_, _) => 0.03333,
-                    Inst::Sub(_, _) => 0.03333,
-                    Inst::Mul(_, _) => 0.01667,
-                    Inst::Div(_, _) => 0.00833,
-                    Inst::Shl(_, _) => 0.03333,
-                    Inst::Shr(_, _) => 0.03333,
-                    Inst::Store(_, _) => 0.0667,
-                    Inst::Transfer(_, _) => 0.0667,
-                    Inst::Result(_) => 0.0667,
-                    Inst::Write(_, _) => 0.0033,
-                    Inst::Load(_, _) => 0.0033,
-                };
-                *progress += progress_increment;
-                if *progress >= 1.0 {
+                if std::iter::once(i).chain(partner).all(|j| asm[j].1 >= 1.0) {
                     self.step_triggered = false;
+                    if let Inst::Result(_) = asm[i].0 {
+                        sound::play(Sound::Fanfare);
+                    }
                 }
-                self.total_time += 0.016667; // 60 fps?
             } else {
                 done = true;
             }
         }
 
+        let current_progress = asm.iter().map(|(_, p)| *p).find(|p| *p > 0.0 && *p < 1.0);
+        let mut skip = false;
+        let mut bookmark = false;
+        let mut jump = None;
+        let mut edit_toggled = false;
+        let mut screenshot = false;
+        let mut record_toggled = false;
+        // screen positions of registers and RAM cells, for the data-flow arrows
+        let mut places: HashMap<Place, egui::Rect> = HashMap::new();
+
+        let base = self.base;
+        let bits = self.hw.as_ref().unwrap().word_size.bits();
+        ui.horizontal(|ui| {
+            ui.label(t!("output.base"));
+            for b in NumberBase::ALL {
+                ui.selectable_value(&mut self.base, b, t!(b.key()));
+            }
+            ui.separator();
+            ui.toggle_value(&mut self.micro_ops, t!("output.micro_ops"))
+                .on_hover_text(t!("output.micro_ops.desc"));
+            ui.toggle_value(&mut self.grouped, t!("output.group"))
+                .on_hover_text(t!("output.group.desc"));
+
+            ui.separator();
+            let data_dir = files::data_dir().display().to_string();
+            screenshot = ui
+                .button(t!("output.screenshot"))
+                .on_hover_text(t!("output.screenshot.desc", dir = data_dir))
+                .clicked();
+            record_toggled = ui
+                .selectable_label(self.recording.is_some(), t!("output.record"))
+                .on_hover_text(t!("output.record.desc", dir = data_dir))
+                .clicked();
+            if let Some(status) = &self.capture_status {
+                ui.weak(status);
+            }
+
+            // Markdown tables, for pasting into slides or chats
+            ui.separator();
+            ui.label(t!("output.copy"));
+            let interpreter = self.interpreter.as_ref();
+            let hw = self.hw.as_ref().unwrap();
+            if ui
+                .selectable_label(self.editing.is_some(), t!("output.edit"))
+                .on_hover_text(t!("output.edit.desc"))
+                .clicked()
+            {
+                edit_toggled = true;
+            }
+            if ui.button(t!("output.copy.asm")).clicked() {
+                ui.ctx().copy_text(instructions_markdown(asm));
+            }
+            if ui.button(t!("output.registers")).clicked() {
+                let values = (0..hw.num_registers).map(|n| {
+                    let v = interpreter.and_then(|i| i.register(Reg(n)));
+                    (Reg(n).to_string(), v)
+                });
+                ui.ctx()
+                    .copy_text(values_markdown(&t!("output.copy.reg"), values, base, bits));
+            }
+            if ui.button(t!("output.ram")).clicked() {
+                let values = (0..hw.num_cachelines).map(|addr| {
+                    let v = interpreter.and_then(|i| i.ram.get(addr).copied());
+                    (addr.to_string(), v)
+                });
+                ui.ctx()
+                    .copy_text(values_markdown(&t!("output.copy.addr"), values, base, bits));
+            }
+        });
+
         ui.vertical_centered(|ui| {
             // Table showing register contents, expands horizontally
             ui.heading(t!("output.registers"));
@@ -175,24 +680,54 @@ impl AssemblyOutput {
                 .spacing([5.0, 5.0])
                 .show(ui, |ui| {
                     let reg_count = self.hw.as_ref().unwrap().num_registers;
+                    let ram_size = self.hw.as_ref().unwrap().num_cachelines;
                     for num in 0..reg_count {
-                        let reg = u8tochar(num);
+                        let reg = Reg(num);
                         ui.label(format!("{reg}"));
                     }
+                    ui.label("SP").on_hover_text(t!("output.sp"));
                     ui.end_row();
+                    // before running, all registers are empty
+                    let fresh;
+                    let interpreter = match &self.interpreter {
+                        Some(interpreter) => interpreter,
+                        None => {
+                            fresh = Interpreter::with_config(self.hw.as_ref().unwrap());
+                            &fresh
+                        }
+                    };
                     for num in 0..reg_count {
-                        let reg = u8tochar(num);
-                        let mut txt = format!(
-                            "{}",
-                            self.interpreter
-                                .as_ref()
-                                .map_or(&0, |i| i.reg_store.get(&reg).unwrap_or(&0))
-                        );
-                        egui::TextEdit::singleline(&mut txt)
+                        let reg = Reg(num);
+                        // empty registers look like what reading them gives
+                        let mut txt = interpreter
+                            .register(reg)
+                            .map_or("–".to_string(), |v| base.format(v, bits));
+                        let color = if interpreter.reg_store.contains_key(&reg) {
+                            ui.visuals().text_color()
+                        } else {
+                            ui.visuals().weak_text_color()
+                        };
+                        let width = (txt.len() as f32 * 8.0).max(30.0);
+                        let response = egui::TextEdit::singleline(&mut txt)
                             .interactive(false)
-                            .desired_width(30.0)
+                            .text_color(color)
+                            .desired_width(width)
                             .ui(ui);
+                        response.widget_info(|| {
+                            let label = t!("output.a11y.reg", r = reg, v = txt);
+                            egui::WidgetInfo::labeled(egui::WidgetType::Label, true, label)
+                        });
+                        places.insert(Place::Reg(reg), response.rect);
                     }
+                    let mut sp = self
+                        .interpreter
+                        .as_ref()
+                        .map_or(ram_size, Interpreter::stack_pointer)
+                        .to_string();
+                    egui::TextEdit::singleline(&mut sp)
+                        .interactive(false)
+                        .desired_width(30.0)
+                        .ui(ui);
                 });
 
             ui.add_space(50.0);
@@ -215,8 +750,17 @@ impl AssemblyOutput {
                             .unwrap_or(0)
                     });
 
+                    let sp = self
+                        .interpreter
+                        .as_ref()
+                        .map_or(ram_size, Interpreter::stack_pointer);
+
                     // Print at least one more cell after the last nonzero one, but within [4, size]
-                    let ram_size_display = (end + 1).max(4).min(ram_size);
+                    let mut ram_size_display = (end + 1).max(4).min(ram_size);
+                    if sp < ram_size {
+                        // the stack lives at the end of RAM
+                        ram_size_display = ram_size;
+                    }
 
                     egui::Grid::new("ram_layout")
                         .num_columns(2)
@@ -226,11 +770,22 @@ impl AssemblyOutput {
                             ui.label(t!("output.ram.content"));
                             ui.end_row();
                             for num in 0..ram_size_display {
-                                ui.label(num.to_string());
-                                ui.label(format!(
-                                    "{}",
-                                    self.interpreter.as_ref().map_or(0, |i| i.ram[num])
-                                ));
+                                if num == sp {
+                                    ui.strong(format!("SP ▶ {num}"))
+                                        .on_hover_text(t!("output.sp"));
+                                } else {
+                                    ui.label(num.to_string());
+                                }
+                                let value = base.format(
+                                    self.interpreter.as_ref().map_or(0, |i| i.ram[num]),
+                                    bits,
+                                );
+                                let response = ui.label(&value);
+                                response.widget_info(|| {
+                                    let label = t!("output.a11y.ram", addr = num, v = value);
+                                    egui::WidgetInfo::labeled(egui::WidgetType::Label, true, label)
+                                });
+                                places.insert(Place::Ram(num), response.rect);
                                 ui.end_row();
                             }
                             if ram_size_display < ram_size {
@@ -249,7 +804,7 @@ impl AssemblyOutput {
                     let text_color = visuals.text_color();
 
                     egui::Frame::canvas(ui.style())
-                        .fill(visuals.bg_fill.gamma_multiply(0.3))
+                        .fill(Theme::global().canvas_fill(visuals.bg_fill))
                         .stroke(visuals.bg_stroke)
                         .inner_margin(ui.spacing().menu_margin)
                         .show(ui, |ui| {
@@ -257,7 +812,7 @@ impl AssemblyOutput {
 
                             ui.add_space(32.0);
                             ui.vertical_centered(|ui| {
-                                egui::Label::new(
+                                let current = egui::Label::new(
                                     egui::RichText::new(
                                         self.interpreter
                                             .as_ref()
@@ -268,23 +823,34 @@ impl AssemblyOutput {
                                 )
                                 .selectable(false)
                                 .ui(ui);
+                                // screen readers announce each step of the animation
+                                announce(ui, &current);
                             });
                             ui.add_space(32.0);
                         });
 
                     ui.vertical_centered_justified(|ui| {
                         // ui.add_space(55.0);
+                        let can_step = self
+                            .interpreter
+                            .as_ref()
+                            .is_some_and(Interpreter::is_running)
+                            && self.stepwise
+                            && !self.step_triggered;
+                        // not consumed, so all outputs take the step together
+                        let step_pressed = ui.input(|i| {
+                            i.modifiers.matches_exact(NEXT_STEP.modifiers)
+                                && i.key_pressed(NEXT_STEP.logical_key)
+                        });
                         if ui
                             .add_enabled(
-                                self.interpreter
-                                    .as_ref()
-                                    .is_some_and(Interpreter::is_running)
-                                    && self.stepwise
-                                    && !self.step_triggered,
-                                egui::Button::new(t!("output.step.button")),
+                                can_step,
+                                egui::Button::new(t!("output.step.button"))
+                                    .shortcut_text(ui.ctx().format_shortcut(&NEXT_STEP)),
                             )
                             .on_hover_text(t!("output.step.desc"))
                             .clicked()
+                            || (can_step && step_pressed)
                         {
                             self.step_triggered = true;
                         }
@@ -301,44 +867,933 @@ impl AssemblyOutput {
                         {
                             self.stepwise = false;
                         }
+                        if ui
+                            .add_enabled(
+                                self.interpreter
+                                    .as_ref()
+                                    .is_some_and(Interpreter::is_running),
+                                egui::Button::new(t!("output.skip.button")),
+                            )
+                            .on_hover_text(t!("output.skip.desc"))
+                            .clicked()
+                        {
+                            skip = true;
+                        }
+
+                        let keys = std::mem::take(&mut self.bookmark_keys);
+                        if ui
+                            .add_enabled(
+                                self.interpreter.is_some(),
+                                egui::Button::new(t!("output.bookmark.button"))
+                                    .shortcut_text(ui.ctx().format_shortcut(&BOOKMARK)),
+                            )
+                            .on_hover_text(t!("output.bookmark.desc"))
+                            .clicked()
+                            || (self.interpreter.is_some() && keys.add)
+                        {
+                            bookmark = true;
+                        }
+                        let steps = self.interpreter.as_ref().map_or(0, |i| i.stats().steps);
+                        if keys.previous {
+                            jump = neighbour(&self.bookmarks, steps, false);
+                        }
+                        if keys.next {
+                            jump = neighbour(&self.bookmarks, steps, true);
+                        }
+                        let mut removed = None;
+                        ui.horizontal_wrapped(|ui| {
+                            for (i, bookmark) in self.bookmarks.iter_mut().enumerate() {
+                                let button = ui
+                                    .selectable_label(
+                                        bookmark.snapshot.steps() == steps,
+                                        format!("🔖 {}", bookmark.label),
+                                    )
+                                    .on_hover_text(t!(
+                                        "output.bookmark.jump",
+                                        previous = ui.ctx().format_shortcut(&PREVIOUS_BOOKMARK),
+                                        next = ui.ctx().format_shortcut(&NEXT_BOOKMARK)
+                                    ));
+                                if button.clicked() {
+                                    jump = Some(i);
+                                }
+                                button.context_menu(|ui| {
+                                    ui.text_edit_singleline(&mut bookmark.label);
+                                    if ui.button(t!("output.bookmark.remove")).clicked() {
+                                        removed = Some(i);
+                                        ui.close_menu();
+                                    }
+                                });
+                            }
+                        });
+                        if let Some(i) = removed {
+                            self.bookmarks.remove(i);
+                        }
                     });
                 });
             });
         });
 
+        if edit_toggled {
+            match self.editing {
+                // invalid edits are dropped
+                Some(_) => {
+                    self.apply_edit();
+                    self.editing = None;
+                }
+                None => {
+                    let lines: Vec<_> = asm
+                        .iter()
+                        .map(|(inst, _)| assembler::to_text(inst))
+                        .collect();
+                    self.editing = Some(lines.join("\n"));
+                }
+            }
+            return;
+        }
+        if let (true, Some(interpreter)) = (bookmark, &self.interpreter) {
+            add_bookmark(&mut self.bookmarks, asm, interpreter);
+        }
+        self.screenshot_requested |= screenshot;
+        if record_toggled {
+            match self.recording.take() {
+                // stopped early, the frames so far are kept
+                Some(recording) if recording.gif.frames() > 0 => {
+                    let gif = recording.gif.finish();
+                    self.capture_status = Some(save_picture("run", "gif", Ok(gif)));
+                }
+                Some(_) => {}
+                None => {
+                    let [width, height] = PICTURE_SIZE;
+                    self.recording = Some(Recording {
+                        gif: capture::GifEncoder::new(width, height),
+                        offscreen: capture::Offscreen::new(ui.ctx().style()),
+                        last_frame: None,
+                    });
+                    self.rerun_requested = true;
+                }
+            }
+        }
+        if let Some(i) = jump {
+            self.jump_to(i);
+            ui.ctx().request_repaint();
+            return;
+        }
+
+        if skip {
+            self.fast_forward();
+            ui.ctx().request_repaint();
+            return;
+        }
+
+        if let (Some(progress), Some(interpreter)) = (current_progress, &self.interpreter) {
+            draw_data_flow(ui, &places, interpreter, progress);
+        }
+
         ui.separator();
 
-        egui::ScrollArea::vertical()
-            .max_height(ui.available_height() - 50.0)
+        if let (true, Some(source)) = (self.optimized, &self.source) {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(t!("output.as_source"));
+                ui.code(source);
+            });
+            ui.separator();
+        }
+
+        egui::CollapsingHeader::new(t!("output.machine_code"))
+            .id_salt(ui.id().with("machine_code"))
             .show(ui, |ui| {
+                let instructions: Vec<_> = asm.iter().map(|(inst, _)| inst.clone()).collect();
+                machine_code(ui, &instructions, &mut self.corrupted);
+            });
+
+        if let Some(interpreter) = &self.interpreter {
+            egui::CollapsingHeader::new(t!("output.write_log"))
+                .id_salt(ui.id().with("write_log"))
+                .show(ui, |ui| {
+                    let hovered = write_log(
+                        ui,
+                        interpreter.write_log(),
+                        asm,
+                        &mut self.log_filter,
+                        base,
+                        bits,
+                    );
+                    if let Some(spans) = hovered.and_then(|i| self.debug_info.get(i)) {
+                        self.hovered_source = spans.clone();
+                    }
+                });
+        }
+
+        if let Some(text) = &mut self.editing {
+            edit_ui(ui, text, &hw);
+            return;
+        }
+
+        let matches: Vec<usize> = (0..asm.len())
+            .filter(|&i| search_matches(&asm[i].0, &self.search))
+            .collect();
+        ui.horizontal(|ui| {
+            let field = ui.add(
+                egui::TextEdit::singleline(&mut self.search)
+                    .hint_text(t!("output.search.hint"))
+                    .desired_width(150.0),
+            );
+            let position = self
+                .search_current
+                .and_then(|c| matches.iter().position(|&m| m == c));
+            let enter = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let mut go = None;
+            if ui
+                .add_enabled(!matches.is_empty(), egui::Button::new("⏶"))
+                .on_hover_text(t!("output.search.previous"))
+                .clicked()
+            {
+                go = Some(position.map_or(matches.len() - 1, |p| {
+                    (p + matches.len() - 1) % matches.len()
+                }));
+            }
+            if (ui
+                .add_enabled(!matches.is_empty(), egui::Button::new("⏷"))
+                .on_hover_text(t!("output.search.next"))
+                .clicked()
+                || enter)
+                && !matches.is_empty()
+            {
+                go = Some(position.map_or(0, |p| (p + 1) % matches.len()));
+            }
+            if let Some(p) = go {
+                self.search_current = Some(matches[p]);
+                self.search_jump = true;
+                if enter {
+                    field.request_focus();
+                }
+            }
+            if !self.search.trim().is_empty() {
+                let position = go
+                    .or(position)
+                    .map_or("–".to_string(), |p| (p + 1).to_string());
+                ui.label(t!(
+                    "output.search.count",
+                    n = position,
+                    total = matches.len()
+                ));
+            }
+        });
+
+        // only the visible instructions are laid out, long programs would be slow otherwise
+        let size = Theme::global().instruction_size();
+        let row_height = ui.fonts(|f| f.row_height(&egui::FontId::proportional(size)));
+        let spacing = 4.0;
+        let mut scroll = egui::ScrollArea::vertical().max_height(ui.available_height() - 50.0);
+        // the instruction being executed may be outside the rows that are laid out
+        if let Some(current) = asm.iter().position(|(_, p)| *p > 0.0 && *p < 1.0)
+            && !self.visible_rows.contains(&current)
+        {
+            scroll = scroll.vertical_scroll_offset(current as f32 * (row_height + spacing));
+        }
+        let jump = std::mem::take(&mut self.search_jump)
+            .then_some(self.search_current)
+            .flatten();
+        if let Some(current) = jump
+            && !self.visible_rows.contains(&current)
+        {
+            scroll = scroll.vertical_scroll_offset(current as f32 * (row_height + spacing));
+        }
+        let groups = statement_groups(&self.statements, &self.debug_info, asm.len());
+        let grouped = self.grouped && groups.len() > 1;
+        let highlighted = rows_at(&self.debug_info, self.source_highlight);
+        let columns = if self.micro_ops { 3 } else { 2 };
+        let mut row = |ui: &mut egui::Ui, i: usize, inst: &Inst, progress: f32| {
+            let bar = egui::ProgressBar::new(progress)
+                .animate(true)
+                .desired_width(30.0)
+                .desired_height(7.5);
+            ui.add_visible(progress > 0.0, bar);
+            let mut text = egui::RichText::new(format!("{inst}")).size(size);
+            if highlighted.contains(&i) {
+                text = text.background_color(ui.visuals().selection.bg_fill);
+            } else if matches.contains(&i) {
+                let color = ui.visuals().warn_fg_color.gamma_multiply(0.3);
+                text = text.background_color(color);
+            }
+            if self.search_current == Some(i) && matches.contains(&i) {
+                text = text.underline();
+            }
+            let interpreter = self.interpreter.as_ref();
+            let label = ui.label(text).on_hover_ui(|ui| {
+                ui.label(inst.semantics().explain(interpreter));
+            });
+            if progress > 0.0 && progress < 1.0 || jump == Some(i) {
+                label.scroll_to_me(None);
+            }
+            if let (true, Some(spans)) = (label.hovered(), self.debug_info.get(i)) {
+                self.hovered_source = spans.clone();
+            }
+            if self.micro_ops {
+                micro_ops_ui(ui, inst, progress, size);
+            }
+            ui.end_row();
+        };
+        ui.scope(|ui| {
+            ui.spacing_mut().item_spacing.y = spacing;
+            if grouped {
+                // every instruction is laid out here, the collapsed groups keep it short
+                scroll.show(ui, |ui| {
+                    for (n, (line, rows)) in groups.iter().enumerate() {
+                        let running = rows.clone().any(|i| asm[i].1 > 0.0 && asm[i].1 < 1.0)
+                            || jump.is_some_and(|j| rows.contains(&j));
+                        egui::CollapsingHeader::new(egui::RichText::new(line).monospace())
+                            .id_salt((&self.heading, n))
+                            .default_open(true)
+                            .open(running.then_some(true))
+                            .show(ui, |ui| {
+                                egui::Grid::new((&self.heading, "group", n))
+                                    .num_columns(columns)
+                                    .spacing([10.0, spacing])
+                                    .min_col_width(30.0)
+                                    .show(ui, |ui| {
+                                        for i in rows.clone() {
+                                            let (inst, progress) = &asm[i];
+                                            row(ui, i, inst, *progress);
+                                        }
+                                    });
+                            });
+                    }
+                });
+                return;
+            }
+            let output = scroll.show_rows(ui, row_height, asm.len(), |ui, rows| {
                 egui::Grid::new(self.heading.clone())
-                    .num_columns(2)
-                    .spacing([10.0, 4.0])
+                    .num_columns(columns)
+                    .spacing([10.0, spacing])
                     .min_col_width(30.0)
                     .show(ui, |ui| {
-                        for (inst, progress) in asm {
-                            let bar = egui::ProgressBar::new(*progress)
-                                .animate(true)
-                                .desired_width(30.0)
-                                .desired_height(7.5);
-                            let v = progress > &mut 0.0;
-                            ui.add_visible(v, bar);
-                            let label = ui.label(format!("{inst}"));
-                            if *progress > 0.0 && *progress < 1.0 {
-                                label.scroll_to_me(None);
-                            }
-                            ui.end_row();
+                        for i in rows.clone() {
+                            let (inst, progress) = &asm[i];
+                            row(ui, i, inst, *progress);
                         }
                     });
+                rows
             });
+            self.visible_rows = output.inner;
+        });
+
+        let warnings = self.interpreter.as_ref().map_or(&[][..], |i| i.warnings());
+        if !warnings.is_empty() {
+            ui.separator();
+            for warning in warnings {
+                let text = t!(
+                    "output.warning",
+                    n = warning.instruction + 1,
+                    msg = warning.message
+                );
+                let label = ui.colored_label(ui.visuals().warn_fg_color, text);
+                if let (true, Some(spans)) =
+                    (label.hovered(), self.debug_info.get(warning.instruction))
+                {
+                    self.hovered_source = spans.clone();
+                }
+            }
+        }
 
         if self.running {
             ui.separator();
-            ui.label(t!("output.time", t = self.total_time.round()));
+            let markers: Vec<_> = self
+                .bookmarks
+                .iter()
+                .map(|b| (b.snapshot.cycles(), b.label.as_str()))
+                .collect();
+            if let Some(i) = draw_timeline(ui, asm, &hw, &markers) {
+                self.jump_to(i);
+                ui.ctx().request_repaint();
+            }
         }
         if done {
             ui.separator();
-            ui.label(t!("output.result", res = self.program_result.unwrap()));
+            ui.horizontal(|ui| {
+                let result = ui.label(t!("output.result", res = self.program_result.unwrap()));
+                announce(ui, &result);
+                if ui
+                    .button(t!("output.rerun"))
+                    .on_hover_text(t!("output.rerun.desc"))
+                    .clicked()
+                {
+                    self.rerun_requested = true;
+                }
+            });
+            if let Some(interpreter) = &self.interpreter {
+                let baseline = self.baseline.filter(|_| self.optimized);
+                summary_card(
+                    ui,
+                    self.run_stats.as_ref().unwrap_or(interpreter.stats()),
+                    &Cost::of(&self.instructions()),
+                    baseline,
+                );
+            }
+        }
+    }
+}
+
+/// Costs of the unoptimized program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Baseline {
+    pub cycles: u64,
+    /// See [`Cost::ram_accesses`], the optimizations can replace some with copies between
+    /// registers.
+    pub ram_accesses: usize,
+}
+
+/// How many percent fewer cycles than the `baseline` the program took, negative if it's slower.
+fn improvement(baseline: u64, cycles: u64) -> Option<f64> {
+    (baseline > 0).then(|| (baseline as f64 - cycles as f64) / baseline as f64 * 100.0)
+}
+
+/// Numbers about the finished run, and how it compares to the unoptimized program.
+fn summary_card(ui: &mut egui::Ui, stats: &Stats, cost: &Cost, baseline: Option<Baseline>) {
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        egui::Grid::new(ui.id().with("summary"))
+            .num_columns(2)
+            .spacing([20.0, 2.0])
+            .show(ui, |ui| {
+                let mut row = |name: &str, value: String| {
+                    ui.label(t!(name));
+                    ui.monospace(value);
+                    ui.end_row();
+                };
+                row("output.summary.instructions", cost.instructions.to_string());
+                row("output.summary.steps", stats.steps.to_string());
+                row("output.summary.cycles", stats.cycles.to_string());
+                row(
+                    "output.summary.ram",
+                    (stats.ram_reads + stats.ram_writes).to_string(),
+                );
+                row(
+                    "output.summary.registers",
+                    stats.max_live_registers.to_string(),
+                );
+                if stats.paired > 0 {
+                    row("output.summary.paired", stats.paired.to_string());
+                }
+                if stats.stalls > 0 {
+                    row("output.summary.stalls", stats.stalls.to_string());
+                }
+                if stats.cache_hits > 0 {
+                    row("output.summary.cache_hits", stats.cache_hits.to_string());
+                }
+                row("output.summary.spills", cost.spills.to_string());
+                if let Some(baseline) = baseline
+                    && baseline.ram_accesses > cost.ram_accesses
+                {
+                    let saved = baseline.ram_accesses - cost.ram_accesses;
+                    row("output.summary.ram_saved", saved.to_string());
+                }
+            });
+        if let Some(percent) = baseline.and_then(|b| improvement(b.cycles, stats.cycles)) {
+            let (text, color) = if percent >= 0.0 {
+                (
+                    t!("output.summary.faster", p = format!("{percent:.0}")),
+                    egui::Color32::from_rgb(80, 180, 80),
+                )
+            } else {
+                (
+                    t!("output.summary.slower", p = format!("{:.0}", -percent)),
+                    ui.visuals().warn_fg_color,
+                )
+            };
+            ui.colored_label(color, egui::RichText::new(text).strong());
+        }
+    });
+}
+
+fn markdown_table(header: [&str; 2], rows: impl Iterator<Item = [String; 2]>) -> String {
+    let mut res = format!("| {} | {} |\n| --- | --- |\n", header[0], header[1]);
+    for [a, b] in rows {
+        res += &format!("| {a} | {b} |\n");
+    }
+    res
+}
+
+/// The instruction listing as a Markdown table.
+fn instructions_markdown(asm: &[(Inst, f32)]) -> String {
+    markdown_table(
+        ["#", &t!("output.copy.inst")],
+        asm.iter()
+            .enumerate()
+            .map(|(i, (inst, _))| [i.to_string(), format!("`{inst}`")]),
+    )
+}
+
+/// Register or RAM contents as a Markdown table, empty cells are left blank.
+fn values_markdown(
+    name: &str,
+    values: impl Iterator<Item = (String, Option<i32>)>,
+    base: NumberBase,
+    bits: u32,
+) -> String {
+    markdown_table(
+        [name, &t!("output.copy.value")],
+        values.map(|(k, v)| [k, v.map_or(String::new(), |v| base.format(v, bits))]),
+    )
+}
+
+/// Shortcut for the "next step" button, works in all outputs at once.
+pub const NEXT_STEP: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F10);
+
+/// Marks the current state of the animation in all outputs.
+pub const BOOKMARK: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F9);
+
+/// Jumping between bookmarks, the keys presentation remotes send.
+pub const PREVIOUS_BOOKMARK: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::PageUp);
+pub const NEXT_BOOKMARK: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::PageDown);
+
+/// Remembers the current state of the machine and the animation, unless it already is.
+fn add_bookmark(bookmarks: &mut Vec<Bookmark>, asm: &[(Inst, f32)], interpreter: &Interpreter) {
+    let snapshot = interpreter.snapshot();
+    let steps = snapshot.steps();
+    if bookmarks.iter().any(|b| b.snapshot.steps() == steps) {
+        return;
+    }
+    let bookmark = Bookmark {
+        label: t!("output.bookmark.default", n = steps).into(),
+        snapshot,
+        progress: asm.iter().map(|(_, p)| *p).collect(),
+    };
+    let i = bookmarks.partition_point(|b| b.snapshot.steps() < steps);
+    bookmarks.insert(i, bookmark);
+}
+
+/// Which of the bookmark shortcuts were pressed, for all outputs at once.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BookmarkKeys {
+    pub add: bool,
+    pub previous: bool,
+    pub next: bool,
+}
+
+impl BookmarkKeys {
+    /// Takes the shortcuts out of the input, so text fields don't scroll or type with them too.
+    pub fn consume(ctx: &egui::Context) -> Self {
+        ctx.input_mut(|i| BookmarkKeys {
+            add: i.consume_shortcut(&BOOKMARK),
+            previous: i.consume_shortcut(&PREVIOUS_BOOKMARK),
+            next: i.consume_shortcut(&NEXT_BOOKMARK),
+        })
+    }
+}
+
+/// The bookmark right after (or before) the given number of executed steps.
+fn neighbour(bookmarks: &[Bookmark], steps: u64, forward: bool) -> Option<usize> {
+    let after = bookmarks.partition_point(|b| b.snapshot.steps() <= steps);
+    if forward {
+        Some(after).filter(|&i| i < bookmarks.len())
+    } else {
+        let before = bookmarks.partition_point(|b| b.snapshot.steps() < steps);
+        before.checked_sub(1)
+    }
+}
+
+/// Hex dump of the program's machine code, with a button to flip a bit and see what the
+/// processor makes of it.
+fn machine_code(
+    ui: &mut egui::Ui,
+    instructions: &[Inst],
+    corrupted: &mut Option<(usize, Result<Vec<Inst>, DecodeError>)>,
+) {
+    let bytes = match encoding::encode(instructions) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            ui.colored_label(egui::Color32::RED, e.to_string());
+            return;
+        }
+    };
+    ui.label(t!("output.machine_code.explain", n = bytes.len()));
+    ui.monospace(encoding::hex_dump(&bytes).join("\n"));
+
+    if ui
+        .add_enabled(
+            !bytes.is_empty(),
+            egui::Button::new(t!("output.machine_code.flip")),
+        )
+        .on_hover_text(t!("output.machine_code.flip.desc"))
+        .clicked()
+    {
+        let bit = Rng::from_time().next_u64() as usize % (bytes.len() * 8);
+        let mut flipped = bytes.clone();
+        flipped[bit / 8] ^= 1 << (bit % 8);
+        *corrupted = Some((bit, encoding::decode(&flipped)));
+    }
+    let Some((bit, decoded)) = corrupted else {
+        return;
+    };
+    ui.label(t!(
+        "output.machine_code.flipped",
+        bit = *bit % 8,
+        byte = *bit / 8
+    ));
+    match decoded {
+        Err(e) => {
+            ui.colored_label(ui.visuals().warn_fg_color, e.to_string());
+        }
+        // every bit is used, so it's always another program
+        Ok(program) => {
+            let text: Vec<_> = program.iter().map(assembler::to_text).collect();
+            ui.label(t!("output.machine_code.changed"));
+            ui.monospace(text.join("\n"));
+        }
+    }
+}
+
+/// The listing as text, lines that don't fit the hardware are underlined and explained below.
+fn edit_ui(ui: &mut egui::Ui, text: &mut String, hw: &InterpreterOptions) {
+    ui.label(t!("output.edit.explain"));
+    let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+        let errors = assembler::parse_for(text, hw).err().unwrap_or_default();
+        let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+        let mut job = egui::text::LayoutJob::default();
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let mut format = egui::TextFormat::simple(font_id.clone(), ui.visuals().text_color());
+            if errors.iter().any(|(line, _)| *line == i) {
+                format.color = ui.visuals().error_fg_color;
+                format.underline = egui::Stroke::new(1.0, ui.visuals().error_fg_color);
+            }
+            job.append(line, 0.0, format);
+        }
+        job.wrap.max_width = wrap_width;
+        ui.fonts(|f| f.layout_job(job))
+    };
+    egui::ScrollArea::vertical()
+        .max_height(ui.available_height() - 80.0)
+        .show(ui, |ui| {
+            egui::TextEdit::multiline(text)
+                .code_editor()
+                .desired_width(f32::INFINITY)
+                .layouter(&mut layouter)
+                .show(ui);
+        });
+
+    if let Err(errors) = assembler::parse_for(text, hw) {
+        for (line, msg) in errors {
+            let msg = t!("assembler.at", line = line + 1, msg = msg);
+            ui.colored_label(ui.visuals().error_fg_color, msg);
+        }
+        ui.weak(t!("output.edit.blocked"));
+    }
+}
+
+/// The shared inputs, with the own values of an output replacing them.
+fn with_own_inputs(
+    shared: &HashMap<String, String>,
+    own: Option<&HashMap<String, String>>,
+) -> HashMap<String, String> {
+    let mut inputs = shared.clone();
+    inputs.extend(
+        own.into_iter()
+            .flatten()
+            .map(|(k, v)| (k.clone(), v.clone())),
+    );
+    inputs
+}
+
+/// Whether every word of the search is the opcode or one of the operands of the instruction,
+/// e.g. `load 3` finds the loads from address 3 and `a` every instruction using register a.
+fn search_matches(inst: &Inst, search: &str) -> bool {
+    let operands: Vec<_> = inst.operands().iter().map(ToString::to_string).collect();
+    let mut words = search
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|w| w.trim_matches(['[', ']']))
+        .filter(|w| !w.is_empty())
+        .peekable();
+    words.peek().is_some()
+        && words.all(|w| {
+            inst.name().eq_ignore_ascii_case(w)
+                || operands.iter().any(|o| o.eq_ignore_ascii_case(w))
+        })
+}
+
+/// Whether the log entry is about one of the comma-separated places, e.g. `a, 3`.
+fn log_matches(place: &Place, filter: &str) -> bool {
+    let name = place.to_string();
+    let name = name.trim_matches(['[', ']']);
+    filter.trim().is_empty()
+        || filter
+            .split(',')
+            .any(|f| f.trim().trim_matches(['[', ']']) == name)
+}
+
+/// The micro-operations of an instruction in a row, each with its part of the instruction's
+/// progress, so they run one after the other while the instruction is animated.
+fn micro_ops_ui(ui: &mut egui::Ui, inst: &Inst, progress: f32, size: f32) {
+    let ops = inst.semantics().micro_ops();
+    let n = ops.len() as f32;
+    ui.horizontal(|ui| {
+        for (k, op) in ops.iter().enumerate() {
+            let part = (progress * n - k as f32).clamp(0.0, 1.0);
+            let bar = egui::ProgressBar::new(part)
+                .desired_width(15.0)
+                .desired_height(7.5);
+            ui.add(bar);
+            let text = egui::RichText::new(op.to_string()).size(size * 0.8);
+            ui.label(if part <= 0.0 {
+                text.weak()
+            } else if part < 1.0 {
+                text.strong()
+            } else {
+                text
+            });
+        }
+    });
+}
+
+/// The moves of values so far, newest last. Returns the instruction of the hovered entry.
+fn write_log(
+    ui: &mut egui::Ui,
+    log: &[LoggedWrite],
+    asm: &[(Inst, f32)],
+    filter: &mut String,
+    base: NumberBase,
+    bits: u32,
+) -> Option<usize> {
+    ui.label(t!("output.write_log.explain"));
+    ui.horizontal(|ui| {
+        ui.label(t!("output.write_log.filter"));
+        ui.add(egui::TextEdit::singleline(filter).hint_text("a, 3"));
+    });
+    let mut hovered = None;
+    egui::ScrollArea::vertical()
+        .max_height(200.0)
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            egui::Grid::new("write_log")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    for entry in log.iter().filter(|e| log_matches(&e.place, filter)) {
+                        ui.label(t!("output.write_log.step", n = entry.step));
+                        let inst = ui.monospace(assembler::to_text(&asm[entry.instruction].0));
+                        ui.monospace(format!(
+                            "{} ← {}",
+                            entry.place,
+                            base.format(entry.value, bits)
+                        ));
+                        if inst.hovered() {
+                            hovered = Some(entry.instruction);
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+    hovered
+}
+
+/// Marks the widget as a live region, so screen readers read out changes.
+fn announce(ui: &egui::Ui, response: &egui::Response) {
+    ui.ctx().accesskit_node_builder(response.id, |node| {
+        node.set_live(egui::accesskit::Live::Polite);
+    });
+}
+
+/// Instructions in a row that came from the same statement of the source.
+///
+/// Each group is labeled with its statement, instructions without a source belong to the ones
+/// before them. Where a constant is used, the instructions belong to its declaration.
+fn statement_groups(
+    statements: &[Statement],
+    debug_info: &[Vec<Span>],
+    len: usize,
+) -> Vec<(String, Range<usize>)> {
+    let statement_of = |span: &Span| {
+        let inside = |s: &Span| s.start <= span.start && span.end <= s.end;
+        statements.iter().position(|s| s.spans.iter().any(inside))
+    };
+    let mut groups: Vec<(Option<usize>, Range<usize>)> = Vec::new();
+    for i in 0..len {
+        let statement = debug_info
+            .get(i)
+            .and_then(|s| s.first())
+            .and_then(statement_of);
+        match groups.last_mut() {
+            Some((last, rows)) if statement.is_none() || *last == statement || last.is_none() => {
+                *last = last.or(statement);
+                rows.end = i + 1;
+            }
+            _ => groups.push((statement, i..i + 1)),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(statement, rows)| {
+            let text = statement.map_or("", |s| statements[s].text.as_str());
+            (text.to_string(), rows)
+        })
+        .collect()
+}
+
+/// Instructions generated from the innermost expression at `offset` in the source.
+fn rows_at(debug_info: &[Vec<Span>], offset: Option<usize>) -> Vec<usize> {
+    let Some(offset) = offset else {
+        return vec![];
+    };
+    let innermost = |spans: &[Span]| {
+        spans
+            .iter()
+            .filter(|s| s.contains(&offset))
+            .map(|s| s.len())
+            .min()
+    };
+    let Some(len) = debug_info.iter().filter_map(|s| innermost(s)).min() else {
+        return vec![];
+    };
+    (0..debug_info.len())
+        .filter(|&i| innermost(&debug_info[i]) == Some(len))
+        .collect()
+}
+
+/// When each instruction runs, executed in order on the given hardware.
+fn timeline<'a>(insts: impl Iterator<Item = &'a Inst>, hw: &InterpreterOptions) -> Vec<Slot> {
+    let mut schedule = Schedule::new(hw);
+    insts.map(|inst| schedule.place(inst)).collect()
+}
+
+/// Draws the program as a strip of bars as wide as each instruction takes, filling up while
+/// it's executed. Stalls are drawn before their bar in the warning color, and with two ALUs
+/// the instructions on the second one get their own lane.
+/// Returns the bookmark marker that was clicked, the markers are at the cycles they were set.
+fn draw_timeline(
+    ui: &mut egui::Ui,
+    asm: &[(Inst, f32)],
+    hw: &InterpreterOptions,
+    markers: &[(u64, &str)],
+) -> Option<usize> {
+    let slots = timeline(asm.iter().map(|(inst, _)| inst), hw);
+    let total: u32 = slots.iter().map(|slot| slot.added).sum();
+    // the progress of an instruction includes its stall
+    let elapsed = asm
+        .iter()
+        .zip(&slots)
+        .filter(|((_, progress), _)| *progress > 0.0)
+        .map(|((_, progress), slot)| {
+            let waited = slot.cycles.start - slot.stall;
+            waited as f32 + slot.duration() as f32 * progress.min(1.0)
+        })
+        .fold(0.0, f32::max);
+    ui.label(t!("output.timeline", done = elapsed.round(), total = total));
+
+    let lanes = if slots.iter().any(|slot| slot.lane == 1) {
+        2
+    } else {
+        1
+    };
+    let (response, painter) = ui.allocate_painter(
+        egui::vec2(ui.available_width(), 24.0 * lanes as f32),
+        egui::Sense::click(),
+    );
+    if total == 0 {
+        return None;
+    }
+    let rect = response.rect;
+    let scale = rect.width() / total as f32;
+    let visuals = ui.visuals();
+    let hovered = response.hover_pos();
+
+    let mut tooltip = None;
+    for ((inst, progress), slot) in asm.iter().zip(&slots) {
+        let x = |cycle: f32| rect.left() + cycle * scale;
+        let top = rect.top() + 24.0 * f32::from(slot.lane);
+        let span =
+            |from: f32, to: f32| egui::Rect::from_x_y_ranges(x(from)..=x(to), top..=top + 24.0);
+        let (start, end) = (slot.cycles.start as f32, slot.cycles.end as f32);
+        let waited = start - slot.stall as f32;
+        let stall = span(waited, start);
+        let full = span(start, end);
+        let done = span(waited, waited + slot.duration() as f32 * progress.min(1.0));
+        painter.rect_filled(full, 0.0, visuals.extreme_bg_color);
+        if slot.stall > 0 {
+            painter.rect_filled(stall, 0.0, visuals.warn_fg_color.gamma_multiply(0.4));
+            if hovered.is_some_and(|p| stall.contains(p)) {
+                tooltip = Some(t!("output.timeline.stall", c = slot.stall));
+            }
+        }
+        painter.rect_filled(done, 0.0, Theme::global().accent());
+        painter.rect_stroke(
+            full,
+            0.0,
+            visuals.widgets.noninteractive.bg_stroke,
+            egui::StrokeKind::Inside,
+        );
+        if hovered.is_some_and(|p| full.contains(p)) {
+            tooltip = Some(t!(
+                "output.timeline.inst",
+                inst = inst,
+                c = slot.cycles.len()
+            ));
+        }
+    }
+
+    let mut clicked = None;
+    for (i, (cycles, label)) in markers.iter().enumerate() {
+        let x = rect.left() + *cycles as f32 * scale;
+        let stroke = egui::Stroke::new(2.0, visuals.strong_text_color());
+        painter.vline(x, rect.y_range(), stroke);
+        painter.text(
+            egui::pos2(x + 2.0, rect.top()),
+            egui::Align2::LEFT_TOP,
+            "🔖",
+            egui::FontId::proportional(10.0),
+            visuals.strong_text_color(),
+        );
+        let near = |p: egui::Pos2| (p.x - x).abs() < 6.0 && rect.contains(p);
+        if hovered.is_some_and(near) {
+            tooltip = Some(label.to_string().into());
+            if response.clicked() {
+                clicked = Some(i);
+            }
+        }
+    }
+
+    if let Some(text) = tooltip {
+        response.on_hover_text_at_pointer(text);
+    }
+    clicked
+}
+
+/// Draws arrows from the places the current instruction reads to the places it writes, growing
+/// with the instruction's progress.
+fn draw_data_flow(
+    ui: &egui::Ui,
+    places: &HashMap<Place, egui::Rect>,
+    interpreter: &Interpreter,
+    progress: f32,
+) {
+    let painter = ui.ctx().layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        ui.id().with("data_flow"),
+    ));
+    let stroke = egui::Stroke::new(2.5, Theme::global().accent());
+
+    let effects = interpreter.last_effects();
+    for write in &effects.writes {
+        let Some(to) = places.get(write) else {
+            continue;
+        };
+        for read in effects.reads.iter().filter(|r| *r != write) {
+            let Some(from) = places.get(read) else {
+                continue;
+            };
+            let dir = to.center() - from.center();
+            painter.arrow(from.center(), dir * progress, stroke);
+        }
+        if effects.reads.is_empty() || effects.reads == [*write] {
+            // values coming from outside the machine (numbers, variables) or staying in place
+            painter.rect_stroke(
+                to.expand(2.0),
+                2.0,
+                egui::Stroke::new(stroke.width, stroke.color.gamma_multiply(progress)),
+                egui::StrokeKind::Outside,
+            );
         }
     }
 }
@@ -348,11 +1803,249 @@ impl crate::gui::Window for AssemblyOutput {
         self.heading.clone()
     }
 
+    fn title(&self) -> String {
+        match self.comparison {
+            Some((number, _)) => t!("output.compare", n = number).into(),
+            None => t!(self.name()).into(),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
     fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
-        egui::Window::new(t!(self.name()))
+        egui::Window::new(self.title())
             .id(Id::new(self.name()))
             .open(open)
             .default_height(600.0)
             .show(ctx, |ui| self.ui(ui));
+        self.take_pictures(ctx);
+    }
+}
+
+impl AssemblyOutput {
+    /// Saves the screenshot that was asked for. Adds a frame to the recording every
+    /// [`FRAME_TIME`] while the program runs, and saves it after the frame of the result.
+    fn take_pictures(&mut self, ctx: &egui::Context) {
+        if std::mem::take(&mut self.screenshot_requested) {
+            let mut offscreen = capture::Offscreen::new(ctx.style());
+            let frame = offscreen.render(PICTURE_SIZE, 0, |ui| self.picture_ui(ui));
+            let png = capture::encode_png(&frame).map_err(|e| e.to_string());
+            self.capture_status = Some(save_picture("screenshot", "png", png));
+        }
+        if !self.running {
+            return;
+        }
+        let Some(mut recording) = self.recording.take() else {
+            return;
+        };
+        let done = (self.asm.iter().flatten()).all(|(_, p)| *p >= 1.0);
+        let now = Instant::now();
+        if done || recording.last_frame.is_none_or(|t| now - t >= FRAME_TIME) {
+            let last = done || recording.gif.frames() + 1 >= MAX_FRAMES;
+            let delay = if last {
+                LAST_FRAME_DELAY
+            } else {
+                (FRAME_TIME.as_millis() / 10) as u16
+            };
+            let frame = (recording.offscreen).render(PICTURE_SIZE, delay, |ui| self.picture_ui(ui));
+            recording.gif.add(&frame);
+            recording.last_frame = Some(now);
+            if last {
+                let gif = recording.gif.finish();
+                self.capture_status = Some(save_picture("run", "gif", Ok(gif)));
+                return;
+            }
+        }
+        self.recording = Some(recording);
+        ctx.request_repaint();
+    }
+
+    /// What screenshots and recordings show: the instructions around the current one and the
+    /// registers, at a fixed size so they look the same whatever the window looks like.
+    fn picture_ui(&self, ui: &mut egui::Ui) {
+        let (Some(asm), Some(hw)) = (&self.asm, &self.hw) else {
+            return;
+        };
+        ui.heading(crate::gui::Window::title(self));
+        ui.separator();
+
+        let current = asm.iter().position(|(_, p)| *p < 1.0).unwrap_or(asm.len());
+        let start = current.saturating_sub(PICTURE_LINES / 2);
+        for (inst, progress) in asm.iter().skip(start).take(PICTURE_LINES) {
+            let text = egui::RichText::new(assembler::to_text(inst)).monospace();
+            if *progress >= 1.0 {
+                ui.label(text);
+            } else if *progress > 0.0 {
+                ui.label(text.strong().color(Theme::global().accent()));
+            } else {
+                ui.label(text.weak());
+            }
+        }
+        ui.separator();
+
+        let bits = hw.word_size.bits();
+        egui::Grid::new("picture_registers").show(ui, |ui| {
+            for num in 0..hw.num_registers {
+                ui.monospace(Reg(num).to_string());
+            }
+            ui.end_row();
+            for num in 0..hw.num_registers {
+                let value = self.interpreter.as_ref().and_then(|i| i.register(Reg(num)));
+                ui.monospace(value.map_or("–".to_string(), |v| self.base.format(v, bits)));
+            }
+        });
+    }
+}
+
+/// Writes a picture to the data directory, and tells where it went.
+fn save_picture(name: &str, extension: &str, bytes: Result<Vec<u8>, String>) -> String {
+    let path = files::timestamped(name, extension);
+    let file = path.display();
+    match bytes.and_then(|b| std::fs::write(&path, b).map_err(|e| e.to_string())) {
+        Ok(()) => t!("output.saved", file = file).into(),
+        Err(e) => t!("output.save_failed", file = file, e = e).into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::{Compiler, RAM_STALL_CYCLES};
+
+    fn bars(code: &[Inst], hw: InterpreterOptions) -> Vec<Range<u32>> {
+        timeline(code.iter(), &hw)
+            .into_iter()
+            .map(|s| s.cycles)
+            .collect()
+    }
+
+    #[test]
+    fn timeline_bars_follow_each_other() {
+        let code = [
+            Inst::Store(1, Reg(0)),
+            Inst::Store(2, Reg(1)),
+            Inst::Mul(Reg(0), Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        assert_eq!(
+            bars(&code, InterpreterOptions::default()),
+            [0..15, 15..30, 30..90, 90..105]
+        );
+    }
+
+    #[test]
+    fn single_ram_port_stalls_consecutive_accesses() {
+        let code = [
+            Inst::Store(1, Reg(0)),
+            Inst::Write(Reg(0), 0),
+            Inst::Load(0, Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        let hw = InterpreterOptions {
+            ram_ports: 1,
+            ..Default::default()
+        };
+        assert_eq!(bars(&code, hw), [0..15, 15..315, 415..715, 715..730]);
+        assert_eq!(bars(&code, InterpreterOptions::default())[2], 315..615);
+
+        let mut interpreter = Interpreter::with_config(&hw)
+            .load_instructions(code.to_vec())
+            .ready();
+        interpreter.run_to_end().unwrap();
+        assert_eq!(interpreter.stats().cycles, 730);
+        assert_eq!(interpreter.stats().stalls, u64::from(RAM_STALL_CYCLES));
+    }
+
+    #[test]
+    fn own_inputs_replace_shared_ones() {
+        let shared = HashMap::from([("a".to_string(), "1".to_string()), ("b".into(), "2".into())]);
+        assert_eq!(with_own_inputs(&shared, None), shared);
+        let own = HashMap::from([("b".to_string(), "0".to_string())]);
+        let inputs = with_own_inputs(&shared, Some(&own));
+        assert_eq!(inputs["a"], "1");
+        assert_eq!(inputs["b"], "0");
+    }
+
+    #[test]
+    fn instructions_are_grouped_by_statement() -> Result<(), LpErr> {
+        let code = "const K = 2;\r\na * K\r\n  + (b * b)";
+        let ir = Compiler::with(CompileOptions::default()).compile(code)?;
+        assert_eq!(ir.statements[1].text, "a * K + (b * b)");
+        let groups = statement_groups(&ir.statements, &ir.debug_info, ir.instructions.len());
+        let labels: Vec<_> = groups.iter().map(|(text, _)| text.as_str()).collect();
+        assert!(labels.contains(&"const K = 2;"), "{labels:?}");
+        assert!(labels.contains(&"a * K + (b * b)"), "{labels:?}");
+        let rows: usize = groups.iter().map(|(_, rows)| rows.len()).sum();
+        assert_eq!(rows, ir.instructions.len());
+
+        assert_eq!(statement_groups(&[], &[], 2), [(String::new(), 0..2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn search_finds_opcodes_and_operands() {
+        let load = Inst::Load(3, Reg(1));
+        assert!(search_matches(&load, "load"));
+        assert!(search_matches(&load, "LOAD [3], b"));
+        assert!(search_matches(&load, "b"));
+        assert!(!search_matches(&load, "load a"));
+        assert!(!search_matches(&load, "lo"));
+        assert!(!search_matches(&load, " "));
+        assert!(search_matches(&Inst::Transfer("x".into(), Reg(0)), "x"));
+    }
+
+    #[test]
+    fn write_log_filters_by_place() {
+        assert!(log_matches(&Place::Reg(Reg(1)), " "));
+        assert!(log_matches(&Place::Reg(Reg(1)), "a, b"));
+        assert!(log_matches(&Place::Ram(3), "[3]"));
+        assert!(log_matches(&Place::Ram(3), "b,3"));
+        assert!(!log_matches(&Place::Ram(13), "3"));
+        assert!(!log_matches(&Place::Reg(Reg(0)), "3"));
+    }
+
+    #[test]
+    fn bookmarks_are_jumped_to_in_order() -> Result<(), LpErr> {
+        let hw = InterpreterOptions::default();
+        let mut interpreter = Interpreter::with_config(&hw)
+            .load_instructions(vec![Inst::Store(1, Reg(0)); 8])
+            .ready();
+        let mut bookmarks = Vec::new();
+        for steps in [2, 5, 7] {
+            interpreter.run_until(|i| i.stats().steps == steps)?;
+            bookmarks.push(Bookmark {
+                label: steps.to_string(),
+                snapshot: interpreter.snapshot(),
+                progress: Vec::new(),
+            });
+        }
+
+        assert_eq!(neighbour(&bookmarks, 0, true), Some(0));
+        assert_eq!(neighbour(&bookmarks, 0, false), None);
+        // on a bookmark, the neighbours are the ones around it
+        assert_eq!(neighbour(&bookmarks, 5, true), Some(2));
+        assert_eq!(neighbour(&bookmarks, 5, false), Some(0));
+        assert_eq!(neighbour(&bookmarks, 6, false), Some(1));
+        assert_eq!(neighbour(&bookmarks, 7, true), None);
+        Ok(())
+    }
+
+    #[test]
+    fn improvement_over_the_baseline() {
+        assert_eq!(improvement(200, 50), Some(75.0));
+        assert_eq!(improvement(100, 120), Some(-20.0));
+        assert_eq!(improvement(0, 10), None);
+    }
+
+    #[test]
+    fn registers_as_markdown() {
+        let values = [("a".to_string(), Some(10)), ("b".to_string(), None)].into_iter();
+        let table = values_markdown("Register", values, NumberBase::Hexadecimal, 8);
+        assert_eq!(
+            table,
+            "| Register | Value |\n| --- | --- |\n| a | 0xa |\n| b |  |\n"
+        );
     }
 }
@@ -0,0 +1,421 @@
+use std::time::{Duration, Instant};
+
+use crate::compiler::{CompileOptions, OptLevel};
+use crate::files;
+use crate::generator::{GenOptions, Rng, random_program};
+use crate::passes::{Pass, registry};
+use eframe::egui::{self, Color32, Event, Pos2, Rect, RichText};
+use rust_i18n::t;
+
+/// Script played when there's no `demo.txt` in the data directory.
+const BUILT_IN: &str = include_str!("../../assets/demo.txt");
+
+/// A script in the data directory replaces the built-in one, so the booth can adjust it.
+const DEMO_FILE: &str = "demo.txt";
+
+/// How long nobody has to touch the app before the demo starts.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Input right after starting the demo from the menu doesn't stop it, e.g. moving the mouse away.
+const GRACE_TIME: Duration = Duration::from_secs(3);
+
+/// How long each step is announced before it happens, so visitors can follow along.
+const HINT_TIME: Duration = Duration::from_millis(1200);
+
+/// One step of a demo script, a line like `source a * 2` or `wait 3`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DemoStep {
+    /// Replace the code in the editor, `\n` starts a new line. `random 6 42` is a random program
+    /// with 6 operations instead, the same one every time for the same seed 42.
+    Source(String),
+    /// Turn one optimization on or off, by the name of its pass like `folding` or `shifts`.
+    Toggle(Optimization, bool),
+    /// Use a predefined set of optimizations.
+    Level(OptLevel),
+    /// Set the value of an input variable.
+    Var(String, String),
+    Compile,
+    /// Run the program, the next step waits until the animation is done.
+    Run,
+    Wait(Duration),
+}
+
+/// An optimization a script can toggle one by one, which is one of the passes in the
+/// [`registry`], by its name.
+#[derive(Clone, Copy)]
+pub struct Optimization(&'static dyn Pass);
+
+impl Optimization {
+    /// Every optimization, in the order of the pipeline.
+    pub fn all() -> impl Iterator<Item = Optimization> {
+        registry().iter().map(|pass| Optimization(*pass))
+    }
+
+    /// The optimization with this name in scripts, e.g. `folding`.
+    pub fn named(name: &str) -> Option<Optimization> {
+        Self::all().find(|opt| opt.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        self.0.name()
+    }
+
+    /// The option this optimization is turned on with.
+    pub fn flag(self, options: &mut CompileOptions) -> &mut bool {
+        self.0.flag(options)
+    }
+
+    pub fn key(self) -> &'static str {
+        self.0.label()
+    }
+}
+
+impl PartialEq for Optimization {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl Eq for Optimization {}
+
+impl std::fmt::Debug for Optimization {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Which window a step is shown at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintTarget {
+    Editor,
+    Output,
+}
+
+impl DemoStep {
+    pub fn target(&self) -> HintTarget {
+        match self {
+            DemoStep::Run | DemoStep::Wait(_) => HintTarget::Output,
+            _ => HintTarget::Editor,
+        }
+    }
+
+    /// What's happening, in words for the visitors.
+    fn caption(&self) -> String {
+        match self {
+            DemoStep::Source(code) => t!("autopilot.source", code = code).into(),
+            DemoStep::Toggle(opt, true) => t!("autopilot.on", opt = t!(opt.key())).into(),
+            DemoStep::Toggle(opt, false) => t!("autopilot.off", opt = t!(opt.key())).into(),
+            DemoStep::Level(level) => t!("autopilot.level", level = level).into(),
+            DemoStep::Var(var, value) => t!("autopilot.var", var = var, value = value).into(),
+            DemoStep::Compile => t!("autopilot.compile").into(),
+            DemoStep::Run => t!("autopilot.run").into(),
+            DemoStep::Wait(_) => t!("autopilot.wait").into(),
+        }
+    }
+}
+
+/// Reads a demo script: one step per line, empty lines and lines starting with `#` are skipped.
+pub fn parse_script(script: &str) -> Result<Vec<DemoStep>, String> {
+    let mut steps = Vec::new();
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let err = || t!("autopilot.invalid", line = i + 1, text = line).to_string();
+        let step = match command {
+            "source" => DemoStep::Source(rest.replace("\\n", "\n")),
+            "random" => {
+                let (operations, seed) = rest.split_once(' ').ok_or_else(err)?;
+                let options = GenOptions {
+                    operations: operations.parse().map_err(|_| err())?,
+                    ..Default::default()
+                };
+                let seed = seed.trim().parse().map_err(|_| err())?;
+                DemoStep::Source(random_program(&mut Rng::with_seed(seed), &options).pretty())
+            }
+            "option" => {
+                let (name, state) = rest.split_once(' ').ok_or_else(err)?;
+                let opt = Optimization::named(name).ok_or_else(err)?;
+                match state.trim() {
+                    "on" => DemoStep::Toggle(opt, true),
+                    "off" => DemoStep::Toggle(opt, false),
+                    _ => return Err(err()),
+                }
+            }
+            "level" => OptLevel::ALL
+                .into_iter()
+                .find(|l| l.to_string().trim_start_matches('-') == rest)
+                .map(DemoStep::Level)
+                .ok_or_else(err)?,
+            "var" => {
+                let (var, value) = rest.split_once(' ').ok_or_else(err)?;
+                DemoStep::Var(var.into(), value.trim().into())
+            }
+            "compile" if rest.is_empty() => DemoStep::Compile,
+            "run" if rest.is_empty() => DemoStep::Run,
+            "wait" => {
+                let seconds: f32 = rest.parse().map_err(|_| err())?;
+                DemoStep::Wait(Duration::try_from_secs_f32(seconds).map_err(|_| err())?)
+            }
+            _ => return Err(err()),
+        };
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+/// Plays a demo script in a loop while nobody is using the app, as an attract mode for the booth.
+///
+/// Every step is announced with a pointer and a caption for a moment before it happens, and any
+/// input from a visitor stops the demo right away.
+pub struct Autopilot {
+    steps: Vec<DemoStep>,
+    /// Why `demo.txt` couldn't be used, the built-in script is played instead.
+    pub error: Option<String>,
+    /// Index of the next step, if the demo is playing.
+    next: Option<usize>,
+    /// The step being announced, and when it was announced.
+    announced: Option<(DemoStep, Instant)>,
+    /// The current step can't end before this, e.g. for `wait`.
+    until: Instant,
+    started: Instant,
+    last_input: Instant,
+}
+
+impl Default for Autopilot {
+    fn default() -> Self {
+        let built_in = || parse_script(BUILT_IN).expect("the built-in demo script is valid");
+        let (steps, error) = match std::fs::read_to_string(files::data_dir().join(DEMO_FILE)) {
+            Ok(script) => match parse_script(&script) {
+                Ok(steps) => (steps, None),
+                Err(e) => (built_in(), Some(e)),
+            },
+            Err(_) => (built_in(), None),
+        };
+        Self::with_steps(steps, error)
+    }
+}
+
+impl Autopilot {
+    fn with_steps(steps: Vec<DemoStep>, error: Option<String>) -> Self {
+        Self {
+            steps,
+            error,
+            next: None,
+            announced: None,
+            until: Instant::now(),
+            started: Instant::now(),
+            last_input: Instant::now(),
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.next.is_some()
+    }
+
+    pub fn start(&mut self) {
+        self.next = Some(0);
+        self.announced = None;
+        self.until = Instant::now();
+        self.started = Instant::now();
+    }
+
+    pub fn stop(&mut self) {
+        self.next = None;
+        self.announced = None;
+        self.last_input = Instant::now();
+    }
+
+    /// Stops the demo when a visitor does something, and starts it after a while without input.
+    pub fn watch_input(&mut self, ctx: &egui::Context) {
+        let touched = ctx.input(|i| {
+            i.events.iter().any(|e| {
+                matches!(
+                    e,
+                    Event::PointerMoved(_)
+                        | Event::PointerButton { .. }
+                        | Event::MouseWheel { .. }
+                        | Event::Touch { .. }
+                        | Event::Key { .. }
+                        | Event::Text(_)
+                )
+            })
+        });
+        if touched {
+            if !self.is_playing() || self.started.elapsed() >= GRACE_TIME {
+                self.stop();
+            }
+        } else if !self.is_playing() {
+            let idle = self.last_input.elapsed();
+            if idle >= IDLE_TIMEOUT {
+                self.start();
+            } else {
+                ctx.request_repaint_after(IDLE_TIMEOUT - idle);
+            }
+        }
+    }
+
+    /// The step to carry out now, if any. `busy` tells whether the app is still compiling or
+    /// animating, the next step waits for that.
+    pub fn next_step(&mut self, now: Instant, busy: bool) -> Option<DemoStep> {
+        let next = self.next?;
+        if busy || now < self.until || self.steps.is_empty() {
+            return None;
+        }
+        let Some((step, announced)) = &self.announced else {
+            let step = self.steps[next].clone();
+            self.announced = Some((step, now));
+            return None;
+        };
+        if now.duration_since(*announced) < HINT_TIME {
+            return None;
+        }
+
+        let step = step.clone();
+        self.announced = None;
+        self.until = match step {
+            DemoStep::Wait(time) => now + time,
+            _ => now,
+        };
+        // the demo loops until someone walks up to the booth
+        self.next = Some((next + 1) % self.steps.len());
+        Some(step)
+    }
+
+    /// Draws the pointer and caption of the announced step at `target`, the rectangle of the
+    /// window the step happens in.
+    pub fn show_hint(&self, ctx: &egui::Context, target: impl Fn(HintTarget) -> Option<Rect>) {
+        if !self.is_playing() {
+            return;
+        }
+        ctx.request_repaint();
+        let Some((step, announced)) = &self.announced else {
+            return;
+        };
+        let screen = ctx.screen_rect();
+        let rect = target(step.target()).unwrap_or(screen);
+
+        // the pointer glides in from the bottom of the screen and pulses once it's there
+        let t = (announced.elapsed().as_secs_f32() / HINT_TIME.as_secs_f32()).min(1.0);
+        let eased = 1.0 - (1.0 - t).powi(3);
+        let from = Pos2::new(rect.center().x, screen.bottom());
+        let pointer = from.lerp(rect.center(), eased);
+        let radius = 14.0 + 4.0 * (t * std::f32::consts::TAU * 2.0).sin().abs();
+
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("autopilot"),
+        ));
+        let color = ctx.style().visuals.selection.bg_fill;
+        painter.circle_stroke(pointer, radius, egui::Stroke::new(3.0, color));
+        painter.circle_filled(pointer, 5.0, color);
+
+        egui::Area::new(egui::Id::new("autopilot_caption"))
+            .fixed_pos(pointer + egui::vec2(radius + 8.0, -radius))
+            .order(egui::Order::Foreground)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(RichText::new(step.caption()).strong());
+                    ui.label(
+                        RichText::new(t!("autopilot.touch"))
+                            .small()
+                            .color(Color32::GRAY),
+                    );
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn built_in_script_is_valid() {
+        assert!(!parse_script(BUILT_IN).unwrap().is_empty());
+    }
+
+    #[test]
+    fn script_steps_are_parsed() {
+        let steps = parse_script(
+            "# comment\n\nsource a * 2\noption shifts on\nlevel O1\nvar a 7\ncompile\nrun\nwait 1.5",
+        )
+        .unwrap();
+        assert_eq!(
+            steps,
+            [
+                DemoStep::Source("a * 2".into()),
+                DemoStep::Toggle(Optimization::named("shifts").unwrap(), true),
+                DemoStep::Level(OptLevel::O1),
+                DemoStep::Var("a".into(), "7".into()),
+                DemoStep::Compile,
+                DemoStep::Run,
+                DemoStep::Wait(Duration::from_millis(1500)),
+            ]
+        );
+
+        // every step has a caption for the visitors
+        for step in &steps {
+            assert!(!step.caption().contains("autopilot."), "{step:?}");
+        }
+
+        for invalid in [
+            "jump 3",
+            "option turbo on",
+            "wait soon",
+            "level O9",
+            "run fast",
+            "random 4",
+        ] {
+            let Err(e) = parse_script(&format!("compile\n{invalid}")) else {
+                panic!("`{invalid}` should not be accepted");
+            };
+            assert!(e.contains('2'), "{e}");
+        }
+
+        // random programs are the same every time the demo comes around
+        let random = parse_script("random 6 42").unwrap();
+        assert_eq!(random, parse_script("random 6 42").unwrap());
+        assert_ne!(random, parse_script("random 6 43").unwrap());
+    }
+
+    #[test]
+    fn steps_are_announced_then_played_in_a_loop() {
+        let mut autopilot = Autopilot::with_steps(
+            vec![DemoStep::Compile, DemoStep::Wait(Duration::from_secs(5))],
+            None,
+        );
+        assert_eq!(autopilot.next_step(Instant::now(), false), None);
+
+        autopilot.start();
+        let start = Instant::now();
+        // the first call only announces the step
+        assert_eq!(autopilot.next_step(start, false), None);
+        assert_eq!(autopilot.next_step(start + HINT_TIME / 2, false), None);
+        assert_eq!(autopilot.next_step(start + HINT_TIME, true), None);
+        let now = start + HINT_TIME;
+        assert_eq!(autopilot.next_step(now, false), Some(DemoStep::Compile));
+
+        autopilot.next_step(now, false);
+        let now = now + HINT_TIME;
+        let wait = autopilot.next_step(now, false);
+        assert_eq!(wait, Some(DemoStep::Wait(Duration::from_secs(5))));
+
+        // waiting holds back the next step, then the script starts over
+        autopilot.next_step(now + Duration::from_secs(4), false);
+        assert!(autopilot.announced.is_none());
+        let now = now + Duration::from_secs(5);
+        autopilot.next_step(now, false);
+        assert_eq!(
+            autopilot.next_step(now + HINT_TIME, false),
+            Some(DemoStep::Compile)
+        );
+
+        autopilot.stop();
+        assert!(!autopilot.is_playing());
+    }
+}
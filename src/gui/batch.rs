@@ -0,0 +1,418 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::RangeInclusive;
+use std::thread::JoinHandle;
+
+use crate::compiler::Inst;
+use crate::gui::{InterpreterOptions, Theme};
+use crate::interpreter::Interpreter;
+use eframe::egui::{self, Color32, Id, RichText};
+use rust_i18n::t;
+
+/// Most inputs the programs are run for at once, so the GUI stays responsive.
+const MAX_INPUTS: usize = 1000;
+
+/// Line colors of the programs after the first one, which uses the theme's accent color.
+const COLORS: [Color32; 4] = [
+    Color32::from_rgb(80, 160, 255),
+    Color32::from_rgb(80, 200, 120),
+    Color32::from_rgb(220, 80, 220),
+    Color32::from_rgb(200, 200, 80),
+];
+
+/// A compiled program to evaluate, with the title of the output it's shown in.
+#[derive(Clone)]
+pub struct BatchProgram {
+    pub title: String,
+    pub instructions: Vec<Inst>,
+}
+
+/// Results of all programs for one value of the swept variable.
+struct Row {
+    input: i32,
+    results: Vec<Result<i32, String>>,
+    /// Simulated cycles each program ran for, see [`Inst::cycles`].
+    cycles: Vec<u64>,
+}
+
+/// What the plot shows over the input.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Plot {
+    Results,
+    Cycles,
+}
+
+impl Row {
+    /// Whether all programs computed the same result.
+    fn agrees(&self) -> bool {
+        self.results.windows(2).all(|w| w[0] == w[1])
+    }
+}
+
+/// Runs the compiled programs over a range of values for one variable, to show they compute the
+/// same thing everywhere.
+pub struct BatchRun {
+    programs: Vec<BatchProgram>,
+    hw: InterpreterOptions,
+    /// Values of the variables that aren't swept.
+    fixed: HashMap<String, String>,
+    variable: String,
+    range: (i32, i32),
+    rows: Vec<Row>,
+    plot: Plot,
+    /// The sweep running in the background, large ranges can take a while.
+    pending: Option<JoinHandle<Vec<Row>>>,
+}
+
+impl Default for BatchRun {
+    fn default() -> Self {
+        Self {
+            programs: Vec::new(),
+            hw: InterpreterOptions::default(),
+            fixed: HashMap::new(),
+            variable: String::new(),
+            range: (0, 20),
+            rows: Vec::new(),
+            plot: Plot::Results,
+            pending: None,
+        }
+    }
+}
+
+impl BatchRun {
+    /// Programs compiled from the same source, with its input variables.
+    pub fn set(
+        &mut self,
+        programs: Vec<BatchProgram>,
+        hw: InterpreterOptions,
+        variables: &HashMap<String, String>,
+    ) {
+        self.programs = programs;
+        self.hw = hw;
+        self.fixed = variables.clone();
+        if !self.fixed.contains_key(&self.variable) {
+            self.variable = self.fixed.keys().min().cloned().unwrap_or_default();
+        }
+        self.clear_results();
+    }
+
+    pub fn clear(&mut self) {
+        self.programs.clear();
+        self.fixed.clear();
+        self.clear_results();
+    }
+
+    /// Drops the rows, also those of a sweep of the previous programs that's still running.
+    fn clear_results(&mut self) {
+        self.rows.clear();
+        self.pending = None;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.programs.is_empty() {
+            ui.label(t!("output.empty"));
+            return;
+        }
+
+        ui.label(t!("batch.explain"));
+        ui.add_space(8.0);
+
+        egui::Grid::new("batch_inputs")
+            .num_columns(2)
+            .spacing([20.0, 4.0])
+            .show(ui, |ui| {
+                if !self.fixed.is_empty() {
+                    ui.label(t!("batch.variable"));
+                    let names: BTreeSet<_> = self.fixed.keys().cloned().collect();
+                    egui::ComboBox::from_id_salt("batch_variable")
+                        .selected_text(&self.variable)
+                        .show_ui(ui, |ui| {
+                            for name in names {
+                                ui.selectable_value(&mut self.variable, name.clone(), name);
+                            }
+                        });
+                    ui.end_row();
+                }
+
+                let (low, high) = &mut self.range;
+                ui.label(t!("batch.range"));
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(low).range(i32::MIN..=*high));
+                    ui.label("..=");
+                    ui.add(egui::DragValue::new(high).range(*low..=i32::MAX));
+                });
+                ui.end_row();
+
+                for (var, val) in self.fixed.iter_mut() {
+                    if *var != self.variable {
+                        ui.label(var.as_str());
+                        ui.text_edit_singleline(val);
+                        ui.end_row();
+                    }
+                }
+            });
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.pending.is_none(), egui::Button::new(t!("batch.run")))
+                .clicked()
+            {
+                let (low, high) = self.range;
+                let high = high.min(low.saturating_add(MAX_INPUTS as i32 - 1));
+                let (programs, hw) = (self.programs.clone(), self.hw);
+                let (var, fixed) = (self.variable.clone(), self.fixed.clone());
+                self.pending = Some(std::thread::spawn(move || {
+                    sweep(&programs, &hw, &var, low..=high, &fixed)
+                }));
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+        });
+
+        if self.pending.as_ref().is_some_and(JoinHandle::is_finished) {
+            let pending = self.pending.take().unwrap();
+            self.rows = pending.join().unwrap_or_default();
+        } else if self.pending.is_some() {
+            ui.ctx().request_repaint();
+        }
+
+        if self.rows.is_empty() {
+            return;
+        }
+
+        ui.separator();
+        let disagreeing = self.rows.iter().filter(|r| !r.agrees()).count();
+        if disagreeing == 0 {
+            ui.label(t!("batch.agree", n = self.rows.len()));
+        } else {
+            ui.colored_label(
+                Color32::RED,
+                t!("batch.disagree", k = disagreeing, n = self.rows.len()),
+            );
+        }
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.plot, Plot::Results, t!("batch.plot.results"));
+            ui.selectable_value(&mut self.plot, Plot::Cycles, t!("batch.plot.cycles"))
+                .on_hover_text(t!("batch.plot.cycles.desc"));
+        });
+        match self.plot {
+            Plot::Results => draw_plot(ui, &self.rows, |row, p| {
+                row.results[p].as_ref().ok().map(|&v| i64::from(v))
+            }),
+            Plot::Cycles => draw_plot(ui, &self.rows, |row, p| Some(row.cycles[p] as i64)),
+        }
+        ui.horizontal_wrapped(|ui| {
+            for (i, program) in self.programs.iter().enumerate() {
+                ui.label(RichText::new(format!("━ {}", program.title)).color(color(i)));
+            }
+        });
+
+        ui.separator();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("batch_results")
+                .num_columns(self.programs.len() + 1)
+                .striped(true)
+                .spacing([20.0, 4.0])
+                .show(ui, |ui| {
+                    ui.strong(&self.variable);
+                    for program in &self.programs {
+                        ui.strong(&program.title);
+                    }
+                    ui.end_row();
+
+                    for row in &self.rows {
+                        ui.label(row.input.to_string());
+                        let agrees = row.agrees();
+                        for result in &row.results {
+                            let text = match result {
+                                Ok(n) => RichText::new(n.to_string()),
+                                Err(_) => RichText::new("⚠"),
+                            };
+                            let text = if agrees {
+                                text
+                            } else {
+                                text.color(Color32::RED)
+                            };
+                            let label = ui.label(text);
+                            if let Err(e) = result {
+                                label.on_hover_text(e);
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+fn color(program: usize) -> Color32 {
+    match program {
+        0 => Theme::global().accent(),
+        n => COLORS[(n - 1) % COLORS.len()],
+    }
+}
+
+/// Runs all programs for each input value of `var`, the other variables are taken from `fixed`.
+fn sweep(
+    programs: &[BatchProgram],
+    hw: &InterpreterOptions,
+    var: &str,
+    inputs: RangeInclusive<i32>,
+    fixed: &HashMap<String, String>,
+) -> Vec<Row> {
+    inputs
+        .map(|input| {
+            let mut vars = fixed.clone();
+            if !var.is_empty() {
+                vars.insert(var.to_string(), input.to_string());
+            }
+            let (results, cycles) = programs
+                .iter()
+                .map(|program| {
+                    let mut interpreter = Interpreter::with_config(hw)
+                        .load_instructions(program.instructions.clone())
+                        .with_variables(vars.clone())
+                        .ready();
+                    let result = interpreter.run_to_end().map_err(|e| e.to_string());
+                    (result, interpreter.stats().cycles)
+                })
+                .unzip();
+            Row {
+                input,
+                results,
+                cycles,
+            }
+        })
+        .collect()
+}
+
+/// Line chart of a value of each program over the input.
+fn draw_plot(ui: &mut egui::Ui, rows: &[Row], value: impl Fn(&Row, usize) -> Option<i64>) {
+    let (response, painter) = ui.allocate_painter(
+        egui::vec2(ui.available_width(), 160.0),
+        egui::Sense::hover(),
+    );
+    let rect = response.rect.shrink(8.0);
+    let visuals = ui.visuals();
+    painter.rect_filled(response.rect, 2.0, visuals.extreme_bg_color);
+
+    let programs = rows[0].results.len();
+    let values = || {
+        rows.iter()
+            .flat_map(|r| (0..programs).filter_map(|p| value(r, p)))
+    };
+    let (Some(min), Some(max)) = (values().min(), values().max()) else {
+        return;
+    };
+    let (first, last) = (rows[0].input, rows[rows.len() - 1].input);
+
+    let x = |input: i32| {
+        let t = if first == last {
+            0.5
+        } else {
+            (input - first) as f32 / (last - first) as f32
+        };
+        rect.left() + t * rect.width()
+    };
+    let y = |v: i64| {
+        let t = if min == max {
+            0.5
+        } else {
+            (v - min) as f64 / (max - min) as f64
+        };
+        rect.bottom() - t as f32 * rect.height()
+    };
+
+    for program in 0..programs {
+        let points: Vec<_> = rows
+            .iter()
+            .filter_map(|r| Some(egui::pos2(x(r.input), y(value(r, program)?))))
+            .collect();
+        // later programs are drawn thinner on top, so matching lines stay visible
+        let width = 1.5 + 2.0 * (programs - program - 1) as f32;
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(width, color(program)),
+        ));
+    }
+
+    let font = egui::TextStyle::Small.resolve(ui.style());
+    let text = visuals.weak_text_color();
+    painter.text(
+        rect.left_top(),
+        egui::Align2::LEFT_TOP,
+        max.to_string(),
+        font.clone(),
+        text,
+    );
+    painter.text(
+        rect.left_bottom(),
+        egui::Align2::LEFT_BOTTOM,
+        min.to_string(),
+        font.clone(),
+        text,
+    );
+    painter.text(
+        rect.right_bottom(),
+        egui::Align2::RIGHT_BOTTOM,
+        last.to_string(),
+        font,
+        text,
+    );
+}
+
+impl crate::gui::Window for BatchRun {
+    fn name(&self) -> String {
+        "batch.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(420.0)
+            .default_height(500.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::{CompileOptions, Compiler, LpErr, OptLevel};
+
+    #[test]
+    fn optimized_program_agrees_everywhere() -> Result<(), LpErr> {
+        let compile = |level| -> Result<BatchProgram, LpErr> {
+            let opts = CompileOptions::default().with_level(level);
+            Ok(BatchProgram {
+                title: level.to_string(),
+                instructions: Compiler::with(opts)
+                    .compile("a * 8 + b / (a + 1)")?
+                    .instructions,
+            })
+        };
+        let programs = [compile(OptLevel::O0)?, compile(OptLevel::O2)?];
+
+        let fixed = [("b".to_string(), "12".to_string())].into();
+        let rows = sweep(
+            &programs,
+            &InterpreterOptions::default(),
+            "a",
+            -3..=5,
+            &fixed,
+        );
+        assert_eq!(rows.len(), 9);
+        // a = -1 divides by zero in both programs
+        assert!(rows.iter().all(Row::agrees));
+        assert_eq!(rows[5].results[0], Ok(2 * 8 + 12 / 3));
+        // shifting instead of multiplying makes the optimized program faster
+        assert!(rows[5].cycles[1] < rows[5].cycles[0]);
+        Ok(())
+    }
+}
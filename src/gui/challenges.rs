@@ -0,0 +1,114 @@
+use crate::challenges::{CHALLENGES, Challenge, Report};
+use crate::compiler::{CompileOptions, Compiler};
+use crate::gui::InterpreterOptions;
+use crate::passes::RewriteRule;
+use eframe::egui::{self, Color32, Id, RichText};
+use rust_i18n::t;
+
+/// Window to pick a challenge and check the program in the code editor against it.
+#[derive(Default)]
+pub struct ChallengeView {
+    selected: usize,
+    /// Set when the user wants the program checked, see [`ChallengeView::check`].
+    pub check_requested: bool,
+    report: Option<Result<Report, String>>,
+}
+
+impl ChallengeView {
+    pub fn challenge(&self) -> &'static Challenge {
+        &CHALLENGES[self.selected]
+    }
+
+    /// Compiles the program and runs it against the hidden test vectors.
+    pub fn check(
+        &mut self,
+        source: &str,
+        opts: CompileOptions,
+        hw: InterpreterOptions,
+        rules: Vec<RewriteRule>,
+    ) {
+        self.check_requested = false;
+        self.report = Some(
+            Compiler::with(opts)
+                .with_interpreter(hw)
+                .with_rules(rules)
+                .compile(source)
+                .map(|ir| self.challenge().check(&ir))
+                .map_err(|e| e.to_string()),
+        );
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let before = self.selected;
+        egui::ComboBox::from_label(t!("challenge.pick"))
+            .selected_text(t!(self.challenge().title))
+            .show_ui(ui, |ui| {
+                for (i, challenge) in CHALLENGES.iter().enumerate() {
+                    ui.selectable_value(&mut self.selected, i, t!(challenge.title));
+                }
+            });
+        if self.selected != before {
+            self.report = None;
+        }
+
+        let challenge = self.challenge();
+        ui.label(RichText::new(t!(challenge.description)).strong());
+        ui.label(t!("challenge.inputs", inputs = challenge.inputs.join(", ")));
+        ui.label(t!("challenge.budget", cycles = challenge.cycle_budget));
+        ui.separator();
+
+        ui.label(t!("challenge.explain"));
+        if ui.button(t!("challenge.check")).clicked() {
+            self.check_requested = true;
+        }
+
+        match &self.report {
+            None => {}
+            Some(Err(e)) => {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+            Some(Ok(report)) => {
+                let color = |ok| if ok { Color32::GREEN } else { Color32::RED };
+                ui.label(
+                    RichText::new(t!(
+                        "challenge.passed",
+                        passed = report.passed,
+                        total = report.total
+                    ))
+                    .color(color(report.all_passed())),
+                );
+                ui.label(
+                    RichText::new(t!(
+                        "challenge.cycles",
+                        cycles = report.cycles,
+                        budget = report.cycle_budget
+                    ))
+                    .color(color(report.within_budget())),
+                );
+                if report.solved() {
+                    ui.heading(t!("challenge.solved"));
+                } else if report.all_passed() {
+                    ui.label(t!("challenge.too_slow"));
+                }
+            }
+        }
+    }
+}
+
+impl crate::gui::Window for ChallengeView {
+    fn name(&self) -> String {
+        "challenge.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(350.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
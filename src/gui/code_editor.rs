@@ -1,13 +1,25 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
-use crate::compiler::CompileOptions;
-use eframe::egui::{self, Align, Id, Layout, Modifiers};
+use crate::compile_service::SourceStats;
+use crate::compiler::{CompileOptions, OptLevel};
+use crate::generator::{GenOptions, Rng, random_program};
+use crate::parser::{self, Diagnostic, Severity};
+use crate::passes::registry;
+use crate::ranges::{Hints, ValueRange};
+use crate::source_map::Span;
+use crate::symbols::ConstTable;
+use eframe::egui::text::{LayoutJob, TextFormat};
+use eframe::egui::{self, Align, Id, Key, KeyboardShortcut, Layout, Modifiers};
 use rust_i18n::t;
 
 /// Actions that can be triggered by the editor window.
 pub enum EditorAction {
     /// Run the compilation process.
     Compile,
+    /// Compile again because something changed, e.g. while typing. Unlike [`Self::Compile`] it
+    /// doesn't open the outputs the user closed.
+    Recompile,
     /// Start execution of all generated IR versions.
     /// The enclosed boolean value determines whether the execution is stepwise (`true`) or not (`false`).
     Run(bool),
@@ -15,12 +27,45 @@ pub enum EditorAction {
     Clear,
 }
 
+const COMPILE_RUN: KeyboardShortcut = KeyboardShortcut::new(Modifiers::CTRL, Key::Enter);
+const COMPILE: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F5);
+const RUN: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F6);
+const STEP: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F7);
+const CLEAR: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F8);
+
+/// How long to wait after the last edit before compiling live.
+const LIVE_DELAY: Duration = Duration::from_millis(300);
+
 pub struct CodeEditor {
     pub code: String,
     pub compile_options: CompileOptions,
     pub actions: Vec<EditorAction>,
     pub input_variables: HashMap<String, String>,
+    /// The input variables in the order they occur in the code, with how often they occur.
+    variable_order: Vec<(String, usize)>,
+    /// Input variables used as divisors, random inputs for these are never zero.
+    pub divisors: HashSet<String>,
+    /// What the user promises about the values of input variables, the optimizer relies on it.
+    pub hints: Hints,
+    /// Smallest and largest value for random inputs.
+    random_range: (i32, i32),
     pub disable_run: bool,
+    /// Leaves out the compile options, e.g. while the tutorial didn't get to them yet.
+    pub hide_options: bool,
+    /// Parts of the code to highlight, e.g. where the hovered instruction came from.
+    pub highlight: Vec<Span>,
+    /// Byte offset of the code under the mouse.
+    pub hovered: Option<usize>,
+    /// Problems found in the code when it was last compiled.
+    pub diagnostics: Vec<Diagnostic>,
+    /// The constants declared in the code when it was last compiled.
+    pub constants: ConstTable,
+    /// How large the code was when it was last compiled.
+    pub stats: SourceStats,
+    /// Compile automatically shortly after typing, can be turned off on slow machines.
+    live: bool,
+    /// When the code or options were last changed, if that wasn't compiled yet.
+    edited: Option<Instant>,
 }
 
 impl Default for CodeEditor {
@@ -34,7 +79,19 @@ impl Default for CodeEditor {
             compile_options,
             actions: vec![],
             input_variables: HashMap::new(),
+            variable_order: vec![],
+            divisors: HashSet::new(),
+            hints: Hints::new(),
+            random_range: (-20, 20),
             disable_run: false,
+            hide_options: false,
+            highlight: vec![],
+            hovered: None,
+            diagnostics: vec![],
+            constants: ConstTable::default(),
+            stats: SourceStats::default(),
+            live: true,
+            edited: None,
         }
     }
 }
@@ -44,6 +101,10 @@ impl crate::gui::Window for CodeEditor {
         "editor.name".into()
     }
 
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
     fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
         egui::Window::new(t!(self.name()))
             .id(Id::new(self.name()))
@@ -54,89 +115,456 @@ impl crate::gui::Window for CodeEditor {
 }
 
 impl CodeEditor {
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
-        let compile_run_shortcut = egui::KeyboardShortcut::new(Modifiers::CTRL, egui::Key::Enter);
+    /// Shows inputs for these variables, in this order. Values of variables that are still used
+    /// are kept, so live compiles don't wipe them.
+    pub fn set_variables(&mut self, vars: Vec<(String, usize)>) {
+        let old = std::mem::take(&mut self.input_variables);
+        self.input_variables = vars
+            .iter()
+            .map(|(var, _)| (var.clone(), old.get(var).cloned().unwrap_or_default()))
+            .collect();
+        self.hints
+            .retain(|var, _| self.input_variables.contains_key(var));
+        self.variable_order = vars;
+    }
+
+    /// Keyboard shortcuts for all editor actions, these work even if the window is closed.
+    pub fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        let pressed = |shortcut| ctx.input_mut(|i| i.consume_shortcut(shortcut));
 
-        if ui.input_mut(|i| i.consume_shortcut(&compile_run_shortcut)) {
+        if pressed(&COMPILE_RUN) {
             self.actions.push(EditorAction::Compile);
             self.actions.push(EditorAction::Run(false));
         }
+        if pressed(&COMPILE) {
+            self.actions.push(EditorAction::Compile);
+        }
+        if pressed(&RUN) && !self.disable_run {
+            self.actions.push(EditorAction::Run(false));
+        }
+        if pressed(&STEP) && !self.disable_run {
+            self.actions.push(EditorAction::Run(true));
+        }
+        if pressed(&CLEAR) {
+            self.actions.push(EditorAction::Clear);
+        }
+
+        if let Some(edited) = self.edited {
+            let waited = edited.elapsed();
+            if waited >= LIVE_DELAY {
+                self.edited = None;
+                self.actions.push(EditorAction::Recompile);
+            } else {
+                ctx.request_repaint_after(LIVE_DELAY - waited);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let ctx = ui.ctx().clone();
+        let shortcut = |s| ctx.format_shortcut(s);
 
         ui.horizontal(|ui| {
             ui.set_height(0.0);
             ui.label(t!("editor.explain"));
         });
 
-        egui::ScrollArea::vertical().show(ui, |ui| {
-            ui.add(
+        let highlight = &self.highlight;
+        // errors at the end of the input have empty spans, underline the last character instead
+        let spans = |severity| -> Vec<Span> {
+            self.diagnostics
+                .iter()
+                .filter(|d| d.severity == severity)
+                .map(|d| d.span.start.min(d.span.end.saturating_sub(1))..d.span.end)
+                .collect()
+        };
+        let (errors, warnings) = (spans(Severity::Error), spans(Severity::Warning));
+        let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+            let mut job = highlighted(ui, text, highlight, &errors, &warnings);
+            job.wrap.max_width = wrap_width;
+            ui.fonts(|f| f.layout_job(job))
+        };
+        let options_before = self.compile_options;
+        let output = egui::ScrollArea::vertical()
+            .show(ui, |ui| {
                 egui::TextEdit::multiline(&mut self.code)
                     .font(egui::TextStyle::Monospace) // for cursor height
                     .code_editor()
                     .desired_rows(10)
                     .lock_focus(true)
-                    .desired_width(f32::INFINITY),
-            );
-        });
+                    .desired_width(f32::INFINITY)
+                    .layouter(&mut layouter)
+                    .show(ui)
+            })
+            .inner;
 
-        ui.vertical(|ui| {
-            ui.checkbox(
-                &mut self.compile_options.do_constant_folding,
-                t!("editor.constant_folding"),
-            );
-            ui.checkbox(
-                &mut self.compile_options.run_cache_optimization,
-                t!("editor.cache_opt"),
-            );
-            ui.checkbox(
-                &mut self.compile_options.do_common_factor_elimination,
-                t!("editor.common_factor_elimination"),
-            );
-            ui.checkbox(
-                &mut self.compile_options.do_shift_replacement,
-                t!("editor.replace_mul_with_shift"),
-            );
+        self.hovered = output.response.hover_pos().and_then(|pos| {
+            let rect = output.galley.rect.translate(output.galley_pos.to_vec2());
+            if !rect.contains(pos) {
+                return None;
+            }
+            let cursor = output.galley.cursor_from_pos(pos - output.galley_pos);
+            self.code
+                .char_indices()
+                .nth(cursor.ccursor.index)
+                .map(|(i, _)| i)
         });
 
+        if self.stats.tokens > 0 {
+            let stats = self.stats;
+            let mut parts = vec![t!("editor.stats.tokens", n = stats.tokens)];
+            if let Some((nodes, depth)) = stats.tree {
+                parts.push(t!("editor.stats.tree", nodes = nodes, depth = depth));
+            }
+            if let Some(n) = stats.instructions {
+                parts.push(t!("editor.stats.instructions", n = n));
+            }
+            ui.weak(parts.join(" · "))
+                .on_hover_text(t!("editor.stats.desc"));
+        }
+
+        for diagnostic in &self.diagnostics {
+            let color = match diagnostic.severity {
+                Severity::Error => ui.visuals().error_fg_color,
+                Severity::Warning => ui.visuals().warn_fg_color,
+            };
+            ui.colored_label(color, &diagnostic.message);
+        }
+
+        if !self.constants.is_empty() {
+            ui.label(t!("editor.constants", n = self.constants.len()))
+                .on_hover_ui(|ui| {
+                    egui::Grid::new("constants")
+                        .num_columns(2)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (name, value) in self.constants.iter() {
+                                ui.monospace(name);
+                                ui.monospace(value.to_string());
+                                ui.end_row();
+                            }
+                        });
+                });
+        }
+
+        if !self.hide_options {
+            compile_options_ui(ui, &mut self.compile_options);
+            ui.horizontal(|ui| {
+                ui.label(t!("editor.max_nesting"));
+                ui.add(
+                    egui::DragValue::new(&mut self.compile_options.max_nesting)
+                        .range(1..=parser::MAX_NESTING),
+                )
+                .on_hover_text(t!("editor.max_nesting.desc"));
+            });
+            ui.horizontal(|ui| {
+                ui.label(t!("editor.max_tokens"));
+                ui.add(
+                    egui::DragValue::new(&mut self.compile_options.max_tokens).range(1..=1_000_000),
+                )
+                .on_hover_text(t!("editor.max_tokens.desc"));
+                ui.label(t!("editor.max_nodes"));
+                ui.add(
+                    egui::DragValue::new(&mut self.compile_options.max_nodes).range(1..=1_000_000),
+                )
+                .on_hover_text(t!("editor.max_nodes.desc"));
+            });
+        }
+
+        if self.live && (output.response.changed() || self.compile_options != options_before) {
+            self.edited = Some(Instant::now());
+        }
+
         ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
-            if ui.button(t!("editor.compile")).clicked() {
+            if ui
+                .add(egui::Button::new(t!("editor.compile")).shortcut_text(shortcut(&COMPILE)))
+                .clicked()
+            {
                 self.actions.push(EditorAction::Compile);
             }
 
             if ui
-                .add_enabled(!self.disable_run, egui::Button::new(t!("editor.run")))
-                .on_hover_text(t!("editor.run.alt"))
+                .add_enabled(
+                    !self.disable_run,
+                    egui::Button::new(t!("editor.run")).shortcut_text(shortcut(&RUN)),
+                )
+                .on_hover_text(t!("editor.run.alt", shortcut = shortcut(&COMPILE_RUN)))
                 .clicked()
             {
                 self.actions.push(EditorAction::Run(false));
             }
 
             if ui
-                .add_enabled(!self.disable_run, egui::Button::new(t!("editor.step")))
+                .add_enabled(
+                    !self.disable_run,
+                    egui::Button::new(t!("editor.step")).shortcut_text(shortcut(&STEP)),
+                )
                 .clicked()
             {
                 self.actions.push(EditorAction::Run(true));
             }
 
-            if ui.button(t!("editor.clear")).clicked() {
+            if ui
+                .add(egui::Button::new(t!("editor.clear")).shortcut_text(shortcut(&CLEAR)))
+                .clicked()
+            {
                 self.actions.push(EditorAction::Clear);
             }
+
+            if ui
+                .button(t!("editor.surprise"))
+                .on_hover_text(t!("editor.surprise.desc"))
+                .clicked()
+            {
+                let program = random_program(&mut Rng::from_time(), &GenOptions::default());
+                self.code = program.pretty();
+                self.actions.push(EditorAction::Compile);
+            }
+
+            if ui
+                .button(t!("editor.format"))
+                .on_hover_text(t!("editor.format.desc"))
+                .clicked()
+            {
+                match parser::format(&self.code) {
+                    Ok(code) if code != self.code => {
+                        self.code = code;
+                        if self.live {
+                            self.edited = Some(Instant::now());
+                        }
+                    }
+                    Ok(_) => {}
+                    // compiling shows what's wrong in the diagnostics
+                    Err(_) => self.actions.push(EditorAction::Compile),
+                }
+            }
+
+            if ui
+                .checkbox(&mut self.live, t!("editor.live"))
+                .on_hover_text(t!("editor.live.desc"))
+                .changed()
+            {
+                self.edited = None;
+            }
         });
 
         if !self.input_variables.is_empty() {
             ui.separator();
             ui.heading(t!("editor.inputs"));
 
+            ui.horizontal(|ui| {
+                if ui
+                    .button(t!("editor.random"))
+                    .on_hover_text(t!("editor.random.desc"))
+                    .clicked()
+                {
+                    let mut rng = Rng::from_time();
+                    for (var, val) in self.input_variables.iter_mut() {
+                        let range = within_hint(self.random_range, self.hints.get(var));
+                        let n = random_input(&mut rng, range, self.divisors.contains(var));
+                        *val = n.to_string();
+                    }
+                }
+                let (low, high) = &mut self.random_range;
+                ui.label(t!("editor.random.from"));
+                ui.add(egui::DragValue::new(low).range(i32::MIN..=*high));
+                ui.label(t!("editor.random.to"));
+                ui.add(egui::DragValue::new(high).range(*low..=i32::MAX));
+            });
+
+            let mut hints_changed = false;
             egui::Grid::new("vars")
-                .num_columns(2)
+                .num_columns(3)
                 .spacing([40.0, 4.0])
                 .striped(true)
                 .show(ui, |ui| {
-                    for (var, val) in self.input_variables.iter_mut() {
-                        ui.label(var);
+                    for (var, count) in &self.variable_order {
+                        let Some(val) = self.input_variables.get_mut(var) else {
+                            continue;
+                        };
+                        ui.label(var)
+                            .on_hover_text(t!("editor.occurrences", n = count));
                         ui.text_edit_singleline(val);
+                        ui.horizontal(|ui| {
+                            let mut hint = self.hints.get(var).copied();
+                            if hint_ui(ui, var, &mut hint, self.random_range) {
+                                hints_changed = true;
+                                match hint {
+                                    Some(range) => self.hints.insert(var.clone(), range),
+                                    None => self.hints.remove(var),
+                                };
+                            }
+                            if let (Some(range), Ok(n)) = (hint, val.trim().parse())
+                                && !range.contains(n)
+                            {
+                                ui.colored_label(ui.visuals().warn_fg_color, "⚠")
+                                    .on_hover_text(t!("editor.hint.violated"));
+                            }
+                        });
                         ui.end_row();
                     }
                 });
+            if self.live && hints_changed {
+                self.edited = Some(Instant::now());
+            }
+        }
+    }
+}
+
+/// Optimization level and individual passes, shared by the editor and the comparison outputs.
+pub fn compile_options_ui(ui: &mut egui::Ui, options: &mut CompileOptions) {
+    ui.horizontal(|ui| {
+        ui.label(t!("editor.opt_level"));
+        let current = options.level();
+        for level in OptLevel::ALL {
+            if ui
+                .selectable_label(current == Some(level), level.to_string())
+                .on_hover_text(t!(format!("editor.opt_level.{level:?}")))
+                .clicked()
+            {
+                *options = options.with_level(level);
+            }
+        }
+    });
+
+    ui.vertical(|ui| {
+        for pass in registry() {
+            ui.checkbox(pass.flag(options), t!(pass.label()))
+                .on_hover_text(t!(pass.description()));
+        }
+    });
+}
+
+/// A random value in the (inclusive) range, never zero for divisors unless that's all there is.
+fn random_input(rng: &mut Rng, (low, high): (i32, i32), divisor: bool) -> i32 {
+    loop {
+        let n = rng.range(low, high);
+        if !divisor || n != 0 || (low, high) == (0, 0) {
+            return n;
+        }
+    }
+}
+
+/// The part of `range` inside the hint, or the hint itself if they don't overlap.
+fn within_hint((low, high): (i32, i32), hint: Option<&ValueRange>) -> (i32, i32) {
+    let Some(hint) = hint else {
+        return (low, high);
+    };
+    if low <= hint.max && hint.min <= high {
+        (low.max(hint.min), high.min(hint.max))
+    } else {
+        // as many of the smallest values as the range would have
+        let width = i32::try_from(high.abs_diff(low)).unwrap_or(i32::MAX);
+        (hint.min, hint.max.min(hint.min.saturating_add(width)))
+    }
+}
+
+/// Choice of what's known about a variable, `None` is nothing. Returns whether it was changed.
+fn hint_ui(
+    ui: &mut egui::Ui,
+    var: &str,
+    hint: &mut Option<ValueRange>,
+    default: (i32, i32),
+) -> bool {
+    let before = *hint;
+    let label = match *hint {
+        None => t!("editor.hint.any"),
+        Some(ValueRange::NON_NEGATIVE) => t!("editor.hint.non_negative"),
+        Some(_) => t!("editor.hint.range"),
+    };
+    egui::ComboBox::from_id_salt(("hint", var))
+        .selected_text(label)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(hint, None, t!("editor.hint.any"));
+            ui.selectable_value(
+                hint,
+                Some(ValueRange::NON_NEGATIVE),
+                t!("editor.hint.non_negative"),
+            );
+            let range = ValueRange {
+                min: default.0,
+                max: default.1,
+            };
+            let custom = hint.is_some_and(|h| h != ValueRange::NON_NEGATIVE);
+            if ui
+                .selectable_label(custom, t!("editor.hint.range"))
+                .clicked()
+                && !custom
+            {
+                *hint = Some(range);
+            }
+        })
+        .response
+        .on_hover_text(t!("editor.hint.desc"));
+    if let Some(range) = hint
+        && *range != ValueRange::NON_NEGATIVE
+    {
+        let ValueRange { min, max } = range;
+        ui.add(egui::DragValue::new(min).range(i32::MIN..=*max));
+        ui.label("..=");
+        ui.add(egui::DragValue::new(max).range(*min..=i32::MAX));
+    }
+    *hint != before
+}
+
+/// Monospace text with the given spans highlighted, and errors underlined.
+fn highlighted(
+    ui: &egui::Ui,
+    text: &str,
+    highlight: &[Span],
+    errors: &[Span],
+    warnings: &[Span],
+) -> LayoutJob {
+    let normal = TextFormat::simple(
+        egui::TextStyle::Monospace.resolve(ui.style()),
+        ui.visuals().text_color(),
+    );
+    let marked = TextFormat {
+        background: ui.visuals().selection.bg_fill,
+        ..normal.clone()
+    };
+
+    let underline = egui::Stroke::new(2.0, ui.visuals().error_fg_color);
+    let warning = egui::Stroke::new(2.0, ui.visuals().warn_fg_color);
+
+    // the spans may be outdated if the code was edited since compiling
+    let mut cuts: Vec<usize> = highlight
+        .iter()
+        .chain(errors)
+        .chain(warnings)
+        .flat_map(|s| [s.start, s.end])
+        .chain([0, text.len()])
+        .filter(|&i| text.is_char_boundary(i))
+        .collect();
+    cuts.sort_unstable();
+    cuts.dedup();
+
+    let mut job = LayoutJob::default();
+    for w in cuts.windows(2) {
+        let (start, end) = (w[0], w[1]);
+        let within = |spans: &[Span]| spans.iter().any(|s| s.start <= start && end <= s.end);
+        let mut format = if within(highlight) { &marked } else { &normal }.clone();
+        if within(errors) {
+            format.underline = underline;
+        } else if within(warnings) {
+            format.underline = warning;
+        }
+        job.append(&text[start..end], 0.0, format);
+    }
+    job
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn random_divisors_are_never_zero() {
+        let mut rng = Rng::with_seed(3);
+        for _ in 0..200 {
+            let n = random_input(&mut rng, (-1, 1), true);
+            assert!((-1..=1).contains(&n) && n != 0);
         }
+        assert_eq!(random_input(&mut rng, (0, 0), true), 0);
     }
 }
@@ -0,0 +1,94 @@
+use crate::compile_log::{CompileLog, Entry, Verbosity};
+use eframe::egui::{self, Id, RichText};
+use rust_i18n::t;
+
+/// Window showing what the compiler reported while compiling the program: its phases with how
+/// long they took, and at more verbosity what it decided on the way.
+#[derive(Default)]
+pub struct CompileLogView {
+    log: CompileLog,
+    verbosity: Verbosity,
+}
+
+impl CompileLogView {
+    pub fn set(&mut self, log: &CompileLog) {
+        self.log = log.clone();
+    }
+
+    pub fn clear(&mut self) {
+        self.log = CompileLog::default();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(t!("log.verbosity"));
+            for verbosity in Verbosity::ALL {
+                ui.selectable_value(&mut self.verbosity, verbosity, t!(verbosity.key()));
+            }
+        });
+        ui.separator();
+
+        let lines: Vec<_> = self.log.filtered(self.verbosity).collect();
+        if lines.is_empty() {
+            ui.label(t!("log.empty"));
+            return;
+        }
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::both()
+            .auto_shrink([false, true])
+            .show_rows(ui, row_height, lines.len(), |ui, rows| {
+                for line in &lines[rows] {
+                    let indent = "  ".repeat(line.depth);
+                    let text = match &line.entry {
+                        Entry::Enter {
+                            phase,
+                            name,
+                            details,
+                        } => {
+                            let phase = t!(format!("log.phase.{phase}"));
+                            let name = name.as_deref().unwrap_or_default();
+                            RichText::new(format!("{indent}▶ {phase} {name} {details}")).strong()
+                        }
+                        Entry::Exit { phase, elapsed } => {
+                            let ms = format!("{:.3}", elapsed.as_secs_f64() * 1000.0);
+                            let phase = t!(format!("log.phase.{phase}"));
+                            RichText::new(format!(
+                                "{indent}◀ {}",
+                                t!("log.took", phase = phase, ms = ms)
+                            ))
+                            .weak()
+                        }
+                        Entry::Event {
+                            warning, message, ..
+                        } => {
+                            let text = RichText::new(format!("{indent}  {message}"));
+                            match warning {
+                                true => text.color(ui.visuals().warn_fg_color),
+                                false => text,
+                            }
+                        }
+                    };
+                    ui.label(text.monospace());
+                }
+            });
+    }
+}
+
+impl crate::gui::Window for CompileLogView {
+    fn name(&self) -> String {
+        "log.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(480.0)
+            .default_height(400.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
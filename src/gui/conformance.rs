@@ -0,0 +1,111 @@
+use crate::conformance::{self, Expected, Report, Topic};
+use crate::gui::{Engine, InterpreterOptions};
+use eframe::egui::{self, Key, KeyboardShortcut, Modifiers, RichText};
+use rust_i18n::t;
+
+/// Shows or hides the window. It isn't in the sidebar, visitors have no use for it.
+const TOGGLE: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::CTRL.plus(Modifiers::SHIFT), Key::F12);
+
+/// Hidden window running the [`conformance`] suite on the current hardware with every engine, to
+/// check them on the machine the demo runs on and against each other.
+#[derive(Default)]
+pub struct ConformanceView {
+    open: bool,
+    /// The report of each of [`Engine::ALL`], in that order.
+    reports: Vec<Report>,
+}
+
+impl ConformanceView {
+    /// Toggles the window on its shortcut.
+    pub fn handle_shortcut(&mut self, ctx: &egui::Context) {
+        if ctx.input_mut(|i| i.consume_shortcut(&TOGGLE)) {
+            self.open = !self.open;
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, hw: &InterpreterOptions) {
+        egui::Window::new(t!("conformance.name"))
+            .open(&mut self.open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ui.label(t!("conformance.explain"));
+                if ui.button(t!("conformance.run")).clicked() {
+                    self.reports = (Engine::ALL.iter())
+                        .map(|&engine| conformance::run_all(&InterpreterOptions { engine, ..*hw }))
+                        .collect();
+                }
+                let Some(first) = self.reports.first() else {
+                    return;
+                };
+                ui.separator();
+                for (engine, report) in Engine::ALL.iter().zip(&self.reports) {
+                    let summary = t!(
+                        "conformance.summary",
+                        engine = t!(engine.key()),
+                        passed = report.passed(),
+                        total = report.outcomes.len()
+                    );
+                    match report.failures().next() {
+                        None => ui.label(RichText::new(summary).strong()),
+                        Some(_) => ui.colored_label(ui.visuals().error_fg_color, summary),
+                    };
+                }
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for topic in Topic::ALL {
+                        // the suite is the same for every engine, so the cases line up
+                        let cases: Vec<_> = (0..first.outcomes.len())
+                            .filter(|&i| first.outcomes[i].case.topic == topic)
+                            .collect();
+                        let failed = (self.reports.iter())
+                            .any(|r| cases.iter().any(|&i| !r.outcomes[i].passed()));
+                        egui::CollapsingHeader::new(t!(topic.key()))
+                            .id_salt(topic.key())
+                            .default_open(failed)
+                            .show(ui, |ui| {
+                                egui::Grid::new(topic.key()).striped(true).show(ui, |ui| {
+                                    ui.label("");
+                                    ui.label("");
+                                    ui.strong(t!("conformance.expected"));
+                                    for engine in Engine::ALL {
+                                        ui.strong(t!(engine.key()));
+                                    }
+                                    ui.end_row();
+                                    for &i in &cases {
+                                        let case = &first.outcomes[i].case;
+                                        let passed =
+                                            self.reports.iter().all(|r| r.outcomes[i].passed());
+                                        let (mark, color) = match passed {
+                                            true => ("✔", ui.visuals().text_color()),
+                                            false => ("✘", ui.visuals().error_fg_color),
+                                        };
+                                        ui.colored_label(color, mark);
+                                        ui.monospace(&case.name);
+                                        let expected = match case.expected {
+                                            Expected::Result(n) => n.to_string(),
+                                            Expected::Error => t!("conformance.error").into(),
+                                        };
+                                        ui.label(expected);
+                                        for report in &self.reports {
+                                            let outcome = &report.outcomes[i];
+                                            let text = match &outcome.actual {
+                                                Ok(n) => RichText::new(n.to_string()),
+                                                Err(e) => RichText::new(e).weak(),
+                                            };
+                                            match outcome.passed() {
+                                                true => ui.label(text),
+                                                false => ui
+                                                    .label(text.color(ui.visuals().error_fg_color)),
+                                            };
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                            });
+                    }
+                });
+                ui.weak(t!("conformance.hint", key = ctx.format_shortcut(&TOGGLE)));
+            });
+    }
+}
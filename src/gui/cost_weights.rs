@@ -0,0 +1,118 @@
+use crate::gui::WordSize;
+use crate::passes::{CostModel, Saturated, saturate};
+use crate::types::Expr;
+use eframe::egui::{self, Id, RichText};
+use rust_i18n::t;
+
+/// Window to change how many cycles each instruction takes. Equality saturation picks the
+/// cheapest program with these, so e.g. with a fast multiplication shifts stop being worth it.
+#[derive(Default)]
+pub struct CostWeights {
+    costs: CostModel,
+    /// The program as written, before any pass.
+    program: Option<Expr>,
+    /// The word size of the machine it runs on.
+    word_size: WordSize,
+    /// The cheapest version of `program` with the costs it was found with, found again when
+    /// either changes.
+    best: Option<(Expr, CostModel, WordSize, Saturated)>,
+    /// Set when the costs changed, the program is compiled again then.
+    pub changed: bool,
+}
+
+impl CostWeights {
+    pub fn costs(&self) -> &CostModel {
+        &self.costs
+    }
+
+    pub fn set(&mut self, program: Option<Expr>, word_size: WordSize) {
+        self.program = program;
+        self.word_size = word_size;
+    }
+
+    pub fn clear(&mut self) {
+        self.program = None;
+    }
+
+    /// The cheapest version of the program with the current costs.
+    fn best(&mut self) -> Option<&Saturated> {
+        let program = self.program?;
+        let outdated = self
+            .best
+            .as_ref()
+            .is_none_or(|(expr, costs, word_size, _)| {
+                *expr != program || *costs != self.costs || *word_size != self.word_size
+            });
+        if outdated {
+            let saturated = saturate(program, &[], &self.costs, self.word_size);
+            self.best = Some((program, self.costs.clone(), self.word_size, saturated));
+        }
+        self.best.as_ref().map(|(_, _, _, saturated)| saturated)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(t!("costs.explain"));
+        ui.separator();
+
+        egui::Grid::new("cost_weights")
+            .striped(true)
+            .show(ui, |ui| {
+                for inst in CostModel::instructions() {
+                    ui.label(RichText::new(inst.name()).monospace());
+                    let mut cycles = self.costs.cycles(&inst);
+                    let response = ui.add(
+                        egui::DragValue::new(&mut cycles)
+                            .range(1..=1000)
+                            .suffix(t!("costs.cycles")),
+                    );
+                    if response.changed() {
+                        self.costs.set(&inst, cycles);
+                        self.changed = true;
+                    }
+                    if cycles != inst.cycles() {
+                        ui.weak(t!("costs.isa", cycles = inst.cycles()));
+                    }
+                    ui.end_row();
+                }
+            });
+        if ui
+            .add_enabled(
+                !self.costs.is_default(),
+                egui::Button::new(t!("costs.reset")),
+            )
+            .clicked()
+        {
+            self.costs = CostModel::default();
+            self.changed = true;
+        }
+        ui.separator();
+
+        let Some(best) = self.best() else {
+            ui.label(t!("output.empty"));
+            return;
+        };
+        let (expr, cost, before) = (best.expr, best.cost, best.cost_before);
+        ui.label(t!("costs.best"));
+        ui.label(RichText::new(expr.pretty()).monospace().strong());
+        ui.label(t!("costs.total", after = cost, before = before));
+        ui.weak(t!("costs.hint"));
+    }
+}
+
+impl crate::gui::Window for CostWeights {
+    fn name(&self) -> String {
+        "costs.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(320.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
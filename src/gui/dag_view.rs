@@ -0,0 +1,236 @@
+use crate::dag::Dag;
+use crate::gui::WordSize;
+use crate::parser::run_parser;
+use crate::passes::short;
+use crate::ranges::{Hints, Ranges, ValueRange};
+use crate::types::Expr;
+use eframe::egui::{self, Align2, FontId, Id, Rect, Sense, Stroke, vec2};
+use rust_i18n::t;
+
+/// Graphs with more nodes than this aren't drawn, they wouldn't be readable anyway.
+const MAX_NODES: usize = 150;
+const NODE_SIZE: egui::Vec2 = vec2(56.0, 28.0);
+/// Space between the nodes of a row, and between rows for the edges.
+const GAP: egui::Vec2 = vec2(16.0, 40.0);
+
+/// Window drawing the program as a graph instead of a tree: sub-expressions used in several
+/// places are one node with several edges into it, see [`Dag`].
+///
+/// More expressions can be added below the program, to see what they have in common with it.
+/// They are only drawn, the program that's compiled is still the one in the editor.
+///
+/// Hovering a node shows its value range, see [`crate::ranges`].
+#[derive(Default)]
+pub struct DagView {
+    /// The program after the passes on the syntax tree.
+    program: Option<Expr>,
+    /// One more expression per line.
+    extra: String,
+    /// What the program was compiled with, for the value ranges.
+    hints: Hints,
+    word: WordSize,
+}
+
+impl DagView {
+    pub fn set(&mut self, program: Option<Expr>, hints: Hints, word: WordSize) {
+        self.program = program;
+        self.hints = hints;
+        self.word = word;
+    }
+
+    pub fn clear(&mut self) {
+        self.program = None;
+    }
+
+    /// The program and the extra expressions that parse, with an error for each that doesn't.
+    fn expressions(&self) -> (Vec<Expr>, Vec<String>) {
+        let mut exprs: Vec<_> = self.program.into_iter().collect();
+        let mut errors = Vec::new();
+        for (i, line) in self.extra.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match run_parser(line) {
+                Ok(expr) => exprs.push(expr),
+                Err(e) => errors.push(t!("dag.invalid", line = i + 1, e = e).into()),
+            }
+        }
+        (exprs, errors)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(t!("dag.explain"));
+        ui.label(t!("dag.more"));
+        ui.add(
+            egui::TextEdit::multiline(&mut self.extra)
+                .code_editor()
+                .desired_rows(2)
+                .hint_text(t!("dag.more.hint")),
+        );
+        let (exprs, errors) = self.expressions();
+        for error in errors {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        ui.separator();
+
+        if exprs.is_empty() {
+            ui.label(t!("output.empty"));
+            return;
+        }
+        let dag = Dag::new(&exprs);
+        ui.label(t!(
+            "dag.stats",
+            tree = dag.tree_size(),
+            nodes = dag.nodes().len(),
+            shared = dag.shared()
+        ));
+        if dag.nodes().len() > MAX_NODES {
+            ui.label(t!("dag.too_large", max = MAX_NODES));
+            return;
+        }
+        let ranges = self.ranges(&dag);
+        egui::ScrollArea::both().show(ui, |ui| draw(ui, &dag, &ranges));
+    }
+
+    /// The value range of each node, described for its tooltip. Operands come first in the
+    /// graph, so their ranges are known by the time an operation needs them.
+    fn ranges(&self, dag: &Dag) -> Vec<String> {
+        let mut ranges = Ranges::with_hints(&self.hints, self.word);
+        let any = ValueRange::any(self.word);
+        (dag.nodes().iter())
+            .map(|node| match ranges.add(node.expr) {
+                range if range == any => t!("dag.range.any").into(),
+                range => t!("dag.range", range = range).into(),
+            })
+            .collect()
+    }
+}
+
+/// Draws the graph row by row, the expressions on top and the numbers and variables at the
+/// bottom. Shared nodes are highlighted, hovering one shows its range.
+fn draw(ui: &mut egui::Ui, dag: &Dag, ranges: &[String]) {
+    let layers = dag.layers();
+    let widest = layers.iter().map(Vec::len).max().unwrap_or(0);
+    let step = NODE_SIZE + GAP;
+    let size = vec2(
+        widest as f32 * step.x,
+        layers.len() as f32 * step.y - GAP.y + GAP.x,
+    );
+    let (response, painter) = ui.allocate_painter(size, Sense::hover());
+    let origin = response.rect.min + vec2(0.0, GAP.x);
+
+    let mut rects = vec![Rect::NOTHING; dag.nodes().len()];
+    for (row, layer) in layers.iter().enumerate() {
+        // rows are centered under the widest one
+        let left = (widest - layer.len()) as f32 * step.x / 2.0;
+        for (column, &i) in layer.iter().enumerate() {
+            let min = origin + vec2(left + column as f32 * step.x, row as f32 * step.y);
+            rects[i] = Rect::from_min_size(min, NODE_SIZE);
+        }
+    }
+
+    let visuals = ui.visuals();
+    let shared_color = visuals.selection.bg_fill;
+    for (i, node) in dag.nodes().iter().enumerate() {
+        for &child in &node.children {
+            let color = if dag.nodes()[child].is_shared() {
+                shared_color
+            } else {
+                visuals.weak_text_color()
+            };
+            painter.line_segment(
+                [rects[i].center_bottom(), rects[child].center_top()],
+                Stroke::new(1.5, color),
+            );
+        }
+    }
+
+    let numbered = dag.roots().len() > 1;
+    let hovered = response.hover_pos();
+    for (i, node) in dag.nodes().iter().enumerate() {
+        let rect = rects[i];
+        let fill = if node.is_shared() {
+            shared_color
+        } else {
+            visuals.widgets.inactive.bg_fill
+        };
+        painter.rect_filled(rect, 6.0, fill);
+        painter.text(
+            rect.center(),
+            Align2::CENTER_CENTER,
+            node.label(),
+            FontId::monospace(14.0),
+            visuals.text_color(),
+        );
+        if hovered.is_some_and(|p| rect.contains(p)) {
+            let text = match node.uses {
+                1 => short(node.expr),
+                uses => t!("dag.used", expr = short(node.expr), n = uses).into(),
+            };
+            response
+                .clone()
+                .on_hover_text_at_pointer(format!("{text}\n{}", ranges[i]));
+        }
+    }
+
+    // which node each expression ends in, when there are several
+    if numbered {
+        for (i, rect) in rects.iter().enumerate() {
+            let numbers: Vec<_> = (dag.roots().iter().enumerate())
+                .filter(|(_, root)| **root == i)
+                .map(|(n, _)| (n + 1).to_string())
+                .collect();
+            if !numbers.is_empty() {
+                painter.text(
+                    rect.left_top(),
+                    Align2::LEFT_BOTTOM,
+                    numbers.join(", "),
+                    FontId::proportional(11.0),
+                    visuals.strong_text_color(),
+                );
+            }
+        }
+    }
+}
+
+impl crate::gui::Window for DagView {
+    fn name(&self) -> String {
+        "dag.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(480.0)
+            .default_height(420.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_node_has_its_range() {
+        let mut view = DagView::default();
+        let hints = Hints::from([("x".to_string(), ValueRange { min: 0, max: 9 })]);
+        view.set(run_parser("x * 2 + y").ok(), hints, WordSize::W8);
+        let dag = Dag::new(&view.expressions().0);
+        let ranges = view.ranges(&dag);
+
+        let range_of = |source: &str| {
+            let expr = run_parser(source).unwrap();
+            let i = dag.nodes().iter().position(|n| n.expr == expr).unwrap();
+            ranges[i].clone()
+        };
+        assert_eq!(range_of("x * 2"), t!("dag.range", range = "0..=18"));
+        assert_eq!(range_of("y"), t!("dag.range.any"));
+        assert_eq!(range_of("x * 2 + y"), t!("dag.range.any"));
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeSet;
+
+use crate::gui::Window;
+use eframe::egui::{self, Id};
+use rust_i18n::t;
+
+/// Number of columns the screen is split into when docked.
+const COLUMNS: usize = 3;
+
+/// Lays out the open windows in resizable columns next to each other, so nothing overlaps.
+///
+/// Every column is split vertically between its panes, all splitters can be dragged.
+pub struct Dock {
+    /// Whether to dock the windows, or let them float around freely.
+    pub enabled: bool,
+    /// Names of the windows in each column, from top to bottom.
+    columns: Vec<Vec<String>>,
+}
+
+/// Something the user did with a pane, applied after drawing.
+enum PaneAction {
+    Close(String),
+    Move(String, usize),
+}
+
+impl Default for Dock {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            columns: vec![Vec::new(); COLUMNS],
+        }
+    }
+}
+
+impl Dock {
+    /// Forget where the windows were put, they're placed again as if newly opened.
+    pub fn reset(&mut self) {
+        self.columns.iter_mut().for_each(Vec::clear);
+    }
+
+    /// Show all open windows docked into the central panel.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        open: &mut BTreeSet<String>,
+        windows: &mut [&mut dyn Window],
+    ) {
+        self.sync(open, windows);
+
+        let mut actions = Vec::new();
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let width = ui.available_width() / COLUMNS as f32;
+            for col in 0..COLUMNS - 1 {
+                egui::SidePanel::left(Id::new(("dock_column", col)))
+                    .resizable(true)
+                    .default_width(width)
+                    .show_inside(ui, |ui| self.column(ui, col, windows, &mut actions));
+            }
+            egui::CentralPanel::default()
+                .show_inside(ui, |ui| self.column(ui, COLUMNS - 1, windows, &mut actions));
+        });
+
+        for action in actions {
+            match action {
+                PaneAction::Close(name) => {
+                    open.remove(&name);
+                }
+                PaneAction::Move(name, col) => {
+                    self.columns
+                        .iter_mut()
+                        .for_each(|c| c.retain(|n| *n != name));
+                    self.columns[col].push(name);
+                }
+            }
+        }
+    }
+
+    /// Drop closed windows and put newly opened ones in the emptiest column.
+    fn sync(&mut self, open: &BTreeSet<String>, windows: &[&mut dyn Window]) {
+        for column in &mut self.columns {
+            column.retain(|name| open.contains(name));
+        }
+
+        // iterate in the order of `windows`, so the editor comes first
+        for window in windows {
+            let name = window.name();
+            if open.contains(&name) && !self.columns.iter().flatten().any(|n| *n == name) {
+                let emptiest = (0..COLUMNS)
+                    .min_by_key(|&c| self.columns[c].len())
+                    .unwrap_or_default();
+                self.columns[emptiest].push(name);
+            }
+        }
+    }
+
+    fn column(
+        &self,
+        ui: &mut egui::Ui,
+        col: usize,
+        windows: &mut [&mut dyn Window],
+        actions: &mut Vec<PaneAction>,
+    ) {
+        let names = &self.columns[col];
+        let Some((last, rest)) = names.split_last() else {
+            return;
+        };
+
+        let height = ui.available_height() / names.len() as f32;
+        for name in rest {
+            egui::TopBottomPanel::top(Id::new(("dock_pane", name)))
+                .resizable(true)
+                .default_height(height)
+                .show_inside(ui, |ui| pane(ui, name, col, windows, actions));
+        }
+        egui::CentralPanel::default().show_inside(ui, |ui| pane(ui, last, col, windows, actions));
+    }
+}
+
+fn pane(
+    ui: &mut egui::Ui,
+    name: &str,
+    col: usize,
+    windows: &mut [&mut dyn Window],
+    actions: &mut Vec<PaneAction>,
+) {
+    let Some(window) = windows.iter_mut().find(|w| w.name() == name) else {
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.strong(window.title());
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            if ui
+                .small_button("✖")
+                .on_hover_text(t!("dock.close"))
+                .clicked()
+            {
+                actions.push(PaneAction::Close(name.to_string()));
+            }
+            if col + 1 < COLUMNS
+                && ui
+                    .small_button("▶")
+                    .on_hover_text(t!("dock.right"))
+                    .clicked()
+            {
+                actions.push(PaneAction::Move(name.to_string(), col + 1));
+            }
+            if col > 0
+                && ui
+                    .small_button("◀")
+                    .on_hover_text(t!("dock.left"))
+                    .clicked()
+            {
+                actions.push(PaneAction::Move(name.to_string(), col - 1));
+            }
+        });
+    });
+    ui.separator();
+
+    egui::ScrollArea::both()
+        .id_salt(("dock_scroll", name))
+        .auto_shrink(false)
+        .show(ui, |ui| window.ui(ui));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Named(&'static str);
+
+    impl Window for Named {
+        fn name(&self) -> String {
+            self.0.into()
+        }
+
+        fn ui(&mut self, _ui: &mut egui::Ui) {}
+
+        fn show(&mut self, _ctx: &egui::Context, _open: &mut bool) {}
+    }
+
+    #[test]
+    fn new_windows_go_to_the_emptiest_column() {
+        let (mut a, mut b, mut c, mut d) = (Named("a"), Named("b"), Named("c"), Named("d"));
+        let windows: [&mut dyn Window; 4] = [&mut a, &mut b, &mut c, &mut d];
+        let mut open: BTreeSet<String> = ["a", "b", "c", "d"].map(String::from).into();
+
+        let mut dock = Dock::default();
+        dock.sync(&open, &windows);
+        assert_eq!(dock.columns, [vec!["a", "d"], vec!["b"], vec!["c"]]);
+
+        open.remove("a");
+        dock.sync(&open, &windows);
+        assert_eq!(dock.columns, [vec!["d"], vec!["b"], vec!["c"]]);
+    }
+}
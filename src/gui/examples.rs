@@ -22,6 +22,10 @@ impl crate::gui::Window for Examples {
         "examples.name".into()
     }
 
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
     fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
         egui::Window::new(t!(self.name()))
             .id(Id::new(self.name()))
@@ -71,60 +75,51 @@ impl Examples {
             title: "examples.basic.title",
             desc: "examples.basic.desc",
             input: "3 + 2 + 1",
-            options: CompileOptions {
-                do_constant_folding: false,
-                run_cache_optimization: false,
-                do_common_factor_elimination: false,
-                do_shift_replacement: false,
-            },
+            options: CompileOptions::builder()
+                .build()
+                .expect("examples run on the default hardware"),
         });
 
         res.examples.push(Example {
             title: "examples.complex.title",
             desc: "examples.complex.desc",
             input: "1000 * 2 + 4 * 5 + (15 / 3) + x * 13 - y * 2",
-            options: CompileOptions {
-                do_constant_folding: true,
-                run_cache_optimization: false,
-                do_common_factor_elimination: false,
-                do_shift_replacement: false,
-            },
+            options: CompileOptions::builder()
+                .constant_folding(true)
+                .build()
+                .expect("examples run on the default hardware"),
         });
 
         res.examples.push(Example {
             title: "examples.ram_opt.title",
             desc: "examples.ram_opt.desc",
             input: "(1000 + 2) * (4 * 5 + (15 / 3) + 17 * 13 - 8 * 2)",
-            options: CompileOptions {
-                do_constant_folding: false,
-                run_cache_optimization: true,
-                do_common_factor_elimination: false,
-                do_shift_replacement: false,
-            },
+            options: CompileOptions::builder()
+                .cache_optimization(true)
+                .build()
+                .expect("examples run on the default hardware"),
         });
 
         res.examples.push(Example {
             title: "examples.shift_mul.title",
             desc: "examples.shift_mul.desc",
             input: "16 / 2 * 4 / 4",
-            options: CompileOptions {
-                do_constant_folding: false,
-                run_cache_optimization: true,
-                do_common_factor_elimination: false,
-                do_shift_replacement: true,
-            },
+            options: CompileOptions::builder()
+                .cache_optimization(true)
+                .shift_replacement(true)
+                .build()
+                .expect("examples run on the default hardware"),
         });
 
         res.examples.push(Example {
             title: "examples.factorization.title",
             desc: "examples.factorization.desc",
             input: "t * 16 + t * (3 + 2)",
-            options: CompileOptions {
-                do_constant_folding: false,
-                run_cache_optimization: true,
-                do_common_factor_elimination: true,
-                do_shift_replacement: false,
-            },
+            options: CompileOptions::builder()
+                .cache_optimization(true)
+                .common_factor_elimination(true)
+                .build()
+                .expect("examples run on the default hardware"),
         });
 
         res
@@ -0,0 +1,113 @@
+use crate::passes::TransformationLog;
+use eframe::egui::{self, Id};
+use rust_i18n::t;
+
+/// Window telling in words what each pass changed in the last compilation, and why that's
+/// allowed.
+#[derive(Default)]
+pub struct Explanations {
+    log: TransformationLog,
+}
+
+impl Explanations {
+    pub fn set(&mut self, log: &TransformationLog) {
+        self.log = log.clone();
+    }
+
+    pub fn clear(&mut self) {
+        self.log = TransformationLog::default();
+    }
+
+    /// The explanations of each pass, in the order the passes ran. A pass that runs twice has
+    /// a group for each time.
+    fn groups(&self) -> Vec<(&'static str, Vec<String>)> {
+        (self.log.runs())
+            .map(|run| (run[0].pass, run.iter().map(|e| e.to_string()).collect()))
+            .collect()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.log.is_empty() {
+            ui.label(t!("explanations.empty"));
+            return;
+        }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (i, (pass, lines)) in self.groups().into_iter().enumerate() {
+                let title = t!("explanations.group", pass = t!(pass), n = lines.len());
+                egui::CollapsingHeader::new(title)
+                    .id_salt(i)
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for line in lines {
+                            ui.label(line);
+                        }
+                    });
+            }
+        });
+    }
+}
+
+impl crate::gui::Window for Explanations {
+    fn name(&self) -> String {
+        "explanations.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(480.0)
+            .default_height(360.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::{CompileOptions, Compiler, LpErr, OptLevel};
+
+    #[test]
+    fn every_pass_explains_its_changes() -> Result<(), LpErr> {
+        let options = CompileOptions::default().with_level(OptLevel::O2);
+        let ir = Compiler::with(options).compile("x * 8 + (2 + 3) * y")?;
+        let mut explanations = Explanations::default();
+        explanations.set(&ir.explanations);
+
+        let groups = explanations.groups();
+        let passes: Vec<_> = groups.iter().map(|(pass, _)| *pass).collect();
+        assert_eq!(
+            passes[..2],
+            ["editor.constant_folding", "editor.replace_mul_with_shift"]
+        );
+        assert_eq!(
+            groups[0].1,
+            ["replaced `2 + 3` with `5` because all of its operands are numbers"]
+        );
+        assert_eq!(
+            groups[1].1,
+            ["replaced `x * 8` with `x << 3` because 8 = 2³"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn each_run_of_a_pass_is_a_group() {
+        let mut log = TransformationLog::default();
+        for before in ["1 + 2", "3 + 4"] {
+            log.start("editor.constant_folding");
+            log.replaced(before.into(), "…".into(), String::new());
+        }
+        log.start("editor.replace_mul_with_shift");
+        let mut explanations = Explanations::default();
+        explanations.set(&log);
+
+        let groups = explanations.groups();
+        assert_eq!(groups.len(), 2);
+        assert!(groups.iter().all(|(_, lines)| lines.len() == 1));
+    }
+}
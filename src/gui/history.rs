@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use crate::compiler::{CompileOptions, Inst};
+use eframe::egui::{self, Color32, Id, RichText};
+use rust_i18n::t;
+
+/// Most entries kept, older ones are dropped.
+const MAX_ENTRIES: usize = 50;
+
+/// A program that was run during this session.
+#[derive(Clone)]
+pub struct HistoryEntry {
+    pub source: String,
+    pub options: CompileOptions,
+    pub variables: HashMap<String, String>,
+    pub result: Option<i32>,
+    /// Stalls included, so runs on different hardware can be compared.
+    pub cycles: u64,
+    /// The instructions of the best output, to compare entries.
+    pub listing: Vec<String>,
+}
+
+/// One line of a comparison of two listings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    /// Only in the older entry.
+    Removed(String),
+    /// Only in the newer entry.
+    Added(String),
+}
+
+/// Lines of `old` and `new` lined up along their longest common subsequence.
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i].clone()));
+            (i, j) = (i + 1, j + 1);
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            lines.push(DiffLine::Added(new[j].clone()));
+            j += 1;
+        } else {
+            lines.push(DiffLine::Removed(old[i].clone()));
+            i += 1;
+        }
+    }
+    lines
+}
+
+/// Past runs of this session, to go back to an earlier version or compare two of them while
+/// improving a program.
+#[derive(Default)]
+pub struct History {
+    /// The oldest entry first.
+    entries: Vec<HistoryEntry>,
+    /// Entries ticked for comparing, at most two.
+    compared: Vec<usize>,
+    /// The comparison of the older and the newer ticked entry, computed once per pair.
+    diff: Option<((usize, usize), Vec<DiffLine>)>,
+    /// Set when an entry should be loaded into the editor again.
+    pub restore: Option<HistoryEntry>,
+}
+
+impl History {
+    /// Remembers a run, unless it's the same program as the last one.
+    pub fn record(
+        &mut self,
+        source: &str,
+        options: CompileOptions,
+        variables: &HashMap<String, String>,
+        result: Option<i32>,
+        instructions: &[Inst],
+        cycles: u64,
+    ) {
+        // the entries change places or contents
+        self.diff = None;
+        let listing: Vec<_> = instructions.iter().map(Inst::to_string).collect();
+        let entry = HistoryEntry {
+            source: source.into(),
+            options,
+            variables: variables.clone(),
+            result,
+            cycles,
+            listing,
+        };
+        if let Some(last) = self.entries.last_mut()
+            && last.source == entry.source
+            && last.options == entry.options
+        {
+            *last = entry;
+            return;
+        }
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.remove(0);
+            // indices of the remaining entries move down by one
+            self.compared.retain(|&i| i > 0);
+            self.compared.iter_mut().for_each(|i| *i -= 1);
+        }
+        self.entries.push(entry);
+    }
+
+    fn toggle_compared(&mut self, i: usize) {
+        if let Some(pos) = self.compared.iter().position(|&c| c == i) {
+            self.compared.remove(pos);
+        } else {
+            if self.compared.len() == 2 {
+                self.compared.remove(0);
+            }
+            self.compared.push(i);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.entries.is_empty() {
+            ui.label(t!("history.empty"));
+            return;
+        }
+        ui.label(t!("history.explain"));
+        ui.separator();
+
+        let mut toggled = None;
+        egui::ScrollArea::vertical()
+            .id_salt("history_entries")
+            .max_height(ui.available_height() / 2.0)
+            .show(ui, |ui| {
+                egui::Grid::new("history_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (i, entry) in self.entries.iter().enumerate().rev() {
+                            let mut compared = self.compared.contains(&i);
+                            if ui
+                                .checkbox(&mut compared, "")
+                                .on_hover_text(t!("history.compare"))
+                                .changed()
+                            {
+                                toggled = Some(i);
+                            }
+                            ui.label(RichText::new(&entry.source).monospace());
+                            let level = entry
+                                .options
+                                .level()
+                                .map_or(t!("history.custom").to_string(), |l| l.to_string());
+                            let result = entry.result.map_or("–".into(), |r| r.to_string());
+                            ui.label(t!(
+                                "history.summary",
+                                level = level,
+                                res = result,
+                                cycles = entry.cycles
+                            ));
+                            if ui.button(t!("history.restore")).clicked() {
+                                self.restore = Some(entry.clone());
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        if let Some(i) = toggled {
+            self.toggle_compared(i);
+        }
+
+        let [a, b] = self.compared[..] else {
+            return;
+        };
+        let pair = (a.min(b), a.max(b));
+        let (old, new) = (&self.entries[pair.0], &self.entries[pair.1]);
+        if self.diff.as_ref().is_none_or(|(cached, _)| *cached != pair) {
+            self.diff = Some((pair, diff_lines(&old.listing, &new.listing)));
+        }
+        ui.separator();
+        ui.label(t!("history.diff", old = old.cycles, new = new.cycles));
+        let Some((_, diff)) = &self.diff else {
+            return;
+        };
+        egui::ScrollArea::vertical()
+            .id_salt("history_diff")
+            .show(ui, |ui| {
+                for line in diff {
+                    let (prefix, text, color) = match &line {
+                        DiffLine::Same(text) => (" ", text, ui.visuals().text_color()),
+                        DiffLine::Removed(text) => ("-", text, Color32::from_rgb(220, 80, 80)),
+                        DiffLine::Added(text) => ("+", text, Color32::from_rgb(80, 180, 80)),
+                    };
+                    ui.label(
+                        RichText::new(format!("{prefix} {text}"))
+                            .monospace()
+                            .color(color),
+                    );
+                }
+            });
+    }
+}
+
+impl crate::gui::Window for History {
+    fn name(&self) -> String {
+        "history.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(480.0)
+            .default_height(500.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::Reg;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn diff_keeps_common_lines() {
+        let diff = diff_lines(&lines("a b c d"), &lines("a c d e"));
+        assert_eq!(
+            diff,
+            [
+                DiffLine::Same("a".into()),
+                DiffLine::Removed("b".into()),
+                DiffLine::Same("c".into()),
+                DiffLine::Same("d".into()),
+                DiffLine::Added("e".into()),
+            ]
+        );
+        assert!(diff_lines(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn reruns_replace_the_last_entry() {
+        let mut history = History::default();
+        let code = [Inst::Store(1, Reg(0)), Inst::Result(Reg(0))];
+        let vars = HashMap::new();
+        let mut record = |source, cycles| {
+            history.record(
+                source,
+                CompileOptions::default(),
+                &vars,
+                Some(1),
+                &code,
+                cycles,
+            )
+        };
+        record("1", 30);
+        record("1", 30);
+        record("1 + 0", 45);
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.entries[1].cycles, 45);
+    }
+
+    #[test]
+    fn only_two_entries_are_compared() {
+        let mut history = History::default();
+        for i in 0..3 {
+            history.toggle_compared(i);
+        }
+        assert_eq!(history.compared, [1, 2]);
+        history.toggle_compared(1);
+        assert_eq!(history.compared, [2]);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::assembler;
+use crate::compiler::{ISA, RAM_STALL_CYCLES};
+use crate::encoding;
+use eframe::egui::{self, Id, RichText};
+use rust_i18n::t;
+
+/// Reference of all instructions, generated from the [`ISA`] table so it always matches what
+/// the interpreter and the encoder do.
+#[derive(Default)]
+pub struct IsaReference;
+
+impl IsaReference {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(t!("isa.explain"));
+        ui.separator();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("isa_grid")
+                .num_columns(4)
+                .striped(true)
+                .spacing([16.0, 6.0])
+                .show(ui, |ui| {
+                    for heading in ["isa.syntax", "isa.meaning", "isa.encoding", "isa.cycles"] {
+                        ui.label(RichText::new(t!(heading)).strong());
+                    }
+                    ui.end_row();
+
+                    for def in ISA {
+                        let example = def.example();
+                        ui.monospace(assembler::to_text(&example));
+                        ui.label(example.to_string());
+                        ui.monospace(encoding::layout(def));
+                        ui.monospace(def.cycles.to_string());
+                        ui.end_row();
+                    }
+                });
+
+            ui.separator();
+            ui.label(t!("isa.operands"));
+            ui.label(t!("isa.stall", c = RAM_STALL_CYCLES));
+        });
+    }
+}
+
+impl crate::gui::Window for IsaReference {
+    fn name(&self) -> String {
+        "isa.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(620.0)
+            .default_height(500.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
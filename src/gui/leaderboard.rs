@@ -0,0 +1,201 @@
+use std::path::PathBuf;
+
+use crate::challenges::LEADERBOARD_CHALLENGE;
+use crate::compiler::{CompileOptions, Compiler};
+use crate::files;
+use crate::gui::InterpreterOptions;
+use crate::passes::RewriteRule;
+use eframe::egui::{self, Color32, Id, RichText};
+use rust_i18n::t;
+
+/// File in the data directory the standings are kept in, so they survive restarts.
+const LEADERBOARD_FILE: &str = "leaderboard.tsv";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    nickname: String,
+    source: String,
+    cycles: u64,
+}
+
+impl Entry {
+    /// One tab-separated line: cycles, nickname, source.
+    fn to_line(&self) -> String {
+        let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+        format!(
+            "{}\t{}\t{}",
+            self.cycles,
+            clean(&self.nickname),
+            clean(&self.source)
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut parts = line.splitn(3, '\t');
+        Some(Self {
+            cycles: parts.next()?.parse().ok()?,
+            nickname: parts.next()?.to_string(),
+            source: parts.next()?.to_string(),
+        })
+    }
+}
+
+/// Standings of the fastest correct programs for [`LEADERBOARD_CHALLENGE`].
+#[derive(Default)]
+pub struct Leaderboard {
+    /// Nickname the next run is recorded under, nothing is recorded while it's empty.
+    pub nickname: String,
+    /// Sorted by cycles, fastest first.
+    entries: Vec<Entry>,
+    /// Result of the last submission.
+    status: Option<Result<String, String>>,
+}
+
+impl Leaderboard {
+    /// Loads the standings saved earlier, if there are any.
+    pub fn load() -> Self {
+        let entries = std::fs::read_to_string(Self::path())
+            .map(|s| s.lines().filter_map(Entry::from_line).collect())
+            .unwrap_or_default();
+        let mut res = Self {
+            entries,
+            ..Self::default()
+        };
+        res.entries.sort_by_key(|e| e.cycles);
+        res
+    }
+
+    /// Where the standings are kept, see [`LEADERBOARD_FILE`].
+    fn path() -> PathBuf {
+        files::data_dir().join(LEADERBOARD_FILE)
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let contents: String = self.entries.iter().map(|e| e.to_line() + "\n").collect();
+        std::fs::write(files::data_file(LEADERBOARD_FILE), contents)
+    }
+
+    /// Checks the program against the challenge and records it if it's correct.
+    ///
+    /// Every entry runs on the default machine, whatever the visitor set up, so places are won
+    /// by better programs rather than by more registers or ALUs.
+    pub fn submit(&mut self, source: &str, opts: CompileOptions, rules: Vec<RewriteRule>) {
+        if self.nickname.trim().is_empty() {
+            return;
+        }
+
+        let report = Compiler::with(opts)
+            .with_interpreter(InterpreterOptions::default())
+            .with_rules(rules)
+            .compile(source)
+            .map(|ir| LEADERBOARD_CHALLENGE.check(&ir));
+        self.status = Some(match report {
+            Ok(report) if !report.all_passed() => Err(t!(
+                "challenge.failed",
+                passed = report.passed,
+                total = report.total
+            )
+            .into()),
+            Ok(report) => {
+                let cycles = report.cycles;
+                let entry = Entry {
+                    nickname: self.nickname.trim().to_string(),
+                    source: source.trim().to_string(),
+                    cycles,
+                };
+                let pos = self.entries.partition_point(|e| e.cycles <= cycles);
+                self.entries.insert(pos, entry);
+                match self.save() {
+                    Ok(()) => {
+                        Ok(t!("leaderboard.recorded", place = pos + 1, cycles = cycles).into())
+                    }
+                    Err(e) => {
+                        let file = Self::path();
+                        Err(t!("output.save_failed", file = file.display(), e = e).into())
+                    }
+                }
+            }
+            Err(e) => Err(e.to_string()),
+        });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(t!(LEADERBOARD_CHALLENGE.description));
+        ui.label(RichText::new(t!("leaderboard.machine")).italics());
+        ui.horizontal(|ui| {
+            ui.label(t!("leaderboard.nickname"));
+            ui.text_edit_singleline(&mut self.nickname);
+        });
+        if self.nickname.trim().is_empty() {
+            ui.label(RichText::new(t!("leaderboard.no_nickname")).italics());
+        }
+        match &self.status {
+            Some(Ok(msg)) => ui.label(RichText::new(msg).color(Color32::GREEN)),
+            Some(Err(msg)) => ui.label(RichText::new(msg).color(Color32::RED)),
+            None => ui.label(""),
+        };
+        ui.separator();
+
+        if self.entries.is_empty() {
+            ui.label(t!("leaderboard.empty"));
+            return;
+        }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("leaderboard")
+                .striped(true)
+                .num_columns(4)
+                .show(ui, |ui| {
+                    ui.strong("#");
+                    ui.strong(t!("leaderboard.nickname"));
+                    ui.strong(t!("leaderboard.cycles"));
+                    ui.strong(t!("leaderboard.program"));
+                    ui.end_row();
+
+                    for (i, entry) in self.entries.iter().enumerate() {
+                        ui.label(format!("{}", i + 1));
+                        ui.label(&entry.nickname);
+                        ui.label(entry.cycles.to_string());
+                        ui.label(RichText::new(&entry.source).monospace());
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+impl crate::gui::Window for Leaderboard {
+    fn name(&self) -> String {
+        "leaderboard.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(400.0)
+            .default_height(300.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entry_roundtrip() {
+        let entry = Entry {
+            nickname: "Ada".into(),
+            source: "x * 1000\n+ 24".into(),
+            cycles: 180,
+        };
+        let parsed = Entry::from_line(&entry.to_line()).unwrap();
+        assert_eq!(parsed.cycles, 180);
+        assert_eq!(parsed.nickname, "Ada");
+        assert_eq!(parsed.source, "x * 1000 + 24");
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::compiler::{Inst, Reg};
+use crate::gui::{InterpreterOptions, Theme};
+use crate::interpreter::{Interpreter, InterpreterState};
+use crate::source_map::Span;
+use eframe::egui::{self, Id, RichText};
+use rust_i18n::t;
+
+/// A compiled program with the parts of the source each instruction came from.
+#[derive(Clone, Default)]
+pub struct LockstepProgram {
+    pub title: String,
+    pub instructions: Vec<Inst>,
+    pub debug_info: Vec<Vec<Span>>,
+}
+
+/// One of the programs, run as far as the source operations so far.
+struct Side {
+    program: LockstepProgram,
+    interpreter: Interpreter,
+    /// Instructions executed in the last step.
+    last: Range<usize>,
+    /// How the program ended, once it did.
+    outcome: Option<Result<i32, String>>,
+}
+
+impl Side {
+    fn new(
+        program: LockstepProgram,
+        hw: &InterpreterOptions,
+        vars: &HashMap<String, String>,
+    ) -> Self {
+        let interpreter = Interpreter::with_config(hw)
+            .load_instructions(program.instructions.clone())
+            .with_variables(vars.clone())
+            .ready();
+        Self {
+            program,
+            interpreter,
+            last: 0..0,
+            outcome: None,
+        }
+    }
+
+    fn pc(&self) -> usize {
+        self.interpreter.program_counter()
+    }
+
+    /// Whether there's nothing left to run, also for programs that didn't compile.
+    fn is_done(&self) -> bool {
+        self.outcome.is_some() || self.pc() >= self.program.instructions.len()
+    }
+
+    /// The parts of the source the next instruction came from, none if it isn't known.
+    fn next_spans(&self) -> &[Span] {
+        (self.program.debug_info.get(self.pc())).map_or(&[], Vec::as_slice)
+    }
+
+    /// Executes instructions as long as `more` holds for the next one.
+    fn run_while(&mut self, more: impl Fn(&[Span]) -> bool) {
+        let start = self.pc();
+        while !self.is_done() && more(self.next_spans()) {
+            match self.interpreter.step() {
+                Ok(InterpreterState::Continue) => {}
+                Ok(InterpreterState::Finished(res)) => self.outcome = Some(Ok(res)),
+                Err(e) => self.outcome = Some(Err(e.to_string())),
+            }
+        }
+        self.last = start..self.pc();
+    }
+}
+
+/// Steps the unoptimized and the optimized program side by side, one operation of the source
+/// at a time. Each step runs the instructions the unoptimized program needs for the next
+/// operation, and everything of the optimized program that came from operations done so far.
+/// Where the optimized program has nothing left to do, it skipped the work.
+#[derive(Default)]
+pub struct Lockstep {
+    code: String,
+    hw: InterpreterOptions,
+    vars: HashMap<String, String>,
+    programs: Vec<LockstepProgram>,
+    sides: Vec<Side>,
+    /// Source spans of the operations done so far.
+    done: Vec<Span>,
+    /// The operation of the last step.
+    current: Option<Span>,
+}
+
+impl Lockstep {
+    /// The unoptimized and the optimized program, compiled from `code`.
+    pub fn set(
+        &mut self,
+        code: &str,
+        programs: [LockstepProgram; 2],
+        hw: InterpreterOptions,
+        vars: &HashMap<String, String>,
+    ) {
+        self.code = code.to_string();
+        self.programs = programs.into();
+        self.hw = hw;
+        self.vars = vars.clone();
+        self.restart();
+    }
+
+    /// Runs the programs from the start again with these inputs.
+    pub fn set_variables(&mut self, vars: &HashMap<String, String>) {
+        self.vars = vars.clone();
+        self.restart();
+    }
+
+    pub fn clear(&mut self) {
+        self.programs.clear();
+        self.restart();
+    }
+
+    fn restart(&mut self) {
+        self.sides = self
+            .programs
+            .iter()
+            .map(|p| Side::new(p.clone(), &self.hw, &self.vars))
+            .collect();
+        self.done.clear();
+        self.current = None;
+    }
+
+    fn finished(&self) -> bool {
+        self.sides.iter().all(Side::is_done)
+    }
+
+    /// Runs the next operation of the source in both programs, and tells whether either of them
+    /// got further.
+    fn step(&mut self) -> bool {
+        let [original, optimized] = &mut self.sides[..] else {
+            return false;
+        };
+        if !original.is_done() {
+            // the instructions in a row that came from the same place
+            let spans = original.next_spans().to_vec();
+            original.run_while(|s| s == spans);
+            self.current = spans.last().cloned();
+            self.done.extend(spans);
+        }
+        let done = &self.done;
+        let finished = original.is_done();
+        optimized.run_while(|spans| {
+            finished
+                || spans
+                    .iter()
+                    .all(|s| done.iter().any(|d| d.start <= s.start && s.end <= d.end))
+        });
+        !original.last.is_empty() || !optimized.last.is_empty()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.sides.len() != 2 {
+            ui.label(t!("lockstep.empty"));
+            return;
+        }
+        ui.label(t!("lockstep.explain"));
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!self.finished(), egui::Button::new(t!("lockstep.step")))
+                .clicked()
+            {
+                self.step();
+            }
+            if ui
+                .add_enabled(!self.finished(), egui::Button::new(t!("lockstep.to_end")))
+                .clicked()
+            {
+                while !self.finished() && self.step() {}
+            }
+            if ui.button(t!("lockstep.restart")).clicked() {
+                self.restart();
+            }
+        });
+        if let Some(span) = self.current.clone()
+            && let Some(text) = self.code.get(span)
+        {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(t!("lockstep.operation"));
+                ui.code(text);
+            });
+        }
+        ui.separator();
+
+        let num_registers = self.hw.num_registers;
+        ui.columns(2, |columns| {
+            for (ui, side) in columns.iter_mut().zip(&self.sides) {
+                side_ui(ui, side, num_registers);
+            }
+        });
+    }
+}
+
+/// The state of one program: its instructions, what the last step ran and the registers.
+fn side_ui(ui: &mut egui::Ui, side: &Side, num_registers: u8) {
+    ui.heading(&side.program.title);
+    let stats = side.interpreter.stats();
+    ui.label(t!(
+        "lockstep.stats",
+        steps = stats.steps,
+        cycles = stats.cycles
+    ));
+    if side.last.is_empty() && stats.steps > 0 && side.outcome.is_none() {
+        ui.colored_label(Theme::global().accent(), t!("lockstep.skipped"));
+    }
+
+    ui.horizontal_wrapped(|ui| {
+        for n in 0..num_registers {
+            let value = side
+                .interpreter
+                .register(Reg(n))
+                .filter(|_| side.interpreter.reg_store.contains_key(&Reg(n)))
+                .map_or("–".to_string(), |v| v.to_string());
+            ui.monospace(format!("{}={value}", Reg(n)));
+        }
+    });
+
+    egui::ScrollArea::vertical()
+        .id_salt(&side.program.title)
+        .max_height(300.0)
+        .show(ui, |ui| {
+            for (i, inst) in side.program.instructions.iter().enumerate() {
+                let mut text = RichText::new(inst.to_string()).monospace();
+                if side.last.contains(&i) {
+                    text = text.background_color(ui.visuals().selection.bg_fill);
+                } else if i >= side.pc() {
+                    text = text.weak();
+                }
+                let label = ui.label(text);
+                if side.last.end == i + 1 {
+                    label.scroll_to_me(None);
+                }
+            }
+        });
+
+    match &side.outcome {
+        Some(Ok(res)) => {
+            ui.label(t!("output.result", res = res));
+        }
+        Some(Err(e)) => {
+            ui.colored_label(egui::Color32::RED, e);
+        }
+        None => {}
+    }
+}
+
+impl crate::gui::Window for Lockstep {
+    fn name(&self) -> String {
+        "lockstep.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(640.0)
+            .default_height(500.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::{CompileOptions, Compiler, LpErr, OptLevel};
+
+    fn program(source: &str, level: OptLevel) -> Result<LockstepProgram, LpErr> {
+        let ir = Compiler::with(CompileOptions::default().with_level(level)).compile(source)?;
+        Ok(LockstepProgram {
+            title: level.to_string(),
+            instructions: ir.instructions,
+            debug_info: ir.debug_info,
+        })
+    }
+
+    #[test]
+    fn optimized_program_skips_folded_operations() -> Result<(), LpErr> {
+        let source = "x * (2 + 3)";
+        let mut lockstep = Lockstep::default();
+        let vars = HashMap::from([("x".to_string(), "4".to_string())]);
+        let programs = [
+            program(source, OptLevel::O0)?,
+            program(source, OptLevel::O2)?,
+        ];
+        lockstep.set(source, programs, InterpreterOptions::default(), &vars);
+
+        let mut skipped = 0;
+        let mut steps = 0;
+        while !lockstep.finished() {
+            lockstep.step();
+            steps += 1;
+            let [original, optimized] = &lockstep.sides[..] else {
+                unreachable!("there are two programs");
+            };
+            assert!(!original.last.is_empty() || original.outcome.is_some());
+            skipped += usize::from(optimized.last.is_empty());
+            assert!(steps < 100, "the programs never finish");
+        }
+        let outcomes: Vec<_> = lockstep.sides.iter().map(|s| s.outcome.clone()).collect();
+        assert_eq!(outcomes, [Some(Ok(20)), Some(Ok(20))]);
+        // 2 + 3 was folded away, there's nothing left to do for it
+        assert!(skipped > 0);
+
+        lockstep.set_variables(&HashMap::new());
+        assert!(lockstep.done.is_empty());
+        assert_eq!(lockstep.sides[0].pc(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn instructions_without_source_are_run() -> Result<(), LpErr> {
+        let source = "x * (2 + 3)";
+        let mut programs = [
+            program(source, OptLevel::O0)?,
+            program(source, OptLevel::O2)?,
+        ];
+        for program in &mut programs {
+            program.debug_info.truncate(1);
+        }
+        let mut lockstep = Lockstep::default();
+        let vars = HashMap::from([("x".to_string(), "4".to_string())]);
+        lockstep.set(source, programs, InterpreterOptions::default(), &vars);
+        for _ in 0..100 {
+            if !lockstep.step() {
+                break;
+            }
+        }
+        assert!(lockstep.finished());
+        Ok(())
+    }
+}
@@ -1,18 +1,72 @@
 mod assembly_output;
+mod autopilot;
+mod batch;
+mod challenges;
 mod code_editor;
+mod compile_log;
+mod conformance;
+mod cost_weights;
+mod dag_view;
+mod dock;
 mod examples;
+mod explanations;
+mod history;
+mod isa;
+mod leaderboard;
+mod lockstep;
 mod options;
+mod pass_matrix;
+mod pipeline;
+mod presenter;
+mod profiler;
+mod quiz;
+mod repl;
+mod rewrite_rules;
+mod target_output;
+mod theme;
+mod tutorial;
 
 pub use assembly_output::*;
+pub use autopilot::*;
+pub use batch::*;
+pub use challenges::*;
 pub use code_editor::*;
+pub use compile_log::*;
+pub use conformance::*;
+pub use cost_weights::*;
+pub use dag_view::*;
+pub use dock::*;
 use eframe::egui;
 pub use examples::*;
+pub use explanations::*;
+pub use history::*;
+pub use isa::*;
+pub use leaderboard::*;
+pub use lockstep::*;
 pub use options::*;
+pub use pass_matrix::*;
+pub use pipeline::*;
+pub use presenter::*;
+pub use profiler::*;
+pub use quiz::*;
+pub use repl::*;
+pub use rewrite_rules::*;
+pub use target_output::*;
+pub use theme::*;
+pub use tutorial::*;
 
 pub trait Window {
     /// Name of the window
     fn name(&self) -> String;
 
+    /// Title shown to the user, the translated name unless there's more to say.
+    fn title(&self) -> String {
+        rust_i18n::t!(self.name()).into()
+    }
+
+    /// Draw the contents of the window, either inside its own window or a docked pane.
+    fn ui(&mut self, ui: &mut egui::Ui);
+
     /// Show the window, depending on `open`.
     fn show(&mut self, ctx: &egui::Context, open: &mut bool);
 }
@@ -1,11 +1,129 @@
+use crate::compiler::{CompileOptions, ConfigError, ConfigField, RegNaming};
 use eframe::egui;
 use eframe::egui::Id;
 use rust_i18n::t;
 
-#[derive(Copy, Clone)]
+/// Width of the simulated machine words.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub enum WordSize {
+    W8,
+    W16,
+    #[default]
+    W32,
+}
+
+impl WordSize {
+    pub const ALL: [WordSize; 3] = [WordSize::W8, WordSize::W16, WordSize::W32];
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            WordSize::W8 => 8,
+            WordSize::W16 => 16,
+            WordSize::W32 => 32,
+        }
+    }
+
+    /// Wraps the value around like a (two's complement) machine word of this size would.
+    pub fn wrap(&self, n: i32) -> i32 {
+        let unused = 32 - self.bits();
+        // shift the unused bits out, then sign-extend
+        n.wrapping_shl(unused).wrapping_shr(unused)
+    }
+
+    /// The largest number a word of this size holds.
+    pub fn max(&self) -> i32 {
+        i32::MAX >> (32 - self.bits())
+    }
+
+    /// The smallest (most negative) number a word of this size holds.
+    pub fn min(&self) -> i32 {
+        i32::MIN >> (32 - self.bits())
+    }
+}
+
+/// What reading a register that was never written gives.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub enum UninitPolicy {
+    /// The program stops with an error.
+    #[default]
+    Error,
+    Zero,
+    /// Whatever was left in the register before, a random looking value. It's the same every
+    /// time the program runs, so results can be compared.
+    Garbage,
+}
+
+impl UninitPolicy {
+    pub const ALL: [UninitPolicy; 3] = [
+        UninitPolicy::Error,
+        UninitPolicy::Zero,
+        UninitPolicy::Garbage,
+    ];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            UninitPolicy::Error => "interp_opts.uninit.error",
+            UninitPolicy::Zero => "interp_opts.uninit.zero",
+            UninitPolicy::Garbage => "interp_opts.uninit.garbage",
+        }
+    }
+}
+
+/// How arithmetic instructions name the register getting the result.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub enum IsaFlavor {
+    /// The result replaces the second operand, `add a, b` computes `b = a + b`.
+    #[default]
+    TwoOperand,
+    /// The result goes to its own register, `add3 a, b, c` computes `c = a + b`.
+    ThreeOperand,
+}
+
+impl IsaFlavor {
+    pub const ALL: [IsaFlavor; 2] = [IsaFlavor::TwoOperand, IsaFlavor::ThreeOperand];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            IsaFlavor::TwoOperand => "interp_opts.flavor.two",
+            IsaFlavor::ThreeOperand => "interp_opts.flavor.three",
+        }
+    }
+}
+
+/// What runs the programs, see [`crate::engine`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub enum Engine {
+    /// The interpreter every other engine is checked against.
+    #[default]
+    Reference,
+    /// The reference with a small cache in front of the RAM.
+    CacheSim,
+}
+
+impl Engine {
+    pub const ALL: [Engine; 2] = [Engine::Reference, Engine::CacheSim];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Engine::Reference => "interp_opts.engine.reference",
+            Engine::CacheSim => "interp_opts.engine.cache",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize)]
 pub struct InterpreterOptions {
     pub num_registers: u8,
     pub num_cachelines: usize,
+    pub reg_naming: RegNaming,
+    pub word_size: WordSize,
+    pub uninit: UninitPolicy,
+    /// RAM accesses that can be in flight at once, with one port they have to wait for each other.
+    pub ram_ports: u8,
+    /// Arithmetic units, with two independent arithmetic instructions can run at the same time.
+    pub alus: u8,
+    pub flavor: IsaFlavor,
+    pub engine: Engine,
 }
 
 impl Default for InterpreterOptions {
@@ -13,6 +131,13 @@ impl Default for InterpreterOptions {
         Self {
             num_registers: 6,
             num_cachelines: 16,
+            reg_naming: RegNaming::default(),
+            word_size: WordSize::default(),
+            uninit: UninitPolicy::default(),
+            ram_ports: 2,
+            alus: 1,
+            flavor: IsaFlavor::default(),
+            engine: Engine::default(),
         }
     }
 }
@@ -22,6 +147,10 @@ impl crate::gui::Window for InterpreterOptions {
         "interp_opts.name".into()
     }
 
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
     fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
         egui::Window::new(t!(self.name()))
             .id(Id::new(self.name()))
@@ -36,6 +165,8 @@ impl crate::gui::Window for InterpreterOptions {
 
 impl InterpreterOptions {
     pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let errors = CompileOptions::hardware_errors(self);
+
         ui.label(t!("interp_opts.label"));
 
         ui.add_space(12.0);
@@ -51,6 +182,31 @@ impl InterpreterOptions {
                     self.num_registers = val;
                 }
                 ui.end_row();
+                show_errors(ui, &errors, ConfigField::Registers);
+
+                ui.label(t!("interp_opts.reg_naming"));
+                egui::ComboBox::from_id_salt("reg_naming")
+                    .selected_text(naming_example(self.reg_naming))
+                    .show_ui(ui, |ui| {
+                        for naming in RegNaming::ALL {
+                            ui.selectable_value(
+                                &mut self.reg_naming,
+                                naming,
+                                naming_example(naming),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.label(t!("interp_opts.uninit"));
+                egui::ComboBox::from_id_salt("uninit")
+                    .selected_text(t!(self.uninit.key()))
+                    .show_ui(ui, |ui| {
+                        for policy in UninitPolicy::ALL {
+                            ui.selectable_value(&mut self.uninit, policy, t!(policy.key()));
+                        }
+                    });
+                ui.end_row();
             });
 
         egui::CollapsingHeader::new(t!("interp_opts.explanation"))
@@ -58,6 +214,7 @@ impl InterpreterOptions {
             .default_open(true)
             .show(ui, |ui| {
                 ui.label(t!("interp_opts.label_regs"));
+                ui.label(t!("interp_opts.uninit_label"));
             });
 
         ui.add_space(12.0);
@@ -73,6 +230,51 @@ impl InterpreterOptions {
                     self.num_cachelines = val;
                 }
                 ui.end_row();
+                show_errors(ui, &errors, ConfigField::Ram);
+
+                ui.label(t!("interp_opts.word_size"));
+                ui.horizontal(|ui| {
+                    for size in WordSize::ALL {
+                        ui.selectable_value(
+                            &mut self.word_size,
+                            size,
+                            t!("interp_opts.bits", n = size.bits()),
+                        );
+                    }
+                });
+                ui.end_row();
+
+                ui.label(t!("interp_opts.ram_ports"));
+                ui.horizontal(|ui| {
+                    for ports in [1, 2] {
+                        ui.selectable_value(&mut self.ram_ports, ports, ports.to_string());
+                    }
+                });
+                ui.end_row();
+
+                ui.label(t!("interp_opts.alus"));
+                ui.horizontal(|ui| {
+                    for alus in [1, 2] {
+                        ui.selectable_value(&mut self.alus, alus, alus.to_string());
+                    }
+                });
+                ui.end_row();
+
+                ui.label(t!("interp_opts.flavor"));
+                ui.horizontal(|ui| {
+                    for flavor in IsaFlavor::ALL {
+                        ui.selectable_value(&mut self.flavor, flavor, t!(flavor.key()));
+                    }
+                });
+                ui.end_row();
+
+                ui.label(t!("interp_opts.engine"));
+                ui.horizontal(|ui| {
+                    for engine in Engine::ALL {
+                        ui.selectable_value(&mut self.engine, engine, t!(engine.key()));
+                    }
+                });
+                ui.end_row();
             });
 
         egui::CollapsingHeader::new(t!("interp_opts.explanation"))
@@ -80,6 +282,43 @@ impl InterpreterOptions {
             .default_open(true)
             .show(ui, |ui| {
                 ui.label(t!("interp_opts.cache_label"));
+                ui.label(t!("interp_opts.word_size_label"));
+                ui.label(t!("interp_opts.ram_ports_label"));
+                ui.label(t!("interp_opts.alus_label"));
+                ui.label(t!("interp_opts.flavor_label"));
+                ui.label(t!("interp_opts.engine_label"));
             });
     }
 }
+
+/// Shows the configuration errors concerning `field` as a row below it.
+fn show_errors(ui: &mut egui::Ui, errors: &[ConfigError], field: ConfigField) {
+    for error in errors.iter().filter(|e| e.field() == field) {
+        ui.label("");
+        ui.colored_label(egui::Color32::RED, error.to_string());
+        ui.end_row();
+    }
+}
+
+/// E.g. `a, b, c, ..` for letter naming.
+fn naming_example(naming: RegNaming) -> String {
+    (0..3)
+        .map(|r| naming.name(crate::compiler::Reg(r)))
+        .chain(["..".to_string()])
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wrap_word_sizes() {
+        assert_eq!(WordSize::W8.wrap(127), 127);
+        assert_eq!(WordSize::W8.wrap(128), -128);
+        assert_eq!(WordSize::W8.wrap(300), 44);
+        assert_eq!(WordSize::W16.wrap(40_000), -25_536);
+        assert_eq!(WordSize::W32.wrap(i32::MIN), i32::MIN);
+    }
+}
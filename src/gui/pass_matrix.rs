@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::thread::JoinHandle;
+
+use crate::bench;
+use crate::compiler::{CompileOptions, Compiler};
+use crate::files;
+use crate::gui::{InterpreterOptions, Optimization, Theme};
+use crate::parser;
+use crate::ranges::Hints;
+use eframe::egui::{self, Color32, Id, RichText};
+use rust_i18n::t;
+
+/// File the table is saved to, in the data directory.
+pub const CSV_FILE: &str = "passes.csv";
+
+/// How one combination of optimizations did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Measurement {
+    result: i32,
+    instructions: usize,
+    cycles: u64,
+    /// Values read from and written to RAM, including the stack.
+    ram_accesses: u64,
+}
+
+/// The program compiled and run with some of the optimizations.
+#[derive(Debug, Clone)]
+struct Row {
+    passes: Vec<Optimization>,
+    /// Why the program couldn't be compiled or run with these.
+    measurement: Result<Measurement, String>,
+}
+
+/// What the bars show.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Metric {
+    Instructions,
+    Cycles,
+    Ram,
+}
+
+impl Metric {
+    fn value(self, m: &Measurement) -> u64 {
+        match self {
+            Metric::Instructions => m.instructions as u64,
+            Metric::Cycles => m.cycles,
+            Metric::Ram => m.ram_accesses,
+        }
+    }
+}
+
+/// Compiles the program with every combination of the chosen optimizations and runs each once,
+/// to see which of them help and how much, without trying them all by hand.
+pub struct PassMatrix {
+    code: String,
+    /// The editor's options, for everything that isn't one of the optimizations.
+    options: CompileOptions,
+    hw: InterpreterOptions,
+    hints: Hints,
+    vars: HashMap<String, String>,
+    /// Which optimizations are combined, the others stay off.
+    included: Vec<bool>,
+    rows: Vec<Row>,
+    metric: Metric,
+    /// The combinations being measured in the background.
+    pending: Option<JoinHandle<Vec<Row>>>,
+    /// Result of the last export.
+    save_status: Option<String>,
+}
+
+impl Default for PassMatrix {
+    fn default() -> Self {
+        Self {
+            code: String::new(),
+            options: CompileOptions::default(),
+            hw: InterpreterOptions::default(),
+            hints: Hints::new(),
+            vars: HashMap::new(),
+            included: Optimization::all().map(|_| true).collect(),
+            rows: Vec::new(),
+            metric: Metric::Cycles,
+            pending: None,
+            save_status: None,
+        }
+    }
+}
+
+impl PassMatrix {
+    /// The program to measure, with the settings it was compiled with.
+    pub fn set(
+        &mut self,
+        code: &str,
+        options: CompileOptions,
+        hw: InterpreterOptions,
+        hints: Hints,
+        vars: &HashMap<String, String>,
+    ) {
+        self.code = code.to_string();
+        self.options = options;
+        self.hw = hw;
+        self.hints = hints;
+        self.vars = vars.clone();
+        self.clear_results();
+    }
+
+    pub fn clear(&mut self) {
+        self.code.clear();
+        self.clear_results();
+    }
+
+    /// Forgets the measurements, including the ones still running, which are of another program.
+    fn clear_results(&mut self) {
+        self.rows.clear();
+        self.pending = None;
+        self.save_status = None;
+    }
+
+    fn save(&mut self) {
+        let path = files::data_file(CSV_FILE);
+        let file = path.display();
+        self.save_status = Some(match std::fs::write(&path, to_csv(&self.rows)) {
+            Ok(()) => t!("output.saved", file = file).to_string(),
+            Err(e) => t!("output.save_failed", file = file, e = e).to_string(),
+        });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.code.is_empty() {
+            ui.label(t!("output.empty"));
+            return;
+        }
+
+        ui.label(t!("matrix.explain"));
+        ui.horizontal_wrapped(|ui| {
+            for (opt, included) in Optimization::all().zip(&mut self.included) {
+                ui.checkbox(included, t!(opt.key()));
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let count = 1 << self.included.iter().filter(|&&i| i).count();
+            if ui
+                .add_enabled(
+                    self.pending.is_none(),
+                    egui::Button::new(t!("matrix.run", n = count)),
+                )
+                .clicked()
+            {
+                let passes: Vec<_> = Optimization::all()
+                    .zip(&self.included)
+                    .filter(|(_, included)| **included)
+                    .map(|(opt, _)| opt)
+                    .collect();
+                let (code, options, hw) = (self.code.clone(), self.options, self.hw);
+                let (hints, vars) = (self.hints.clone(), self.vars.clone());
+                let worker = std::thread::Builder::new().stack_size(parser::STACK_SIZE);
+                let spawned =
+                    worker.spawn(move || measure_all(&code, options, &passes, hw, &hints, &vars));
+                self.pending = Some(spawned.expect("failed to start measuring"));
+            }
+            if self.pending.is_some() {
+                ui.spinner();
+            }
+            if ui
+                .add_enabled(
+                    !self.rows.is_empty(),
+                    egui::Button::new(t!("output.save", file = CSV_FILE)),
+                )
+                .clicked()
+            {
+                self.save();
+            }
+            if ui
+                .add_enabled(!self.rows.is_empty(), egui::Button::new("📋"))
+                .on_hover_text(t!("matrix.copy"))
+                .clicked()
+            {
+                ui.ctx().copy_text(to_csv(&self.rows));
+            }
+        });
+        if let Some(status) = &self.save_status {
+            ui.weak(status);
+        }
+
+        if self.pending.as_ref().is_some_and(JoinHandle::is_finished) {
+            let pending = self.pending.take().unwrap();
+            self.rows = pending.join().unwrap_or_default();
+        } else if self.pending.is_some() {
+            ui.ctx().request_repaint();
+        }
+
+        if self.rows.is_empty() {
+            return;
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.selectable_value(
+                &mut self.metric,
+                Metric::Instructions,
+                t!("matrix.instructions"),
+            );
+            ui.selectable_value(&mut self.metric, Metric::Cycles, t!("matrix.cycles"));
+            ui.selectable_value(&mut self.metric, Metric::Ram, t!("matrix.ram"));
+        });
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            draw_bars(ui, &self.rows, self.metric);
+        });
+    }
+}
+
+/// The optimizations by name for the table, `none` without any.
+fn names(passes: &[Optimization]) -> String {
+    let names: Vec<_> = Optimization::all()
+        .filter(|opt| passes.contains(opt))
+        .map(Optimization::name)
+        .collect();
+    match names.is_empty() {
+        true => "none".to_string(),
+        false => names.join("+"),
+    }
+}
+
+/// Every subset of `passes`, from none to all, each compiled and run with the inputs.
+fn measure_all(
+    code: &str,
+    options: CompileOptions,
+    passes: &[Optimization],
+    hw: InterpreterOptions,
+    hints: &Hints,
+    vars: &HashMap<String, String>,
+) -> Vec<Row> {
+    (0..1usize << passes.len())
+        .map(|bits| {
+            let passes: Vec<_> = (0..passes.len())
+                .filter(|i| bits & (1 << i) != 0)
+                .map(|i| passes[i])
+                .collect();
+            let mut options = options;
+            for opt in Optimization::all() {
+                *opt.flag(&mut options) = passes.contains(&opt);
+            }
+            let measurement = measure(code, options, hw, hints, vars);
+            Row {
+                passes,
+                measurement,
+            }
+        })
+        .collect()
+}
+
+fn measure(
+    code: &str,
+    options: CompileOptions,
+    hw: InterpreterOptions,
+    hints: &Hints,
+    vars: &HashMap<String, String>,
+) -> Result<Measurement, String> {
+    let compiler = Compiler::with(options).with_hints(hints.clone());
+    let run = bench::compile_and_run(compiler, hw, code, vars).map_err(|e| e.to_string())?;
+    Ok(Measurement {
+        result: run.result,
+        instructions: run.instructions,
+        cycles: run.stats.cycles,
+        ram_accesses: run.stats.ram_reads + run.stats.ram_writes,
+    })
+}
+
+/// One line per combination, with the error message instead of the numbers if it failed.
+fn to_csv(rows: &[Row]) -> String {
+    let mut csv = String::from("passes,instructions,cycles,ram_accesses,result,error\n");
+    for row in rows {
+        let passes = names(&row.passes);
+        csv += &match &row.measurement {
+            Ok(m) => format!(
+                "{passes},{},{},{},{},\n",
+                m.instructions, m.cycles, m.ram_accesses, m.result
+            ),
+            Err(e) => format!("{passes},,,,,\"{}\"\n", e.replace('"', "\"\"")),
+        };
+    }
+    csv
+}
+
+/// A horizontal bar per combination, the longest one is the slowest.
+fn draw_bars(ui: &mut egui::Ui, rows: &[Row], metric: Metric) {
+    let max = rows
+        .iter()
+        .filter_map(|r| r.measurement.as_ref().ok())
+        .map(|m| metric.value(m))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let best = rows
+        .iter()
+        .filter_map(|r| r.measurement.as_ref().ok())
+        .map(|m| metric.value(m))
+        .min();
+    egui::Grid::new("matrix_bars")
+        .num_columns(2)
+        .spacing([10.0, 2.0])
+        .show(ui, |ui| {
+            for row in rows {
+                let label = match row.passes.is_empty() {
+                    true => t!("matrix.none").to_string(),
+                    false => {
+                        let names: Vec<_> = row.passes.iter().map(|p| t!(p.key())).collect();
+                        names.join(" + ")
+                    }
+                };
+                ui.label(label);
+                match &row.measurement {
+                    Ok(m) => {
+                        let value = metric.value(m);
+                        let color = match Some(value) == best {
+                            true => Theme::global().accent(),
+                            false => ui.visuals().widgets.inactive.bg_fill,
+                        };
+                        let width = 200.0 * value as f32 / max as f32;
+                        ui.horizontal(|ui| {
+                            let (rect, _) = ui.allocate_exact_size(
+                                egui::vec2(width.max(1.0), 12.0),
+                                egui::Sense::hover(),
+                            );
+                            ui.painter().rect_filled(rect, 2.0, color);
+                            ui.monospace(value.to_string());
+                        });
+                    }
+                    Err(e) => {
+                        ui.label(RichText::new("⚠").color(Color32::RED))
+                            .on_hover_text(e);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+}
+
+impl crate::gui::Window for PassMatrix {
+    fn name(&self) -> String {
+        "matrix.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(480.0)
+            .default_height(500.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_combination_is_measured() {
+        let vars = HashMap::from([("a".to_string(), "5".to_string())]);
+        let passes = ["folding", "shifts"].map(|name| Optimization::named(name).unwrap());
+        let rows = measure_all(
+            "a * 8 + 2 * 3",
+            CompileOptions::default(),
+            &passes,
+            InterpreterOptions::default(),
+            &Hints::new(),
+            &vars,
+        );
+        assert_eq!(rows.len(), 4);
+        let measurements: Vec<_> = rows
+            .iter()
+            .map(|r| r.measurement.clone().unwrap())
+            .collect();
+        assert!(measurements.iter().all(|m| m.result == 46));
+        // both together are the fastest
+        assert!(measurements[3].cycles < measurements[1].cycles);
+        assert!(measurements[3].cycles < measurements[2].cycles);
+
+        let csv = to_csv(&rows);
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "passes,instructions,cycles,ram_accesses,result,error"
+        );
+        assert!(lines[1].starts_with("none,"));
+        assert!(lines[4].starts_with("folding+shifts,"));
+        assert!(lines[4].ends_with(",46,"));
+
+        let failed = measure_all(
+            "a / b",
+            CompileOptions::default(),
+            &[],
+            InterpreterOptions::default(),
+            &Hints::new(),
+            &vars,
+        );
+        assert!(failed[0].measurement.is_err());
+        assert!(
+            to_csv(&failed)
+                .lines()
+                .nth(1)
+                .unwrap()
+                .starts_with("none,,,,,\"")
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::compiler::{CompileOptions, Stage};
+use crate::export::{self, Artifact};
+use crate::files;
+use crate::gui::InterpreterOptions;
+use crate::passes::RewriteRule;
+use eframe::egui::{self, Id, RichText};
+use rust_i18n::t;
+
+/// Window stepping through the program as it moves through the compilation pipeline.
+#[derive(Default)]
+pub struct PipelineView {
+    stages: Vec<Stage>,
+    selected: usize,
+    /// Set when the whole pipeline run should be saved as JSON, the app knows what was compiled.
+    pub export_requested: bool,
+    /// Result of the last export.
+    export_status: Option<String>,
+}
+
+impl PipelineView {
+    pub fn set(&mut self, stages: &[Stage]) {
+        self.stages = stages.to_vec();
+        self.selected = self.selected.min(self.stages.len().saturating_sub(1));
+    }
+
+    pub fn clear(&mut self) {
+        self.stages.clear();
+        self.selected = 0;
+        self.export_status = None;
+    }
+
+    /// Compiles the program once more and saves everything about it to [`export::FILE_NAME`].
+    pub fn export(
+        &mut self,
+        source: &str,
+        opts: CompileOptions,
+        hw: InterpreterOptions,
+        rules: Vec<RewriteRule>,
+    ) {
+        self.export_requested = false;
+        let path = files::data_file(export::FILE_NAME);
+        let file = path.display();
+        let json = Artifact::new(source, opts, hw, rules).to_json();
+        self.export_status = Some(match std::fs::write(&path, json + "\n") {
+            Ok(()) => t!("output.saved", file = file).to_string(),
+            Err(e) => t!("output.save_failed", file = file, e = e).to_string(),
+        });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.stages.is_empty() {
+            ui.label(t!("output.empty"));
+            return;
+        }
+
+        let last = self.stages.len() - 1;
+        let name = t!(self.stages[self.selected].name);
+        ui.add(
+            egui::Slider::new(&mut self.selected, 0..=last)
+                .show_value(false)
+                .text(name),
+        );
+
+        // the whole pipeline at a glance, clicking a step selects it
+        ui.horizontal_wrapped(|ui| {
+            for (i, stage) in self.stages.iter().enumerate() {
+                if i > 0 {
+                    ui.label("→");
+                }
+                if ui
+                    .selectable_label(i == self.selected, t!(stage.name))
+                    .clicked()
+                {
+                    self.selected = i;
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui
+                .button(t!("output.save", file = export::FILE_NAME))
+                .clicked()
+            {
+                self.export_requested = true;
+            }
+            if let Some(status) = &self.export_status {
+                ui.label(status);
+            }
+        });
+
+        ui.separator();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for line in &self.stages[self.selected].lines {
+                ui.label(RichText::new(line).monospace());
+            }
+        });
+    }
+}
+
+impl crate::gui::Window for PipelineView {
+    fn name(&self) -> String {
+        "pipeline.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(400.0)
+            .default_height(300.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::compiler::CompileOptions;
+use crate::files;
+use crate::passes::registry;
+use eframe::egui::{self, Id, RichText, ViewportBuilder, ViewportId};
+use rust_i18n::t;
+
+/// Notes shipped with the app, for when there's no file of the language in the working
+/// directory.
+const BUILT_IN: [(&str, &str); 2] = [
+    ("en", include_str!("../../assets/notes.en.txt")),
+    ("de", include_str!("../../assets/notes.de.txt")),
+];
+
+/// The notes of a language, e.g. `notes.de.txt`. A file in the data directory replaces the
+/// built-in one, so the booth can adjust it.
+fn notes_file(language: &str) -> PathBuf {
+    files::data_dir().join(format!("notes.{language}.txt"))
+}
+
+/// What the visitor just did, each has its own section of notes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Situation {
+    /// Nothing happened yet.
+    Start,
+    Compiled,
+    /// The program didn't compile.
+    Error,
+    Ran,
+    /// A pass was turned on, by its [`crate::passes::Pass::name`].
+    PassEnabled(&'static str),
+}
+
+impl Situation {
+    /// The heading of the section in the note files, e.g. `[pass folding]`.
+    fn section(&self) -> String {
+        match self {
+            Situation::Start => "start".into(),
+            Situation::Compiled => "compiled".into(),
+            Situation::Error => "error".into(),
+            Situation::Ran => "run".into(),
+            Situation::PassEnabled(name) => format!("pass {name}"),
+        }
+    }
+
+    fn all() -> Vec<Situation> {
+        let mut all = vec![
+            Situation::Start,
+            Situation::Compiled,
+            Situation::Error,
+            Situation::Ran,
+        ];
+        all.extend(registry().iter().map(|p| Situation::PassEnabled(p.name())));
+        all
+    }
+
+    fn caption(&self) -> String {
+        match self {
+            Situation::PassEnabled(name) => {
+                let pass = registry().iter().find(|p| p.name() == *name);
+                let label = pass.map_or((*name).into(), |p| t!(p.label()));
+                t!("presenter.situation.pass", pass = label).into()
+            }
+            _ => t!(format!("presenter.situation.{}", self.section())).into(),
+        }
+    }
+}
+
+/// Reads presenter notes: a `[section]` line for each [`Situation`], followed by its talking
+/// points, one per line. Empty lines and lines starting with `#` are skipped.
+pub fn parse_notes(text: &str) -> Result<HashMap<Situation, Vec<String>>, String> {
+    let situations = Situation::all();
+    let mut notes: HashMap<Situation, Vec<String>> = HashMap::new();
+    let mut current = None;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = || t!("presenter.invalid", line = i + 1, text = line).to_string();
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let situation = situations
+                .iter()
+                .find(|s| s.section() == section.trim())
+                .ok_or_else(err)?;
+            current = Some(situation.clone());
+            continue;
+        }
+        let situation = current.clone().ok_or_else(err)?;
+        notes.entry(situation).or_default().push(line.to_string());
+    }
+    Ok(notes)
+}
+
+/// Talking points for the volunteers at the booth, changing with what the visitor just did.
+/// It can be moved to a window of its own, e.g. on a second screen facing the presenter.
+#[derive(Default)]
+pub struct PresenterNotes {
+    notes: HashMap<Situation, Vec<String>>,
+    /// The language the notes were loaded for.
+    language: String,
+    /// Why the file in the data directory couldn't be used.
+    error: Option<String>,
+    situation: Option<Situation>,
+    /// The options of the last frame, to see which passes were turned on.
+    options: Option<CompileOptions>,
+    /// A pass was turned on and the program wasn't compiled with it yet.
+    pass_enabled: bool,
+    /// Shown in a window of its own instead of inside the app.
+    detached: bool,
+}
+
+impl PresenterNotes {
+    pub fn set_situation(&mut self, situation: Situation) {
+        self.situation = Some(situation);
+    }
+
+    /// The program was compiled, or not. The compilation right after turning on a pass keeps
+    /// the notes about the pass.
+    pub fn compiled(&mut self, ok: bool) {
+        if !ok {
+            self.set_situation(Situation::Error);
+        } else if !std::mem::take(&mut self.pass_enabled) {
+            self.set_situation(Situation::Compiled);
+        }
+    }
+
+    /// Notices passes that were turned on since the last frame.
+    pub fn watch_options(&mut self, options: CompileOptions) {
+        if let Some(before) = self.options {
+            let enabled = registry()
+                .iter()
+                .find(|pass| pass.enabled(&options) && !pass.enabled(&before));
+            if let Some(pass) = enabled {
+                self.set_situation(Situation::PassEnabled(pass.name()));
+                self.pass_enabled = true;
+            }
+        }
+        self.options = Some(options);
+    }
+
+    /// Loads the notes of the current language, if that changed.
+    fn load(&mut self) {
+        let language = rust_i18n::locale().to_string();
+        if language == self.language {
+            return;
+        }
+        let built_in = BUILT_IN
+            .iter()
+            .find(|(l, _)| *l == language)
+            .unwrap_or(&BUILT_IN[0])
+            .1;
+        let built_in = || parse_notes(built_in).expect("the built-in notes are valid");
+        (self.notes, self.error) = match std::fs::read_to_string(notes_file(&language)) {
+            Ok(text) => match parse_notes(&text) {
+                Ok(notes) => (notes, None),
+                Err(e) => (built_in(), Some(e)),
+            },
+            Err(_) => (built_in(), None),
+        };
+        self.language = language;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.load();
+        ui.checkbox(&mut self.detached, t!("presenter.detach"))
+            .on_hover_text(t!("presenter.detach.desc"));
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().warn_fg_color, error);
+        }
+        ui.separator();
+
+        let situation = self.situation.clone().unwrap_or(Situation::Start);
+        ui.heading(situation.caption());
+        match self.notes.get(&situation) {
+            Some(points) => {
+                for point in points {
+                    ui.label(RichText::new(format!("• {point}")).size(18.0));
+                }
+            }
+            None => {
+                ui.label(t!("presenter.none"));
+            }
+        }
+    }
+}
+
+impl crate::gui::Window for PresenterNotes {
+    fn name(&self) -> String {
+        "presenter.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        if !*open {
+            return;
+        }
+        if self.detached {
+            let builder = ViewportBuilder::default()
+                .with_title(t!(self.name()))
+                .with_inner_size([480.0, 360.0]);
+            ctx.show_viewport_immediate(
+                ViewportId::from_hash_of(self.name()),
+                builder,
+                |ctx, _| {
+                    egui::CentralPanel::default().show(ctx, |ui| self.ui(ui));
+                    if ctx.input(|i| i.viewport().close_requested()) {
+                        *open = false;
+                    }
+                },
+            );
+            return;
+        }
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(360.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn built_in_notes_cover_every_situation() {
+        for (language, text) in BUILT_IN {
+            let notes = parse_notes(text).unwrap();
+            for situation in Situation::all() {
+                assert!(notes.contains_key(&situation), "{language}: {situation:?}");
+            }
+        }
+        assert!(parse_notes("[start]\nHello\n[nonsense]").is_err());
+        assert!(parse_notes("a point without a section").is_err());
+    }
+
+    #[test]
+    fn turning_on_a_pass_changes_the_notes() {
+        let mut presenter = PresenterNotes::default();
+        let mut options = CompileOptions::default();
+        presenter.watch_options(options);
+        assert_eq!(presenter.situation, None);
+        options.do_shift_replacement = true;
+        presenter.watch_options(options);
+        assert_eq!(presenter.situation, Some(Situation::PassEnabled("shifts")));
+        presenter.compiled(true);
+        assert_eq!(presenter.situation, Some(Situation::PassEnabled("shifts")));
+        presenter.compiled(true);
+        assert_eq!(presenter.situation, Some(Situation::Compiled));
+        // turning it off again isn't worth a note
+        options.do_shift_replacement = false;
+        presenter.watch_options(options);
+        assert_eq!(presenter.situation, Some(Situation::Compiled));
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::compile_log::{CompileLog, PhaseTime};
+use eframe::egui::{self, Key, KeyboardShortcut, Modifiers, RichText};
+use rust_i18n::t;
+
+/// Shows or hides the overlay.
+const TOGGLE: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F12);
+
+/// Number of frames the frame time statistics are taken over.
+const FRAMES: usize = 120;
+
+/// Overlay with frame and compile times, to see where time goes on slow kiosk hardware.
+#[derive(Default)]
+pub struct Profiler {
+    enabled: bool,
+    /// Time spent in the app's update of the last [`FRAMES`] frames, the newest one last.
+    frames: VecDeque<Duration>,
+    /// How long each phase of the last compilation took, summed over all outputs.
+    compile: Vec<PhaseTime>,
+    /// How long the worker took for the last compilation.
+    compile_elapsed: Option<Duration>,
+    /// How long the interpreter took for the last run, summed over all outputs.
+    run: Vec<PhaseTime>,
+    /// How long the interpreter took for the steps animated since, summed over all outputs.
+    steps: Vec<PhaseTime>,
+}
+
+/// The times of the phases in all logs, those of equal phases summed.
+fn sum<'a>(logs: impl IntoIterator<Item = &'a CompileLog>) -> Vec<PhaseTime> {
+    let mut sum: Vec<PhaseTime> = Vec::new();
+    for time in logs.into_iter().flat_map(CompileLog::phase_times) {
+        match sum
+            .iter_mut()
+            .find(|t| t.phase == time.phase && t.name == time.name)
+        {
+            Some(t) => t.elapsed += time.elapsed,
+            None => sum.push(time),
+        }
+    }
+    sum
+}
+
+impl Profiler {
+    /// Toggles the overlay on its shortcut.
+    pub fn handle_shortcut(&mut self, ctx: &egui::Context) {
+        if ctx.input_mut(|i| i.consume_shortcut(&TOGGLE)) {
+            self.enabled = !self.enabled;
+        }
+    }
+
+    /// Records how long the UI took for one frame.
+    pub fn frame(&mut self, time: Duration) {
+        if self.frames.len() == FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(time);
+    }
+
+    /// Records the phase times in the logs of a finished compilation.
+    pub fn compiled<'a>(
+        &mut self,
+        logs: impl IntoIterator<Item = &'a CompileLog>,
+        elapsed: Duration,
+    ) {
+        self.compile = sum(logs);
+        self.compile_elapsed = Some(elapsed);
+    }
+
+    /// Records the phase times in the logs of the interpreter runs.
+    pub fn ran<'a>(&mut self, logs: impl IntoIterator<Item = &'a CompileLog>) {
+        self.run = sum(logs);
+        self.steps.clear();
+    }
+
+    /// Records the phase times in the logs of the animated steps so far.
+    pub fn stepped<'a>(&mut self, logs: impl IntoIterator<Item = &'a CompileLog>) {
+        self.steps = sum(logs);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Average and slowest frame time.
+    fn frame_stats(&self) -> (Duration, Duration) {
+        let max = self.frames.iter().max().copied().unwrap_or_default();
+        let avg = match self.frames.len() {
+            0 => Duration::ZERO,
+            n => self.frames.iter().sum::<Duration>() / n as u32,
+        };
+        (avg, max)
+    }
+
+    pub fn show(&self, ctx: &egui::Context) {
+        if !self.enabled {
+            return;
+        }
+        // keeps the frame times coming in even when nothing else moves
+        ctx.request_repaint();
+
+        egui::Area::new(egui::Id::new("profiler"))
+            .anchor(egui::Align2::LEFT_BOTTOM, [8.0, -8.0])
+            .order(egui::Order::Foreground)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(RichText::new(t!("profiler.title")).strong());
+                    egui::Grid::new("profiler_grid")
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            let (avg, max) = self.frame_stats();
+                            let row = |ui: &mut egui::Ui, name, time: Duration| {
+                                ui.label(name);
+                                ui.monospace(format!("{:.2} ms", time.as_secs_f64() * 1000.0));
+                                ui.end_row();
+                            };
+                            row(ui, t!("profiler.ui"), avg);
+                            row(ui, t!("profiler.ui_max"), max);
+                            for time in self.compile.iter().chain(&self.run) {
+                                row(ui, time.label().into(), time.elapsed);
+                            }
+                            if let Some(elapsed) = self.compile_elapsed {
+                                row(ui, t!("profiler.compile"), elapsed);
+                            }
+                            if !self.steps.is_empty() {
+                                ui.strong(t!("profiler.steps"));
+                                ui.end_row();
+                            }
+                            for time in &self.steps {
+                                row(ui, time.label().into(), time.elapsed);
+                            }
+                        });
+                    ui.weak(t!("profiler.hint", key = ctx.format_shortcut(&TOGGLE)));
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frame_stats_cover_the_latest_frames() {
+        let mut profiler = Profiler::default();
+        assert_eq!(profiler.frame_stats(), (Duration::ZERO, Duration::ZERO));
+
+        profiler.frame(Duration::from_millis(100));
+        for _ in 0..FRAMES {
+            profiler.frame(Duration::from_millis(2));
+        }
+        profiler.frame(Duration::from_millis(8));
+
+        let (avg, max) = profiler.frame_stats();
+        assert_eq!(max, Duration::from_millis(8));
+        assert!(avg > Duration::from_millis(2) && avg < Duration::from_millis(3));
+    }
+
+    #[test]
+    fn phases_of_all_outputs_are_summed() {
+        let compile = || {
+            crate::compile_log::record(|| {
+                let _parse = tracing::info_span!("parse").entered();
+            })
+            .1
+        };
+        let logs = [compile(), compile()];
+        let mut profiler = Profiler::default();
+        profiler.compiled(&logs, Duration::ZERO);
+
+        assert_eq!(profiler.compile.len(), 1);
+        let both: Duration = logs.iter().map(|l| l.phase_times()[0].elapsed).sum();
+        assert_eq!(profiler.compile[0].elapsed, both);
+    }
+}
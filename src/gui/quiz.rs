@@ -0,0 +1,238 @@
+use crate::compiler::{CompileOptions, Compiler, Inst};
+use crate::generator::{Rng, random_expr};
+use crate::gui::InterpreterOptions;
+use crate::interpreter::Interpreter;
+use crate::types::Operator;
+use eframe::egui::{self, Color32, Id, RichText};
+use rust_i18n::t;
+use std::collections::HashMap;
+
+/// How long the confetti is shown after a correct guess, in seconds.
+const CONFETTI_SECS: f64 = 2.0;
+
+struct Question {
+    source: String,
+    asm: Vec<Inst>,
+    answer: i32,
+}
+
+enum Feedback {
+    Correct,
+    Wrong(i32),
+    NotANumber,
+}
+
+/// Game mode: visitors guess the result of a short program before it runs.
+pub struct Quiz {
+    /// Hardware the programs are compiled for.
+    pub hw: InterpreterOptions,
+    rng: Rng,
+    question: Option<Question>,
+    guess: String,
+    feedback: Option<Feedback>,
+    correct: u32,
+    answered: u32,
+    /// Time at which the last correct guess was made, for the confetti.
+    confetti_since: Option<f64>,
+}
+
+impl Default for Quiz {
+    fn default() -> Self {
+        Self {
+            hw: InterpreterOptions::default(),
+            rng: Rng::from_time(),
+            question: None,
+            guess: String::new(),
+            feedback: None,
+            correct: 0,
+            answered: 0,
+            confetti_since: None,
+        }
+    }
+}
+
+impl Quiz {
+    fn next_question(&mut self) {
+        let hw = self.hw;
+        self.guess.clear();
+        self.feedback = None;
+
+        // generated programs are always valid, but a tiny machine might not be able to run them
+        for _ in 0..10 {
+            let expr = random_expr(
+                &mut self.rng,
+                2,
+                &[Operator::Add, Operator::Sub, Operator::Mul],
+            );
+            let source = expr.to_string();
+            let Ok(ir) = Compiler::with(CompileOptions::default())
+                .with_interpreter(hw)
+                .compile(&source)
+            else {
+                continue;
+            };
+            let Ok(answer) = Interpreter::with_config(&hw)
+                .load_instructions(ir.instructions.clone())
+                .with_variables(HashMap::new())
+                .ready()
+                .run_to_end()
+            else {
+                continue;
+            };
+            self.question = Some(Question {
+                source,
+                asm: ir.instructions,
+                answer,
+            });
+            return;
+        }
+        self.question = None;
+    }
+
+    fn check(&mut self, now: f64) {
+        let Some(question) = &self.question else {
+            return;
+        };
+        let Ok(guess) = self.guess.trim().parse::<i32>() else {
+            self.feedback = Some(Feedback::NotANumber);
+            return;
+        };
+
+        self.answered += 1;
+        if guess == question.answer {
+            self.correct += 1;
+            self.feedback = Some(Feedback::Correct);
+            self.confetti_since = Some(now);
+        } else {
+            self.feedback = Some(Feedback::Wrong(question.answer));
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(t!("quiz.explain"));
+        ui.label(t!(
+            "quiz.score",
+            correct = self.correct,
+            total = self.answered
+        ));
+        ui.separator();
+
+        if self.question.is_none() {
+            self.next_question();
+        }
+        let Some(question) = &self.question else {
+            ui.colored_label(Color32::RED, t!("quiz.no_question"));
+            return;
+        };
+
+        ui.vertical_centered(|ui| {
+            ui.label(RichText::new(&question.source).monospace().size(24.0));
+        });
+
+        egui::CollapsingHeader::new(t!("quiz.asm"))
+            .id_salt("quiz.asm")
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for inst in &question.asm {
+                            ui.label(inst.to_string());
+                        }
+                    });
+            });
+
+        let answered = matches!(self.feedback, Some(Feedback::Correct | Feedback::Wrong(_)));
+        let now = ui.input(|i| i.time);
+        ui.horizontal(|ui| {
+            ui.label(t!("quiz.guess"));
+            let edit = ui.add_enabled(
+                !answered,
+                egui::TextEdit::singleline(&mut self.guess).desired_width(80.0),
+            );
+            let submitted = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui
+                .add_enabled(!answered, egui::Button::new(t!("quiz.check")))
+                .clicked()
+                || (submitted && !answered)
+            {
+                self.check(now);
+            }
+            if ui.button(t!("quiz.next")).clicked() {
+                self.next_question();
+            }
+        });
+
+        match &self.feedback {
+            Some(Feedback::Correct) => {
+                ui.colored_label(Color32::GREEN, t!("quiz.correct"));
+            }
+            Some(Feedback::Wrong(answer)) => {
+                ui.colored_label(Color32::RED, t!("quiz.wrong", res = answer));
+            }
+            Some(Feedback::NotANumber) => {
+                ui.colored_label(Color32::RED, t!("quiz.nan"));
+            }
+            None => (),
+        }
+
+        if let Some(since) = self.confetti_since {
+            if now - since < CONFETTI_SECS {
+                draw_confetti(ui, (now - since) as f32);
+                ui.ctx().request_repaint();
+            } else {
+                self.confetti_since = None;
+            }
+        }
+    }
+}
+
+/// Paints confetti bursting out of the window, `t` seconds after it started.
+fn draw_confetti(ui: &egui::Ui, t: f32) {
+    const COLORS: [Color32; 5] = [
+        Color32::RED,
+        Color32::GOLD,
+        Color32::GREEN,
+        Color32::LIGHT_BLUE,
+        Color32::from_rgb(255, 105, 180),
+    ];
+    let painter = ui.ctx().layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        Id::new("quiz_confetti"),
+    ));
+    let origin = ui.min_rect().center_top();
+    let fade = 1.0 - t / CONFETTI_SECS as f32;
+
+    // the same "random" layout each frame, so particles move smoothly
+    let mut rng = Rng::with_seed(42);
+    for _ in 0..80 {
+        let vx = rng.range(-300, 300) as f32;
+        let vy = rng.range(-500, -150) as f32;
+        let color = *rng.choose(&COLORS);
+        let pos = origin + egui::vec2(vx * t, vy * t + 600.0 * t * t);
+        painter.rect_filled(
+            egui::Rect::from_center_size(pos, egui::vec2(6.0, 4.0)),
+            1.0,
+            color.gamma_multiply(fade),
+        );
+    }
+}
+
+impl crate::gui::Window for Quiz {
+    fn name(&self) -> String {
+        "quiz.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(360.0)
+            .default_height(400.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
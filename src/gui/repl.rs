@@ -0,0 +1,229 @@
+use crate::compiler::{CompileOptions, Compiler};
+use crate::gui::InterpreterOptions;
+use crate::interpreter::Interpreter;
+use eframe::egui::{self, Color32, Id, Key, RichText};
+use rust_i18n::t;
+use std::collections::HashMap;
+
+/// What running one line gave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub result: i32,
+    pub instructions: usize,
+    pub cycles: u64,
+}
+
+struct Entry {
+    input: String,
+    outcome: Result<Outcome, String>,
+}
+
+/// Console where each line is compiled and run right away, without the animation.
+///
+/// A line like `x = a + 1` also binds the result to `x`, later lines can use it as a variable.
+#[derive(Default)]
+pub struct Repl {
+    /// Hardware and options the lines are compiled with, the same as in the editor.
+    pub hw: InterpreterOptions,
+    pub options: CompileOptions,
+    input: String,
+    history: Vec<Entry>,
+    /// Values bound so far, in the order they were first bound.
+    bindings: Vec<(String, i32)>,
+    /// How far back the arrow keys went in the history, 0 is the line being typed.
+    recalled: usize,
+}
+
+/// The name and expression of a line like `x = a + 1`.
+fn binding(line: &str) -> Option<(&str, &str)> {
+    let (name, expr) = line.split_once('=')?;
+    let name = name.trim();
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    valid.then_some((name, expr))
+}
+
+impl Repl {
+    /// Compiles and runs one line, remembering the value if it binds a name.
+    pub fn eval(&mut self, line: &str) -> Result<Outcome, String> {
+        let (name, expr) = match binding(line) {
+            Some((name, expr)) => (Some(name), expr),
+            None => (None, line),
+        };
+        let ir = Compiler::with(self.options)
+            .with_interpreter(self.hw)
+            .compile(expr)
+            .map_err(|e| e.to_string())?;
+
+        let mut variables = HashMap::new();
+        for (var, _) in &ir.variables {
+            let Some((_, value)) = self.bindings.iter().find(|(name, _)| name == var) else {
+                return Err(t!("repl.unbound", var = var).to_string());
+            };
+            variables.insert(var.clone(), value.to_string());
+        }
+        let mut interpreter = Interpreter::with_config(&self.hw)
+            .load_instructions(ir.instructions.clone())
+            .with_variables(variables)
+            .ready();
+        let result = interpreter.run_to_end().map_err(|e| e.to_string())?;
+
+        if let Some(name) = name {
+            match self.bindings.iter_mut().find(|(n, _)| n == name) {
+                Some((_, value)) => *value = result,
+                None => self.bindings.push((name.into(), result)),
+            }
+        }
+        Ok(Outcome {
+            result,
+            instructions: ir.instructions.len(),
+            cycles: interpreter.stats().cycles,
+        })
+    }
+
+    fn submit(&mut self) {
+        let input = std::mem::take(&mut self.input);
+        if input.trim().is_empty() {
+            return;
+        }
+        let outcome = self.eval(&input);
+        self.history.push(Entry { input, outcome });
+        self.recalled = 0;
+    }
+
+    /// Fills in an earlier line, `back` lines before the one being typed.
+    fn recall(&mut self, back: usize) {
+        self.recalled = back.min(self.history.len());
+        self.input = match self.recalled {
+            0 => String::new(),
+            n => self.history[self.history.len() - n].input.clone(),
+        };
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(t!("repl.explain"));
+        ui.horizontal_wrapped(|ui| {
+            for (name, value) in &self.bindings {
+                ui.code(format!("{name} = {value}"));
+            }
+            if ui.button(t!("repl.clear")).clicked() {
+                self.history.clear();
+                self.bindings.clear();
+            }
+        });
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .max_height(ui.available_height() - 40.0)
+            .stick_to_bottom(true)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                for entry in &self.history {
+                    ui.label(RichText::new(format!("> {}", entry.input)).monospace());
+                    match &entry.outcome {
+                        Ok(outcome) => {
+                            ui.horizontal(|ui| {
+                                ui.label(
+                                    RichText::new(format!("= {}", outcome.result))
+                                        .monospace()
+                                        .strong(),
+                                );
+                                ui.weak(t!(
+                                    "repl.cost",
+                                    n = outcome.instructions,
+                                    cycles = outcome.cycles
+                                ));
+                            });
+                        }
+                        Err(e) => {
+                            ui.colored_label(Color32::RED, e);
+                        }
+                    }
+                }
+            });
+
+        ui.separator();
+        let edit = ui.add(
+            egui::TextEdit::singleline(&mut self.input)
+                .font(egui::TextStyle::Monospace)
+                .hint_text(t!("repl.hint"))
+                .desired_width(f32::INFINITY),
+        );
+        if edit.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+            self.submit();
+            edit.request_focus();
+        }
+        if edit.has_focus() {
+            if ui.input(|i| i.key_pressed(Key::ArrowUp)) {
+                self.recall(self.recalled + 1);
+            }
+            if ui.input(|i| i.key_pressed(Key::ArrowDown)) {
+                self.recall(self.recalled.saturating_sub(1));
+            }
+        }
+    }
+}
+
+impl crate::gui::Window for Repl {
+    fn name(&self) -> String {
+        "repl.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(400.0)
+            .default_height(400.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bindings_are_kept_across_lines() {
+        let mut repl = Repl::default();
+        assert_eq!(repl.eval("x = 2 * 3").map(|o| o.result), Ok(6));
+        assert_eq!(repl.eval("y = x + 1").map(|o| o.result), Ok(7));
+        let outcome = repl.eval("x * y").unwrap();
+        assert_eq!(outcome.result, 42);
+        assert!(outcome.instructions > 0 && outcome.cycles > 0);
+
+        // binding a name again replaces its value
+        repl.eval("x = 1").unwrap();
+        assert_eq!(repl.eval("x * y").map(|o| o.result), Ok(7));
+        assert_eq!(repl.bindings, [("x".into(), 1), ("y".into(), 7)]);
+    }
+
+    #[test]
+    fn errors_dont_bind_names() {
+        let mut repl = Repl::default();
+        assert!(repl.eval("z = 1 +").is_err());
+        assert!(repl.eval("z + 1").unwrap_err().contains('z'));
+        assert!(repl.eval("1 / 0").is_err());
+        assert!(repl.bindings.is_empty());
+    }
+
+    #[test]
+    fn lines_are_recalled() {
+        let mut repl = Repl::default();
+        for line in ["1", "2"] {
+            repl.input = line.into();
+            repl.submit();
+        }
+        repl.recall(1);
+        assert_eq!(repl.input, "2");
+        repl.recall(5);
+        assert_eq!(repl.input, "1");
+        repl.recall(0);
+        assert_eq!(repl.input, "");
+    }
+}
@@ -0,0 +1,141 @@
+use crate::passes::{PEEPHOLE_FILE, PeepholeRule, RewriteRule};
+use eframe::egui::{self, Color32, Id, RichText};
+use rust_i18n::t;
+
+/// A rule as typed in by the user, together with the result of checking it.
+struct RuleEntry {
+    source: String,
+    enabled: bool,
+    checked: Result<RewriteRule, String>,
+}
+
+impl RuleEntry {
+    fn new(source: &str) -> Self {
+        let mut entry = Self {
+            source: source.to_string(),
+            enabled: true,
+            checked: Err(String::new()),
+        };
+        entry.check();
+        entry
+    }
+
+    fn check(&mut self) {
+        self.checked = RewriteRule::parse(&self.source)
+            .and_then(|rule| rule.validate().map(|()| rule))
+            .map_err(|e| e.to_string());
+    }
+}
+
+/// "Build your own optimizer": a list of rewrite rules the compiler applies to the program.
+pub struct RewriteRules {
+    rules: Vec<RuleEntry>,
+    /// Rules for the instructions, from [`PEEPHOLE_FILE`].
+    peephole: Vec<PeepholeRule>,
+    /// Lines of the file that aren't rules.
+    peephole_errors: Vec<String>,
+    /// Set when the rules changed, the program is compiled again then.
+    pub changed: bool,
+}
+
+impl Default for RewriteRules {
+    fn default() -> Self {
+        Self {
+            rules: vec![RuleEntry::new("?x * 2 → ?x + ?x")],
+            peephole: Vec::new(),
+            peephole_errors: Vec::new(),
+            changed: false,
+        }
+    }
+}
+
+impl RewriteRules {
+    /// All enabled rules that passed validation, in order.
+    pub fn active(&self) -> Vec<RewriteRule> {
+        self.rules
+            .iter()
+            .filter(|r| r.enabled)
+            .filter_map(|r| r.checked.as_ref().ok().cloned())
+            .collect()
+    }
+
+    /// Reads the peephole rules from their file again.
+    pub fn load_peephole(&mut self) {
+        (self.peephole, self.peephole_errors) = PeepholeRule::load();
+    }
+
+    pub fn peephole(&self) -> &[PeepholeRule] {
+        &self.peephole
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(t!("rewrite.explain"));
+        ui.separator();
+
+        let mut remove = None;
+        for (i, rule) in self.rules.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut rule.enabled, "");
+                if ui
+                    .add(egui::TextEdit::singleline(&mut rule.source).code_editor())
+                    .changed()
+                {
+                    rule.check();
+                }
+                if ui.button("🗑").on_hover_text(t!("rewrite.remove")).clicked() {
+                    remove = Some(i);
+                }
+            });
+            match &rule.checked {
+                Ok(_) => ui.label(RichText::new(t!("rewrite.valid")).color(Color32::GREEN)),
+                Err(e) => ui.label(RichText::new(e).color(Color32::RED)),
+            };
+        }
+        if let Some(i) = remove {
+            self.rules.remove(i);
+        }
+
+        if ui.button(t!("rewrite.add")).clicked() {
+            self.rules.push(RuleEntry::new("?x + 0 → ?x"));
+        }
+
+        ui.separator();
+        ui.strong(t!("rewrite.peephole"));
+        let path = PeepholeRule::path();
+        ui.label(t!("rewrite.peephole.explain", file = path.display()));
+        if self.peephole.is_empty() {
+            ui.weak(t!("rewrite.peephole.none"));
+        }
+        for rule in &self.peephole {
+            ui.monospace(&rule.text);
+        }
+        for error in &self.peephole_errors {
+            ui.colored_label(Color32::RED, error);
+        }
+        if ui
+            .button(t!("rewrite.peephole.reload", file = PEEPHOLE_FILE))
+            .clicked()
+        {
+            self.load_peephole();
+            self.changed = true;
+        }
+    }
+}
+
+impl crate::gui::Window for RewriteRules {
+    fn name(&self) -> String {
+        "rewrite.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(400.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::files;
+use eframe::egui::{self, Id, RichText};
+use rust_i18n::t;
+
+/// Read-only window showing the program lowered to a real-world target, e.g. RISC-V, or
+/// written back as source code.
+#[derive(Default)]
+pub struct TargetOutput {
+    heading: String,
+    lines: Option<Vec<String>>,
+    /// If set, the output can be saved to this file in the data directory.
+    file_name: Option<&'static str>,
+    /// Result of the last save attempt.
+    save_status: Option<String>,
+}
+
+impl TargetOutput {
+    /// Construct empty UI with a name.
+    pub fn empty(heading: String) -> Self {
+        Self {
+            heading,
+            ..Default::default()
+        }
+    }
+
+    /// Allow saving the output as `file_name`.
+    pub fn downloadable(mut self, file_name: &'static str) -> Self {
+        self.file_name = Some(file_name);
+        self
+    }
+
+    pub fn set(&mut self, lines: Vec<String>) {
+        self.lines = Some(lines);
+        self.save_status = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.lines = None;
+        self.save_status = None;
+    }
+
+    fn save(&mut self, file_name: &str) {
+        let contents = self
+            .lines
+            .as_ref()
+            .map_or(String::new(), |l| l.join("\n") + "\n");
+        let path = files::data_file(file_name);
+        let file = path.display();
+        self.save_status = Some(match std::fs::write(&path, contents) {
+            Ok(()) => t!("output.saved", file = file).to_string(),
+            Err(e) => t!("output.save_failed", file = file, e = e).to_string(),
+        });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.lines.is_none() {
+            ui.label(t!("output.empty"));
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button(t!("output.copy.code")).clicked() {
+                let text = self.lines.as_ref().map_or(String::new(), |l| l.join("\n"));
+                ui.ctx().copy_text(text);
+            }
+            if let Some(file_name) = self.file_name {
+                if ui.button(t!("output.save", file = file_name)).clicked() {
+                    self.save(file_name);
+                }
+                if let Some(status) = &self.save_status {
+                    ui.label(status);
+                }
+            }
+        });
+        ui.separator();
+
+        let Some(lines) = &self.lines else {
+            return;
+        };
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for line in lines {
+                ui.label(RichText::new(line).monospace());
+            }
+        });
+    }
+}
+
+impl crate::gui::Window for TargetOutput {
+    fn name(&self) -> String {
+        self.heading.clone()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(280.0)
+            .default_height(600.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::files;
+use eframe::egui::{self, Color32, Stroke, Visuals};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// File in the data directory the chosen theme is kept in, so it survives restarts.
+const THEME_FILE: &str = "theme.txt";
+
+static THEME: AtomicU8 = AtomicU8::new(Theme::Dark as u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    /// Black and white with bright accents and larger instructions, for projectors and bright
+    /// rooms.
+    HighContrast,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Dark, Theme::Light, Theme::HighContrast];
+
+    /// The theme used for drawing, see [`Theme::set_global`].
+    pub fn global() -> Self {
+        Self::ALL[THEME.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn set_global(self) {
+        THEME.store(self as u8, Ordering::Relaxed);
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Theme::Dark => "app.theme.dark",
+            Theme::Light => "app.theme.light",
+            Theme::HighContrast => "app.theme.high_contrast",
+        }
+    }
+
+    /// Loads the theme saved earlier, or the default one.
+    pub fn load() -> Self {
+        std::fs::read_to_string(files::data_dir().join(THEME_FILE))
+            .ok()
+            .and_then(|s| Self::ALL.into_iter().find(|t| format!("{t:?}") == s.trim()))
+            .unwrap_or_default()
+    }
+
+    /// Saves the theme, failing silently since it's merely a preference.
+    pub fn save(self) {
+        let _ = std::fs::write(files::data_file(THEME_FILE), format!("{self:?}\n"));
+    }
+
+    /// Makes this the current theme, for egui and our own drawing.
+    pub fn apply(self, ctx: &egui::Context) {
+        self.set_global();
+        ctx.set_visuals(match self {
+            Theme::Dark => Visuals::dark(),
+            Theme::Light => Visuals::light(),
+            Theme::HighContrast => high_contrast(),
+        });
+    }
+
+    /// Font size of instructions in the listings.
+    pub fn instruction_size(self) -> f32 {
+        match self {
+            Theme::Dark | Theme::Light => 14.0,
+            Theme::HighContrast => 20.0,
+        }
+    }
+
+    /// Color of the data-flow arrows.
+    pub fn accent(self) -> Color32 {
+        match self {
+            Theme::Dark => Color32::from_rgb(255, 140, 0),
+            Theme::Light => Color32::from_rgb(200, 80, 0),
+            Theme::HighContrast => Color32::YELLOW,
+        }
+    }
+
+    /// How strongly backgrounds of canvases are drawn, transparent ones are hard to see in high
+    /// contrast.
+    pub fn canvas_fill(self, fill: Color32) -> Color32 {
+        match self {
+            Theme::Dark | Theme::Light => fill.gamma_multiply(0.3),
+            Theme::HighContrast => Color32::BLACK,
+        }
+    }
+}
+
+fn high_contrast() -> Visuals {
+    let mut visuals = Visuals::dark();
+    visuals.override_text_color = Some(Color32::WHITE);
+    visuals.panel_fill = Color32::BLACK;
+    visuals.window_fill = Color32::BLACK;
+    visuals.extreme_bg_color = Color32::BLACK;
+    visuals.faint_bg_color = Color32::from_gray(40);
+    visuals.window_stroke = Stroke::new(2.0, Color32::WHITE);
+    visuals.selection.bg_fill = Color32::from_rgb(0, 90, 200);
+    visuals.selection.stroke = Stroke::new(2.0, Color32::WHITE);
+    visuals.hyperlink_color = Color32::YELLOW;
+    for widget in [
+        &mut visuals.widgets.noninteractive,
+        &mut visuals.widgets.inactive,
+        &mut visuals.widgets.hovered,
+        &mut visuals.widgets.active,
+        &mut visuals.widgets.open,
+    ] {
+        widget.bg_stroke = Stroke::new(1.5, Color32::WHITE);
+        widget.fg_stroke = Stroke::new(2.0, Color32::WHITE);
+    }
+    visuals.widgets.inactive.weak_bg_fill = Color32::from_gray(30);
+    visuals.widgets.hovered.weak_bg_fill = Color32::from_gray(70);
+    visuals.widgets.active.weak_bg_fill = Color32::from_gray(100);
+    visuals
+}
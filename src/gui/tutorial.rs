@@ -0,0 +1,217 @@
+use crate::compiler::CompileOptions;
+use eframe::egui::{self, Id, RichText};
+use rust_i18n::t;
+
+/// The steps of the tutorial, in the order of [`Lesson::ALL`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lesson {
+    /// Type an expression with a variable.
+    Expression,
+    Compile,
+    Run,
+    /// Compile again with constant folding.
+    Folding,
+    /// Run both programs and compare them.
+    Compare,
+}
+
+impl Lesson {
+    pub const ALL: [Lesson; 5] = [
+        Lesson::Expression,
+        Lesson::Compile,
+        Lesson::Run,
+        Lesson::Folding,
+        Lesson::Compare,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            Lesson::Expression => "tutorial.expression",
+            Lesson::Compile => "tutorial.compile",
+            Lesson::Run => "tutorial.run",
+            Lesson::Folding => "tutorial.folding",
+            Lesson::Compare => "tutorial.compare",
+        }
+    }
+}
+
+/// A guided tour for visitors on their own: each lesson asks for one thing to do in the app and
+/// checks it was done before going on. Tools are unlocked as the lessons get to them, the rest
+/// of the sidebar is hidden until the end.
+#[derive(Default)]
+pub struct Tutorial {
+    /// Index of the current lesson, `None` if the tutorial isn't running.
+    current: Option<usize>,
+    finished: bool,
+    /// The options of the last compilation since the lesson started.
+    compiled: Option<CompileOptions>,
+    /// Whether a program was run since the lesson started, and if the optimized one was too.
+    ran: Option<bool>,
+    /// Set when the tutorial should start, the app prepares the editor for it.
+    pub start_requested: bool,
+}
+
+impl Tutorial {
+    pub fn start(&mut self) {
+        self.start_requested = false;
+        self.current = Some(0);
+        self.finished = false;
+        self.compiled = None;
+        self.ran = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub fn lesson(&self) -> Option<Lesson> {
+        self.current.map(|i| Lesson::ALL[i])
+    }
+
+    /// The compile options are only shown from the lesson about them on.
+    pub fn options_unlocked(&self) -> bool {
+        self.current.is_none_or(|i| i >= Lesson::Folding as usize)
+    }
+
+    /// A program was compiled with these options.
+    pub fn compiled(&mut self, options: CompileOptions) {
+        self.compiled = Some(options);
+    }
+
+    /// The programs were run, `optimized` if the optimized one was as well.
+    pub fn ran(&mut self, optimized: bool) {
+        self.ran = Some(optimized);
+    }
+
+    /// Goes on to the next lesson once the current one was done, `variables` tells whether the
+    /// last compiled program has any.
+    pub fn update(&mut self, variables: bool) {
+        while let Some(lesson) = self.lesson()
+            && self.done(lesson, variables)
+        {
+            self.compiled = None;
+            self.ran = None;
+            let next = self.current.map_or(0, |i| i + 1);
+            if next == Lesson::ALL.len() {
+                self.current = None;
+                self.finished = true;
+            } else {
+                self.current = Some(next);
+            }
+        }
+    }
+
+    fn done(&self, lesson: Lesson, variables: bool) -> bool {
+        match lesson {
+            Lesson::Expression => variables,
+            Lesson::Compile => self.compiled.is_some(),
+            Lesson::Run => self.ran.is_some(),
+            Lesson::Folding => self.compiled.is_some_and(|o| o.do_constant_folding),
+            Lesson::Compare => self.ran == Some(true),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let Some(current) = self.current else {
+            if self.finished {
+                ui.label(t!("tutorial.finished"));
+            } else {
+                ui.label(t!("tutorial.explain"));
+            }
+            let start = if self.finished {
+                t!("tutorial.restart")
+            } else {
+                t!("tutorial.start")
+            };
+            if ui.button(start).clicked() {
+                self.start_requested = true;
+            }
+            return;
+        };
+
+        let progress = current as f32 / Lesson::ALL.len() as f32;
+        ui.add(egui::ProgressBar::new(progress).text(t!(
+            "tutorial.progress",
+            n = current + 1,
+            total = Lesson::ALL.len()
+        )));
+        for (i, lesson) in Lesson::ALL.into_iter().enumerate() {
+            let title = t!(format!("{}.title", lesson.key()));
+            let text = match i.cmp(&current) {
+                std::cmp::Ordering::Less => RichText::new(format!("✔ {title}")).weak(),
+                std::cmp::Ordering::Equal => RichText::new(format!("▶ {title}")).strong(),
+                std::cmp::Ordering::Greater => RichText::new(format!("· {title}")).weak(),
+            };
+            ui.label(text);
+        }
+        ui.separator();
+        ui.label(t!(Lesson::ALL[current].key()));
+        ui.label(RichText::new(t!("tutorial.waiting")).italics().weak());
+        if ui.button(t!("tutorial.stop")).clicked() {
+            self.stop();
+        }
+    }
+}
+
+impl crate::gui::Window for Tutorial {
+    fn name(&self) -> String {
+        "tutorial.name".into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        Self::ui(self, ui)
+    }
+
+    fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new(t!(self.name()))
+            .id(Id::new(self.name()))
+            .open(open)
+            .default_width(320.0)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lessons_wait_for_the_visitor() {
+        let mut tutorial = Tutorial::default();
+        tutorial.start();
+        assert!(!tutorial.options_unlocked());
+
+        tutorial.update(false);
+        assert_eq!(tutorial.lesson(), Some(Lesson::Expression));
+        tutorial.update(true);
+        assert_eq!(tutorial.lesson(), Some(Lesson::Compile));
+
+        tutorial.compiled(CompileOptions::default());
+        tutorial.update(true);
+        assert_eq!(tutorial.lesson(), Some(Lesson::Run));
+        tutorial.ran(false);
+        tutorial.update(true);
+        assert_eq!(tutorial.lesson(), Some(Lesson::Folding));
+        assert!(tutorial.options_unlocked());
+
+        tutorial.compiled(CompileOptions::default());
+        tutorial.update(true);
+        assert_eq!(tutorial.lesson(), Some(Lesson::Folding));
+        tutorial.compiled(CompileOptions {
+            do_constant_folding: true,
+            ..Default::default()
+        });
+        // what happened during an earlier lesson doesn't count for the next one
+        tutorial.ran(true);
+        tutorial.update(true);
+        assert_eq!(tutorial.lesson(), Some(Lesson::Compare));
+        tutorial.ran(true);
+        tutorial.update(true);
+        assert!(!tutorial.is_active());
+        assert!(tutorial.finished);
+    }
+}
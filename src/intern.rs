@@ -0,0 +1,443 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::ops::Deref;
+use std::sync::{LazyLock, OnceLock, PoisonError, RwLock, RwLockReadGuard};
+
+/// Values are stored in chunks of this many, allocated when the previous one is full.
+const CHUNK_SIZE: usize = 4096;
+/// The most chunks an interner has, so at most `CHUNKS * CHUNK_SIZE` (about 16 million) values.
+const CHUNKS: usize = 4096;
+
+/// Stores each distinct value once, so it can be referred to by a small index.
+///
+/// Values are never freed, so they can be handed out as `&'static` references. That's fine for
+/// the few names typed in one session, and the number of values is bounded: an interner holds at
+/// most [`CHUNKS`] chunks. Expressions are far more, they are kept in an [`Arena`] instead.
+/// Looking up a value by its index doesn't lock anything, only storing a new value does.
+pub struct Interner<V: 'static> {
+    /// Filled in order, a value is in place before its index is handed out.
+    chunks: [OnceLock<&'static [OnceLock<V>]>; CHUNKS],
+    indices: RwLock<HashMap<V, u32>>,
+}
+
+impl<V: Copy + Eq + Hash> Interner<V> {
+    fn new() -> Self {
+        Self {
+            chunks: [const { OnceLock::new() }; CHUNKS],
+            indices: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Index of `value`, storing it with `store` if it's new. Only locks the interner for
+    /// writing when the value is new.
+    pub fn intern<Q>(&self, value: &Q, store: impl FnOnce(&Q) -> V) -> u32
+    where
+        Q: ?Sized + Eq + Hash,
+        V: Borrow<Q>,
+    {
+        let known = (self.indices.read().unwrap_or_else(PoisonError::into_inner))
+            .get(value)
+            .copied();
+        if let Some(index) = known {
+            return index;
+        }
+        let mut indices = self.indices.write().unwrap_or_else(PoisonError::into_inner);
+        // another thread may have stored it in the meantime
+        if let Some(&index) = indices.get(value) {
+            return index;
+        }
+        let index = indices.len();
+        assert!(index < CHUNKS * CHUNK_SIZE, "too many interned values");
+        let chunk = self.chunks[index / CHUNK_SIZE]
+            .get_or_init(|| Box::leak((0..CHUNK_SIZE).map(|_| OnceLock::new()).collect()));
+        let value = store(value);
+        assert!(
+            chunk[index % CHUNK_SIZE].set(value).is_ok(),
+            "indices are unique"
+        );
+        let index = index as u32;
+        indices.insert(value, index);
+        index
+    }
+
+    /// The value interned at `index`.
+    pub fn get(&'static self, index: u32) -> &'static V {
+        let index = index as usize;
+        (self.chunks[index / CHUNK_SIZE].get())
+            .and_then(|chunk| chunk[index % CHUNK_SIZE].get())
+            .expect("indices are only handed out for stored values")
+    }
+}
+
+/// Creates an empty global interner for a `static`.
+pub const fn global<V: Copy + Eq + Hash>() -> LazyLock<Interner<V>> {
+    LazyLock::new(Interner::new)
+}
+
+/// Bits of an [`Arena`] index that tell which generation it belongs to, the rest is the position
+/// in the generation.
+const GENERATION_BITS: u32 = 8;
+const POSITION_BITS: u32 = u32::BITS - GENERATION_BITS;
+const POSITION_MASK: u32 = (1 << POSITION_BITS) - 1;
+
+/// A value stored in an [`Arena`] that refers to other values in it by their index.
+pub trait Node: Copy + Eq + Hash {
+    /// Replaces the index of each value this one refers to with what `f` returns for it.
+    fn map_indices(&mut self, f: impl FnMut(u32) -> u32);
+}
+
+/// Stores each distinct value once like an [`Interner`], but forgets old values, so it doesn't
+/// grow without bound in an app that runs for days.
+///
+/// Values are stored in generations. Once the current generation is full, a new one is started
+/// and the one before the current is dropped. Storing a value that refers to the previous
+/// generation copies what it refers to into the current one, so values only refer to their own
+/// generation. An index can be read until two more generations were started, then
+/// [`Arena::get`] returns `None`. Only the lowest [`GENERATION_BITS`] of the generation are part
+/// of an index, so an index that is hundreds of generations old could find a newer value.
+///
+/// A value has a different index in each generation it is copied into, so indices of different
+/// generations may differ for equal values.
+pub struct Arena<V> {
+    generations: RwLock<Generations<V>>,
+}
+
+struct Generations<V> {
+    /// Values in the current generation, it's full at this many.
+    capacity: usize,
+    /// How many generations were started before the current one.
+    started: u32,
+    current: Generation<V>,
+    previous: Generation<V>,
+}
+
+struct Generation<V> {
+    values: Vec<V>,
+    indices: HashMap<V, u32>,
+}
+
+impl<V> Default for Generation<V> {
+    fn default() -> Self {
+        Self {
+            values: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+}
+
+impl<V: Node> Arena<V> {
+    /// An empty arena that starts a new generation every `capacity` values.
+    pub fn new(capacity: usize) -> Self {
+        // a generation grows to twice its capacity by what it copies from the one before
+        assert!(capacity < 1 << (POSITION_BITS - 1), "capacity too large");
+        Self {
+            generations: RwLock::new(Generations {
+                capacity: capacity.max(1),
+                started: 0,
+                current: Generation::default(),
+                previous: Generation::default(),
+            }),
+        }
+    }
+
+    /// Index of `value`, storing it if it's new. Only locks the arena for writing when the value
+    /// is new.
+    pub fn intern(&self, value: V) -> u32 {
+        let known = self.read().lookup(&value);
+        if let Some(index) = known {
+            return index;
+        }
+        let mut generations = self
+            .generations
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if generations.current.values.len() >= generations.capacity {
+            generations.start_next();
+        }
+        let mut value = value;
+        value.map_indices(|index| generations.adopt(index));
+        generations.insert(value)
+    }
+
+    /// The value stored at `index`, or `None` if it was forgotten.
+    pub fn get(&self, index: u32) -> Option<V> {
+        self.read().get(index)
+    }
+
+    /// How many generations were started so far. Indices handed out before are forgotten once
+    /// this grew by two.
+    pub fn generation(&self) -> u32 {
+        self.read().started
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Generations<V>> {
+        self.generations
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<V: Node> Generations<V> {
+    /// The bits of `generation` kept in its indices.
+    fn tag(generation: u32) -> u32 {
+        generation % (1 << GENERATION_BITS)
+    }
+
+    fn index(generation: u32, position: usize) -> u32 {
+        (Self::tag(generation) << POSITION_BITS) | position as u32
+    }
+
+    fn is_previous(&self, index: u32) -> bool {
+        index >> POSITION_BITS == Self::tag(self.started.wrapping_sub(1))
+    }
+
+    fn get(&self, index: u32) -> Option<V> {
+        let position = (index & POSITION_MASK) as usize;
+        let generation = if index >> POSITION_BITS == Self::tag(self.started) {
+            &self.current
+        } else if self.is_previous(index) {
+            &self.previous
+        } else {
+            return None;
+        };
+        generation.values.get(position).copied()
+    }
+
+    fn lookup(&self, value: &V) -> Option<u32> {
+        let position = *self.current.indices.get(value)?;
+        Some(Self::index(self.started, position as usize))
+    }
+
+    fn start_next(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+        self.started += 1;
+    }
+
+    /// Stores `value` in the current generation, its references have to be in it already.
+    fn insert(&mut self, value: V) -> u32 {
+        if let Some(index) = self.lookup(&value) {
+            return index;
+        }
+        let position = self.current.values.len();
+        self.current.values.push(value);
+        self.current.indices.insert(value, position as u32);
+        Self::index(self.started, position)
+    }
+
+    /// Index in the current generation of the value at `index`, copying it and what it refers
+    /// to if it's in the previous one. Forgotten indices stay forgotten.
+    fn adopt(&mut self, index: u32) -> u32 {
+        if !self.is_previous(index) {
+            return index;
+        }
+        let mut adopted = HashMap::new();
+        // explicit stack, the values may be long chains
+        let mut todo = vec![index];
+        while let Some(&next) = todo.last() {
+            if adopted.contains_key(&next) {
+                todo.pop();
+                continue;
+            }
+            let Some(mut value) = self.get(next) else {
+                adopted.insert(next, next);
+                continue;
+            };
+            let mut waiting = false;
+            value.map_indices(|i| match adopted.get(&i) {
+                Some(&copy) => copy,
+                None if self.is_previous(i) => {
+                    todo.push(i);
+                    waiting = true;
+                    i
+                }
+                None => i,
+            });
+            // copies stop at twice the capacity, the rest is readable until the next generation
+            if self.current.values.len() >= 2 * self.capacity {
+                return index;
+            }
+            if !waiting {
+                todo.pop();
+                let copy = self.insert(value);
+                adopted.insert(next, copy);
+            }
+        }
+        adopted[&index]
+    }
+}
+
+static NAMES: LazyLock<Interner<&'static str>> = global();
+
+/// An interned name, e.g. of an input variable. Copying and comparing it is as cheap as for an
+/// integer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn new(name: &str) -> Self {
+        Symbol(NAMES.intern(name, |name| Box::leak(name.into())))
+    }
+
+    pub fn as_str(self) -> &'static str {
+        NAMES.get(self.0)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::new(&name)
+    }
+}
+
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Symbol::from)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+/// Alphabetical, not in the order the names were interned.
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn equal_names_are_interned_once() {
+        let (a, b) = (
+            Symbol::new("interned"),
+            Symbol::from("interned".to_string()),
+        );
+        assert_eq!(a, b);
+        assert_eq!(a.0, b.0);
+        assert_ne!(a, Symbol::new("other"));
+        assert_eq!(a.as_str(), "interned");
+        assert!(Symbol::new("b") > Symbol::new("a"));
+    }
+
+    #[test]
+    fn values_are_found_from_other_threads() {
+        let names: Vec<String> = (0..2 * CHUNK_SIZE).map(|i| format!("name{i}")).collect();
+        let symbols: Vec<Symbol> = std::thread::scope(|scope| {
+            let halves = names.chunks(CHUNK_SIZE).map(|half| {
+                scope.spawn(move || half.iter().map(|n| Symbol::new(n)).collect::<Vec<_>>())
+            });
+            halves
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        for (name, symbol) in names.iter().zip(symbols) {
+            assert_eq!(symbol.as_str(), name);
+            assert_eq!(Symbol::new(name), symbol);
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Tree {
+        Leaf(u32),
+        Pair(u32, u32),
+    }
+
+    impl Node for Tree {
+        fn map_indices(&mut self, mut f: impl FnMut(u32) -> u32) {
+            if let Tree::Pair(l, r) = self {
+                (*l, *r) = (f(*l), f(*r));
+            }
+        }
+    }
+
+    #[test]
+    fn old_generations_are_forgotten() {
+        let arena = Arena::new(2);
+        let first = arena.intern(Tree::Leaf(1));
+        assert_eq!(arena.intern(Tree::Leaf(1)), first);
+        arena.intern(Tree::Leaf(2));
+        // starts the second generation, the first can still be read
+        let third = arena.intern(Tree::Leaf(3));
+        assert_eq!(arena.generation(), 1);
+        assert_eq!(arena.get(first), Some(Tree::Leaf(1)));
+
+        arena.intern(Tree::Leaf(4));
+        arena.intern(Tree::Leaf(5));
+        assert_eq!(arena.generation(), 2);
+        assert_eq!(arena.get(first), None);
+        assert_eq!(arena.get(third), Some(Tree::Leaf(3)));
+    }
+
+    #[test]
+    fn values_from_the_previous_generation_are_copied() {
+        let arena = Arena::new(2);
+        let (a, b) = (arena.intern(Tree::Leaf(1)), arena.intern(Tree::Leaf(2)));
+        let pair = arena.intern(Tree::Pair(a, b));
+        assert_eq!(arena.generation(), 1);
+        let Some(Tree::Pair(l, r)) = arena.get(pair) else {
+            panic!("expected a pair");
+        };
+        assert_ne!((l, r), (a, b));
+
+        // the copies outlive the originals
+        arena.intern(Tree::Leaf(3));
+        arena.intern(Tree::Leaf(4));
+        assert_eq!(arena.get(a), None);
+        assert_eq!(arena.get(l), Some(Tree::Leaf(1)));
+        assert_eq!(arena.get(r), Some(Tree::Leaf(2)));
+    }
+}
@@ -1,12 +1,270 @@
 use rust_i18n::t;
-use std::collections::HashMap;
-use std::ops::{Add, Div, Mul, Shl, Shr, Sub};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use tracing::info_span;
 
 use crate::{
-    gui::InterpreterOptions,
-    types::{Inst, LpErr, Reg},
+    generator::Rng,
+    gui::{InterpreterOptions, UninitPolicy, WordSize},
+    types::{AluOp, Inst, LpErr, MemAddr, OperandValue, Reg, Unit},
 };
 
+/// A place in the machine that holds a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Place {
+    Reg(Reg),
+    Ram(MemAddr),
+}
+
+impl std::fmt::Display for Place {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Place::Reg(r) => write!(f, "{r}"),
+            Place::Ram(addr) => write!(f, "[{addr}]"),
+        }
+    }
+}
+
+/// A value put somewhere by an instruction that moves data, see [`Interpreter::write_log`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedWrite {
+    /// Number of the step, starting at 1.
+    pub step: u64,
+    /// Index of the instruction.
+    pub instruction: usize,
+    pub place: Place,
+    pub value: i32,
+}
+
+/// The places an instruction reads from and writes to.
+#[derive(Debug, Clone, Default)]
+pub struct StepEffects {
+    pub reads: Vec<Place>,
+    pub writes: Vec<Place>,
+}
+
+impl StepEffects {
+    pub fn of(inst: &Inst) -> Self {
+        let (mut reads, mut writes) = (vec![], vec![]);
+        // the stack slot of push and pop depends on the stack pointer, see `Interpreter::last_effects`
+        for (operand, value) in inst.def().operands.iter().zip(inst.operands()) {
+            let place = match value {
+                OperandValue::Reg(r) => Place::Reg(r),
+                OperandValue::Addr(addr) => Place::Ram(addr),
+                OperandValue::Num(_) | OperandValue::Var(_) => continue,
+            };
+            if operand.read {
+                reads.push(place);
+            }
+            if operand.written {
+                writes.push(place);
+            }
+        }
+        Self { reads, writes }
+    }
+}
+
+/// The operation, the operands and the register getting the result of an arithmetic
+/// instruction, see [`Unit::Alu`].
+pub fn alu(inst: &Inst) -> Option<(AluOp, [OperandValue; 2], Reg)> {
+    let Unit::Alu { op, .. } = inst.def().unit else {
+        return None;
+    };
+    let (mut inputs, mut dest) = (vec![], None);
+    for (operand, value) in inst.def().operands.iter().zip(inst.operands()) {
+        if let OperandValue::Reg(r) = value
+            && operand.written
+        {
+            dest = Some(r);
+        }
+        if operand.read {
+            inputs.push(value);
+        }
+    }
+    if inputs.len() == 1 {
+        // unary operations, see `Unit::Alu`
+        inputs.insert(0, OperandValue::Num(0));
+    }
+    Some((op, inputs.try_into().ok()?, dest?))
+}
+
+/// Counters of everything the program did while running.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Instructions executed.
+    pub steps: u64,
+    /// Simulated cycles spent, see [`Inst::cycles`], including the stalls.
+    pub cycles: u64,
+    /// Cycles spent waiting for the RAM port, see [`Inst::stall`].
+    pub stalls: u64,
+    /// Instructions that ran on the second ALU, at the same time as the one before.
+    pub paired: u64,
+    /// How often each kind of instruction was executed, by [`Inst::name`].
+    pub opcodes: BTreeMap<&'static str, u64>,
+    /// Values read from RAM, including the stack.
+    pub ram_reads: u64,
+    /// Values written to RAM, including the stack.
+    pub ram_writes: u64,
+    /// Most registers holding a value that was still needed, at any one instruction.
+    pub max_live_registers: usize,
+    /// Values loaded from the cache in front of the RAM, see [`crate::engine::CacheSim`].
+    pub cache_hits: u64,
+}
+
+impl Stats {
+    fn record(&mut self, inst: &Inst, slot: &Slot, effects: &StepEffects, live_registers: usize) {
+        let ram = |places: &[Place]| places.iter().filter(|p| matches!(p, Place::Ram(_))).count();
+        self.steps += 1;
+        self.cycles += u64::from(slot.added);
+        self.stalls += u64::from(slot.stall);
+        self.paired += u64::from(slot.lane == 1);
+        *self.opcodes.entry(inst.name()).or_default() += 1;
+        self.ram_reads += ram(&effects.reads) as u64;
+        self.ram_writes += ram(&effects.writes) as u64;
+        self.max_live_registers = self.max_live_registers.max(live_registers);
+    }
+}
+
+/// When an instruction runs, see [`Schedule`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slot {
+    /// The cycles the instruction runs, after waiting for a possible stall.
+    pub cycles: Range<u32>,
+    /// The ALU it runs on, the second one (1) only runs arithmetic next to the first one.
+    pub lane: u8,
+    /// Cycles it waited for the RAM port, see [`Inst::stall`].
+    pub stall: u32,
+    /// How much longer the whole program takes because of it.
+    pub added: u32,
+}
+
+impl Slot {
+    /// Cycles from when the previous instruction finished until this one did.
+    pub fn duration(&self) -> u32 {
+        self.stall + self.cycles.len() as u32
+    }
+}
+
+/// Places each instruction of a program in time on the simulated hardware, in order.
+///
+/// With two ALUs, an arithmetic instruction runs next to the one right before it if that's
+/// arithmetic too and they don't use each other's registers.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    ram_ports: u8,
+    alus: u8,
+    /// The instruction placed last and its slot.
+    previous: Option<(Inst, Slot)>,
+    /// Whether that one used the RAM port.
+    previous_ram: bool,
+    /// When everything placed so far is done.
+    end: u32,
+}
+
+impl Schedule {
+    pub fn new(hw: &InterpreterOptions) -> Self {
+        Self {
+            ram_ports: hw.ram_ports,
+            alus: hw.alus,
+            previous: None,
+            previous_ram: false,
+            end: 0,
+        }
+    }
+
+    /// The next instruction of the program runs in this slot.
+    pub fn place(&mut self, inst: &Inst) -> Slot {
+        self.place_with(inst, inst.cycles(), inst.uses_ram())
+    }
+
+    /// Like [`Schedule::place`], for a RAM access served from a cache instead: it takes `cycles`
+    /// and leaves the RAM port free.
+    pub fn place_cached(&mut self, inst: &Inst, cycles: u32) -> Slot {
+        self.place_with(inst, cycles, false)
+    }
+
+    fn place_with(&mut self, inst: &Inst, cycles: u32, uses_ram: bool) -> Slot {
+        let partner = self.previous.as_ref().filter(|(previous, slot)| {
+            self.alus >= 2 && slot.lane == 0 && independent(previous, inst)
+        });
+        let slot = if let Some((_, partner)) = partner {
+            let start = partner.cycles.start;
+            let end = start + cycles;
+            Slot {
+                cycles: start..end,
+                lane: 1,
+                stall: 0,
+                added: end.saturating_sub(self.end),
+            }
+        } else {
+            let previous = self.previous.as_ref().filter(|_| self.previous_ram);
+            let stall = match uses_ram {
+                true => inst.stall(previous.map(|(i, _)| i), self.ram_ports),
+                false => 0,
+            };
+            let start = self.end + stall;
+            Slot {
+                cycles: start..start + cycles,
+                lane: 0,
+                stall,
+                added: stall + cycles,
+            }
+        };
+        self.end += slot.added;
+        self.previous = Some((inst.clone(), slot.clone()));
+        self.previous_ram = uses_ram;
+        slot
+    }
+
+    /// When everything placed so far is done.
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// Starts placing a program from the beginning again.
+    pub fn restart(&mut self) {
+        self.previous = None;
+        self.previous_ram = false;
+        self.end = 0;
+    }
+}
+
+/// Whether two arithmetic instructions can run at the same time, without one reading or
+/// writing a register the other one writes.
+fn independent(first: &Inst, second: &Inst) -> bool {
+    let (a, b) = (StepEffects::of(first), StepEffects::of(second));
+    let touches = |effects: &StepEffects, place: &Place| {
+        effects.reads.contains(place) || effects.writes.contains(place)
+    };
+    first.is_arithmetic()
+        && second.is_arithmetic()
+        && !a.writes.iter().any(|w| touches(&b, w))
+        && !b.writes.iter().any(|w| a.reads.contains(w))
+}
+
+/// For each instruction, how many registers hold a value that it or a later instruction reads.
+fn live_registers(instructions: &[Inst]) -> Vec<usize> {
+    let mut live: HashSet<Place> = HashSet::new();
+    let mut counts = vec![0; instructions.len()];
+    for (i, inst) in instructions.iter().enumerate().rev() {
+        let effects = StepEffects::of(inst);
+        for place in &effects.writes {
+            live.remove(place);
+        }
+        live.extend(effects.reads.iter().filter(|p| matches!(p, Place::Reg(_))));
+        counts[i] = live.len();
+    }
+    counts
+}
+
+/// Something suspicious the program did while running, it continues anyway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeWarning {
+    /// Index of the instruction that caused it.
+    pub instruction: usize,
+    pub message: String,
+}
+
 /// State of the interpreter after executing a single execution step.
 pub enum InterpreterState {
     /// Continue execution with the next instruction.
@@ -21,6 +279,36 @@ impl From<i32> for InterpreterState {
     }
 }
 
+/// Everything that changes while a program runs, to come back to it later, see
+/// [`Interpreter::snapshot`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    reg_store: HashMap<Reg, i32>,
+    ram: Vec<i32>,
+    schedule: Schedule,
+    stack_pointer: MemAddr,
+    overflow: bool,
+    program_counter: usize,
+    running: bool,
+    str_repr: String,
+    last_effects: StepEffects,
+    stats: Stats,
+    warnings: Vec<RuntimeWarning>,
+    write_log: Vec<LoggedWrite>,
+}
+
+impl Snapshot {
+    /// Number of instructions executed when it was taken.
+    pub fn steps(&self) -> u64 {
+        self.stats.steps
+    }
+
+    /// Simulated cycles spent when it was taken.
+    pub fn cycles(&self) -> u64 {
+        self.stats.cycles
+    }
+}
+
 /// Interpreter for our custom ISA.
 ///
 /// The interpreters stores the memory layout at each step and thus enables introspection.
@@ -29,9 +317,22 @@ pub struct Interpreter {
     pub reg_store: HashMap<Reg, i32>,
     /// Slow cache used for out-of-register storage.
     pub ram: Vec<i32>,
+    /// Width of the machine words, all values wrap around at this size.
+    word_size: WordSize,
+    /// What reading an empty register gives.
+    uninit: UninitPolicy,
+    /// When each executed instruction ran on the simulated hardware.
+    schedule: Schedule,
+    /// Stack pointer, the RAM address of the top of the stack. The stack grows downwards from
+    /// the end of RAM, so it's `ram.len()` when the stack is empty.
+    stack_pointer: MemAddr,
+    /// Whether the result of the last arithmetic didn't fit into a word, see [`Inst::Sat`].
+    overflow: bool,
 
     /// Instruction list to be executed.
     instructions: Vec<Inst>,
+    /// Registers still needed at each instruction, see [`live_registers`].
+    live_registers: Vec<usize>,
     /// Program counter pointing to the next instruction to be executed.
     program_counter: usize,
 
@@ -48,6 +349,18 @@ pub struct Interpreter {
 
     /// Whether string representations should be stored during computation
     repr_enabled: bool,
+
+    /// Effects of the most recently executed instruction.
+    last_effects: StepEffects,
+
+    /// What the program did so far.
+    stats: Stats,
+
+    /// Warnings from the instructions executed so far.
+    warnings: Vec<RuntimeWarning>,
+
+    /// Every value moved so far, only kept with tracing.
+    write_log: Vec<LoggedWrite>,
 }
 
 impl Interpreter {
@@ -56,17 +369,28 @@ impl Interpreter {
         Self {
             reg_store: Default::default(),
             ram: vec![0; hw.num_cachelines],
+            word_size: hw.word_size,
+            uninit: hw.uninit,
+            schedule: Schedule::new(hw),
+            stack_pointer: hw.num_cachelines,
+            overflow: false,
             instructions: Vec::with_capacity(0),
+            live_registers: Vec::new(),
             str_repr: String::with_capacity(0),
             program_counter: 0,
             input_variables: None,
             running: false,
             repr_enabled: false,
+            last_effects: StepEffects::default(),
+            stats: Stats::default(),
+            warnings: Vec::new(),
+            write_log: Vec::new(),
         }
     }
 
     /// Loads a list of instructions into the interpreter.
     pub fn load_instructions(mut self, instructions: Vec<Inst>) -> Self {
+        self.live_registers = live_registers(&instructions);
         self.instructions = instructions;
         if self.repr_enabled {
             self.str_repr = self.cur_as_string();
@@ -95,11 +419,38 @@ impl Interpreter {
     }
 
     /// Executes the instruction list until the interpreter either terminates or encounters a critical error.
-    pub fn run_to_end(mut self) -> Result<i32, LpErr> {
+    pub fn run_to_end(&mut self) -> Result<i32, LpErr> {
+        let _span = info_span!("run").entered();
+        match self.run_until(|_| false)? {
+            InterpreterState::Finished(res) => Ok(res),
+            InterpreterState::Continue => unreachable!("only stops when finished"),
+        }
+    }
+
+    /// Executes at most `steps` steps, stopping early if the program finishes.
+    pub fn run_for(&mut self, steps: usize) -> Result<InterpreterState, LpErr> {
+        let mut state = InterpreterState::Continue;
+        for _ in 0..steps {
+            state = self.step()?;
+            if let InterpreterState::Finished(_) = state {
+                break;
+            }
+        }
+        Ok(state)
+    }
+
+    /// Executes steps until `stop` holds afterwards, or the program finishes.
+    ///
+    /// At least one step is executed, so this can be called repeatedly with the same condition.
+    pub fn run_until(
+        &mut self,
+        stop: impl Fn(&Interpreter) -> bool,
+    ) -> Result<InterpreterState, LpErr> {
         loop {
             match self.step()? {
+                InterpreterState::Continue if stop(self) => return Ok(InterpreterState::Continue),
                 InterpreterState::Continue => (),
-                InterpreterState::Finished(res) => return Ok(res),
+                finished => return Ok(finished),
             }
         }
     }
@@ -110,36 +461,54 @@ impl Interpreter {
 
     /// Executes a single step of the program.
     pub fn step(&mut self) -> Result<InterpreterState, LpErr> {
+        let pc = self.program_counter;
+        let state = info_span!("execute").in_scope(|| self.execute())?;
+        let _span = info_span!("timing").entered();
+        let inst = &self.instructions[pc];
+        let slot = self.schedule.place(inst);
+        self.stats
+            .record(inst, &slot, &self.last_effects, self.live_registers[pc]);
+        if self.repr_enabled && !inst.is_arithmetic() {
+            for place in &self.last_effects.writes {
+                // `sat` leaves an empty register empty unless it overflowed
+                let Some(value) = (match place {
+                    Place::Reg(r) => self.register(*r),
+                    Place::Ram(addr) => Some(self.ram[*addr]),
+                }) else {
+                    continue;
+                };
+                self.write_log.push(LoggedWrite {
+                    step: self.stats.steps,
+                    instruction: pc,
+                    place: *place,
+                    value,
+                });
+            }
+        }
+        Ok(state)
+    }
+
+    fn execute(&mut self) -> Result<InterpreterState, LpErr> {
         if !self.running {
-            return Err(LpErr::Interpret(
-                "The interpreter was either not ready to run or finished execution".into(),
-            ));
+            return Err(LpErr::Interpret(t!("compiler.error.not_running").into()));
         }
 
         if self.program_counter >= self.instructions.len() {
-            return Err(LpErr::Interpret("no result found".to_string()));
+            // nothing can run after the end either
+            self.running = false;
+            return Err(LpErr::Interpret(t!("compiler.error.fell_off").into()));
         }
 
         if self.repr_enabled {
             self.str_repr = self.cur_as_string();
         }
 
+        self.last_effects = StepEffects::of(&self.instructions[self.program_counter]);
+
         match &self.instructions[self.program_counter] {
-            Inst::Add(a, b) => run_binop(*a, *b, i32::add, &mut self.reg_store)?,
-            Inst::Sub(a, b) => run_binop(*a, *b, i32::sub, &mut self.reg_store)?,
-            Inst::Mul(a, b) => run_binop(*a, *b, i32::mul, &mut self.reg_store)?,
-            Inst::Div(a, b) => {
-                if let Some(0) = self.reg_store.get(b) {
-                    return Err(LpErr::Interpret(t!("compiler.error.divzero").to_string()));
-                }
-                run_binop(*a, *b, i32::div, &mut self.reg_store)?
-            }
-            Inst::Shl(a, b) => run_binop(*a, *b, i32::shl, &mut self.reg_store)?,
-            Inst::Shr(a, b) => run_binop(*a, *b, i32::shr, &mut self.reg_store)?,
             Inst::Store(n, reg) => {
-                if self.reg_store.insert(*reg, *n).is_some() {
-                    eprintln!("Warning: overwriting register `{reg}`.");
-                }
+                let n = self.word_size.wrap(*n);
+                self.store(*reg, n);
             }
             Inst::Transfer(var, reg) => {
                 let vars = self
@@ -147,29 +516,33 @@ impl Interpreter {
                     .as_ref()
                     .ok_or(LpErr::Interpret("No variables loaded".into()))?;
                 if !vars.contains_key(var) {
-                    return Err(LpErr::Interpret(t!("compiler.error.unknown_var", v = var).into()));
+                    return Err(LpErr::Interpret(
+                        t!("compiler.error.unknown_var", v = var).into(),
+                    ));
                 }
 
                 let val_str = vars[var].clone();
                 if val_str.is_empty() {
-                    return Err(LpErr::Interpret(t!("compiler.error.empty_var", v = var).into()));
+                    return Err(LpErr::Interpret(
+                        t!("compiler.error.empty_var", v = var).into(),
+                    ));
                 }
 
                 let val = val_str.parse::<i32>().map_err(|_| {
                     LpErr::Interpret(t!("compiler.error.nan_var", var = var, val = val_str).into())
                 })?;
-                if self.reg_store.insert(*reg, val).is_some() {
-                    eprintln!("Warning: overwriting register `{reg}`.");
-                }
+                let val = self.word_size.wrap(val);
+                self.store(*reg, val);
             }
             Inst::Result(r) => {
+                let result = self.read(*r)?;
                 self.program_counter += 1;
                 self.running = false;
-                return Ok((*self
-                    .reg_store
-                    .get(r)
-                    .ok_or(LpErr::Interpret(format!("register `{r}` is empty")))?)
-                .into());
+                return Ok(result.into());
+            }
+            Inst::Halt => {
+                self.running = false;
+                return Err(LpErr::Interpret(t!("compiler.error.no_result").into()));
             }
             Inst::Write(_, addr) | Inst::Load(addr, _) if addr >= &self.ram.len() => {
                 return Err(LpErr::Interpret(format!(
@@ -177,67 +550,590 @@ impl Interpreter {
                 )));
             }
             Inst::Write(r, addr) => {
-                if let Some(val) = self.reg_store.get(r) {
-                    self.ram[*addr] = *val;
-                } else {
-                    return Err(LpErr::Interpret(format!("register `{r}` is empty")));
-                }
+                self.ram[*addr] = self.read(*r)?;
             }
             Inst::Load(addr, r) => {
                 self.reg_store.insert(*r, self.ram[*addr]);
             }
+            Inst::Mov(a, b) => {
+                let val = self.read(*a)?;
+                self.reg_store.insert(*b, val);
+            }
+            Inst::Sat(r) => {
+                let val = self.read(*r)?;
+                if self.overflow {
+                    // the wrapped result has the wrong sign
+                    let limit = if val < 0 {
+                        self.word_size.max()
+                    } else {
+                        self.word_size.min()
+                    };
+                    self.reg_store.insert(*r, limit);
+                }
+            }
+            Inst::Chk(_) if self.overflow => {
+                return Err(LpErr::Interpret(t!("compiler.error.overflow").into()));
+            }
+            Inst::Chk(_) => {}
+            Inst::Push(r) => {
+                if self.stack_pointer == 0 {
+                    let msg = t!("compiler.error.stack_overflow");
+                    return Err(LpErr::Interpret(msg.into()));
+                }
+                let val = self.read(*r)?;
+                self.stack_pointer -= 1;
+                self.ram[self.stack_pointer] = val;
+                let slot = Place::Ram(self.stack_pointer);
+                self.last_effects.writes.push(slot);
+            }
+            Inst::Pop(r) => {
+                if self.stack_pointer >= self.ram.len() {
+                    let msg = t!("compiler.error.stack_underflow");
+                    return Err(LpErr::Interpret(msg.into()));
+                }
+                self.last_effects.reads.push(Place::Ram(self.stack_pointer));
+                self.reg_store.insert(*r, self.ram[self.stack_pointer]);
+                self.stack_pointer += 1;
+            }
+            inst => {
+                let (op, operands, dest) = alu(inst).expect("everything else is handled above");
+                self.run_alu(op, operands, dest)?
+            }
         }
 
         self.program_counter += 1;
         Ok(InterpreterState::Continue)
     }
 
+    /// Computes `dest = op(a, b)`, wrapping the result to the word size and setting the
+    /// overflow flag if that changed it.
+    fn run_alu(&mut self, op: AluOp, [a, b]: [OperandValue; 2], dest: Reg) -> Result<(), LpErr> {
+        let (res, overflow) = op(self.value(&a)?, self.value(&b)?)
+            .ok_or_else(|| LpErr::Interpret(t!("compiler.error.divzero").to_string()))?;
+        let wrapped = self.word_size.wrap(res);
+        self.overflow = overflow || wrapped != res;
+        self.reg_store.insert(dest, wrapped);
+        Ok(())
+    }
+
+    /// Whether the last arithmetic overflowed, see [`Inst::Sat`] and [`Inst::Chk`].
+    pub fn overflow(&self) -> bool {
+        self.overflow
+    }
+
+    /// The value of a register or an immediate number, as an operand of arithmetic.
+    fn value(&self, operand: &OperandValue) -> Result<i32, LpErr> {
+        match operand {
+            OperandValue::Reg(r) => self.read(*r),
+            OperandValue::Num(n) => Ok(self.word_size.wrap(*n)),
+            OperandValue::Var(_) | OperandValue::Addr(_) => {
+                unreachable!("arithmetic only has registers and numbers")
+            }
+        }
+    }
+
+    /// Puts a new value into the register, warning if it still held one.
+    fn store(&mut self, reg: Reg, val: i32) {
+        if self.reg_store.insert(reg, val).is_some() {
+            self.warnings.push(RuntimeWarning {
+                instruction: self.program_counter,
+                message: t!("interpreter.overwrite", reg = reg).into(),
+            });
+        }
+    }
+
+    /// Value of a register as the program sees it, empty ones depend on the [`UninitPolicy`].
+    ///
+    /// `None` if reading the register would stop the program.
+    pub fn register(&self, r: Reg) -> Option<i32> {
+        match (self.reg_store.get(&r), self.uninit) {
+            (Some(v), _) => Some(*v),
+            (None, UninitPolicy::Error) => None,
+            (None, UninitPolicy::Zero) => Some(0),
+            (None, UninitPolicy::Garbage) => {
+                let garbage = Rng::with_seed(0x6a7b_a6e0 + u64::from(r.0)).next_u64();
+                Some(self.word_size.wrap(garbage as i32))
+            }
+        }
+    }
+
+    fn read(&self, r: Reg) -> Result<i32, LpErr> {
+        self.register(r)
+            .ok_or_else(|| LpErr::Interpret(format!("register `{r}` is empty")))
+    }
+
     fn cur_as_string(&self) -> String {
         match &self.instructions[self.program_counter] {
-            Inst::Add(a, b) => self.display_binop(a, b, "+"),
-            Inst::Sub(a, b) => self.display_binop(a, b, "-"),
-            Inst::Mul(a, b) => self.display_binop(a, b, "*"),
-            Inst::Div(a, b) => self.display_binop(a, b, "/"),
-            Inst::Shl(a, b) => self.display_binop(a, b, "<<"),
-            Inst::Shr(a, b) => self.display_binop(a, b, ">>"),
             Inst::Store(num, a) => format!("{num} ➡ [{a}]"),
             Inst::Transfer(var, a) => format!("{var} ➡ [{a}]"),
-            Inst::Result(a) => format!("= {}", self.reg_store.get(a).unwrap()),
+            Inst::Result(a) => format!("= {}", self.display_reg(a)),
+            Inst::Mov(a, b) => format!("{} ➡ [{b}]", self.display_reg(a)),
             Inst::Write(reg, addr) => format!("⎘ [{reg}] ➡ [{addr}]"),
             Inst::Load(addr, reg) => format!("⎗ [{reg}] ⬅ [{addr}]"),
+            Inst::Sat(r) | Inst::Chk(r) => format!("V = {} ? [{r}]", u8::from(self.overflow)),
+            Inst::Push(reg) => format!("[{reg}] ➡ SP"),
+            Inst::Pop(reg) => format!("[{reg}] ⬅ SP"),
+            Inst::Halt => "■".into(),
+            inst => {
+                let Unit::Alu { symbol, .. } = inst.def().unit else {
+                    unreachable!("everything else is handled above")
+                };
+                let (_, [a, b], dest) = alu(inst).expect("arithmetic has two operands");
+                if inst.operands().len() == 1 {
+                    return format!("{symbol}{}", self.display_reg(&dest));
+                }
+                let binop = self.display_binop(&a, &b, symbol);
+                // instructions with an explicit destination, see `IsaFlavor`
+                if [&a, &b].contains(&&OperandValue::Reg(dest)) {
+                    binop
+                } else {
+                    format!("{binop} ➡ [{dest}]")
+                }
+            }
         }
     }
 
-    fn display_binop(&self, a: &Reg, b: &Reg, op: &str) -> String {
-        format!(
-            "{} {op} {}",
-            self.reg_store.get(a).unwrap(),
-            self.reg_store.get(b).unwrap()
-        )
+    fn display_binop(&self, a: &OperandValue, b: &OperandValue, op: &str) -> String {
+        let display = |operand: &OperandValue| match operand {
+            OperandValue::Reg(r) => self.display_reg(r),
+            other => other.to_string(),
+        };
+        format!("{} {op} {}", display(a), display(b))
+    }
+
+    /// The register's value, or `?` if it's empty and can't be read.
+    fn display_reg(&self, r: &Reg) -> String {
+        self.register(*r).map_or("?".to_string(), |v| v.to_string())
+    }
+
+    /// Puts a value into a register without running an instruction, for engines that load it
+    /// from somewhere else than the RAM.
+    pub fn set_register(&mut self, r: Reg, value: i32) {
+        self.reg_store.insert(r, value);
+    }
+
+    /// The instruction executed next, if there is one.
+    pub fn current_instruction(&self) -> Option<&Inst> {
+        self.instructions.get(self.program_counter)
     }
 
     pub fn display_current(&self) -> &str {
         &self.str_repr
     }
 
-    #[allow(dead_code)]
+    /// Value of an input variable, if it's given and a valid number.
+    pub fn input(&self, var: &str) -> Option<i32> {
+        let val = self.input_variables.as_ref()?.get(var)?.parse().ok()?;
+        Some(self.word_size.wrap(val))
+    }
+
+    pub fn word_size(&self) -> WordSize {
+        self.word_size
+    }
+
+    /// Index of the instruction that is executed next.
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    /// RAM address of the top of the stack, equal to the RAM size if the stack is empty.
+    pub fn stack_pointer(&self) -> MemAddr {
+        self.stack_pointer
+    }
+
+    /// The places read and written by the most recently executed instruction.
+    pub fn last_effects(&self) -> &StepEffects {
+        &self.last_effects
+    }
+
+    /// Counters of what the program did so far, e.g. the simulated cycles.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn warnings(&self) -> &[RuntimeWarning] {
+        &self.warnings
+    }
+
+    /// Every RAM write and register transfer so far in order, with tracing enabled. Arithmetic
+    /// results aren't in it.
+    pub fn write_log(&self) -> &[LoggedWrite] {
+        &self.write_log
+    }
+
+    /// Replaces the input variables, e.g. before running the program again.
+    pub fn set_variables(&mut self, input_variables: HashMap<String, String>) {
+        self.input_variables = Some(input_variables);
+    }
+
+    /// The whole state of the machine, see [`Interpreter::restore`].
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            reg_store: self.reg_store.clone(),
+            ram: self.ram.clone(),
+            schedule: self.schedule.clone(),
+            stack_pointer: self.stack_pointer,
+            overflow: self.overflow,
+            program_counter: self.program_counter,
+            running: self.running,
+            str_repr: self.str_repr.clone(),
+            last_effects: self.last_effects.clone(),
+            stats: self.stats.clone(),
+            warnings: self.warnings.clone(),
+            write_log: self.write_log.clone(),
+        }
+    }
+
+    /// Goes back to the state of the snapshot, which has to be taken while running the same
+    /// program. The input variables stay as they are.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        let Snapshot {
+            reg_store,
+            ram,
+            schedule,
+            stack_pointer,
+            overflow,
+            program_counter,
+            running,
+            str_repr,
+            last_effects,
+            stats,
+            warnings,
+            write_log,
+        } = snapshot;
+        self.reg_store = reg_store;
+        self.ram = ram;
+        self.schedule = schedule;
+        self.stack_pointer = stack_pointer;
+        self.overflow = overflow;
+        self.program_counter = program_counter;
+        self.running = running;
+        self.str_repr = str_repr;
+        self.last_effects = last_effects;
+        self.stats = stats;
+        self.warnings = warnings;
+        self.write_log = write_log;
+    }
+
+    /// Goes back to the start of the program with empty memory, ready to run it again.
     pub fn reset(&mut self) {
         self.program_counter = 0;
-        self.ram = self.ram.iter().map(|_| 0).collect();
+        self.ram.fill(0);
         self.reg_store.clear();
+        self.stack_pointer = self.ram.len();
+        self.overflow = false;
+        self.last_effects = StepEffects::default();
+        self.stats = Stats::default();
+        self.schedule.restart();
+        self.warnings.clear();
+        self.write_log.clear();
+        self.running = true;
+        if self.repr_enabled && !self.instructions.is_empty() {
+            self.str_repr = self.cur_as_string();
+        }
     }
 }
 
-fn run_binop(
-    a: Reg,
-    b: Reg,
-    op: impl FnOnce(i32, i32) -> i32,
-    reg_store: &mut HashMap<Reg, i32>,
-) -> Result<(), LpErr> {
-    match (reg_store.get(&a).cloned(), reg_store.get_mut(&b)) {
-        (Some(a), Some(b)) => *b = op(a, *b),
-        (None, _) => return Err(LpErr::Interpret(format!("no such reg `{a}`"))),
-        (_, None) => return Err(LpErr::Interpret(format!("no such reg `{b}`"))),
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn push_pop_roundtrip() -> Result<(), LpErr> {
+        let hw = InterpreterOptions::default();
+        let res = Interpreter::with_config(&hw)
+            .load_instructions(vec![
+                Inst::Store(7, Reg(0)),
+                Inst::Push(Reg(0)),
+                Inst::Store(1, Reg(0)),
+                Inst::Pop(Reg(1)),
+                Inst::Result(Reg(1)),
+            ])
+            .ready()
+            .run_to_end()?;
+
+        assert_eq!(res, 7);
+        Ok(())
+    }
+
+    #[test]
+    fn writes_are_logged_in_order() -> Result<(), LpErr> {
+        let hw = InterpreterOptions::default();
+        let mut interpreter = Interpreter::with_config(&hw)
+            .load_instructions(vec![
+                Inst::Store(7, Reg(0)),
+                Inst::Write(Reg(0), 2),
+                Inst::Store(1, Reg(1)),
+                Inst::Add(Reg(1), Reg(0)),
+                Inst::Push(Reg(0)),
+                Inst::Load(2, Reg(1)),
+                Inst::Result(Reg(1)),
+            ])
+            .with_tracing()
+            .ready();
+        interpreter.run_to_end()?;
+
+        let log: Vec<_> = interpreter
+            .write_log()
+            .iter()
+            .map(|w| (w.step, w.place.to_string(), w.value))
+            .collect();
+        let sp = hw.num_cachelines - 1;
+        assert_eq!(
+            log,
+            [
+                (1, "a".to_string(), 7),
+                (2, "[2]".into(), 7),
+                (3, "b".into(), 1),
+                (5, format!("[{sp}]"), 8),
+                (6, "b".into(), 7),
+            ]
+        );
+        interpreter.reset();
+        assert!(interpreter.write_log().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn saturating_an_empty_register_is_logged() -> Result<(), LpErr> {
+        let hw = InterpreterOptions {
+            uninit: UninitPolicy::Zero,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::with_config(&hw)
+            .load_instructions(vec![Inst::Sat(Reg(3)), Inst::Result(Reg(3))])
+            .with_tracing()
+            .ready();
+        assert_eq!(interpreter.run_to_end()?, 0);
+
+        let log: Vec<_> = interpreter
+            .write_log()
+            .iter()
+            .map(|w| (w.step, w.place, w.value))
+            .collect();
+        assert_eq!(log, [(1, Place::Reg(Reg(3)), 0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn halting_is_traced() {
+        let mut interpreter = Interpreter::with_config(&InterpreterOptions::default())
+            .load_instructions(vec![Inst::Store(1, Reg(0)), Inst::Halt])
+            .with_tracing()
+            .ready();
+        assert!(interpreter.step().is_ok());
+        assert!(interpreter.step().is_err());
+        assert_eq!(interpreter.display_current(), "■");
+    }
+
+    #[test]
+    fn restored_snapshots_run_the_same() -> Result<(), LpErr> {
+        let hw = InterpreterOptions::default();
+        let mut interpreter = Interpreter::with_config(&hw)
+            .load_instructions(vec![
+                Inst::Transfer("x".into(), Reg(0)),
+                Inst::Push(Reg(0)),
+                Inst::Store(3, Reg(1)),
+                Inst::Pop(Reg(0)),
+                Inst::Mul(Reg(1), Reg(0)),
+                Inst::Result(Reg(0)),
+            ])
+            .with_variables(HashMap::from([("x".to_string(), "5".to_string())]))
+            .ready();
+        interpreter.run_for(2)?;
+        let middle = interpreter.snapshot();
+        assert_eq!(middle.steps(), 2);
+
+        let first = interpreter.run_to_end()?;
+        let stats = interpreter.stats().clone();
+        assert!(!interpreter.is_running());
+        interpreter.restore(middle.clone());
+        assert_eq!(interpreter.stack_pointer(), hw.num_cachelines - 1);
+        assert_eq!(interpreter.run_to_end()?, first);
+        assert_eq!(interpreter.stats(), &stats);
+
+        // values that were read before the snapshot stay, even with other inputs
+        interpreter.restore(middle);
+        interpreter.set_variables(HashMap::from([("x".to_string(), "1".to_string())]));
+        assert_eq!(interpreter.run_to_end()?, 15);
+        Ok(())
+    }
+
+    #[test]
+    fn uninitialized_registers_follow_policy() -> Result<(), LpErr> {
+        let run = |uninit| {
+            let hw = InterpreterOptions {
+                uninit,
+                ..Default::default()
+            };
+            Interpreter::with_config(&hw)
+                .load_instructions(vec![
+                    Inst::Store(1, Reg(0)),
+                    Inst::Add(Reg(0), Reg(1)),
+                    Inst::Result(Reg(1)),
+                ])
+                .ready()
+                .run_to_end()
+        };
+
+        assert!(run(UninitPolicy::Error).is_err());
+        assert_eq!(run(UninitPolicy::Zero)?, 1);
+        // garbage is the same every time, so the animation matches the result
+        let garbage = run(UninitPolicy::Garbage)?;
+        assert_eq!(run(UninitPolicy::Garbage)?, garbage);
+        assert_ne!(garbage, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn overwriting_a_register_warns() -> Result<(), LpErr> {
+        let hw = InterpreterOptions::default();
+        let mut interpreter = Interpreter::with_config(&hw)
+            .load_instructions(vec![
+                Inst::Store(1, Reg(0)),
+                Inst::Store(2, Reg(1)),
+                Inst::Store(3, Reg(0)),
+                Inst::Result(Reg(0)),
+            ])
+            .ready();
+        assert_eq!(interpreter.run_to_end()?, 3);
+        let warnings: Vec<_> = interpreter
+            .warnings()
+            .iter()
+            .map(|w| w.instruction)
+            .collect();
+        assert_eq!(warnings, [2]);
+
+        interpreter.reset();
+        assert!(interpreter.warnings().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn run_for_and_until() -> Result<(), LpErr> {
+        let hw = InterpreterOptions::default();
+        let mut interpreter = Interpreter::with_config(&hw)
+            .load_instructions(vec![
+                Inst::Store(2, Reg(0)),
+                Inst::Store(3, Reg(1)),
+                Inst::Mul(Reg(0), Reg(1)),
+                Inst::Result(Reg(1)),
+            ])
+            .ready();
+
+        let state = interpreter.run_for(0)?;
+        assert!(matches!(state, InterpreterState::Continue));
+        assert_eq!(interpreter.program_counter(), 0);
+        interpreter.run_for(2)?;
+        assert_eq!(interpreter.program_counter(), 2);
+
+        let state = interpreter.run_until(|i| i.reg_store.get(&Reg(1)) == Some(&6))?;
+        assert!(matches!(state, InterpreterState::Continue));
+        assert_eq!(interpreter.program_counter(), 3);
+
+        let state = interpreter.run_for(10)?;
+        assert!(matches!(state, InterpreterState::Finished(6)));
+        Ok(())
+    }
+
+    #[test]
+    fn live_registers_count_values_still_needed() {
+        let code = [
+            Inst::Store(1, Reg(0)),
+            Inst::Store(2, Reg(1)),
+            Inst::Store(3, Reg(2)),
+            Inst::Add(Reg(0), Reg(1)),
+            Inst::Mul(Reg(1), Reg(2)),
+            Inst::Result(Reg(2)),
+        ];
+        assert_eq!(live_registers(&code), [0, 1, 2, 3, 2, 1]);
+
+        let mut interpreter = Interpreter::with_config(&InterpreterOptions::default())
+            .load_instructions(code.to_vec())
+            .ready();
+        assert_eq!(interpreter.run_to_end().ok(), Some(9));
+        assert_eq!(interpreter.stats().max_live_registers, 3);
+    }
+
+    #[test]
+    fn two_alus_run_independent_arithmetic_together() -> Result<(), LpErr> {
+        let code = vec![
+            Inst::Store(1, Reg(0)),
+            Inst::Store(2, Reg(1)),
+            Inst::Store(3, Reg(2)),
+            Inst::Store(4, Reg(3)),
+            // independent of each other
+            Inst::Add(Reg(0), Reg(1)),
+            Inst::Mul(Reg(2), Reg(3)),
+            // needs both results
+            Inst::Sub(Reg(1), Reg(3)),
+            Inst::Result(Reg(3)),
+        ];
+        let hw = InterpreterOptions {
+            alus: 2,
+            ..Default::default()
+        };
+        let mut schedule = Schedule::new(&hw);
+        let slots: Vec<_> = code.iter().map(|inst| schedule.place(inst)).collect();
+        assert_eq!(slots[4].cycles, 60..90);
+        assert_eq!((slots[5].cycles.clone(), slots[5].lane), (60..120, 1));
+        assert_eq!((slots[6].cycles.clone(), slots[6].lane), (120..150, 0));
+        assert_eq!(schedule.end(), 165);
+
+        let run = |hw: InterpreterOptions| {
+            let mut interpreter = Interpreter::with_config(&hw)
+                .load_instructions(code.clone())
+                .ready();
+            interpreter
+                .run_to_end()
+                .map(|res| (res, interpreter.stats().clone()))
+        };
+        let (res, stats) = run(hw)?;
+        assert_eq!(res, -9);
+        assert_eq!((stats.cycles, stats.paired), (165, 1));
+        let (res, stats) = run(InterpreterOptions::default())?;
+        assert_eq!(res, -9);
+        assert_eq!((stats.cycles, stats.paired), (195, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn reset_runs_again_with_new_variables() -> Result<(), LpErr> {
+        let hw = InterpreterOptions::default();
+        let mut interpreter = Interpreter::with_config(&hw)
+            .load_instructions(vec![
+                Inst::Transfer("x".into(), Reg(0)),
+                Inst::Push(Reg(0)),
+                Inst::Pop(Reg(1)),
+                Inst::Result(Reg(1)),
+            ])
+            .with_variables([("x".to_string(), "3".to_string())].into())
+            .ready();
+        let state = interpreter.run_for(10)?;
+        assert!(matches!(state, InterpreterState::Finished(3)));
+        assert!(!interpreter.is_running());
+        let stats = interpreter.stats();
+        assert_eq!(stats.steps, 4);
+        assert_eq!((stats.ram_reads, stats.ram_writes), (1, 1));
+        assert_eq!(stats.opcodes["push"], 1);
+
+        interpreter.set_variables([("x".to_string(), "5".to_string())].into());
+        interpreter.reset();
+        assert!(interpreter.is_running());
+        assert_eq!(interpreter.stats(), &Stats::default());
+        let state = interpreter.run_for(10)?;
+        assert!(matches!(state, InterpreterState::Finished(5)));
+        Ok(())
+    }
+
+    #[test]
+    fn pop_empty_stack_fails() {
+        let hw = InterpreterOptions::default();
+        let res = Interpreter::with_config(&hw)
+            .load_instructions(vec![Inst::Pop(Reg(0)), Inst::Result(Reg(0))])
+            .ready()
+            .run_to_end();
+
+        assert!(res.is_err());
     }
-    Ok(())
 }
@@ -1,17 +1,48 @@
+mod analysis;
 mod app;
+mod assembler;
+mod backend;
+mod bench;
+mod capture;
+mod challenges;
+mod compile_log;
+mod compile_service;
 mod compiler;
+mod conformance;
+mod dag;
+mod encoding;
+mod engine;
+mod eval;
+mod export;
+mod files;
+mod generator;
 mod gui;
+mod intern;
 mod interpreter;
 mod parser;
 mod passes;
+mod ranges;
+mod semantics;
+mod sound;
+mod source_map;
+mod symbols;
 mod types;
 
 use crate::app::LndwApp;
 use rust_i18n::t;
+use std::process::ExitCode;
 
 rust_i18n::i18n!("locales", fallback = "en");
 
-fn main() {
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = export::cli(&args) {
+        return code;
+    }
+    if let Some(code) = bench::cli(&args) {
+        return code;
+    }
+
     let mut native_options = eframe::NativeOptions::default();
     native_options.viewport.maximized = Some(true);
     let _ = eframe::run_native(
@@ -19,4 +50,5 @@ fn main() {
         native_options,
         Box::new(|cc| Ok(Box::new(LndwApp::new(cc)))),
     );
+    ExitCode::SUCCESS
 }
@@ -1,75 +1,529 @@
+use crate::analysis;
+use crate::source_map::{SourceMap, Span};
 use crate::types::*;
+use chumsky::error::{RichPattern, RichReason};
 use chumsky::prelude::*;
+use rust_i18n::t;
+
+/// A `const NAME = value;` declaration in front of the program.
+#[derive(Debug, Clone)]
+pub struct ConstDecl {
+    pub name: String,
+    pub value: Expr,
+    pub span: Span,
+    /// Where the parts of the value are in the source.
+    pub map: SourceMap,
+}
+
+/// The parsed source code: constant declarations, then the expression to compute.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub constants: Vec<ConstDecl>,
+    pub body: Expr,
+}
+
+/// A statement of the source: a constant declaration or the expression to compute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    /// The source of the statement, on one line.
+    pub text: String,
+    /// Where the statement is, for a declaration also where the constant is used.
+    pub spans: Vec<Span>,
+}
+
+impl Program {
+    /// The declarations, then the expression, as they appear in `input`.
+    pub fn statements(&self, input: &str) -> Vec<Statement> {
+        let text = |span: &Span| {
+            let text = input.get(span.clone()).unwrap_or_default();
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        };
+        let body = trimmed(
+            input,
+            self.constants.last().map_or(0, |c| c.span.end)..input.len(),
+        );
+        let mut statements: Vec<_> = (self.constants.iter())
+            .map(|decl| {
+                let uses = tokens(input)
+                    .filter(|t| t.start >= body.start && input[t.clone()] == decl.name);
+                Statement {
+                    text: text(&decl.span),
+                    spans: std::iter::once(decl.span.clone()).chain(uses).collect(),
+                }
+            })
+            .collect();
+        statements.push(Statement {
+            text: text(&body),
+            spans: vec![body],
+        });
+        statements
+    }
+}
+
+/// How deeply expressions may be nested by default, see [`MAX_NESTING`].
+pub const DEFAULT_MAX_NESTING: usize = 500;
+/// The deepest nesting that can be allowed: the parser and a few helpers, like printing programs,
+/// still descend into each level recursively, and with much deeper trees they would run out of
+/// stack.
+pub const MAX_NESTING: usize = 2000;
+/// Stack size of the threads that compile programs, enough for [`MAX_NESTING`] levels even in
+/// debug builds. Only the pages that are used get memory.
+pub const STACK_SIZE: usize = 64 << 20;
+/// How many tokens a program may have by default. Parsing a pasted megabyte would freeze the
+/// app, so longer programs are rejected before parsing.
+pub const DEFAULT_MAX_TOKENS: usize = 20_000;
 
 pub fn run_parser(input: &str) -> Result<Expr, LpErr> {
-    parse_expr()
-        .parse(input)
-        .into_result()
-        .map_err(|parse_errs| {
-            LpErr::Parse(
-                parse_errs
-                    .first()
-                    .map(|e| e.to_string())
-                    .unwrap_or("[unknown error]".into()),
-            )
+    run_parser_with_spans(input).map(|(expr, _)| expr)
+}
+
+/// Like [`run_parser`], but also returns where in the input each sub-expression came from.
+pub fn run_parser_with_spans(input: &str) -> Result<(Expr, SourceMap), LpErr> {
+    let (program, mut map) = run_program_parser(input, DEFAULT_MAX_NESTING)?;
+    let (expr, _) = program.resolve(&mut map)?;
+    Ok((expr, map))
+}
+
+/// Parses the program without resolving its constants, the source map only covers the body.
+///
+/// Expressions nested more than `max_nesting` levels deep are rejected.
+pub fn run_program_parser(input: &str, max_nesting: usize) -> Result<(Program, SourceMap), LpErr> {
+    parse(input, false, max_nesting).map_err(parse_error)
+}
+
+/// Parses a pattern for rewrite rules, i.e. an expression that may contain pattern variables
+/// like `?x`. These are returned as [`Expr::Var`]s whose name includes the `?`.
+pub fn run_pattern_parser(input: &str) -> Result<Expr, LpErr> {
+    parse(input, true, DEFAULT_MAX_NESTING)
+        .map(|(program, _)| program.body)
+        .map_err(parse_error)
+}
+
+/// The input with consistent spacing and only the parentheses that are needed, e.g. `2(a)+3`
+/// becomes `2 * a + 3`.
+pub fn format(input: &str) -> Result<String, LpErr> {
+    let (program, _) = run_program_parser(input, DEFAULT_MAX_NESTING)?;
+    let mut code = String::new();
+    for decl in &program.constants {
+        code += &format!("const {} = {};\n", decl.name, decl.value.pretty());
+    }
+    Ok(code + &program.body.pretty())
+}
+
+/// Whether a problem keeps the program from compiling.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum Severity {
+    Error,
+    /// The program compiles, but likely doesn't do what was intended.
+    Warning,
+}
+
+/// A problem in the source code, explained in a way visitors can hopefully fix it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl Diagnostic {
+    /// A problem at `span`, the message also says where it is for reading the list of problems.
+    pub fn new(input: &str, span: Span, message: &str, severity: Severity) -> Self {
+        let position = input[..span.start.min(input.len())].chars().count() + 1;
+        Diagnostic {
+            message: t!("parser.at", msg = message, pos = position).into(),
+            span,
+            severity,
+        }
+    }
+}
+
+/// All problems found in the input: parse errors, or if it parses, what the semantic analysis
+/// finds. The parser continues after errors where it can.
+///
+/// Inputs with more than `max_tokens` tokens aren't parsed at all, see [`too_many_tokens`].
+pub fn diagnose(input: &str, max_nesting: usize, max_tokens: usize) -> Vec<Diagnostic> {
+    if let Some(diagnostic) = too_many_tokens(input, max_tokens) {
+        return vec![diagnostic];
+    }
+    match parse(input, false, max_nesting) {
+        Ok((program, map)) => analysis::analyze(input, &program, &map),
+        Err(diagnostics) => diagnostics,
+    }
+}
+
+/// Where the numbers, names, operators and parentheses of the input are. Anything that isn't
+/// whitespace is a token, so invalid input is counted too.
+pub fn tokens(input: &str) -> impl Iterator<Item = Span> + '_ {
+    let word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut chars = input.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, c) = chars.find(|(_, c)| !c.is_whitespace())?;
+        let mut end = start + c.len_utf8();
+        let rest = |end: usize| input[end..].chars().next();
+        if word(c) {
+            while let Some((i, c)) = chars.next_if(|&(_, c)| word(c)) {
+                end = i + c.len_utf8();
+            }
+        } else if matches!(c, '<' | '>') && rest(end) == Some(c) {
+            // shifts
+            chars.next();
+            end += 1;
+        }
+        Some(start..end)
+    })
+}
+
+/// An error on everything after the first `max_tokens` tokens, if the input is longer.
+pub fn too_many_tokens(input: &str, max_tokens: usize) -> Option<Diagnostic> {
+    let first_extra = tokens(input).nth(max_tokens)?;
+    let message = t!("parser.too_many_tokens", max = max_tokens);
+    let span = trimmed(input, first_extra.start..input.len());
+    Some(Diagnostic::new(input, span, &message, Severity::Error))
+}
+
+fn parse_error(diagnostics: Vec<Diagnostic>) -> LpErr {
+    let messages: Vec<_> = diagnostics.into_iter().map(|d| d.message).collect();
+    LpErr::Parse(messages.join("\n"))
+}
+
+/// The span without the whitespace around it.
+fn trimmed(input: &str, span: Span) -> Span {
+    let text = &input[span.clone()];
+    let start = span.start + text.len() - text.trim_start().len();
+    let end = span.start + text.trim_end().len();
+    start..end.max(start)
+}
+
+fn parse(
+    input: &str,
+    patterns: bool,
+    max_nesting: usize,
+) -> Result<(Program, SourceMap), Vec<Diagnostic>> {
+    let too_deep = |span| {
+        let message = t!("parser.too_deep", max = max_nesting);
+        vec![Diagnostic::new(
+            input,
+            trimmed(input, span),
+            &message,
+            Severity::Error,
+        )]
+    };
+    // the parser descends into each parenthesis recursively, so they are counted first
+    if let Some(span) = too_deep_parentheses(input, max_nesting) {
+        return Err(too_deep(span));
+    }
+    let (output, errors) = parse_program(patterns).parse(input).into_output_errors();
+    let (constants, body) = match output {
+        Some(output) if errors.is_empty() => output,
+        _ => return Err(errors.iter().map(|e| diagnostic(input, e)).collect()),
+    };
+
+    let constants = constants
+        .into_iter()
+        .map(|(name, value, span)| {
+            Ok(ConstDecl {
+                name,
+                value: value.expr,
+                span: trimmed(input, span),
+                map: source_map(input, value, max_nesting)?,
+            })
         })
+        .collect::<Result<_, _>>()
+        .map_err(too_deep)?;
+    let program = Program {
+        constants,
+        body: body.expr,
+    };
+    let map = source_map(input, body, max_nesting).map_err(too_deep)?;
+    Ok((program, map))
 }
 
-fn parse_expr<'a>() -> impl Parser<'a, &'a str, Expr> {
-    recursive(|expr| {
-        let ident = text::ascii::ident().padded();
+/// The first parenthesis opened more than `max` levels deep, if there is one.
+fn too_deep_parentheses(input: &str, max: usize) -> Option<Span> {
+    let mut depth = 0usize;
+    for token in tokens(input) {
+        match &input[token.clone()] {
+            "(" if depth == max => return Some(token),
+            "(" => depth += 1,
+            ")" => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Where each sub-expression came from, or the span of the first one nested more than `max`
+/// levels deep.
+fn source_map(input: &str, spanned: Spanned, max: usize) -> Result<SourceMap, Span> {
+    let mut map = SourceMap::default();
+    // explicit stack, the tree may be too deep to walk recursively
+    let mut todo = vec![(spanned, 1)];
+    while let Some((mut spanned, depth)) = todo.pop() {
+        if depth > max {
+            return Err(spanned.span.clone());
+        }
+        // spans of operations include the padding around their operands
+        map.insert(spanned.expr, trimmed(input, spanned.span.clone()));
+        let operands = std::mem::take(&mut spanned.operands);
+        todo.extend(operands.into_iter().rev().map(|o| (o, depth + 1)));
+    }
+    Ok(map)
+}
+
+/// Turns a parse error into a suggestion, based on what was found where.
+fn diagnostic(input: &str, error: &Rich<char>) -> Diagnostic {
+    let expects = |c: char| {
+        error
+            .expected()
+            .any(|p| matches!(p, RichPattern::Token(t) if **t == c))
+    };
+    let message = match error.found() {
+        // the parser's own messages, e.g. for builtins called with too few values
+        _ if let RichReason::Custom(message) = error.reason()
+            && !message.is_empty() =>
+        {
+            message.as_str().into()
+        }
+        None if input.trim().is_empty() => t!("parser.empty"),
+        None if expects(')') => t!("parser.unclosed"),
+        Some(')') if !expects(')') => t!("parser.unopened"),
+        None => t!("parser.missing_operand"),
+        Some(&c) if "+-*/<>·×÷−".contains(c) => t!("parser.missing_operand"),
+        Some(&c) if c.is_alphanumeric() || c == '(' => t!("parser.missing_operator", c = c),
+        Some(&c) => t!("parser.unexpected", c = c),
+    };
+
+    Diagnostic::new(input, error.span().into_range(), &message, Severity::Error)
+}
+
+/// An expression with its span and those of its operands, collected by [`source_map`] once the
+/// whole program is parsed.
+struct Spanned {
+    expr: Expr,
+    span: Span,
+    operands: Vec<Spanned>,
+}
+
+/// Dropped with an explicit stack, a long chain like `x + x + … + x` is as deep as it is long.
+impl Drop for Spanned {
+    fn drop(&mut self) {
+        let mut todo = std::mem::take(&mut self.operands);
+        while let Some(mut spanned) = todo.pop() {
+            todo.append(&mut spanned.operands);
+        }
+    }
+}
+
+fn node(expr: Expr, span: SimpleSpan, operands: Vec<Spanned>) -> Spanned {
+    Spanned {
+        expr,
+        span: span.into_range(),
+        operands,
+    }
+}
+
+fn binary(lhs: Spanned, op: Operator, rhs: Spanned, span: SimpleSpan) -> Spanned {
+    let expr = Expr::BinaryOp(ExprId::new(lhs.expr), op, ExprId::new(rhs.expr));
+    node(expr, span, vec![lhs, rhs])
+}
+
+/// The expression a builtin stands for, if it's called with the right number of values.
+fn call(
+    builtin: Builtin,
+    args: Vec<Spanned>,
+    span: SimpleSpan,
+) -> Result<Spanned, Rich<'static, char>> {
+    if args.len() != builtin.arity() {
+        let message = t!("parser.arity", name = builtin.name(), n = builtin.arity());
+        return Err(Rich::custom(span, message));
+    }
+    let mut args = args.into_iter();
+    let mut arg = || args.next().expect("the number of values was checked");
+    Ok(match builtin {
+        Builtin::Operator(op) => {
+            let lhs = arg();
+            binary(lhs, op, arg(), span)
+        }
+        // `min(max(x, lo), hi)`
+        Builtin::Clamp => {
+            let x = arg();
+            let low = binary(x, Operator::Max, arg(), span);
+            binary(low, Operator::Min, arg(), span)
+        }
+    })
+}
+
+fn parse_expr<'a>(patterns: bool) -> impl Parser<'a, &'a str, Spanned, extra::Err<Rich<'a, char>>> {
+    recursive(move |expr| {
+        // pattern variables (`?x`) are only allowed in patterns
+        let ident = just('?')
+            .or_not()
+            .then(text::ascii::ident())
+            .filter(move |(q, _)| patterns || q.is_none())
+            // builtin names are reserved, see `call`
+            .filter(|(_, name): &(Option<char>, &str)| Builtin::named(name).is_none())
+            .map(|(q, name): (Option<char>, &str)| match q {
+                Some(_) => format!("?{name}"),
+                None => name.to_string(),
+            })
+            .map_with(|name, e| node(Expr::Var(name.into()), e.span(), vec![]))
+            .padded();
 
         let int = text::int(10)
-            .map(|s: &str| s.parse().unwrap())
-            .map(Expr::Num);
+            // reported without backtracking, otherwise the other atoms' errors hide the message
+            .validate(|s: &str, e, emitter| {
+                s.parse().unwrap_or_else(|_| {
+                    emitter.emit(Rich::custom(
+                        e.span(),
+                        t!("parser.too_large", max = i32::MAX),
+                    ));
+                    0
+                })
+            })
+            .map_with(|n, e| node(Expr::Num(n), e.span(), vec![]));
 
-        // a single atom, either an integer, a parenthesized expression or an identifier
-        let atom = int
-            .or(expr.delimited_by(just('('), just(')')))
-            .or(ident.map(String::from).map(Expr::Var))
+        // errors inside parentheses are skipped, so the parser can report more than one
+        let parenthesized = expr
+            .clone()
+            .delimited_by(just('('), just(')'))
+            .recover_with(via_parser(nested_delimiters('(', ')', [], |span| {
+                node(Expr::Num(0), span, vec![])
+            })))
             .padded();
 
-        // operations, both unary and binary
-        let mul_op = one_of("*/").map(Operator::try_from).map(Result::unwrap);
-        let add_op = one_of("+-").map(Operator::try_from).map(Result::unwrap);
+        // builtins like `sat_add(a, b)`, other names followed by parentheses are multiplied
+        let builtin = text::ascii::ident()
+            // not a builtin, without a message of its own since it's a variable then
+            .try_map(|name: &str, span| Builtin::named(name).ok_or(Rich::custom(span, "")));
+        let call = builtin
+            .padded()
+            .then(
+                expr.clone()
+                    .separated_by(just(','))
+                    .collect::<Vec<_>>()
+                    .delimited_by(just('('), just(')')),
+            )
+            .validate(|(builtin, args), e, emitter| {
+                call(builtin, args, e.span()).unwrap_or_else(|error| {
+                    emitter.emit(error);
+                    node(Expr::Num(0), e.span(), vec![])
+                })
+            })
+            .padded();
+
+        // a single atom, either an integer, a call, a parenthesized expression or an identifier
+        let atom = int.or(call).or(parenthesized.clone()).or(ident).padded();
+
+        // operations, both unary and binary. We also accept the symbols used in school.
+        let mul_op = one_of("*/·×÷").map(Operator::try_from).map(Result::unwrap);
+        let add_op = one_of("+-−").map(Operator::try_from).map(Result::unwrap);
+        let shift_op = just("<<")
+            .to(Operator::Shl)
+            .or(just(">>").to(Operator::Shr))
+            // rules are about the arithmetic
+            .validate(move |op, e, emitter| {
+                if patterns {
+                    emitter.emit(Rich::custom(e.span(), t!("rewrite.error.shift")));
+                }
+                op
+            });
 
         // ====== THE ACTUAL PARSER =====
         // we define parsers for operations based on precedence
         // First, unary expressions, which may occur 0..N times
         // Second, multiplications,
-        // Third, additions.
+        // Third, additions,
+        // Fourth, bit shifts.
         //
         // Each of the three steps repeatedly looks for the pattern and then moves on.
         // For example, in addition we look for the pattern: 1 unary expression,
         // followed by an arbitrary number of tuples of (op, unary_op), like so:
         //
         // expr = unary + (op + unary)*
+        //
+        // Every node also records its span in the input, see `Spanned`.
 
-        let unary = just('-')
+        let unary = one_of("-−")
             .padded()
             .repeated()
-            .foldr(atom, |_op, rhs| Expr::UnaryOp(Operator::Sub, Box::new(rhs)));
-
-        let product = unary
-            .clone()
-            .foldl(mul_op.then(unary).repeated(), |lhs, (op, rhs)| {
-                Expr::BinaryOp(Box::new(lhs), op, Box::new(rhs))
+            .foldr_with(atom, |_op, rhs: Spanned, e| {
+                let expr = Expr::UnaryOp(Operator::Sub, ExprId::new(rhs.expr));
+                node(expr, e.span(), vec![rhs])
             });
 
-        
+        // a parenthesized expression right after another factor is multiplied: `2(a + 3)`
+        let implicit_mul = parenthesized.map(|rhs| (Operator::Mul, rhs));
+        let product = unary.clone().foldl_with(
+            mul_op.then(unary).or(implicit_mul).repeated(),
+            |lhs, (op, rhs), e| binary(lhs, op, rhs, e.span()),
+        );
 
-        product
+        let sum = product
             .clone()
-            .foldl(add_op.then(product).repeated(), |lhs, (op, rhs)| {
-                Expr::BinaryOp(Box::new(lhs), op, Box::new(rhs))
+            .foldl_with(add_op.then(product).repeated(), |lhs, (op, rhs), e| {
+                binary(lhs, op, rhs, e.span())
+            });
+
+        sum.clone()
+            .foldl_with(shift_op.then(sum).repeated(), |lhs, (op, rhs), e| {
+                binary(lhs, op, rhs, e.span())
             })
     })
 }
 
+/// A constant's name, value and the span of the whole declaration.
+type Decl = (String, Spanned, Span);
+
+/// Constant declarations followed by the expression, patterns can't declare constants.
+fn parse_program<'a>(
+    patterns: bool,
+) -> impl Parser<'a, &'a str, (Vec<Decl>, Spanned), extra::Err<Rich<'a, char>>> {
+    // a whole identifier, so variables like `constant` still work
+    let decl = text::ascii::ident()
+        .filter(|word: &&str| *word == "const")
+        .padded()
+        .ignore_then(text::ascii::ident().padded())
+        .then_ignore(just('='))
+        .then(parse_expr(false))
+        .then_ignore(just(';'))
+        .map_with(|(name, value): (&'a str, Spanned), e| {
+            (name.to_string(), value, e.span().into_range())
+        })
+        .padded();
+
+    decl.repeated()
+        .at_most(if patterns { 0 } else { usize::MAX })
+        .collect()
+        .then(parse_expr(patterns))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn statements_are_found_in_the_input() -> Result<(), LpErr> {
+        let input = "const K = 2;\r\nx *\r\n K ";
+        let (program, _) = run_program_parser(input, DEFAULT_MAX_NESTING)?;
+        let statements = program.statements(input);
+        assert_eq!(
+            statements,
+            [
+                Statement {
+                    text: "const K = 2;".into(),
+                    spans: vec![0..12, 20..21],
+                },
+                Statement {
+                    text: "x * K".into(),
+                    spans: vec![14..21],
+                },
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn parse_simple_expr() -> Result<(), LpErr> {
         let expr = run_parser("(1 + 2)")?;
@@ -77,9 +531,9 @@ mod test {
         assert_eq!(
             expr,
             Expr::BinaryOp(
-                Box::new(Expr::Num(1)),
+                ExprId::new(Expr::Num(1)),
                 Operator::Add,
-                Box::new(Expr::Num(2))
+                ExprId::new(Expr::Num(2))
             )
         );
         Ok(())
@@ -92,9 +546,9 @@ mod test {
         assert_eq!(
             expr,
             Expr::BinaryOp(
-                Box::new(Expr::Num(1)),
+                ExprId::new(Expr::Num(1)),
                 Operator::Add,
-                Box::new(Expr::Var("a".to_string()))
+                ExprId::new(Expr::Var("a".into()))
             )
         );
         Ok(())
@@ -107,9 +561,9 @@ mod test {
         assert_eq!(
             expr,
             Expr::BinaryOp(
-                Box::new(Expr::Num(1)),
+                ExprId::new(Expr::Num(1)),
                 Operator::Add,
-                Box::new(Expr::Num(2))
+                ExprId::new(Expr::Num(2))
             )
         );
         Ok(())
@@ -127,11 +581,164 @@ mod test {
         ];
 
         for input in inputs {
-            assert!(run_parser(input).is_err(), "`{input}` should fail but got `{:?}`", run_parser(input));
+            assert!(
+                run_parser(input).is_err(),
+                "`{input}` should fail but got `{:?}`",
+                run_parser(input)
+            );
         }
         Ok(())
     }
 
+    #[test]
+    fn parse_shift_lowest_precedence() -> Result<(), LpErr> {
+        let expr = run_parser("x << 1 + 2")?;
+
+        assert_eq!(
+            expr,
+            Expr::BinaryOp(
+                ExprId::new(Expr::Var("x".into())),
+                Operator::Shl,
+                ExprId::new(Expr::BinaryOp(
+                    ExprId::new(Expr::Num(1)),
+                    Operator::Add,
+                    ExprId::new(Expr::Num(2))
+                ))
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn diagnose_with_suggestions() {
+        let diagnostics = diagnose("(1 + *) * (2", DEFAULT_MAX_NESTING, DEFAULT_MAX_TOKENS);
+        assert_eq!(diagnostics.len(), 2, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].span, 5..6);
+        assert!(
+            diagnostics[0]
+                .message
+                .contains(&*t!("parser.missing_operand"))
+        );
+        assert!(diagnostics[1].message.contains(&*t!("parser.unclosed")));
+
+        assert!(diagnose("2 * (a + 1)", DEFAULT_MAX_NESTING, DEFAULT_MAX_TOKENS).is_empty());
+        assert!(
+            diagnose("1 + 1)", DEFAULT_MAX_NESTING, DEFAULT_MAX_TOKENS)[0]
+                .message
+                .contains(&*t!("parser.unopened"))
+        );
+    }
+
+    #[test]
+    fn huge_numbers_are_diagnosed() -> Result<(), LpErr> {
+        let diagnostics = diagnose("1 + 99999999999", DEFAULT_MAX_NESTING, DEFAULT_MAX_TOKENS);
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].span, 4..15, "{diagnostics:?}");
+        assert!(
+            diagnostics[0]
+                .message
+                .contains(&*t!("parser.too_large", max = i32::MAX))
+        );
+        assert!(run_parser("2147483648").is_err());
+        assert_eq!(run_parser("2147483647")?, Expr::Num(i32::MAX));
+        Ok(())
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let nested = format!("{}a{}", "(1 + ".repeat(8), ")".repeat(8));
+        assert!(diagnose(&nested, 9, DEFAULT_MAX_TOKENS).is_empty());
+
+        let diagnostics = diagnose(&nested, 5, DEFAULT_MAX_TOKENS);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(
+            diagnostics[0]
+                .message
+                .contains(&*t!("parser.too_deep", max = 5))
+        );
+        // the first parenthesis that is too deep is marked, not the whole calculation
+        assert_eq!(diagnostics[0].span, 25..26);
+
+        // long chains are deep trees too, even without parentheses
+        assert!(run_program_parser("a + 1 + 2 + 3 + 4", 3).is_err());
+        assert!(run_program_parser("const A = a + 1 + 2;\n A", 2).is_err());
+    }
+
+    #[test]
+    fn long_programs_are_rejected_before_parsing() {
+        let input = "a<<2 + foo_1*(3)";
+        let tokens: Vec<_> = tokens(input).map(|span| &input[span]).collect();
+        assert_eq!(tokens, ["a", "<<", "2", "+", "foo_1", "*", "(", "3", ")"]);
+
+        assert!(diagnose("1 + 2 + 3", DEFAULT_MAX_NESTING, 5).is_empty());
+        let diagnostics = diagnose("1 + 2 + 3 +", DEFAULT_MAX_NESTING, 3);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(
+            diagnostics[0]
+                .message
+                .contains(&*t!("parser.too_many_tokens", max = 3))
+        );
+        // everything that is too much is marked, even though it doesn't parse
+        assert_eq!(diagnostics[0].span, 6..11);
+    }
+
+    #[test]
+    fn parse_school_notation() -> Result<(), LpErr> {
+        assert_eq!(run_parser("2(a+3)")?, run_parser("2 * (a + 3)")?);
+        assert_eq!(run_parser("(a)(b) (c)")?, run_parser("a * b * c")?);
+        assert_eq!(run_parser("2·3 × 4 ÷ 2")?, run_parser("2 * 3 * 4 / 2")?);
+        assert_eq!(run_parser("−a − 1")?, run_parser("-a - 1")?);
+        assert!(run_parser("2 3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_builtin_calls() -> Result<(), LpErr> {
+        let sum = run_parser("2 * sat_add(a, b - 1)")?;
+        let Expr::BinaryOp(_, Operator::Mul, call) = sum else {
+            panic!("{sum:?}")
+        };
+        assert!(matches!(call.get(), Expr::BinaryOp(_, Operator::SatAdd, _)));
+        assert_eq!(
+            format("checked_div( a ,sat_sub(b,1) )+1")?,
+            "checked_div(a, sat_sub(b, 1)) + 1"
+        );
+        // other names are still variables
+        assert_eq!(run_parser("f(a + 1)")?, run_parser("f * (a + 1)")?);
+        assert!(run_parser("sat_add(a)").is_err());
+        let error = run_parser("clamp(x, 9)").unwrap_err().to_string();
+        assert!(error.contains("clamp"), "{error}");
+        assert_eq!(
+            run_parser("clamp(x, 0, 9)")?,
+            run_parser("min(max(x, 0), 9)")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn format_normalizes_spacing_and_parentheses() -> Result<(), LpErr> {
+        assert_eq!(format("2(a)+3")?, "2 * a + 3");
+        assert_eq!(format("((a-b))  -(c-d)")?, "a - b - (c - d)");
+        assert_eq!(format("x·(y÷2)")?, "x * (y / 2)");
+        assert_eq!(format("const  A=2*(3);A+1")?, "const A = 2 * 3;\nA + 1");
+        assert!(format("(1 +").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pattern_vars_only_in_patterns() -> Result<(), LpErr> {
+        assert!(run_parser("?x * 2").is_err());
+        assert_eq!(
+            run_pattern_parser("?x * 2")?,
+            Expr::BinaryOp(
+                ExprId::new(Expr::Var("?x".into())),
+                Operator::Mul,
+                ExprId::new(Expr::Num(2))
+            )
+        );
+        Ok(())
+    }
+
     #[test]
     fn parse_nested_1() -> Result<(), LpErr> {
         let expr = run_parser("(1 + (2 * 3))")?;
@@ -139,12 +746,12 @@ mod test {
         assert_eq!(
             expr,
             Expr::BinaryOp(
-                Box::new(Expr::Num(1)),
+                ExprId::new(Expr::Num(1)),
                 Operator::Add,
-                Box::new(Expr::BinaryOp(
-                    Box::new(Expr::Num(2)),
+                ExprId::new(Expr::BinaryOp(
+                    ExprId::new(Expr::Num(2)),
                     Operator::Mul,
-                    Box::new(Expr::Num(3))
+                    ExprId::new(Expr::Num(3))
                 ))
             )
         );
@@ -158,13 +765,13 @@ mod test {
         assert_eq!(
             expr,
             Expr::BinaryOp(
-                Box::new(Expr::BinaryOp(
-                    Box::new(Expr::Num(1)),
+                ExprId::new(Expr::BinaryOp(
+                    ExprId::new(Expr::Num(1)),
                     Operator::Add,
-                    Box::new(Expr::Num(2))
+                    ExprId::new(Expr::Num(2))
                 )),
                 Operator::Mul,
-                Box::new(Expr::Num(3))
+                ExprId::new(Expr::Num(3))
             )
         );
         Ok(())
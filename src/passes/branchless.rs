@@ -0,0 +1,185 @@
+use super::explanations::short;
+use super::{Pass, PassContext, TransformationLog};
+use crate::compiler::{CompileOptions, OptLevel, Stage};
+use crate::gui::WordSize;
+use crate::ranges::{Hints, Ranges, ValueRange};
+use crate::types::{Expr, ExprId, Operator};
+use rust_i18n::t;
+
+pub trait Branchless {
+    /// The hints and the word size give the value ranges that show which differences fit.
+    fn lower_min_max_branchless(
+        self,
+        hints: &Hints,
+        word: WordSize,
+        log: &mut TransformationLog,
+    ) -> Self;
+}
+
+/// See [`Branchless`]. It makes programs slower, so no optimization level includes it.
+pub struct BranchlessMinMax;
+
+impl Pass for BranchlessMinMax {
+    fn name(&self) -> &'static str {
+        "branchless"
+    }
+
+    fn label(&self) -> &'static str {
+        "editor.branchless"
+    }
+
+    fn description(&self) -> &'static str {
+        "editor.branchless.desc"
+    }
+
+    fn position(&self) -> u32 {
+        50
+    }
+
+    fn level(&self) -> Option<OptLevel> {
+        None
+    }
+
+    fn flag<'a>(&self, options: &'a mut CompileOptions) -> &'a mut bool {
+        &mut options.branchless_min_max
+    }
+
+    fn run_tree(
+        &self,
+        ast: Expr,
+        cx: &PassContext,
+        stages: &mut Vec<Stage>,
+        log: &mut TransformationLog,
+    ) -> Expr {
+        let ast = ast.lower_min_max_branchless(cx.hints, cx.hw.word_size, log);
+        stages.push(Stage::expr("pipeline.branchless", &ast));
+        ast
+    }
+}
+
+/// Computes `min` and `max` with subtraction, shifts and multiplication instead of their own
+/// instructions, like on processors without them.
+///
+/// Real compilers choose between comparing and branching or arithmetic like this. Our programs
+/// have no branches, so here the choice is between the `min`/`max` instructions and this.
+impl Branchless for Expr {
+    fn lower_min_max_branchless(
+        self,
+        hints: &Hints,
+        word: WordSize,
+        log: &mut TransformationLog,
+    ) -> Self {
+        let mut ranges = Ranges::with_hints(hints, word);
+        self.rebuild_bottom_up(|expr| {
+            let expr = lower(expr, &ranges, word, log);
+            ranges.add(expr);
+            expr
+        })
+    }
+}
+
+/// Lowers a single `min` or `max` whose operands were already handled.
+///
+/// The sign of `a - b` is only right if it doesn't wrap around, so the others are kept, unless
+/// the [`Ranges`] show the difference always fits into a word.
+fn lower(expr: Expr, ranges: &Ranges, word: WordSize, log: &mut TransformationLog) -> Expr {
+    let Expr::BinaryOp(a, op @ (Operator::Min | Operator::Max), b) = expr else {
+        return expr;
+    };
+    let (ra, rb) = (ranges.get(&a.get()), ranges.get(&b.get()));
+    let any = ValueRange::any(word);
+    let fits = i64::from(ra.min) - i64::from(rb.max) >= i64::from(any.min)
+        && i64::from(ra.max) - i64::from(rb.min) <= i64::from(any.max);
+    if !fits {
+        return expr;
+    }
+    let binary = |l, op, r| ExprId::new(Expr::BinaryOp(l, op, r));
+    let diff = binary(a, Operator::Sub, b);
+    // -1 if a < b, 0 otherwise
+    let top_bit = ExprId::new(Expr::Num(word.bits() as i32 - 1));
+    let sign = binary(diff, Operator::Shr, top_bit);
+    // b - a if a < b, 0 otherwise
+    let correction = binary(diff, Operator::Mul, sign);
+    let res = match op {
+        Operator::Min => Expr::BinaryOp(b, Operator::Sub, correction),
+        _ => Expr::BinaryOp(a, Operator::Add, correction),
+    };
+    let reason = t!(
+        "explain.branchless",
+        sign = short(sign.get()),
+        a = short(a.get()),
+        b = short(b.get())
+    );
+    log.replaced(short(expr), short(res), reason.into());
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::evaluate;
+    use crate::export::Cost;
+    use crate::parser::run_parser;
+    use std::collections::HashMap;
+
+    fn lowered(source: &str, word: WordSize) -> Expr {
+        let hints = Hints::from([
+            ("a".to_string(), ValueRange { min: -60, max: 60 }),
+            ("b".to_string(), ValueRange { min: -50, max: 50 }),
+        ]);
+        run_parser(source).unwrap().lower_min_max_branchless(
+            &hints,
+            word,
+            &mut TransformationLog::default(),
+        )
+    }
+
+    #[test]
+    fn arithmetic_picks_the_same_value() -> Result<(), crate::types::LpErr> {
+        for word in [WordSize::W8, WordSize::W16] {
+            let expr = run_parser("min(b, a) + max(a, -10)")?;
+            let lowered = lowered("min(b, a) + max(a, -10)", word);
+            assert!(!lowered.pretty().contains("min"), "{}", lowered.pretty());
+            let top_bit = format!(">> {}", word.bits() - 1);
+            assert!(lowered.pretty().contains(&top_bit), "{}", lowered.pretty());
+            for a in [-60, -10, -3, 0, 5, 60] {
+                for b in [-50, -1, 0, 7, 50] {
+                    let vars = HashMap::from([("a".to_string(), a), ("b".to_string(), b)]);
+                    assert_eq!(
+                        evaluate(&lowered, &vars, word)?,
+                        evaluate(&expr, &vars, word)?,
+                        "{a}, {b} at {word:?}"
+                    );
+                }
+            }
+        }
+
+        // without the instructions it takes longer
+        let cycles = |branchless| {
+            let options = crate::compiler::CompileOptions {
+                branchless_min_max: branchless,
+                ..Default::default()
+            };
+            crate::compiler::Compiler::with(options)
+                .compile("min(clamp(a, 0, 100), 7)")
+                .map(|ir| Cost::of(&ir.instructions).cycles)
+        };
+        assert!(cycles(true)? > cycles(false)?);
+        Ok(())
+    }
+
+    #[test]
+    fn differences_that_may_wrap_keep_the_instruction() {
+        // nothing is known about `c`, at 8 bits e.g. 50 - -100 wraps around
+        assert_eq!(lowered("min(b, c)", WordSize::W8).pretty(), "min(b, c)");
+        assert_eq!(
+            lowered("min(a, b + b)", WordSize::W8).pretty(),
+            "min(a, b + b)"
+        );
+        assert_eq!(lowered("max(x, y)", WordSize::W16).pretty(), "max(x, y)");
+        assert_ne!(
+            lowered("min(a, b + b)", WordSize::W16).pretty(),
+            "min(a, b + b)"
+        );
+    }
+}
@@ -1,19 +1,64 @@
-use crate::types::{Expr, Operator};
+use super::explanations::short;
+use super::{Pass, PassContext, TransformationLog};
+use crate::compiler::{CompileOptions, OptLevel, Stage};
+use crate::types::{Expr, ExprId, Operator};
+use rust_i18n::t;
 
 pub trait CommonFactorElimination {
-    fn extract_common_factors(self) -> Self;
+    fn extract_common_factors(self, log: &mut TransformationLog) -> Self;
+}
+
+/// Takes factors out of sums, `a * b + a * c` becomes `a * (b + c)`.
+pub struct CommonFactors;
+
+impl Pass for CommonFactors {
+    fn name(&self) -> &'static str {
+        "factors"
+    }
+
+    fn label(&self) -> &'static str {
+        "editor.common_factor_elimination"
+    }
+
+    fn description(&self) -> &'static str {
+        "editor.common_factor_elimination.desc"
+    }
+
+    fn position(&self) -> u32 {
+        30
+    }
+
+    fn level(&self) -> Option<OptLevel> {
+        Some(OptLevel::O2)
+    }
+
+    fn flag<'a>(&self, options: &'a mut CompileOptions) -> &'a mut bool {
+        &mut options.do_common_factor_elimination
+    }
+
+    fn run_tree(
+        &self,
+        ast: Expr,
+        _cx: &PassContext,
+        stages: &mut Vec<Stage>,
+        log: &mut TransformationLog,
+    ) -> Expr {
+        let ast = ast.extract_common_factors(log);
+        stages.push(Stage::expr("pipeline.factored", &ast));
+        ast
+    }
 }
 
 impl CommonFactorElimination for Expr {
-    fn extract_common_factors(self) -> Self {
+    fn extract_common_factors(self, log: &mut TransformationLog) -> Self {
         match self {
             Expr::BinaryOp(left, Operator::Add, right) => {
-                let left = left.extract_common_factors();
-                let right = right.extract_common_factors();
+                let left = left.get().extract_common_factors(log);
+                let right = right.get().extract_common_factors(log);
 
                 let common_factors = extract_factors(&left, &right);
                 if common_factors.is_empty() {
-                    return Expr::BinaryOp(Box::new(left), Operator::Add, Box::new(right));
+                    return Expr::BinaryOp(ExprId::new(left), Operator::Add, ExprId::new(right));
                 }
 
                 let factor = &common_factors[0];
@@ -21,21 +66,28 @@ impl CommonFactorElimination for Expr {
                 let right_remainder = remove_factor_from_expr(&right, factor);
 
                 let sum = Expr::BinaryOp(
-                    Box::new(left_remainder),
+                    ExprId::new(left_remainder),
                     Operator::Add,
-                    Box::new(right_remainder),
+                    ExprId::new(right_remainder),
                 );
 
-                Expr::BinaryOp(Box::new(factor.clone()), Operator::Mul, Box::new(sum))
+                let res = Expr::BinaryOp(ExprId::new(*factor), Operator::Mul, ExprId::new(sum));
+                let before = Expr::BinaryOp(ExprId::new(left), Operator::Add, ExprId::new(right));
+                log.replaced(
+                    short(before),
+                    short(res),
+                    t!("explain.factor", factor = short(*factor)).into(),
+                );
+                res
             }
             Expr::BinaryOp(left, op, right) => {
-                let left = left.extract_common_factors();
-                let right = right.extract_common_factors();
-                Expr::BinaryOp(Box::new(left), op, Box::new(right))
+                let left = left.get().extract_common_factors(log);
+                let right = right.get().extract_common_factors(log);
+                Expr::BinaryOp(ExprId::new(left), op, ExprId::new(right))
             }
             Expr::UnaryOp(op, expr) => {
-                let expr = expr.extract_common_factors();
-                Expr::UnaryOp(op, Box::new(expr))
+                let expr = expr.get().extract_common_factors(log);
+                Expr::UnaryOp(op, ExprId::new(expr))
             }
             _ => self,
         }
@@ -50,8 +102,8 @@ fn extract_factors(left: &Expr, right: &Expr) -> Vec<Expr> {
 
     for left_factor in &left_factors {
         for right_factor in &right_factors {
-            if expressions_equal(left_factor, right_factor) {
-                common_factors.push(left_factor.clone());
+            if left_factor == right_factor {
+                common_factors.push(*left_factor);
             }
         }
     }
@@ -62,49 +114,38 @@ fn extract_factors(left: &Expr, right: &Expr) -> Vec<Expr> {
 fn get_multiplication_factors(expr: &Expr) -> Vec<Expr> {
     match expr {
         Expr::BinaryOp(left, Operator::Mul, right) => {
-            let mut factors = get_multiplication_factors(left);
-            factors.extend(get_multiplication_factors(right));
+            let mut factors = get_multiplication_factors(&left.get());
+            factors.extend(get_multiplication_factors(&right.get()));
             factors
         }
-        _ => vec![expr.clone()],
-    }
-}
-
-fn expressions_equal(a: &Expr, b: &Expr) -> bool {
-    match (a, b) {
-        (Expr::Num(n1), Expr::Num(n2)) => n1 == n2,
-        (Expr::Var(v1), Expr::Var(v2)) => v1 == v2,
-        (Expr::UnaryOp(op1, e1), Expr::UnaryOp(op2, e2)) => op1 == op2 && expressions_equal(e1, e2),
-        (Expr::BinaryOp(l1, op1, r1), Expr::BinaryOp(l2, op2, r2)) => {
-            op1 == op2 && expressions_equal(l1, l2) && expressions_equal(r1, r2)
-        }
-        _ => false,
+        _ => vec![*expr],
     }
 }
 
 fn remove_factor_from_expr(expr: &Expr, factor: &Expr) -> Expr {
     match expr {
         Expr::BinaryOp(left, Operator::Mul, right) => {
-            if expressions_equal(left, factor) {
-                (**right).clone()
-            } else if expressions_equal(right, factor) {
-                (**left).clone()
+            let (left, right) = (&left.get(), &right.get());
+            if left == factor {
+                *right
+            } else if right == factor {
+                *left
             } else {
                 let new_left = remove_factor_from_expr(left, factor);
                 let new_right = remove_factor_from_expr(right, factor);
 
-                if expressions_equal(&new_left, left) && expressions_equal(&new_right, right) {
-                    expr.clone()
+                if (&new_left, &new_right) == (left, right) {
+                    *expr
                 } else {
-                    Expr::BinaryOp(Box::new(new_left), Operator::Mul, Box::new(new_right))
+                    Expr::BinaryOp(ExprId::new(new_left), Operator::Mul, ExprId::new(new_right))
                 }
             }
         }
         _ => {
-            if expressions_equal(expr, factor) {
+            if expr == factor {
                 Expr::Num(1)
             } else {
-                expr.clone()
+                *expr
             }
         }
     }
@@ -1,56 +1,93 @@
-use std::ops::Neg;
-
+use super::explanations::short;
+use super::{CODE_POSITION, Pass, PassContext, TransformationLog};
+use crate::compiler::{CompileOptions, OptLevel, Stage};
+use crate::gui::WordSize;
 use crate::types::{Expr, Operator};
+use rust_i18n::t;
 
 pub trait ConstantFold {
-    fn run_constant_fold(self) -> Self;
+    /// Computes what only depends on numbers, with words of the given size.
+    fn run_constant_fold(self, word: WordSize, log: &mut TransformationLog) -> Self;
 }
 
-impl ConstantFold for Expr {
-    fn run_constant_fold(self) -> Self {
-        match self {
-            Expr::Num(_) | Expr::Var(_) =>
-            /* no work to be done */
-            {
-                self
-            }
-            Expr::UnaryOp(operator, expr) => {
-                let e = expr.run_constant_fold();
+/// Computes the parts of the program that only depend on numbers. It runs once more after all
+/// other passes on the syntax tree, since they can leave new numbers to compute.
+pub struct Folding;
+
+impl Pass for Folding {
+    fn name(&self) -> &'static str {
+        "folding"
+    }
+
+    fn label(&self) -> &'static str {
+        "editor.constant_folding"
+    }
+
+    fn description(&self) -> &'static str {
+        "editor.constant_folding.desc"
+    }
 
-                if let Expr::Num(n) = e
-                    && operator == Operator::Sub
-                {
-                    return Expr::Num(n.neg());
-                }
+    fn position(&self) -> u32 {
+        10
+    }
+
+    fn rerun(&self) -> Option<u32> {
+        Some(CODE_POSITION - 1)
+    }
 
-                Expr::UnaryOp(operator, Box::new(e))
+    fn level(&self) -> Option<OptLevel> {
+        Some(OptLevel::O1)
+    }
+
+    fn flag<'a>(&self, options: &'a mut CompileOptions) -> &'a mut bool {
+        &mut options.do_constant_folding
+    }
+
+    fn run_tree(
+        &self,
+        ast: Expr,
+        cx: &PassContext,
+        stages: &mut Vec<Stage>,
+        log: &mut TransformationLog,
+    ) -> Expr {
+        let ast = ast.run_constant_fold(cx.hw.word_size, log);
+        stages.push(Stage::expr("pipeline.folded", &ast));
+        ast
+    }
+}
+
+impl ConstantFold for Expr {
+    fn run_constant_fold(self, word: WordSize, log: &mut TransformationLog) -> Self {
+        self.rebuild_bottom_up(|expr| {
+            let folded = fold(expr, word);
+            if folded != expr {
+                log.replaced(short(expr), short(folded), t!("explain.fold").into());
             }
-            Expr::BinaryOp(lhs, operator, rhs) => {
-                let l = lhs.run_constant_fold();
-                let r = rhs.run_constant_fold();
-
-                if let Expr::Num(left) = l
-                    && let Expr::Num(right) = r
-                {
-                    let res = match operator {
-                        Operator::Add => left + right,
-                        Operator::Sub => left - right,
-                        Operator::Mul => left * right,
-                        Operator::Div => {
-                            if right == 0 {
-                                eprintln!("Warning: detected division by zero during constant folding; not folding.");
-                                return Expr::BinaryOp(Box::new(l), operator, Box::new(r));
-                            }
-                            left / right
-                        }
-                        Operator::Shl => left << right,
-                        Operator::Shr => left >> right,
-                    };
-                    return res.into();
-                }
-
-                Expr::BinaryOp(Box::new(l), operator, Box::new(r))
+            folded
+        })
+    }
+}
+
+/// Folds a single node whose operands were already folded.
+fn fold(expr: Expr, word: WordSize) -> Expr {
+    match expr {
+        Expr::UnaryOp(Operator::Sub, e) => match e.get() {
+            Expr::Num(n) => Expr::Num(word.wrap(word.wrap(n).wrapping_neg())),
+            _ => expr,
+        },
+        Expr::BinaryOp(lhs, operator, rhs) => {
+            let (Expr::Num(left), Expr::Num(right)) = (lhs.get(), rhs.get()) else {
+                return expr;
+            };
+            // computes at the machine's word size, so the program gives the same result with
+            // and without folding. Dividing by zero and checked operations that overflow fail
+            // when run, the semantic analysis warns about the former.
+            match operator.apply(left, right, word) {
+                Some(n) => n.into(),
+                None => expr,
             }
         }
+        /* no work to be done */
+        Expr::Num(_) | Expr::Var(_) | Expr::UnaryOp(..) => expr,
     }
 }
@@ -0,0 +1,752 @@
+use super::explanations::short;
+use super::rewrite::is_pattern_var;
+use super::{Pass, PassContext, RewriteRule, TransformationLog};
+use crate::compiler::{CompileOptions, OptLevel, Stage};
+use crate::gui::WordSize;
+use crate::intern::Symbol;
+use crate::types::{Expr, ExprId, Inst, Operator, Reg};
+use rust_i18n::t;
+use std::collections::{HashMap, HashSet};
+
+/// Rounds of applying every rule everywhere at most, and how large the graph may grow. Rules
+/// like commutativity never run out of new programs, so saturation has to stop somewhere.
+const MAX_ITERATIONS: usize = 8;
+const MAX_NODES: usize = 5000;
+
+/// Rules that are always applied. Each is checked with [`RewriteRule::validate`] in the tests,
+/// like the rules visitors write. Multiplying by a power of two is a shift as well, see
+/// [`EGraph::shifts`]. Rules that drop an operand, like `?a * 0 → 0`, only fire when computing
+/// the operand can't fail, see [`EGraph::may_fail`].
+const BUILT_IN_RULES: &[&str] = &[
+    "?a + ?b → ?b + ?a",
+    "?a * ?b → ?b * ?a",
+    "(?a + ?b) + ?c → ?a + (?b + ?c)",
+    "(?a * ?b) * ?c → ?a * (?b * ?c)",
+    "?a + (?b + ?c) → (?a + ?b) + ?c",
+    "?a * (?b * ?c) → (?a * ?b) * ?c",
+    "?a * (?b + ?c) → ?a * ?b + ?a * ?c",
+    "?a * ?b + ?a * ?c → ?a * (?b + ?c)",
+    "?a + 0 → ?a",
+    "?a - 0 → ?a",
+    "?a * 1 → ?a",
+    "?a * 0 → 0",
+    "?a - ?a → 0",
+    "?a + ?a → ?a * 2",
+    "?a - ?b → ?a + -?b",
+    "?a + -?b → ?a - ?b",
+    "-(-?a) → ?a",
+];
+
+/// Parsed for each use rather than kept in a `static`, the arena forgets old expressions.
+fn built_in_rules() -> impl Iterator<Item = RewriteRule> {
+    BUILT_IN_RULES
+        .iter()
+        .map(|rule| RewriteRule::parse(rule).expect("the built-in rules are valid"))
+}
+
+/// Index of an equivalence class, only meaningful after [`EGraph::find`].
+type ClassId = usize;
+
+/// One way to compute the value of a class, its operands are classes themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ENode {
+    Num(i32),
+    Var(Symbol),
+    Unary(Operator, ClassId),
+    Binary(ClassId, Operator, ClassId),
+}
+
+impl ENode {
+    fn children(self) -> Vec<ClassId> {
+        match self {
+            ENode::Num(_) | ENode::Var(_) => vec![],
+            ENode::Unary(_, e) => vec![e],
+            ENode::Binary(l, _, r) => vec![l, r],
+        }
+    }
+
+    fn map(self, mut f: impl FnMut(ClassId) -> ClassId) -> ENode {
+        match self {
+            ENode::Num(_) | ENode::Var(_) => self,
+            ENode::Unary(op, e) => ENode::Unary(op, f(e)),
+            ENode::Binary(l, op, r) => ENode::Binary(f(l), op, f(r)),
+        }
+    }
+
+    /// The instructions computing the node once its operands are in registers.
+    fn instructions(self) -> Vec<Inst> {
+        let (a, b) = (Reg(0), Reg(1));
+        match self {
+            ENode::Num(n) => vec![Inst::Store(n, a)],
+            ENode::Var(v) => vec![Inst::Transfer(v.to_string(), a)],
+            ENode::Unary(..) => vec![Inst::Neg(a)],
+            ENode::Binary(_, op, _) => {
+                let inst = match op.base() {
+                    Operator::Add => Inst::Add(a, b),
+                    Operator::Sub => Inst::Sub(a, b),
+                    Operator::Mul => Inst::Mul(a, b),
+                    Operator::Div => Inst::Div(a, b),
+                    Operator::Shl => Inst::Shl(a, b),
+                    Operator::Shr => Inst::Shr(a, b),
+                    Operator::Min => Inst::Min(a, b),
+                    _ => Inst::Max(a, b),
+                };
+                let check = match op {
+                    Operator::SatAdd | Operator::SatSub => Some(Inst::Sat(b)),
+                    Operator::CheckedAdd
+                    | Operator::CheckedSub
+                    | Operator::CheckedMul
+                    | Operator::CheckedDiv => Some(Inst::Chk(b)),
+                    _ => None,
+                };
+                std::iter::once(inst).chain(check).collect()
+            }
+        }
+    }
+}
+
+/// What a program costs to run: the cycles of the instructions computing each node of the tree.
+/// Values used twice are computed twice, like the code generator does.
+///
+/// The cycles can be changed, to see which program is the best one on other hardware.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CostModel {
+    /// Cycles of instructions by their name, the others take the cycles of the [`crate::types::ISA`].
+    weights: HashMap<&'static str, u32>,
+}
+
+impl CostModel {
+    /// The instructions programs are costed with, one of each.
+    pub fn instructions() -> Vec<Inst> {
+        let (a, b) = (Reg(0), Reg(1));
+        vec![
+            Inst::Store(0, a),
+            Inst::Transfer("x".into(), a),
+            Inst::Add(a, b),
+            Inst::Sub(a, b),
+            Inst::Mul(a, b),
+            Inst::Div(a, b),
+            Inst::Shl(a, b),
+            Inst::Shr(a, b),
+            Inst::Neg(a),
+            Inst::Min(a, b),
+            Inst::Max(a, b),
+            Inst::Sat(a),
+            Inst::Chk(a),
+        ]
+    }
+
+    /// Makes all instructions with the name of `inst` take `cycles`.
+    pub fn set(&mut self, inst: &Inst, cycles: u32) {
+        if cycles == inst.cycles() {
+            self.weights.remove(inst.name());
+        } else {
+            self.weights.insert(inst.name(), cycles);
+        }
+    }
+
+    /// No instruction takes other cycles than in the ISA.
+    pub fn is_default(&self) -> bool {
+        self.weights.is_empty()
+    }
+
+    pub fn cycles(&self, inst: &Inst) -> u32 {
+        self.weights
+            .get(inst.name())
+            .copied()
+            .unwrap_or_else(|| inst.cycles())
+    }
+
+    fn node(&self, node: ENode) -> u64 {
+        node.instructions()
+            .iter()
+            .map(|i| u64::from(self.cycles(i)))
+            .sum()
+    }
+
+    pub fn of(&self, expr: Expr) -> u64 {
+        let mut cost = 0;
+        let mut todo = vec![expr];
+        while let Some(expr) = todo.pop() {
+            let node = match expr {
+                Expr::Num(n) => ENode::Num(n),
+                Expr::Var(v) => ENode::Var(v),
+                Expr::UnaryOp(op, e) => {
+                    todo.push(e.get());
+                    ENode::Unary(op, 0)
+                }
+                Expr::BinaryOp(l, op, r) => {
+                    todo.extend([l.get(), r.get()]);
+                    ENode::Binary(0, op, 0)
+                }
+            };
+            cost += self.node(node);
+        }
+        cost
+    }
+}
+
+/// Many equivalent programs at once: each class holds every way found so far to compute one
+/// value. Rules only ever add ways, so no rewrite can block a better one later, unlike with
+/// passes that run one after the other.
+#[derive(Debug, Default)]
+struct EGraph {
+    /// Union-find of the classes, a class is canonical if it's its own parent.
+    parents: Vec<ClassId>,
+    /// The nodes of each canonical class.
+    nodes: Vec<Vec<ENode>>,
+    /// The class of each node with canonical operands.
+    memo: HashMap<ENode, ClassId>,
+    /// The value of classes whose value doesn't depend on the inputs.
+    constants: Vec<Option<i32>>,
+    /// The machine's word size, numbers are computed with it.
+    word: WordSize,
+}
+
+impl EGraph {
+    fn find(&self, mut class: ClassId) -> ClassId {
+        while self.parents[class] != class {
+            class = self.parents[class];
+        }
+        class
+    }
+
+    fn canonical(&self, node: ENode) -> ENode {
+        node.map(|c| self.find(c))
+    }
+
+    fn size(&self) -> usize {
+        self.nodes.iter().map(Vec::len).sum()
+    }
+
+    /// The value the node always has, from the values of its operands.
+    fn evaluate(&self, node: ENode) -> Option<i32> {
+        let value = |c: ClassId| self.constants[self.find(c)];
+        match node {
+            ENode::Num(n) => Some(self.word.wrap(n)),
+            ENode::Var(_) => None,
+            ENode::Unary(Operator::Sub, e) => Some(self.word.wrap(value(e)?.wrapping_neg())),
+            ENode::Unary(..) => None,
+            ENode::Binary(l, op, r) => op.apply(value(l)?, value(r)?, self.word),
+        }
+    }
+
+    /// Whether computing each class can stop the program, e.g. because it divides by zero.
+    /// Numbers never fail, other classes fail if one of their nodes might.
+    fn may_fail(&self) -> Vec<bool> {
+        let mut fails = vec![false; self.nodes.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for class in (0..self.nodes.len()).filter(|&c| self.find(c) == c) {
+                if fails[class] || self.constants[class].is_some() {
+                    continue;
+                }
+                let node_fails = |node: &ENode| {
+                    matches!(node, ENode::Binary(_, op, _) if op.can_fail())
+                        || node.children().iter().any(|&c| fails[self.find(c)])
+                };
+                if self.nodes[class].iter().any(node_fails) {
+                    fails[class] = true;
+                    changed = true;
+                }
+            }
+        }
+        fails
+    }
+
+    /// The class of the node, a new one if the graph doesn't have it yet.
+    fn add(&mut self, node: ENode) -> ClassId {
+        let node = self.canonical(node);
+        if let Some(&class) = self.memo.get(&node) {
+            return self.find(class);
+        }
+        let class = self.parents.len();
+        self.parents.push(class);
+        self.nodes.push(vec![node]);
+        self.memo.insert(node, class);
+        self.constants.push(None);
+        self.fold(class, node);
+        class
+    }
+
+    /// Adds the number to the class if the node always has the same value.
+    fn fold(&mut self, class: ClassId, node: ENode) {
+        if self.constants[self.find(class)].is_some() {
+            return;
+        }
+        if let Some(value) = self.evaluate(node) {
+            self.constants[class] = Some(value);
+            let num = self.add(ENode::Num(value));
+            self.union(class, num);
+        }
+    }
+
+    fn add_expr(&mut self, expr: Expr) -> ClassId {
+        // operands are added first, the stack holds the finished classes
+        let mut todo = vec![(expr, false)];
+        let mut done = vec![];
+        while let Some((expr, operands_done)) = todo.pop() {
+            let node = match (expr, operands_done) {
+                (Expr::UnaryOp(_, e), false) => {
+                    todo.extend([(expr, true), (e.get(), false)]);
+                    continue;
+                }
+                (Expr::BinaryOp(l, _, r), false) => {
+                    todo.extend([(expr, true), (r.get(), false), (l.get(), false)]);
+                    continue;
+                }
+                (Expr::Num(n), _) => ENode::Num(n),
+                (Expr::Var(v), _) => ENode::Var(v),
+                (Expr::UnaryOp(op, _), true) => ENode::Unary(op, done.pop().expect("operand")),
+                (Expr::BinaryOp(_, op, _), true) => {
+                    let r = done.pop().expect("operand was added");
+                    let l = done.pop().expect("operand was added");
+                    ENode::Binary(l, op, r)
+                }
+            };
+            done.push(self.add(node));
+        }
+        done.pop().expect("the whole tree was added")
+    }
+
+    /// Merges the classes, returns whether they were different.
+    fn union(&mut self, a: ClassId, b: ClassId) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        self.parents[b] = a;
+        let nodes = std::mem::take(&mut self.nodes[b]);
+        self.nodes[a].extend(nodes);
+        self.constants[a] = self.constants[a].or(self.constants[b]);
+        true
+    }
+
+    /// Restores the invariants after unions: nodes whose operands became the same are merged,
+    /// and classes whose operands became numbers are just the number.
+    fn rebuild(&mut self) {
+        loop {
+            let mut memo: HashMap<ENode, ClassId> = HashMap::new();
+            let mut merges = vec![];
+            for class in 0..self.nodes.len() {
+                let mut seen = HashSet::new();
+                let nodes: Vec<_> = match self.constants[class] {
+                    // the number is the cheapest way to get the value, the other ways would
+                    // only give the rules more to match
+                    Some(n) if !self.nodes[class].is_empty() => vec![ENode::Num(n)],
+                    _ => self.nodes[class]
+                        .iter()
+                        .map(|&n| self.canonical(n))
+                        .filter(|&n| seen.insert(n))
+                        .collect(),
+                };
+                for &node in &nodes {
+                    match memo.get(&node) {
+                        Some(&other) if self.find(other) != self.find(class) => {
+                            merges.push((other, class))
+                        }
+                        Some(_) => {}
+                        None => {
+                            memo.insert(node, class);
+                        }
+                    }
+                }
+                self.nodes[class] = nodes;
+            }
+            let mut changed = false;
+            for (a, b) in merges {
+                changed |= self.union(a, b);
+            }
+            for class in 0..self.nodes.len() {
+                if self.find(class) == class && self.constants[class].is_none() {
+                    let value = self.nodes[class].iter().find_map(|&n| self.evaluate(n));
+                    if let Some(value) = value {
+                        self.constants[class] = Some(value);
+                        let num = self.add(ENode::Num(value));
+                        changed |= self.union(class, num);
+                    }
+                }
+            }
+            if !changed {
+                self.memo = memo;
+                return;
+            }
+        }
+    }
+
+    /// The bindings of the pattern variables with which `pattern` has the value of `class`.
+    fn ematch(
+        &self,
+        pattern: Expr,
+        class: ClassId,
+        bindings: HashMap<Symbol, ClassId>,
+    ) -> Vec<HashMap<Symbol, ClassId>> {
+        let class = self.find(class);
+        let nodes = &self.nodes[class];
+        match pattern {
+            Expr::Var(v) if is_pattern_var(&v) => match bindings.get(&v) {
+                Some(&bound) if self.find(bound) != class => vec![],
+                Some(_) => vec![bindings],
+                None => {
+                    let mut bindings = bindings;
+                    bindings.insert(v, class);
+                    vec![bindings]
+                }
+            },
+            Expr::Num(n) if self.constants[class] == Some(n) => vec![bindings],
+            Expr::Num(_) => vec![],
+            Expr::Var(v) if nodes.contains(&ENode::Var(v)) => vec![bindings],
+            Expr::Var(_) => vec![],
+            Expr::UnaryOp(op, p) => nodes
+                .iter()
+                .filter_map(|n| match *n {
+                    ENode::Unary(o, e) if o == op => Some(e),
+                    _ => None,
+                })
+                .flat_map(|e| self.ematch(p.get(), e, bindings.clone()))
+                .collect(),
+            Expr::BinaryOp(pl, op, pr) => nodes
+                .iter()
+                .filter_map(|n| match *n {
+                    ENode::Binary(l, o, r) if o == op => Some((l, r)),
+                    _ => None,
+                })
+                .flat_map(|(l, r)| {
+                    self.ematch(pl.get(), l, bindings.clone())
+                        .into_iter()
+                        .flat_map(move |b| self.ematch(pr.get(), r, b))
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds the replacement with the pattern variables standing for their classes.
+    fn instantiate(&mut self, replacement: Expr, bindings: &HashMap<Symbol, ClassId>) -> ClassId {
+        match replacement {
+            Expr::Var(v) if is_pattern_var(&v) => bindings[&v],
+            Expr::Num(n) => self.add(ENode::Num(n)),
+            Expr::Var(v) => self.add(ENode::Var(v)),
+            Expr::UnaryOp(op, e) => {
+                let e = self.instantiate(e.get(), bindings);
+                self.add(ENode::Unary(op, e))
+            }
+            Expr::BinaryOp(l, op, r) => {
+                let l = self.instantiate(l.get(), bindings);
+                let r = self.instantiate(r.get(), bindings);
+                self.add(ENode::Binary(l, op, r))
+            }
+        }
+    }
+
+    /// The class, `a` and `k` of each `a * 2ᵏ`, it's `a << k` as well. Patterns can't say that
+    /// a number is a power of two.
+    fn shifts(&self) -> Vec<(ClassId, ClassId, i32)> {
+        let mut shifts = vec![];
+        for (class, nodes) in self.nodes.iter().enumerate() {
+            for node in nodes {
+                if let ENode::Binary(a, Operator::Mul, n) = *node
+                    && let Some(n) = self.constants[self.find(n)]
+                    && n > 1
+                    && n.count_ones() == 1
+                {
+                    shifts.push((class, a, n.trailing_zeros() as i32));
+                }
+            }
+        }
+        shifts
+    }
+
+    /// Applies the rules everywhere until they find nothing new, or the limits are reached.
+    /// Returns how many rounds it took.
+    fn saturate(&mut self, rules: &[RewriteRule]) -> usize {
+        for round in 1..=MAX_ITERATIONS {
+            let fails = self.may_fail();
+            let mut matches = vec![];
+            for class in (0..self.nodes.len()).filter(|&c| self.find(c) == c) {
+                for rule in rules {
+                    for bindings in self.ematch(rule.pattern, class, HashMap::new()) {
+                        // dropping an operand that fails would drop the error as well
+                        if rule.dropped.iter().any(|v| fails[self.find(bindings[v])]) {
+                            continue;
+                        }
+                        matches.push((class, rule.replacement, bindings));
+                    }
+                }
+            }
+            let mut changed = false;
+            for (class, a, k) in self.shifts() {
+                let k = self.add(ENode::Num(k));
+                let shifted = self.add(ENode::Binary(a, Operator::Shl, k));
+                changed |= self.union(class, shifted);
+            }
+            for (class, replacement, bindings) in matches {
+                let new = self.instantiate(replacement, &bindings);
+                changed |= self.union(class, new);
+                // the memo has every node added since the last rebuild
+                if self.memo.len() > MAX_NODES {
+                    break;
+                }
+            }
+            self.rebuild();
+            if !changed || self.memo.len() > MAX_NODES {
+                return round;
+            }
+        }
+        MAX_ITERATIONS
+    }
+
+    /// The cheapest program in the class, with its cost.
+    fn extract(&self, root: ClassId, costs: &CostModel) -> (Expr, u64) {
+        // the cheapest node of each class, improved until nothing gets cheaper
+        let mut best: HashMap<ClassId, (u64, ENode)> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for class in (0..self.nodes.len()).filter(|&c| self.find(c) == c) {
+                for &node in &self.nodes[class] {
+                    let operands: Option<u64> = node
+                        .children()
+                        .iter()
+                        .map(|c| best.get(&self.find(*c)).map(|(cost, _)| *cost))
+                        .sum();
+                    let Some(operands) = operands else {
+                        continue;
+                    };
+                    let cost = operands.saturating_add(costs.node(node));
+                    if best.get(&class).is_none_or(|(b, _)| cost < *b) {
+                        best.insert(class, (cost, node));
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        // rebuilt from the leaves up, like Expr::rebuild_bottom_up
+        let root = self.find(root);
+        let mut todo = vec![(root, false)];
+        let mut done: Vec<Expr> = vec![];
+        while let Some((class, operands_done)) = todo.pop() {
+            let (_, node) = best[&class];
+            if !operands_done && !node.children().is_empty() {
+                todo.push((class, true));
+                todo.extend(node.children().iter().rev().map(|c| (self.find(*c), false)));
+                continue;
+            }
+            let expr = match node {
+                ENode::Num(n) => Expr::Num(n),
+                ENode::Var(v) => Expr::Var(v),
+                ENode::Unary(op, _) => Expr::UnaryOp(op, ExprId::new(done.pop().expect("operand"))),
+                ENode::Binary(_, op, _) => {
+                    let r = done.pop().expect("operand was built");
+                    let l = done.pop().expect("operand was built");
+                    Expr::BinaryOp(ExprId::new(l), op, ExprId::new(r))
+                }
+            };
+            done.push(expr);
+        }
+        (
+            done.pop().expect("the whole program was built"),
+            best[&root].0,
+        )
+    }
+}
+
+/// The program found by equality saturation, with how long it searched.
+pub struct Saturated {
+    pub expr: Expr,
+    pub rounds: usize,
+    /// Ways of computing parts of the program the graph held in the end.
+    pub nodes: usize,
+    pub cost_before: u64,
+    pub cost: u64,
+}
+
+/// Optimizes the program with an e-graph: all rules are applied at once, over and over, and the
+/// cheapest of all the programs found is taken. `rules` are applied too, besides the built-in
+/// ones. Numbers are computed with words of the given size.
+pub fn saturate(expr: Expr, rules: &[RewriteRule], costs: &CostModel, word: WordSize) -> Saturated {
+    let mut graph = EGraph {
+        word,
+        ..Default::default()
+    };
+    let root = graph.add_expr(expr);
+    graph.rebuild();
+    let all_rules: Vec<_> = built_in_rules().chain(rules.iter().cloned()).collect();
+    let rounds = graph.saturate(&all_rules);
+    let (extracted, cost) = graph.extract(root, costs);
+    let cost_before = costs.of(expr);
+    // the program itself is in the graph, but ties shouldn't change it
+    let expr = if cost < cost_before { extracted } else { expr };
+    Saturated {
+        expr,
+        rounds,
+        nodes: graph.size(),
+        cost_before,
+        cost: cost.min(cost_before),
+    }
+}
+
+/// See [`saturate`]. It's a different way of optimizing to compare with the other passes, so no
+/// optimization level includes it.
+pub struct EqualitySaturation;
+
+impl Pass for EqualitySaturation {
+    fn name(&self) -> &'static str {
+        "saturation"
+    }
+
+    fn label(&self) -> &'static str {
+        "editor.saturation"
+    }
+
+    fn description(&self) -> &'static str {
+        "editor.saturation.desc"
+    }
+
+    fn position(&self) -> u32 {
+        60
+    }
+
+    fn level(&self) -> Option<OptLevel> {
+        None
+    }
+
+    fn flag<'a>(&self, options: &'a mut CompileOptions) -> &'a mut bool {
+        &mut options.equality_saturation
+    }
+
+    fn run_tree(
+        &self,
+        ast: Expr,
+        cx: &PassContext,
+        stages: &mut Vec<Stage>,
+        log: &mut TransformationLog,
+    ) -> Expr {
+        let saturated = saturate(ast, cx.rules, cx.costs, cx.hw.word_size);
+        if saturated.expr != ast {
+            let reason = t!(
+                "explain.saturation",
+                rounds = saturated.rounds,
+                nodes = saturated.nodes,
+                before = saturated.cost_before,
+                after = saturated.cost
+            );
+            log.replaced(short(ast), short(saturated.expr), reason.into());
+        }
+        stages.push(Stage::expr("pipeline.saturated", &saturated.expr));
+        saturated.expr
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::evaluate;
+    use crate::parser::run_parser;
+    use crate::types::LpErr;
+
+    #[test]
+    fn built_in_rules_are_sound() -> Result<(), LpErr> {
+        for rule in built_in_rules() {
+            rule.validate()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn finds_programs_the_passes_miss() -> Result<(), LpErr> {
+        let costs = CostModel::default();
+        let optimize = |source: &str| -> Result<(Expr, Expr), LpErr> {
+            let expr = run_parser(source)?;
+            Ok((expr, saturate(expr, &[], &costs, WordSize::W32).expr))
+        };
+        assert_eq!(optimize("(x + 3) - x")?.1.pretty(), "3");
+
+        // the factor is only common after reordering, and `x * 2` is `x << 1` (or `x + x`)
+        for (source, best) in [
+            ("a * b + c * a", "a * (b + c)"),
+            ("x * 2", "x << 1"),
+            ("(a * 4 + a * 4) * (b - b + 1)", "a << 3"),
+        ] {
+            let (expr, optimized) = optimize(source)?;
+            assert_eq!(
+                costs.of(optimized),
+                costs.of(run_parser(best)?),
+                "{}",
+                optimized.pretty()
+            );
+            for (a, b) in [(-7, 5), (0, 1), (1, -3), (1000, 12)] {
+                let vars = HashMap::from([
+                    ("a".to_string(), a),
+                    ("b".to_string(), b),
+                    ("c".to_string(), a + b),
+                    ("x".to_string(), b),
+                ]);
+                assert_eq!(
+                    evaluate(&optimized, &vars, WordSize::W32)?,
+                    evaluate(&expr, &vars, WordSize::W32)?
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn errors_are_kept() -> Result<(), LpErr> {
+        let costs = CostModel::default();
+        let optimize =
+            |source| Ok::<_, LpErr>(saturate(run_parser(source)?, &[], &costs, WordSize::W32).expr);
+        assert_eq!(optimize("(a + 1) * 0")?, Expr::Num(0));
+        assert_eq!(optimize("(a + b) - (a + b)")?, Expr::Num(0));
+        for source in ["(a / b) * 0", "checked_add(a, 1) - checked_add(a, 1)"] {
+            assert!(optimize(source)?.pretty().contains('a'), "{source}");
+        }
+
+        // numbers are computed with the machine's words, like the interpreter does
+        let sum = run_parser("x * 0 + 100 + 100")?;
+        assert_eq!(
+            saturate(sum, &[], &costs, WordSize::W8).expr,
+            Expr::Num(-56)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cheaper_instructions_change_the_best_program() -> Result<(), LpErr> {
+        let expr = run_parser("x * 8")?;
+        let mut costs = CostModel::default();
+        assert_eq!(
+            saturate(expr, &[], &costs, WordSize::W32).expr.pretty(),
+            "x << 3"
+        );
+
+        let mul = Inst::Mul(Reg(0), Reg(1));
+        costs.set(&mul, 10);
+        assert_eq!(costs.cycles(&Inst::Mul(Reg(2), Reg(3))), 10);
+        assert_eq!(saturate(expr, &[], &costs, WordSize::W32).expr, expr);
+
+        // setting the cycles of the ISA again is the same as never changing them
+        costs.set(&mul, mul.cycles());
+        assert!(costs.is_default());
+        assert_eq!(costs, CostModel::default());
+        Ok(())
+    }
+
+    #[test]
+    fn stops_on_large_programs() -> Result<(), LpErr> {
+        let source = (0..40)
+            .map(|i| format!("x{i}"))
+            .collect::<Vec<_>>()
+            .join(" + ");
+        let saturated = saturate(
+            run_parser(&source)?,
+            &[],
+            &CostModel::default(),
+            WordSize::W32,
+        );
+        assert!(saturated.nodes <= MAX_NODES * 2, "{}", saturated.nodes);
+        assert!(saturated.cost <= saturated.cost_before);
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use crate::types::{Expr, ExprId};
+use rust_i18n::t;
+
+/// One change a pass made to the program, with why it gives the same result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    /// Translation key of the label of the pass that made the change.
+    pub pass: &'static str,
+    pub before: String,
+    /// `None` if the code was removed.
+    pub after: Option<String>,
+    /// Translated already, it's filled in with parts of the program.
+    pub reason: String,
+}
+
+impl std::fmt::Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match &self.after {
+            Some(after) => t!(
+                "explain.replaced",
+                before = self.before,
+                after = after,
+                reason = self.reason
+            ),
+            None => t!(
+                "explain.removed",
+                before = self.before,
+                reason = self.reason
+            ),
+        };
+        f.write_str(&text)
+    }
+}
+
+/// Everything the passes of one compilation changed, in the order they did it.
+///
+/// The compiler tells the log which pass runs, so the passes only record what they do.
+#[derive(Debug, Clone, Default)]
+pub struct TransformationLog {
+    pass: &'static str,
+    entries: Vec<Explanation>,
+    /// Where the entries of each run of a pass begin.
+    starts: Vec<usize>,
+}
+
+impl TransformationLog {
+    /// The following changes are made by the pass with this label.
+    pub fn start(&mut self, pass: &'static str) {
+        self.pass = pass;
+        self.starts.push(self.entries.len());
+    }
+
+    pub fn replaced(&mut self, before: String, after: String, reason: String) {
+        self.push(before, Some(after), reason);
+    }
+
+    pub fn removed(&mut self, before: String, reason: String) {
+        self.push(before, None, reason);
+    }
+
+    fn push(&mut self, before: String, after: Option<String>, reason: String) {
+        if self.starts.is_empty() {
+            // changes of a pass run without the compiler
+            self.starts.push(0);
+        }
+        self.entries.push(Explanation {
+            pass: self.pass,
+            before,
+            after,
+            reason,
+        });
+    }
+
+    #[cfg(test)]
+    pub fn entries(&self) -> &[Explanation] {
+        &self.entries
+    }
+
+    /// The entries of each run of a pass that changed something, in the order they ran. A pass
+    /// that runs twice has an entry for each time.
+    pub fn runs(&self) -> impl Iterator<Item = &[Explanation]> {
+        let ends = self
+            .starts
+            .iter()
+            .skip(1)
+            .copied()
+            .chain([self.entries.len()]);
+        (self.starts.iter().zip(ends))
+            .map(|(&start, end)| &self.entries[start..end])
+            .filter(|run| !run.is_empty())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// `2³` for `superscript(3)`, for the explanations of powers.
+pub fn superscript(n: u32) -> String {
+    const DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+    n.to_string()
+        .chars()
+        .map(|c| DIGITS[c.to_digit(10).expect("it's a number") as usize])
+        .collect()
+}
+
+/// How deeply the expressions in explanations are nested at most.
+const MAX_DEPTH: usize = 4;
+
+/// The expression for an explanation, with the operands nested deeper than [`MAX_DEPTH`] left
+/// out. Long programs would give long explanations otherwise.
+pub fn short(expr: Expr) -> String {
+    truncate(expr, MAX_DEPTH).pretty()
+}
+
+fn truncate(expr: Expr, depth: usize) -> Expr {
+    let truncate = |e: ExprId| ExprId::new(truncate(e.get(), depth - 1));
+    match expr {
+        Expr::Num(_) | Expr::Var(_) => expr,
+        _ if depth == 0 => Expr::Var("…".into()),
+        Expr::UnaryOp(op, e) => Expr::UnaryOp(op, truncate(e)),
+        Expr::BinaryOp(l, op, r) => Expr::BinaryOp(truncate(l), op, truncate(r)),
+    }
+}
@@ -0,0 +1,134 @@
+use super::{
+    Listing, Pass, PassContext, TransformationLog, code_stage, dead_after, finish, in_progress,
+    read_between,
+};
+use crate::compiler::{CompileOptions, OptLevel, Stage};
+use crate::types::{Inst, Reg};
+use rust_i18n::t;
+
+/// See [`run_immediate_forms`].
+pub struct Immediates;
+
+impl Pass for Immediates {
+    fn name(&self) -> &'static str {
+        "immediates"
+    }
+
+    fn label(&self) -> &'static str {
+        "editor.immediates"
+    }
+
+    fn description(&self) -> &'static str {
+        "editor.immediates.desc"
+    }
+
+    fn position(&self) -> u32 {
+        120
+    }
+
+    fn level(&self) -> Option<OptLevel> {
+        Some(OptLevel::O2)
+    }
+
+    fn flag<'a>(&self, options: &'a mut CompileOptions) -> &'a mut bool {
+        &mut options.use_immediates
+    }
+
+    fn run_code(
+        &self,
+        code: Listing,
+        _cx: &PassContext,
+        stages: &mut Vec<Stage>,
+        log: &mut TransformationLog,
+    ) -> Listing {
+        let code = run_immediate_forms(code, log);
+        stages.push(code_stage("pipeline.immediates", &code));
+        code
+    }
+}
+
+/// Use the immediate forms of arithmetic for numbers stored in a register only to be used once
+///
+/// `store n, x` followed by `add x, y` becomes `addi y, n`, saving the store and a register. This
+/// only works for addition and multiplication: the other operations need the number as their
+/// second operand, the code generator already uses their immediate forms for that.
+pub fn run_immediate_forms<D>(code: Vec<(Inst, D)>, log: &mut TransformationLog) -> Vec<(Inst, D)> {
+    let mut code = in_progress(code);
+    for j in 0..code.len() {
+        let (x, y, immediate): (_, _, fn(Reg, i32) -> Inst) = match code[j].0 {
+            Some(Inst::Add(x, y)) => (x, y, Inst::AddI),
+            Some(Inst::Mul(x, y)) => (x, y, Inst::MulI),
+            _ => continue,
+        };
+        let store = (0..j).rev().find(|&i| {
+            code[i]
+                .0
+                .as_ref()
+                .is_some_and(|inst| inst.writes_register(x))
+        });
+        if x != y
+            && let Some(i) = store
+            && let Some(Inst::Store(n, _)) = code[i].0
+            && !read_between(&code, x, i, j)
+            && dead_after(&code, x, j)
+        {
+            let before = format!(
+                "{}; {}",
+                Inst::Store(n, x),
+                code[j].0.as_ref().expect("it's there")
+            );
+            code[i].0 = None;
+            code[j].0 = Some(immediate(y, n));
+            let after = immediate(y, n).to_string();
+            log.replaced(before, after, t!("explain.immediate", reg = x).into());
+        }
+    }
+    finish(code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::to_text;
+
+    fn immediates(code: &[Inst]) -> Vec<String> {
+        let code = code.iter().map(|i| (i.clone(), ())).collect();
+        run_immediate_forms(code, &mut TransformationLog::default())
+            .iter()
+            .map(|(i, _)| to_text(i))
+            .collect()
+    }
+
+    #[test]
+    fn numbers_used_once_become_immediates() {
+        let code = [
+            Inst::Store(3, Reg(0)),
+            Inst::Transfer("x".into(), Reg(1)),
+            Inst::Mul(Reg(0), Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        assert_eq!(
+            immediates(&code),
+            ["transfer x, b", "muli b, 3", "result b"]
+        );
+
+        // the number is still needed afterwards
+        let code = [
+            Inst::Store(3, Reg(0)),
+            Inst::Transfer("x".into(), Reg(1)),
+            Inst::Add(Reg(0), Reg(1)),
+            Inst::Add(Reg(0), Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        assert_eq!(immediates(&code)[0], "store 3, a");
+
+        // `3 - x` can't be written with `subi`
+        let code = [
+            Inst::Store(3, Reg(0)),
+            Inst::Transfer("x".into(), Reg(1)),
+            Inst::Sub(Reg(0), Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        assert_eq!(immediates(&code).len(), 4);
+    }
+}
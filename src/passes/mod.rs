@@ -1,29 +1,120 @@
-use crate::types::Inst;
+use crate::types::{Inst, Reg};
+use rust_i18n::t;
 use std::collections::HashSet;
 
+mod branchless;
 mod common_factor_elimination;
 mod constant_folding;
+mod egraph;
+mod explanations;
+mod immediates;
+mod peephole;
+mod register_moves;
+mod registry;
+mod rewrite;
 mod shift_replacement;
+mod three_operand;
 
-pub use common_factor_elimination::CommonFactorElimination;
+#[cfg(test)]
 pub use constant_folding::ConstantFold;
+pub use egraph::{CostModel, Saturated, saturate};
+pub use explanations::{TransformationLog, short};
+pub use peephole::{PEEPHOLE_FILE, PeepholeRule, run_peephole_rules};
+pub use registry::{CODE_POSITION, Listing, Pass, PassContext, code_stage, registry};
+pub use rewrite::{RewriteRule, run_rewrite_rules};
+#[cfg(test)]
 pub use shift_replacement::ShiftReplacement;
+pub use three_operand::run_three_operand;
 
 /// Remove cache writes of lines that are never loaded
-pub fn run_cache_optimization(instructions: Vec<Inst>) -> Vec<Inst> {
-    let loaded_lines: HashSet<usize> = instructions
+///
+/// Each instruction may carry extra data (like debug info), which is kept for the remaining ones.
+pub fn run_cache_optimization<D>(
+    code: Vec<(Inst, D)>,
+    log: &mut TransformationLog,
+) -> Vec<(Inst, D)> {
+    let loaded_lines: HashSet<usize> = code
         .iter()
-        .filter_map(|i| match i {
+        .filter_map(|(i, _)| match i {
             Inst::Load(addr, _) => Some(*addr),
             _ => None,
         })
         .collect();
 
-    instructions
-        .into_iter()
-        .filter(|i| match i {
-            Inst::Write(_, target_addr) => loaded_lines.contains(target_addr),
+    code.into_iter()
+        .filter(|(i, _)| match i {
+            Inst::Write(_, addr) if !loaded_lines.contains(addr) => {
+                log.removed(
+                    i.to_string(),
+                    t!("explain.never_loaded", addr = addr).into(),
+                );
+                false
+            }
             _ => true,
         })
         .collect()
 }
+
+/// The instructions of a pass in progress, removed ones are `None`.
+type Code<D> = Vec<(Option<Inst>, D)>;
+
+fn in_progress<D>(code: Vec<(Inst, D)>) -> Code<D> {
+    code.into_iter().map(|(i, d)| (Some(i), d)).collect()
+}
+
+/// The remaining instructions of a finished pass.
+fn finish<D>(code: Code<D>) -> Vec<(Inst, D)> {
+    code.into_iter()
+        .filter_map(|(inst, d)| inst.map(|inst| (inst, d)))
+        .collect()
+}
+
+/// Instructions after `i`, with their positions.
+fn after<D>(code: &Code<D>, i: usize) -> impl Iterator<Item = (usize, &Inst)> {
+    code.iter()
+        .enumerate()
+        .skip(i + 1)
+        .filter_map(|(k, (inst, _))| inst.as_ref().map(|inst| (k, inst)))
+}
+
+fn written_between<D>(code: &Code<D>, r: Reg, i: usize, j: usize) -> bool {
+    after(code, i)
+        .take_while(|(k, _)| *k < j)
+        .any(|(_, inst)| inst.writes_register(r))
+}
+
+fn read_between<D>(code: &Code<D>, r: Reg, i: usize, j: usize) -> bool {
+    after(code, i)
+        .take_while(|(k, _)| *k < j)
+        .any(|(_, inst)| inst.reads_register(r))
+}
+
+fn accessed_between<D>(code: &Code<D>, r: Reg, i: usize, j: usize) -> bool {
+    after(code, i)
+        .take_while(|(k, _)| *k < j)
+        .any(|(_, inst)| inst.reads_register(r) || inst.writes_register(r))
+}
+
+/// Whether the value in the register after instruction `i` is never read.
+fn dead_after<D>(code: &Code<D>, r: Reg, i: usize) -> bool {
+    after(code, i)
+        .find(|(_, inst)| inst.reads_register(r) || inst.writes_register(r))
+        .is_none_or(|(_, inst)| !inst.reads_register(r))
+}
+
+/// Removes copies that are never read, or copy a register into itself.
+fn remove_dead_copies<D>(code: &mut Code<D>) {
+    // removing a copy can make the one it read from unused as well
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in 0..code.len() {
+            if let Some(Inst::Mov(a, b)) = code[i].0
+                && (a == b || dead_after(code, b, i))
+            {
+                code[i].0 = None;
+                changed = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use super::TransformationLog;
+use crate::assembler::parse_operand;
+use crate::files;
+use crate::types::{ISA, Inst, LpErr, Operand, OperandValue};
+use rust_i18n::t;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// File the peephole rules are read from at startup, in the data directory.
+pub const PEEPHOLE_FILE: &str = "peephole.txt";
+
+/// How often the rules are applied to the whole program at most, in case they keep enabling
+/// each other.
+const MAX_ROUNDS: usize = 10;
+
+/// An operand in a rule: a fixed value, or `?name` for any value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OperandPattern {
+    Fixed(OperandValue),
+    Var(String),
+}
+
+/// An instruction in a rule, with the opcode of its kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InstPattern {
+    opcode: u8,
+    operands: Vec<OperandPattern>,
+}
+
+/// A rule for instructions in a row, e.g. `mov ?a, ?b; mov ?b, ?a => mov ?a, ?b`.
+///
+/// Unlike [`super::RewriteRule`]s these aren't checked, whoever writes them has to make sure
+/// they keep the result. Rules work on the two-operand instructions, before they're turned into
+/// three-operand ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeepholeRule {
+    /// The rule as it was written.
+    pub text: String,
+    pattern: Vec<InstPattern>,
+    replacement: Vec<InstPattern>,
+}
+
+impl PeepholeRule {
+    /// Parses `pattern => replacement` (`→` works too), each a list of instructions separated by
+    /// `;`. The replacement may be empty to remove the instructions.
+    pub fn parse(rule: &str) -> Result<Self, LpErr> {
+        let Some((pattern, replacement)) = rule.split_once("=>").or_else(|| rule.split_once('→'))
+        else {
+            return Err(LpErr::Parse(t!("peephole.no_arrow").into()));
+        };
+        let pattern = parse_instructions(pattern)?;
+        let replacement = parse_instructions(replacement)?;
+        if pattern.is_empty() {
+            return Err(LpErr::Parse(t!("peephole.empty").into()));
+        }
+
+        // every variable stands for one kind of operand
+        let mut kinds = HashMap::new();
+        for (inst, var, kind) in variables(&pattern) {
+            if *kinds.entry(var).or_insert(kind) != kind {
+                return Err(LpErr::Parse(
+                    t!("peephole.kind", v = var, inst = inst).into(),
+                ));
+            }
+        }
+        for (inst, var, kind) in variables(&replacement) {
+            match kinds.get(var) {
+                None => return Err(LpErr::Parse(t!("peephole.unbound", v = var).into())),
+                Some(k) if *k != kind => {
+                    return Err(LpErr::Parse(
+                        t!("peephole.kind", v = var, inst = inst).into(),
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(Self {
+            text: rule.trim().to_string(),
+            pattern,
+            replacement,
+        })
+    }
+
+    /// One rule per line, empty lines and lines starting with `#` are skipped. Gives the rules
+    /// that could be read and the errors of the others with their line number.
+    pub fn parse_file(text: &str) -> (Vec<Self>, Vec<String>) {
+        let mut rules = Vec::new();
+        let mut errors = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match Self::parse(line) {
+                Ok(rule) => rules.push(rule),
+                Err(e) => errors.push(t!("assembler.at", line = i + 1, msg = e).to_string()),
+            }
+        }
+        (rules, errors)
+    }
+
+    /// Where [`PEEPHOLE_FILE`] is read from.
+    pub fn path() -> PathBuf {
+        files::data_dir().join(PEEPHOLE_FILE)
+    }
+
+    /// Reads the rules from [`PEEPHOLE_FILE`], there are none without the file.
+    pub fn load() -> (Vec<Self>, Vec<String>) {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse_file(&text),
+            Err(e) if e.kind() == ErrorKind::NotFound => Default::default(),
+            Err(e) => {
+                let error = t!("output.open_failed", file = path.display(), e = e);
+                (Vec::new(), vec![error.into()])
+            }
+        }
+    }
+
+    /// The values of the variables if the instructions match the pattern.
+    fn matches<D>(&self, code: &[(Inst, D)]) -> Option<HashMap<&str, OperandValue>> {
+        let mut bound = HashMap::new();
+        for (pattern, (inst, _)) in self.pattern.iter().zip(code) {
+            if inst.def().opcode != pattern.opcode {
+                return None;
+            }
+            for (operand, value) in pattern.operands.iter().zip(inst.operands()) {
+                match operand {
+                    OperandPattern::Fixed(fixed) if *fixed != value => return None,
+                    OperandPattern::Fixed(_) => {}
+                    OperandPattern::Var(var) => {
+                        if *bound.entry(var.as_str()).or_insert(value.clone()) != value {
+                            return None;
+                        }
+                    }
+                }
+            }
+        }
+        Some(bound)
+    }
+
+    fn replace(&self, bound: &HashMap<&str, OperandValue>) -> Vec<Inst> {
+        self.replacement
+            .iter()
+            .map(|pattern| {
+                let def = def(pattern.opcode);
+                let values = pattern.operands.iter().map(|operand| match operand {
+                    OperandPattern::Fixed(value) => value.clone(),
+                    OperandPattern::Var(var) => bound[var.as_str()].clone(),
+                });
+                Inst::from_operands(def, values).expect("the kinds were checked when parsing")
+            })
+            .collect()
+    }
+}
+
+fn def(opcode: u8) -> &'static crate::types::InstDef {
+    ISA.iter()
+        .find(|def| def.opcode == opcode)
+        .expect("patterns only use opcodes of the ISA")
+}
+
+/// Each variable of the instructions with the instruction's name and the kind of its operand.
+fn variables(code: &[InstPattern]) -> impl Iterator<Item = (&'static str, &str, Operand)> {
+    code.iter().flat_map(|pattern| {
+        let def = def(pattern.opcode);
+        pattern
+            .operands
+            .iter()
+            .zip(def.operands)
+            .filter_map(move |(operand, op_def)| match operand {
+                OperandPattern::Var(var) => Some((def.name, var.as_str(), op_def.kind)),
+                OperandPattern::Fixed(_) => None,
+            })
+    })
+}
+
+/// Instructions like in assembly text, separated by `;`, operands may be variables.
+fn parse_instructions(text: &str) -> Result<Vec<InstPattern>, LpErr> {
+    let err = |msg: String| LpErr::Parse(msg);
+    let mut code = Vec::new();
+    for inst in text.split(';') {
+        let mut words = inst
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|w| !w.is_empty());
+        let Some(name) = words.next() else {
+            continue;
+        };
+        let operands: Vec<_> = words.collect();
+        let def = ISA
+            .iter()
+            .find(|def| def.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| err(t!("assembler.unknown", name = name).to_string()))?;
+        if operands.len() != def.operands.len() {
+            let n = def.operands.len();
+            return Err(err(t!("assembler.operands", name = name, n = n).to_string()));
+        }
+        let operands = def
+            .operands
+            .iter()
+            .zip(operands)
+            .map(|(operand, s)| match s.strip_prefix('?') {
+                Some(var) if !var.is_empty() => Ok(OperandPattern::Var(var.to_string())),
+                _ => parse_operand(operand.kind, s).map(OperandPattern::Fixed),
+            })
+            .collect::<Result<_, _>>()
+            .map_err(err)?;
+        code.push(InstPattern {
+            opcode: def.opcode,
+            operands,
+        });
+    }
+    Ok(code)
+}
+
+/// Replaces instructions in a row that match a rule, the first rule that matches wins.
+///
+/// Replacement instructions carry the extra data (like debug info) of the last instruction they
+/// replace, the one whose result they compute.
+pub fn run_peephole_rules<D: Clone>(
+    code: Vec<(Inst, D)>,
+    rules: &[PeepholeRule],
+    log: &mut TransformationLog,
+) -> Vec<(Inst, D)> {
+    let mut code = code;
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+        let mut out = Vec::with_capacity(code.len());
+        let mut i = 0;
+        while i < code.len() {
+            let found = rules.iter().find_map(|rule| {
+                let len = rule.pattern.len();
+                let window = &code[i..(i + len).min(code.len())];
+                (window.len() == len)
+                    .then(|| rule.matches(window))
+                    .flatten()
+                    .map(|bound| (rule, len, rule.replace(&bound)))
+            });
+            match found {
+                Some((rule, len, replacement)) => {
+                    let before: Vec<_> = (code[i..i + len].iter())
+                        .map(|(inst, _)| inst.to_string())
+                        .collect();
+                    let after: Vec<_> = replacement.iter().map(Inst::to_string).collect();
+                    let reason = t!("explain.peephole", rule = rule.text).into();
+                    match after.is_empty() {
+                        true => log.removed(before.join("; "), reason),
+                        false => log.replaced(before.join("; "), after.join("; "), reason),
+                    }
+                    let data = code[i + len - 1].1.clone();
+                    out.extend(replacement.into_iter().map(|inst| (inst, data.clone())));
+                    i += len;
+                    changed = true;
+                }
+                None => {
+                    out.push(code[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        code = out;
+        if !changed {
+            break;
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::to_text;
+    use crate::types::Reg;
+
+    fn apply(code: &[Inst], rules: &str, log: &mut TransformationLog) -> Vec<String> {
+        let (rules, errors) = PeepholeRule::parse_file(rules);
+        assert!(errors.is_empty(), "{errors:?}");
+        let code = code.iter().map(|i| (i.clone(), ())).collect();
+        run_peephole_rules(code, &rules, log)
+            .iter()
+            .map(|(i, _)| to_text(i))
+            .collect()
+    }
+
+    #[test]
+    fn matching_instructions_are_replaced() {
+        let rules = "# copies back and forth\n\
+                     mov ?a, ?b; mov ?b, ?a => mov ?a, ?b\n\
+                     \n\
+                     muli ?x, 1 =>\n";
+        let code = [
+            Inst::Transfer("x".into(), Reg(0)),
+            Inst::Mov(Reg(0), Reg(1)),
+            Inst::Mov(Reg(1), Reg(0)),
+            Inst::MulI(Reg(1), 1),
+            Inst::MulI(Reg(1), 2),
+            Inst::Mov(Reg(1), Reg(2)),
+            Inst::Mov(Reg(0), Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        let mut log = TransformationLog::default();
+        assert_eq!(
+            apply(&code, rules, &mut log),
+            [
+                "transfer x, a",
+                "mov a, b",
+                "muli b, 2",
+                "mov b, c",
+                "mov a, b",
+                "result b"
+            ]
+        );
+        let explained: Vec<_> = log.entries().iter().map(|e| e.to_string()).collect();
+        assert_eq!(explained.len(), 2);
+        assert!(explained[1].starts_with("removed `"), "{}", explained[1]);
+        assert!(
+            explained[1].ends_with("`muli ?x, 1 =>` matches them"),
+            "{}",
+            explained[1]
+        );
+    }
+
+    #[test]
+    fn broken_rules_are_reported_with_their_line() {
+        let (rules, errors) = PeepholeRule::parse_file(
+            "mov ?a, ?b\n\
+             => mov ?a, ?a\n\
+             store ?n, ?a => mov ?n, ?a\n\
+             mov ?a, ?b => mov ?a, ?c\n\
+             jump ?a =>\n\
+             store 0, ?a; add ?a, ?b => store 0, ?a",
+        );
+        assert_eq!(rules.len(), 1);
+        assert_eq!(errors.len(), 5);
+        assert!(errors[0].starts_with("line 1:"), "{}", errors[0]);
+        assert!(errors[2].contains("?n"), "{}", errors[2]);
+        assert!(errors[3].contains("?c"), "{}", errors[3]);
+    }
+}
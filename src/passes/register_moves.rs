@@ -0,0 +1,240 @@
+use super::{
+    Code, Listing, Pass, PassContext, TransformationLog, accessed_between, after, code_stage,
+    dead_after, finish, in_progress, remove_dead_copies, run_cache_optimization, written_between,
+};
+use crate::compiler::{CompileOptions, OptLevel, Stage};
+use crate::types::{Inst, Reg};
+use rust_i18n::t;
+
+/// Removes writes to RAM that are never loaded, then keeps the values that are in free registers
+/// instead, see [`run_cache_optimization`] and [`run_register_moves`].
+pub struct CacheOptimization;
+
+impl Pass for CacheOptimization {
+    fn name(&self) -> &'static str {
+        "cache"
+    }
+
+    fn label(&self) -> &'static str {
+        "editor.cache_opt"
+    }
+
+    fn description(&self) -> &'static str {
+        "editor.cache_opt.desc"
+    }
+
+    fn position(&self) -> u32 {
+        110
+    }
+
+    fn level(&self) -> Option<OptLevel> {
+        Some(OptLevel::O1)
+    }
+
+    fn flag<'a>(&self, options: &'a mut CompileOptions) -> &'a mut bool {
+        &mut options.run_cache_optimization
+    }
+
+    fn run_code(
+        &self,
+        code: Listing,
+        cx: &PassContext,
+        stages: &mut Vec<Stage>,
+        log: &mut TransformationLog,
+    ) -> Listing {
+        let code = run_cache_optimization(code, log);
+        stages.push(code_stage("pipeline.cache_opt", &code));
+        let code = run_register_moves(code, cx.hw.num_registers, log);
+        stages.push(code_stage("pipeline.moves", &code));
+        code
+    }
+}
+
+/// Replace round trips of values through RAM with copies between registers
+///
+/// A value written to RAM because its register was needed, and loaded once later, stays in the
+/// processor if another register is free from the write until the load. Chains of copies are
+/// collapsed afterwards. Each instruction may carry extra data (like debug info).
+pub fn run_register_moves<D>(
+    code: Vec<(Inst, D)>,
+    num_registers: u8,
+    log: &mut TransformationLog,
+) -> Vec<(Inst, D)> {
+    let mut code = in_progress(code);
+
+    for j in 0..code.len() {
+        let Some(Inst::Load(addr, dest)) = code[j].0 else {
+            continue;
+        };
+        let write = (0..j).rev().find_map(|i| match code[i].0 {
+            Some(Inst::Write(r, a)) if a == addr => Some((i, r)),
+            _ => None,
+        });
+        let Some((i, src)) = write else {
+            continue;
+        };
+        let loads = after(&code, i)
+            .take_while(|(_, inst)| !matches!(inst, Inst::Write(_, a) if *a == addr))
+            .filter(|(_, inst)| matches!(inst, Inst::Load(a, _) if *a == addr))
+            .count();
+        if loads != 1 {
+            continue;
+        }
+        let before = format!("{}; {}", Inst::Write(src, addr), Inst::Load(addr, dest));
+
+        if !written_between(&code, src, i, j) {
+            // the value is still there
+            code[i].0 = None;
+            code[j].0 = Some(Inst::Mov(src, dest));
+            let reason = t!("explain.still_in", reg = src);
+            log.replaced(before, Inst::Mov(src, dest).to_string(), reason.into());
+            continue;
+        }
+        // the destination is the best place to keep it, no copy is needed at the load then
+        let free = std::iter::once(dest)
+            .chain((0..num_registers).map(Reg))
+            .find(|&r| !accessed_between(&code, r, i, j) && dead_after(&code, r, i));
+        let after = match free {
+            Some(r) if r == dest => {
+                code[i].0 = Some(Inst::Mov(src, dest));
+                code[j].0 = None;
+                Inst::Mov(src, dest).to_string()
+            }
+            Some(r) => {
+                code[i].0 = Some(Inst::Mov(src, r));
+                code[j].0 = Some(Inst::Mov(r, dest));
+                format!("{}; {}", Inst::Mov(src, r), Inst::Mov(r, dest))
+            }
+            None => continue,
+        };
+        let reason = t!("explain.kept_in", reg = free.expect("a register was found"));
+        log.replaced(before, after, reason.into());
+    }
+
+    collapse_copies(&mut code, log);
+    finish(code)
+}
+
+/// `mov a, b` and later `mov b, c` copy from `a` directly, as long as it still holds the value.
+/// Copies that are never read are removed.
+fn collapse_copies<D>(code: &mut Code<D>, log: &mut TransformationLog) {
+    for j in 0..code.len() {
+        let Some(Inst::Mov(b, c)) = code[j].0 else {
+            continue;
+        };
+        let source = (0..j).rev().find(|&i| {
+            code[i]
+                .0
+                .as_ref()
+                .is_some_and(|inst| inst.writes_register(b))
+        });
+        if let Some(i) = source
+            && let Some(Inst::Mov(a, _)) = code[i].0
+            && !written_between(code, a, i, j)
+        {
+            code[j].0 = Some(Inst::Mov(a, c));
+            let reason = t!("explain.copy_of", copy = b, reg = a);
+            log.replaced(
+                Inst::Mov(b, c).to_string(),
+                Inst::Mov(a, c).to_string(),
+                reason.into(),
+            );
+        }
+    }
+
+    remove_dead_copies(code);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::{CompileOptions, Compiler, OptLevel};
+    use crate::gui::InterpreterOptions;
+    use crate::interpreter::Interpreter;
+    use std::collections::HashMap;
+
+    fn moves(code: &[Inst], num_registers: u8) -> Vec<String> {
+        let code = code.iter().map(|i| (i.clone(), ())).collect();
+        run_register_moves(code, num_registers, &mut TransformationLog::default())
+            .iter()
+            .map(|(i, _)| crate::assembler::to_text(i))
+            .collect()
+    }
+
+    #[test]
+    fn values_wait_in_free_registers() {
+        let code = [
+            Inst::Store(1, Reg(0)),
+            Inst::Write(Reg(0), 0),
+            Inst::Store(2, Reg(0)),
+            Inst::Load(0, Reg(1)),
+            Inst::Add(Reg(0), Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        // register 1 is free until the load, the value is copied there right away
+        assert_eq!(
+            moves(&code, 2),
+            [
+                "store 1, a",
+                "mov a, b",
+                "store 2, a",
+                "add a, b",
+                "result b"
+            ]
+        );
+
+        // without a free register, the value has to go through RAM
+        let busy = [
+            Inst::Store(1, Reg(0)),
+            Inst::Store(5, Reg(1)),
+            Inst::Write(Reg(0), 0),
+            Inst::Store(2, Reg(0)),
+            Inst::Add(Reg(0), Reg(1)),
+            Inst::Load(0, Reg(0)),
+            Inst::Add(Reg(0), Reg(1)),
+            Inst::Result(Reg(1)),
+        ];
+        let text: Vec<_> = busy.iter().map(crate::assembler::to_text).collect();
+        assert_eq!(moves(&busy, 2), text);
+        assert!(moves(&busy, 3).contains(&"mov a, c".to_string()));
+    }
+
+    #[test]
+    fn copy_chains_collapse() {
+        let code = [
+            Inst::Store(1, Reg(0)),
+            Inst::Mov(Reg(0), Reg(1)),
+            Inst::Mov(Reg(1), Reg(2)),
+            Inst::Mov(Reg(3), Reg(3)),
+            Inst::Result(Reg(2)),
+        ];
+        assert_eq!(moves(&code, 4), ["store 1, a", "mov a, c", "result c"]);
+    }
+
+    #[test]
+    fn moves_keep_the_result() -> Result<(), crate::compiler::LpErr> {
+        let hw = InterpreterOptions {
+            num_registers: 3,
+            ..Default::default()
+        };
+        let source = "(a + 1) * (b + 2) - (a * 3 + b) / ((a - b) * 2 + (b - 1) * 3)";
+        let variables = HashMap::from([("a".to_string(), "7".into()), ("b".into(), "2".into())]);
+        let run = |level| {
+            let options = CompileOptions::default().with_level(level);
+            let ir = Compiler::with(options)
+                .with_interpreter(hw)
+                .compile(source)?;
+            let mut interpreter = Interpreter::with_config(&hw)
+                .load_instructions(ir.instructions.clone())
+                .with_variables(variables.clone())
+                .ready();
+            Ok::<_, crate::compiler::LpErr>((interpreter.run_to_end()?, ir.instructions))
+        };
+        let (expected, unoptimized) = run(OptLevel::O0)?;
+        let (result, optimized) = run(OptLevel::O1)?;
+        assert_eq!(result, expected);
+        let ram = |code: &[Inst]| code.iter().filter(|i| i.uses_ram()).count();
+        assert!(ram(&optimized) < ram(&unoptimized));
+        Ok(())
+    }
+}
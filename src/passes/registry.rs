@@ -0,0 +1,145 @@
+use super::{CostModel, RewriteRule, TransformationLog};
+use crate::compiler::{CompileOptions, OptLevel, Stage};
+use crate::gui::InterpreterOptions;
+use crate::ranges::Hints;
+use crate::source_map::Span;
+use crate::types::{Expr, Inst};
+use std::sync::LazyLock;
+
+/// Instructions with the parts of the source each was generated from.
+pub type Listing = Vec<(Inst, Vec<Span>)>;
+
+/// What passes may depend on besides the program.
+pub struct PassContext<'a> {
+    pub hw: InterpreterOptions,
+    pub hints: &'a Hints,
+    /// The rewrite rules of the visitors, for passes that apply rules themselves.
+    pub rules: &'a [RewriteRule],
+    /// The cycles of the instructions, for passes picking the cheapest of several programs.
+    pub costs: &'a CostModel,
+}
+
+/// Passes from this position on work on the instructions, the ones before on the syntax tree.
+pub const CODE_POSITION: u32 = 100;
+
+/// An optimization pass of the compiler, with everything needed to offer it in the GUI.
+///
+/// Passes on the syntax tree run before the code is generated, passes on the instructions after,
+/// see [`CODE_POSITION`]. Within each, they run in the order of their [`Pass::position`]. Each
+/// pass is turned on by one of the [`CompileOptions`], and the options panel shows a checkbox
+/// for each pass in [`registry`].
+pub trait Pass: Sync {
+    /// Short name, in the same words as the demo script, e.g. for the presenter notes.
+    fn name(&self) -> &'static str;
+    /// Translation keys of the checkbox's label and the longer explanation.
+    fn label(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    /// Where in the pipeline the pass runs, lower ones first.
+    fn position(&self) -> u32;
+    /// Where the pass runs a second time, for passes that clean up after the others. The
+    /// default runs it once.
+    fn rerun(&self) -> Option<u32> {
+        None
+    }
+    /// The lowest optimization level that includes the pass, `None` for passes that don't make
+    /// the program better.
+    fn level(&self) -> Option<OptLevel>;
+    /// The option the pass is turned on with.
+    fn flag<'a>(&self, options: &'a mut CompileOptions) -> &'a mut bool;
+
+    fn enabled(&self, options: &CompileOptions) -> bool {
+        let mut options = *options;
+        *self.flag(&mut options)
+    }
+
+    /// Rewrites the syntax tree, adds the stages to show for it and explains each change in the
+    /// log. The default leaves it alone.
+    fn run_tree(
+        &self,
+        ast: Expr,
+        _cx: &PassContext,
+        _stages: &mut Vec<Stage>,
+        _log: &mut TransformationLog,
+    ) -> Expr {
+        ast
+    }
+
+    /// Rewrites the instructions, like [`Pass::run_tree`]. The default leaves them alone.
+    fn run_code(
+        &self,
+        code: Listing,
+        _cx: &PassContext,
+        _stages: &mut Vec<Stage>,
+        _log: &mut TransformationLog,
+    ) -> Listing {
+        code
+    }
+}
+
+/// Every pass, put here to register it.
+static PASSES: &[&dyn Pass] = &[
+    &super::constant_folding::Folding,
+    &super::common_factor_elimination::CommonFactors,
+    &super::shift_replacement::Shifts,
+    &super::branchless::BranchlessMinMax,
+    &super::egraph::EqualitySaturation,
+    &super::register_moves::CacheOptimization,
+    &super::immediates::Immediates,
+];
+
+static REGISTRY: LazyLock<Vec<&'static dyn Pass>> = LazyLock::new(|| {
+    let mut passes = PASSES.to_vec();
+    passes.sort_by_key(|pass| pass.position());
+    passes
+});
+
+/// All passes, in the order of their position in the pipeline.
+pub fn registry() -> &'static [&'static dyn Pass] {
+    &REGISTRY
+}
+
+/// The stage of instructions after a pass.
+pub fn code_stage(name: &'static str, code: &Listing) -> Stage {
+    Stage::code(
+        name,
+        &code.iter().map(|(i, _)| i.clone()).collect::<Vec<_>>(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn passes_have_their_own_names_and_options() {
+        let passes = registry();
+        let names: HashSet<_> = passes.iter().map(|p| p.name()).collect();
+        assert_eq!(names.len(), passes.len());
+        assert!(
+            passes
+                .windows(2)
+                .all(|w| w[0].position() <= w[1].position())
+        );
+
+        for pass in passes {
+            let mut options = CompileOptions::default();
+            *pass.flag(&mut options) = true;
+            let others = passes.iter().filter(|p| p.enabled(&options)).count();
+            assert_eq!(others, 1, "{} shares its option", pass.name());
+            assert_ne!(rust_i18n::t!(pass.label()), pass.label(), "{}", pass.name());
+            assert_ne!(
+                rust_i18n::t!(pass.description()),
+                pass.description(),
+                "{}",
+                pass.name()
+            );
+        }
+
+        // the highest level has every pass that makes programs better
+        let o2 = CompileOptions::default().with_level(OptLevel::O2);
+        for pass in passes {
+            assert_eq!(pass.enabled(&o2), pass.level().is_some(), "{}", pass.name());
+        }
+    }
+}
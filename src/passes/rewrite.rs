@@ -0,0 +1,285 @@
+use super::{TransformationLog, short};
+use crate::eval::evaluate;
+use crate::generator::Rng;
+use crate::gui::WordSize;
+use crate::intern::Symbol;
+use crate::parser::run_pattern_parser;
+use crate::types::{Expr, ExprId, LpErr, Operator};
+use rust_i18n::t;
+use std::collections::{BTreeSet, HashMap};
+
+/// How often a rule is applied to the whole tree at most, in case rules keep enabling each other
+/// (or themselves, like `?x → ?x + 0`).
+const MAX_ROUNDS: usize = 10;
+/// Number of random inputs a rule is checked against.
+const VALIDATION_INPUTS: usize = 200;
+
+/// A user-defined rewrite rule, e.g. `?x * 2 → ?x + ?x`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewriteRule {
+    /// The rule as it was written.
+    text: String,
+    pub(super) pattern: Expr,
+    pub(super) replacement: Expr,
+    /// Pattern variables the replacement doesn't use. The rule only fires when computing them
+    /// can't fail, otherwise it would remove the error, like `?a * 0 → 0` with `x / 0`.
+    pub(super) dropped: BTreeSet<Symbol>,
+}
+
+impl RewriteRule {
+    /// Parses a rule of the form `pattern → replacement` (`->` works too).
+    pub fn parse(rule: &str) -> Result<Self, LpErr> {
+        let Some((pattern, replacement)) = rule.split_once('→').or_else(|| rule.split_once("->"))
+        else {
+            return Err(LpErr::Parse(t!("rewrite.error.no_arrow").into()));
+        };
+        let pattern = run_pattern_parser(pattern)?;
+        let replacement = run_pattern_parser(replacement)?;
+
+        let bound = pattern_vars(&pattern);
+        if let Some(unbound) = pattern_vars(&replacement).difference(&bound).next() {
+            return Err(LpErr::Parse(
+                t!("rewrite.error.unbound", v = unbound).into(),
+            ));
+        }
+
+        Ok(Self {
+            text: rule.into(),
+            pattern,
+            dropped: &bound - &pattern_vars(&replacement),
+            replacement,
+        })
+    }
+
+    /// The rule parsed again, its expressions may be gone from the arena when it was parsed long
+    /// ago, see [`crate::intern::Arena`].
+    pub fn reparsed(&self) -> Self {
+        Self::parse(&self.text).expect("the rule was parsed before")
+    }
+
+    /// Checks with the reference evaluator that the rule doesn't change results, by trying it on
+    /// random values for the pattern variables with every word size. Some of the variables
+    /// stand for a computation that fails instead, the replacement has to fail then too.
+    pub fn validate(&self) -> Result<(), LpErr> {
+        let vars = pattern_vars(&self.pattern);
+        let mut rng = Rng::with_seed(1);
+        let failing = Expr::BinaryOp(
+            ExprId::new(Expr::Num(1)),
+            Operator::Div,
+            ExprId::new(Expr::Num(0)),
+        );
+        for word in WordSize::ALL {
+            for i in 0..VALIDATION_INPUTS {
+                // start with small numbers, those are the interesting edge cases
+                let bound = if i < VALIDATION_INPUTS / 2 {
+                    16
+                } else {
+                    1 << 20
+                };
+                let inputs: HashMap<Symbol, Expr> = vars
+                    .iter()
+                    .map(|&v| {
+                        let input = match rng.range(0, 9) {
+                            // the rule doesn't fire then, see `RewriteRule::dropped`
+                            0 if !self.dropped.contains(&v) => failing,
+                            1 => Expr::Num(word.min()),
+                            2 => Expr::Num(word.max()),
+                            _ => Expr::Num(rng.range(-bound, bound)),
+                        };
+                        (v, input)
+                    })
+                    .collect();
+
+                let run = |expr| evaluate(&substitute(expr, &inputs), &HashMap::new(), word);
+                let (expected, actual) = (run(&self.pattern), run(&self.replacement));
+                let same = match (&expected, &actual) {
+                    (Ok(expected), Ok(actual)) => expected == actual,
+                    (Err(_), Err(_)) => true,
+                    _ => false,
+                };
+                if !same {
+                    let inputs = vars
+                        .iter()
+                        .map(|v| format!("{v} = {}", inputs[v].pretty()))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let shown = |res: Result<i32, LpErr>| {
+                        res.map_or_else(|e| e.to_string(), |n| n.to_string())
+                    };
+                    return Err(LpErr::Interpret(
+                        t!(
+                            "rewrite.error.unsound",
+                            inputs = inputs,
+                            expected = shown(expected),
+                            actual = shown(actual)
+                        )
+                        .into(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites `expr` if it matches the pattern.
+    fn apply(&self, expr: &Expr) -> Option<Expr> {
+        let mut bindings = HashMap::new();
+        let matched = match_pattern(&self.pattern, expr, &mut bindings)
+            && !self.dropped.iter().any(|v| may_fail(&bindings[v]));
+        matched.then(|| substitute(&self.replacement, &bindings))
+    }
+}
+
+/// Applies the rules bottom-up until nothing changes anymore (or we give up).
+pub fn run_rewrite_rules(
+    mut expr: Expr,
+    rules: &[RewriteRule],
+    log: &mut TransformationLog,
+) -> Expr {
+    for _ in 0..MAX_ROUNDS {
+        let next = rewrite(&expr, rules, log);
+        if next == expr {
+            break;
+        }
+        expr = next;
+    }
+    expr
+}
+
+fn rewrite(expr: &Expr, rules: &[RewriteRule], log: &mut TransformationLog) -> Expr {
+    let expr = match expr {
+        Expr::Num(_) | Expr::Var(_) => *expr,
+        Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, ExprId::new(rewrite(&e.get(), rules, log))),
+        Expr::BinaryOp(l, op, r) => Expr::BinaryOp(
+            ExprId::new(rewrite(&l.get(), rules, log)),
+            *op,
+            ExprId::new(rewrite(&r.get(), rules, log)),
+        ),
+    };
+    let applied = rules
+        .iter()
+        .find_map(|rule| Some((rule, rule.apply(&expr)?)));
+    match applied {
+        Some((rule, res)) => {
+            let rule = format!("{} → {}", rule.pattern.pretty(), rule.replacement.pretty());
+            log.replaced(
+                short(expr),
+                short(res),
+                t!("explain.rule", rule = rule).into(),
+            );
+            res
+        }
+        None => expr,
+    }
+}
+
+/// Whether computing the expression can stop the program, see [`Operator::can_fail`].
+fn may_fail(expr: &Expr) -> bool {
+    match expr {
+        Expr::Num(_) | Expr::Var(_) => false,
+        Expr::UnaryOp(_, e) => may_fail(&e.get()),
+        Expr::BinaryOp(l, op, r) => op.can_fail() || may_fail(&l.get()) || may_fail(&r.get()),
+    }
+}
+
+pub(super) fn is_pattern_var(name: &str) -> bool {
+    name.starts_with('?')
+}
+
+fn pattern_vars(expr: &Expr) -> BTreeSet<Symbol> {
+    match expr {
+        Expr::Var(v) if is_pattern_var(v) => BTreeSet::from([*v]),
+        Expr::Num(_) | Expr::Var(_) => BTreeSet::new(),
+        Expr::UnaryOp(_, e) => pattern_vars(&e.get()),
+        Expr::BinaryOp(l, _, r) => &pattern_vars(&l.get()) | &pattern_vars(&r.get()),
+    }
+}
+
+fn match_pattern(pattern: &Expr, expr: &Expr, bindings: &mut HashMap<Symbol, Expr>) -> bool {
+    match (pattern, expr) {
+        (Expr::Var(v), _) if is_pattern_var(v) => match bindings.get(v) {
+            // a variable used twice must match the same expression twice
+            Some(bound) => bound == expr,
+            None => {
+                bindings.insert(*v, *expr);
+                true
+            }
+        },
+        (Expr::Num(a), Expr::Num(b)) => a == b,
+        (Expr::Var(a), Expr::Var(b)) => a == b,
+        (Expr::UnaryOp(op1, p), Expr::UnaryOp(op2, e)) => {
+            op1 == op2 && match_pattern(&p.get(), &e.get(), bindings)
+        }
+        (Expr::BinaryOp(pl, op1, pr), Expr::BinaryOp(el, op2, er)) => {
+            op1 == op2
+                && match_pattern(&pl.get(), &el.get(), bindings)
+                && match_pattern(&pr.get(), &er.get(), bindings)
+        }
+        _ => false,
+    }
+}
+
+fn substitute(replacement: &Expr, bindings: &HashMap<Symbol, Expr>) -> Expr {
+    match replacement {
+        Expr::Var(v) if is_pattern_var(v) => bindings[v],
+        Expr::Num(_) | Expr::Var(_) => *replacement,
+        Expr::UnaryOp(op, e) => Expr::UnaryOp(*op, ExprId::new(substitute(&e.get(), bindings))),
+        Expr::BinaryOp(l, op, r) => Expr::BinaryOp(
+            ExprId::new(substitute(&l.get(), bindings)),
+            *op,
+            ExprId::new(substitute(&r.get(), bindings)),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::run_parser;
+
+    #[test]
+    fn rewrite_mul_to_add() -> Result<(), LpErr> {
+        let rule = RewriteRule::parse("?x * 2 → ?x + ?x")?;
+        rule.validate()?;
+
+        let mut log = TransformationLog::default();
+        let expr = run_rewrite_rules(run_parser("(a - b) * 2")?, &[rule], &mut log);
+        assert_eq!(expr, run_parser("(a - b) + (a - b)")?);
+        let explained: Vec<_> = log.entries().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            explained,
+            [
+                "replaced `(a - b) * 2` with `a - b + (a - b)` because your rule `?x * 2 → ?x + ?x` matches it"
+            ]
+        );
+
+        // shifts are the compiler's business
+        assert!(RewriteRule::parse("?x * 2 → ?x << 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unsound_rule() -> Result<(), LpErr> {
+        let rule = RewriteRule::parse("?x / 2 -> ?x - ?x / 2")?;
+        assert!(rule.validate().is_err());
+        // fine for every number, but not when `?x` is 0
+        let rule = RewriteRule::parse("?x / ?x -> 1")?;
+        assert!(rule.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rules_keep_errors() -> Result<(), LpErr> {
+        let rule = RewriteRule::parse("?x * 0 → 0")?;
+        rule.validate()?;
+        let rules = [rule];
+        let mut log = TransformationLog::default();
+        assert_eq!(
+            run_rewrite_rules(run_parser("(a + 1) * 0")?, &rules, &mut log),
+            Expr::Num(0)
+        );
+        let divides = run_parser("(a / b) * 0")?;
+        assert_eq!(run_rewrite_rules(divides, &rules, &mut log), divides);
+        Ok(())
+    }
+}
@@ -1,58 +1,122 @@
-use crate::types::{Expr, Operator};
+use super::explanations::{short, superscript};
+use super::{Pass, PassContext, TransformationLog};
+use crate::compiler::{CompileOptions, OptLevel, Stage};
+use crate::gui::WordSize;
+use crate::ranges::{Hints, Ranges};
+use crate::types::{Expr, ExprId, Operator};
+use rust_i18n::t;
 
 pub trait ShiftReplacement {
-    fn replace_multiplications_with_bitshifts(self) -> Self;
+    /// The hints tell which variables are never negative, so divisions of them can be shifted.
+    /// The ranges are those of words of the given size.
+    fn replace_multiplications_with_bitshifts(
+        self,
+        hints: &Hints,
+        word: WordSize,
+        log: &mut TransformationLog,
+    ) -> Self;
+}
+
+/// Multiplies and divides by powers of two with shifts. The value ranges it relies on are shown
+/// as a stage of their own.
+pub struct Shifts;
+
+impl Pass for Shifts {
+    fn name(&self) -> &'static str {
+        "shifts"
+    }
+
+    fn label(&self) -> &'static str {
+        "editor.replace_mul_with_shift"
+    }
+
+    fn description(&self) -> &'static str {
+        "editor.replace_mul_with_shift.desc"
+    }
+
+    fn position(&self) -> u32 {
+        40
+    }
+
+    fn level(&self) -> Option<OptLevel> {
+        Some(OptLevel::O2)
+    }
+
+    fn flag<'a>(&self, options: &'a mut CompileOptions) -> &'a mut bool {
+        &mut options.do_shift_replacement
+    }
+
+    fn run_tree(
+        &self,
+        ast: Expr,
+        cx: &PassContext,
+        stages: &mut Vec<Stage>,
+        log: &mut TransformationLog,
+    ) -> Expr {
+        stages.push(Stage {
+            name: "pipeline.ranges",
+            lines: Ranges::describe(ast, cx.hints, cx.hw.word_size),
+        });
+        let ast = ast.replace_multiplications_with_bitshifts(cx.hints, cx.hw.word_size, log);
+        stages.push(Stage::expr("pipeline.shifted", &ast));
+        ast
+    }
 }
 
 impl ShiftReplacement for Expr {
-    fn replace_multiplications_with_bitshifts(self) -> Self {
-        match self {
-            Expr::Num(_) | Expr::Var(_) => self,
-            Expr::UnaryOp(op, expr) => {
-                Expr::UnaryOp(op, Box::new(expr.replace_multiplications_with_bitshifts()))
-            }
-            Expr::BinaryOp(
-                lhs,
-                o @ (Operator::Add | Operator::Sub | Operator::Shl | Operator::Shr),
-                rhs,
-            ) => Expr::BinaryOp(
-                Box::new(lhs.replace_multiplications_with_bitshifts()),
-                o,
-                Box::new(rhs.replace_multiplications_with_bitshifts()),
-            ),
-            Expr::BinaryOp(left, op @ (Operator::Mul | Operator::Div), right) => {
-                // this is actually a neat trick since 1000 & 0111 == 0 and that holds true for all powers of 2
-                if let &Expr::Num(lhs) = left.as_ref()
-                    && (lhs & (lhs - 1)) == 0 && !matches!(op, Operator::Div)
-                {
-                    // mul only
-                    Expr::BinaryOp(
-                        right,
-                        Operator::Shl,
-                        Box::new(Expr::Num(lhs.ilog2() as i32)),
-                    )
-                } else if let &Expr::Num(rhs) = right.as_ref()
-                    && (rhs & (rhs - 1)) == 0
-                {
-                    // mul + div
-                    Expr::BinaryOp(
-                        Box::new(left.replace_multiplications_with_bitshifts()),
-                        match op {
-                            Operator::Mul => Operator::Shl,
-                            Operator::Div => Operator::Shr,
-                            _ => unreachable!(),
-                        },
-                        Box::new(Expr::Num(rhs.ilog2() as i32)),
-                    )
-                } else {
-                    // mul + div
-                    Expr::BinaryOp(
-                        Box::new(left.replace_multiplications_with_bitshifts()),
-                        op,
-                        Box::new(right.replace_multiplications_with_bitshifts()),
-                    )
-                }
-            }
-        }
+    fn replace_multiplications_with_bitshifts(
+        self,
+        hints: &Hints,
+        word: WordSize,
+        log: &mut TransformationLog,
+    ) -> Self {
+        let mut ranges = Ranges::with_hints(hints, word);
+        self.rebuild_bottom_up(|expr| {
+            let expr = replace(expr, &ranges, log);
+            ranges.add(expr);
+            expr
+        })
     }
 }
+
+/// Replaces a single multiplication or division whose operands were already handled.
+///
+/// Shifting right rounds down, but division rounds towards zero: `-7 / 8` is 0, `-7 >> 3` is
+/// -1. So divisions are only replaced if the [`Ranges`] show the dividend is never negative.
+fn replace(expr: Expr, ranges: &Ranges, log: &mut TransformationLog) -> Expr {
+    // this is actually a neat trick since 1000 & 0111 == 0 and that holds true for all powers of 2
+    let power_of_two = |e: ExprId| match e.get() {
+        Expr::Num(n) if n > 0 && (n & (n - 1)) == 0 => Some((n, n.ilog2())),
+        _ => None,
+    };
+    let shift = |value: ExprId, op, (_, k): (i32, u32)| {
+        Expr::BinaryOp(value, op, ExprId::new(Expr::Num(k as i32)))
+    };
+    let power = |(n, k): (i32, u32)| format!("{n} = 2{}", superscript(k));
+    let (res, reason) = match expr {
+        Expr::BinaryOp(left, Operator::Mul, right) => {
+            let Some((value, factor)) = power_of_two(left)
+                .map(|p| (right, p))
+                .or(power_of_two(right).map(|p| (left, p)))
+            else {
+                return expr;
+            };
+            let reason = t!("explain.shift_mul", power = power(factor));
+            (shift(value, Operator::Shl, factor), reason)
+        }
+        Expr::BinaryOp(left, Operator::Div, right) => match power_of_two(right) {
+            Some(divisor) if ranges.get(&left.get()).is_non_negative() => {
+                let reason = t!(
+                    "explain.shift_div",
+                    power = power(divisor),
+                    value = short(left.get())
+                );
+                (shift(left, Operator::Shr, divisor), reason)
+            }
+            _ => return expr,
+        },
+        _ => return expr,
+    };
+    log.replaced(short(expr), short(res), reason.into());
+    res
+}
@@ -0,0 +1,161 @@
+use super::TransformationLog;
+use super::{
+    accessed_between, after, dead_after, finish, in_progress, remove_dead_copies, written_between,
+};
+use crate::types::{Inst, Reg};
+use rust_i18n::t;
+
+/// Builds a three-operand instruction from its operands and destination.
+type Ternary = fn(Reg, Reg, Reg) -> Inst;
+
+/// Write arithmetic with explicit destinations, for the three-operand [`IsaFlavor`]
+///
+/// `add a, b` becomes `add3 a, b, b`. Copies are folded into the operations afterwards: a result
+/// that's copied right away is put into the copy's register directly, and operands are read from
+/// where a copy came from.
+///
+/// [`IsaFlavor`]: crate::gui::IsaFlavor
+pub fn run_three_operand<D>(code: Vec<(Inst, D)>, log: &mut TransformationLog) -> Vec<(Inst, D)> {
+    let mut code = in_progress(code);
+    for (inst, _) in &mut code {
+        *inst = inst.take().map(three_operand);
+    }
+
+    for j in 0..code.len() {
+        let Some((a, b, t, make)) = code[j].0.as_ref().and_then(parts) else {
+            continue;
+        };
+        let next =
+            after(&code, j).find(|(_, inst)| inst.reads_register(t) || inst.writes_register(t));
+        if let Some((k, &Inst::Mov(_, c))) = next
+            && c != t
+            && dead_after(&code, t, k)
+            && !accessed_between(&code, c, j, k)
+        {
+            let folded = make(a, b, c);
+            let before = format!("{}; {}", make(a, b, t), Inst::Mov(t, c));
+            let reason = t!("explain.into_copy", reg = c).into();
+            log.replaced(before, folded.to_string(), reason);
+            code[j].0 = Some(folded);
+            code[k].0 = None;
+        }
+    }
+
+    for j in 0..code.len() {
+        let Some((a, b, c, make)) = code[j].0.as_ref().and_then(parts) else {
+            continue;
+        };
+        let source = |r: Reg| {
+            let i = (0..j).rev().find(|&i| {
+                code[i]
+                    .0
+                    .as_ref()
+                    .is_some_and(|inst| inst.writes_register(r))
+            });
+            match i.and_then(|i| code[i].0.as_ref().map(|inst| (i, inst))) {
+                Some((i, Inst::Mov(x, _))) if !written_between(&code, *x, i, j) => *x,
+                _ => r,
+            }
+        };
+        let (x, y) = (source(a), source(b));
+        if (x, y) != (a, b) {
+            let (copy, reg) = if x != a { (a, x) } else { (b, y) };
+            let after = make(x, y, c);
+            let reason = t!("explain.copy_of", copy = copy, reg = reg).into();
+            log.replaced(make(a, b, c).to_string(), after.to_string(), reason);
+        }
+        code[j].0 = Some(make(x, y, c));
+    }
+
+    remove_dead_copies(&mut code);
+    finish(code)
+}
+
+fn three_operand(inst: Inst) -> Inst {
+    match inst {
+        Inst::Add(a, b) => Inst::Add3(a, b, b),
+        Inst::Sub(a, b) => Inst::Sub3(a, b, b),
+        Inst::Mul(a, b) => Inst::Mul3(a, b, b),
+        Inst::Div(a, b) => Inst::Div3(a, b, b),
+        Inst::Shl(a, b) => Inst::Shl3(a, b, b),
+        Inst::Shr(a, b) => Inst::Shr3(a, b, b),
+        Inst::Min(a, b) => Inst::Min3(a, b, b),
+        Inst::Max(a, b) => Inst::Max3(a, b, b),
+        other => other,
+    }
+}
+
+/// The operands and destination of a three-operand instruction.
+fn parts(inst: &Inst) -> Option<(Reg, Reg, Reg, Ternary)> {
+    let (a, b, c, make): (_, _, _, Ternary) = match *inst {
+        Inst::Add3(a, b, c) => (a, b, c, Inst::Add3),
+        Inst::Sub3(a, b, c) => (a, b, c, Inst::Sub3),
+        Inst::Mul3(a, b, c) => (a, b, c, Inst::Mul3),
+        Inst::Div3(a, b, c) => (a, b, c, Inst::Div3),
+        Inst::Shl3(a, b, c) => (a, b, c, Inst::Shl3),
+        Inst::Shr3(a, b, c) => (a, b, c, Inst::Shr3),
+        Inst::Min3(a, b, c) => (a, b, c, Inst::Min3),
+        Inst::Max3(a, b, c) => (a, b, c, Inst::Max3),
+        _ => return None,
+    };
+    Some((a, b, c, make))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::to_text;
+
+    fn three(code: &[Inst]) -> Vec<String> {
+        let code = code.iter().map(|i| (i.clone(), ())).collect();
+        run_three_operand(code, &mut TransformationLog::default())
+            .iter()
+            .map(|(i, _)| to_text(i))
+            .collect()
+    }
+
+    #[test]
+    fn copies_are_folded_into_the_operations() {
+        let code = [
+            Inst::Transfer("x".into(), Reg(0)),
+            Inst::Store(2, Reg(1)),
+            Inst::Mov(Reg(0), Reg(2)),
+            Inst::Mul(Reg(1), Reg(2)),
+            Inst::Mov(Reg(2), Reg(3)),
+            Inst::Add(Reg(0), Reg(3)),
+            Inst::Result(Reg(3)),
+        ];
+        assert_eq!(
+            three(&code),
+            [
+                "transfer x, a",
+                "store 2, b",
+                "mul3 b, a, d",
+                "add3 a, d, d",
+                "result d"
+            ]
+        );
+    }
+
+    #[test]
+    fn min_and_max_get_three_operands() {
+        let code = [
+            Inst::Transfer("x".into(), Reg(0)),
+            Inst::Store(2, Reg(1)),
+            Inst::Mov(Reg(0), Reg(2)),
+            Inst::Max(Reg(1), Reg(2)),
+            Inst::Min(Reg(0), Reg(2)),
+            Inst::Result(Reg(2)),
+        ];
+        assert_eq!(
+            three(&code),
+            [
+                "transfer x, a",
+                "store 2, b",
+                "max3 b, a, c",
+                "min3 a, c, c",
+                "result c"
+            ]
+        );
+    }
+}
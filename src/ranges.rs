@@ -0,0 +1,316 @@
+//! Value range analysis: the smallest and largest value each sub-expression can have, from the
+//! numbers in it and what `min`, `max` and the saturating builtins guarantee. Passes use it to
+//! only apply rewrites that are correct for every input, e.g. a division by 8 is only a shift
+//! for non-negative numbers.
+//!
+//! The ranges follow the semantics of [`crate::eval::evaluate`] with the machine's word size. A
+//! result that can wrap around could be any number that fits into a word. Nothing is known
+//! about input variables, unless the user gives [`Hints`] about them.
+
+use crate::gui::WordSize;
+use crate::intern::Symbol;
+use crate::types::{Expr, Operator};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+/// All values from `min` to `max`, both included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueRange {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl ValueRange {
+    /// Zero or larger.
+    pub const NON_NEGATIVE: ValueRange = ValueRange {
+        min: 0,
+        max: i32::MAX,
+    };
+
+    /// Nothing is known about the value, other than that it fits into a word.
+    pub fn any(word: WordSize) -> Self {
+        ValueRange {
+            min: word.min(),
+            max: word.max(),
+        }
+    }
+
+    pub fn exactly(n: i32) -> Self {
+        ValueRange { min: n, max: n }
+    }
+
+    pub fn is_non_negative(&self) -> bool {
+        self.min >= 0
+    }
+
+    pub fn contains(&self, n: i32) -> bool {
+        (self.min..=self.max).contains(&n)
+    }
+
+    /// The range of a wrapping result, any number if it doesn't fit into a word.
+    fn wrapping(min: i64, max: i64, word: WordSize) -> Self {
+        let fits = i64::from(word.min())..=i64::from(word.max());
+        if fits.contains(&min) && fits.contains(&max) {
+            ValueRange {
+                min: min as i32,
+                max: max as i32,
+            }
+        } else {
+            ValueRange::any(word)
+        }
+    }
+
+    /// The range of a result that's cut off at the limits of a word instead of wrapping.
+    fn saturating(min: i64, max: i64, word: WordSize) -> Self {
+        let clamp = |n: i64| n.clamp(word.min().into(), word.max().into()) as i32;
+        ValueRange {
+            min: clamp(min),
+            max: clamp(max),
+        }
+    }
+
+    /// Smallest and largest result of `f` for the ends of the ranges, which are the extremes
+    /// for operations that only ever increase or decrease with each operand.
+    fn corners(self, other: Self, f: fn(i64, i64) -> i64) -> (i64, i64) {
+        let (a, b) = (i64::from(self.min), i64::from(self.max));
+        let (c, d) = (i64::from(other.min), i64::from(other.max));
+        let values = [f(a, c), f(a, d), f(b, c), f(b, d)];
+        (
+            values.into_iter().min().expect("there are four"),
+            values.into_iter().max().expect("there are four"),
+        )
+    }
+
+    /// The range of `l op r` with words of the given size.
+    pub fn of_operation(l: Self, op: Operator, r: Self, word: WordSize) -> Self {
+        let shift = r.min >= 0 && r.max < 32;
+        let divisor = r.min > 0 || r.max < 0;
+        let (min, max) = match op.base() {
+            Operator::Add => (
+                i64::from(l.min) + i64::from(r.min),
+                i64::from(l.max) + i64::from(r.max),
+            ),
+            Operator::Sub => (
+                i64::from(l.min) - i64::from(r.max),
+                i64::from(l.max) - i64::from(r.min),
+            ),
+            Operator::Mul => l.corners(r, |a, b| a * b),
+            Operator::Div if divisor => l.corners(r, |a, b| a / b),
+            Operator::Shl if shift => l.corners(r, |a, b| a << b),
+            Operator::Shr if shift => l.corners(r, |a, b| a >> b),
+            Operator::Min => {
+                return ValueRange {
+                    min: l.min.min(r.min),
+                    max: l.max.min(r.max),
+                };
+            }
+            Operator::Max => {
+                return ValueRange {
+                    min: l.min.max(r.min),
+                    max: l.max.max(r.max),
+                };
+            }
+            _ => return ValueRange::any(word),
+        };
+        match op {
+            // checked operations stop the program instead of giving a result that doesn't fit
+            Operator::SatAdd
+            | Operator::SatSub
+            | Operator::CheckedAdd
+            | Operator::CheckedSub
+            | Operator::CheckedMul
+            | Operator::CheckedDiv => ValueRange::saturating(min, max, word),
+            _ => ValueRange::wrapping(min, max, word),
+        }
+    }
+}
+
+impl Display for ValueRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ValueRange { min, max } if min == max => write!(f, "{min}"),
+            ValueRange { min, max } => write!(f, "{min}..={max}"),
+        }
+    }
+}
+
+/// What the user promises about the values of input variables. Programs compiled with hints
+/// may give wrong results for inputs outside of them.
+pub type Hints = HashMap<String, ValueRange>;
+
+/// Value ranges of the sub-expressions of a tree, filled in from the leaves up.
+#[derive(Debug, Default)]
+pub struct Ranges {
+    ranges: HashMap<Expr, ValueRange>,
+    word: WordSize,
+}
+
+impl Ranges {
+    /// Starts out knowing the ranges of the hinted variables, as far as they fit into a word.
+    pub fn with_hints(hints: &Hints, word: WordSize) -> Self {
+        let any = ValueRange::any(word);
+        let vars = hints.iter().map(|(var, range)| {
+            let range = ValueRange {
+                min: range.min.max(any.min),
+                max: range.max.min(any.max),
+            };
+            let range = if range.min <= range.max { range } else { any };
+            (Expr::Var(Symbol::new(var)), range)
+        });
+        Ranges {
+            ranges: vars.collect(),
+            word,
+        }
+    }
+
+    /// Computes the range of an expression whose operands were added before, and returns it.
+    pub fn add(&mut self, expr: Expr) -> ValueRange {
+        let range = match expr {
+            Expr::Num(_) | Expr::Var(_) => self.get(&expr),
+            Expr::UnaryOp(_, e) => {
+                let e = self.get(&e.get());
+                ValueRange::wrapping(-i64::from(e.max), -i64::from(e.min), self.word)
+            }
+            Expr::BinaryOp(l, op, r) => {
+                ValueRange::of_operation(self.get(&l.get()), op, self.get(&r.get()), self.word)
+            }
+        };
+        self.ranges.insert(expr, range);
+        range
+    }
+
+    /// The range of the expression, anything if it wasn't added.
+    pub fn get(&self, expr: &Expr) -> ValueRange {
+        match expr {
+            Expr::Num(n) => ValueRange::exactly(self.word.wrap(*n)),
+            _ => (self.ranges.get(expr).copied()).unwrap_or(ValueRange::any(self.word)),
+        }
+    }
+
+    /// One line per sub-expression that isn't a number, from the leaves up, e.g. `x + 1: 1..=10`.
+    /// Ranges that could be anything are a `?`.
+    pub fn describe(expr: Expr, hints: &Hints, word: WordSize) -> Vec<String> {
+        let mut ranges = Ranges::with_hints(hints, word);
+        let mut listed = HashSet::new();
+        let mut lines = Vec::new();
+        expr.rebuild_bottom_up(|e| {
+            let range = ranges.add(e);
+            if !matches!(e, Expr::Num(_)) && listed.insert(e) {
+                let range = if range == ValueRange::any(word) {
+                    "?".to_string()
+                } else {
+                    range.to_string()
+                };
+                lines.push(format!("{}: {range}", e.pretty()));
+            }
+            e
+        });
+        lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::run_parser;
+
+    fn range(source: &str) -> ValueRange {
+        range_with(source, WordSize::W32)
+    }
+
+    fn range_with(source: &str, word: WordSize) -> ValueRange {
+        let mut ranges = Ranges::with_hints(&Hints::new(), word);
+        run_parser(source).unwrap().rebuild_bottom_up(|e| {
+            ranges.add(e);
+            e
+        });
+        ranges.get(&run_parser(source).unwrap())
+    }
+
+    #[test]
+    fn ranges_follow_the_operations() {
+        assert_eq!(range("3 * 4 - 2"), ValueRange::exactly(10));
+        assert_eq!(
+            range("clamp(x, 0, 100) / 4 + 1"),
+            ValueRange { min: 1, max: 26 }
+        );
+        assert_eq!(
+            range("-max(x, -5)"),
+            ValueRange {
+                min: i32::MIN + 1,
+                max: 5
+            }
+        );
+        assert_eq!(
+            range("max(x, 0) >> 1"),
+            ValueRange {
+                min: 0,
+                max: i32::MAX >> 1
+            }
+        );
+        // results that may wrap around could be anything
+        assert_eq!(range("max(x, 0) + 1"), ValueRange::any(WordSize::W32));
+        assert_eq!(range("x / y"), ValueRange::any(WordSize::W32));
+        // unless they stop at the limits
+        assert_eq!(
+            range("sat_add(max(x, 0), 1)"),
+            ValueRange {
+                min: 1,
+                max: i32::MAX
+            }
+        );
+    }
+
+    #[test]
+    fn ranges_are_those_of_the_word_size() {
+        let w8 = |source| range_with(source, WordSize::W8);
+        assert_eq!(
+            w8("clamp(x, 0, 100) + 20"),
+            ValueRange { min: 20, max: 120 }
+        );
+        assert_eq!(w8("clamp(x, 0, 100) + 30"), ValueRange::any(WordSize::W8));
+        assert_eq!(w8("sat_add(max(x, 0), 1)"), ValueRange { min: 1, max: 127 });
+        assert_eq!(
+            w8("x"),
+            ValueRange {
+                min: -128,
+                max: 127
+            }
+        );
+        // the number is wrapped when it's stored
+        assert_eq!(w8("200"), ValueRange::exactly(-56));
+        let hints = Hints::from([("x".to_string(), ValueRange::NON_NEGATIVE)]);
+        assert_eq!(
+            Ranges::describe(run_parser("x").unwrap(), &hints, WordSize::W16),
+            ["x: 0..=32767"]
+        );
+    }
+
+    #[test]
+    fn only_non_negative_divisions_are_shifted() {
+        use crate::passes::{ShiftReplacement, TransformationLog};
+        let shifted = |source: &str| {
+            let expr = run_parser(source).unwrap();
+            expr.replace_multiplications_with_bitshifts(
+                &Hints::new(),
+                WordSize::W32,
+                &mut TransformationLog::default(),
+            )
+            .pretty()
+        };
+        assert_eq!(shifted("x / 8"), "x / 8");
+        assert_eq!(shifted("max(x, 0) / 8"), "max(x, 0) >> 3");
+        assert_eq!(
+            shifted("clamp(x, 0, 9) * 4 / 2"),
+            "min(max(x, 0), 9) << 2 >> 1"
+        );
+        assert_eq!(
+            Ranges::describe(
+                run_parser("max(x, 1) + 2").unwrap(),
+                &Hints::new(),
+                WordSize::W32
+            ),
+            ["x: ?", "max(x, 1): 1..=2147483647", "max(x, 1) + 2: ?"]
+        );
+    }
+}
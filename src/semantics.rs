@@ -0,0 +1,331 @@
+use crate::interpreter::Interpreter;
+use crate::types::{Inst, MemAddr, Operator, Reg};
+use rust_i18n::t;
+use std::fmt::{Display, Formatter};
+
+/// Something an instruction reads or writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Reg(Reg),
+    Num(i32),
+    Var(String),
+    Ram(MemAddr),
+    /// The top of the stack, read by a pop.
+    StackTop,
+    /// The free slot below the top of the stack, written by a push.
+    StackNext,
+    /// The result of the program.
+    Result,
+    /// The overflow flag, set by arithmetic.
+    Overflow,
+    /// No value at all, which is what a program that halts gives.
+    Nothing,
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operand::Reg(r) => write!(f, "{r}"),
+            Operand::Num(n) => write!(f, "{n}"),
+            Operand::Var(v) => write!(f, "{v}"),
+            Operand::Ram(addr) => write!(f, "RAM[{addr}]"),
+            Operand::StackTop => write!(f, "RAM[SP]"),
+            Operand::StackNext => write!(f, "RAM[SP-1]"),
+            Operand::Result => f.write_str(&t!("semantics.result")),
+            Operand::Overflow => write!(f, "V"),
+            Operand::Nothing => f.write_str(&t!("semantics.nothing")),
+        }
+    }
+}
+
+impl Operand {
+    /// Current value in the interpreter, if there is one.
+    fn value(&self, interpreter: &Interpreter) -> Option<i32> {
+        match self {
+            Operand::Reg(r) => interpreter.register(*r),
+            Operand::Num(n) => Some(*n),
+            Operand::Var(v) => interpreter.input(v),
+            Operand::Ram(addr) => interpreter.ram.get(*addr).copied(),
+            Operand::StackTop => interpreter.ram.get(interpreter.stack_pointer()).copied(),
+            Operand::Overflow => Some(interpreter.overflow().into()),
+            Operand::StackNext | Operand::Result | Operand::Nothing => None,
+        }
+    }
+}
+
+/// What an instruction does: `dest ← sources[0] op sources[1]`, or just a copy of the single
+/// source if there's no operator.
+pub struct Semantics {
+    pub mnemonic: &'static str,
+    /// The operands as written in the assembly.
+    pub args: Vec<Operand>,
+    pub dest: Operand,
+    pub sources: Vec<Operand>,
+    pub op: Option<Operator>,
+}
+
+/// One of the small steps the processor takes for an instruction, see [`Semantics::micro_ops`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MicroOp {
+    /// Getting an operand to the ALU.
+    Read(Operand),
+    Alu(Operator),
+    /// Putting the result where it belongs.
+    Write(Operand),
+}
+
+impl Display for MicroOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MicroOp::Read(x) => f.write_str(&t!("semantics.micro.read", x = x)),
+            MicroOp::Alu(op) => f.write_str(&t!("semantics.micro.alu", op = op)),
+            MicroOp::Write(x) => f.write_str(&t!("semantics.micro.write", x = x)),
+        }
+    }
+}
+
+impl Inst {
+    /// The semantics of every instruction, in one place.
+    pub fn semantics(&self) -> Semantics {
+        let binary = |mnemonic, a: &Reg, b: &Reg, op| Semantics {
+            mnemonic,
+            args: vec![Operand::Reg(*a), Operand::Reg(*b)],
+            dest: Operand::Reg(*b),
+            sources: vec![Operand::Reg(*a), Operand::Reg(*b)],
+            op: Some(op),
+        };
+        let ternary = |mnemonic, a: &Reg, b: &Reg, c: &Reg, op| Semantics {
+            mnemonic,
+            args: vec![Operand::Reg(*a), Operand::Reg(*b), Operand::Reg(*c)],
+            dest: Operand::Reg(*c),
+            sources: vec![Operand::Reg(*a), Operand::Reg(*b)],
+            op: Some(op),
+        };
+        let immediate = |mnemonic, r: &Reg, n: &i32, op| Semantics {
+            mnemonic,
+            args: vec![Operand::Reg(*r), Operand::Num(*n)],
+            dest: Operand::Reg(*r),
+            sources: vec![Operand::Reg(*r), Operand::Num(*n)],
+            op: Some(op),
+        };
+        let copy = |mnemonic, args, dest, source| Semantics {
+            mnemonic,
+            args,
+            dest,
+            sources: vec![source],
+            op: None,
+        };
+        match self {
+            Inst::Add(a, b) => binary("ADD", a, b, Operator::Add),
+            Inst::Sub(a, b) => binary("SUB", a, b, Operator::Sub),
+            Inst::Mul(a, b) => binary("MUL", a, b, Operator::Mul),
+            Inst::Div(a, b) => binary("DIV", a, b, Operator::Div),
+            Inst::Shl(a, b) => binary("SHL", a, b, Operator::Shl),
+            Inst::Shr(a, b) => binary("SHR", a, b, Operator::Shr),
+            Inst::Min(a, b) => binary("MIN", a, b, Operator::Min),
+            Inst::Max(a, b) => binary("MAX", a, b, Operator::Max),
+            Inst::Add3(a, b, c) => ternary("ADD3", a, b, c, Operator::Add),
+            Inst::Sub3(a, b, c) => ternary("SUB3", a, b, c, Operator::Sub),
+            Inst::Mul3(a, b, c) => ternary("MUL3", a, b, c, Operator::Mul),
+            Inst::Div3(a, b, c) => ternary("DIV3", a, b, c, Operator::Div),
+            Inst::Shl3(a, b, c) => ternary("SHL3", a, b, c, Operator::Shl),
+            Inst::Shr3(a, b, c) => ternary("SHR3", a, b, c, Operator::Shr),
+            Inst::Min3(a, b, c) => ternary("MIN3", a, b, c, Operator::Min),
+            Inst::Max3(a, b, c) => ternary("MAX3", a, b, c, Operator::Max),
+            Inst::AddI(r, n) => immediate("ADDI", r, n, Operator::Add),
+            Inst::SubI(r, n) => immediate("SUBI", r, n, Operator::Sub),
+            Inst::MulI(r, n) => immediate("MULI", r, n, Operator::Mul),
+            Inst::DivI(r, n) => immediate("DIVI", r, n, Operator::Div),
+            Inst::ShlI(r, n) => immediate("SHLI", r, n, Operator::Shl),
+            Inst::ShrI(r, n) => immediate("SHRI", r, n, Operator::Shr),
+            Inst::Neg(r) => Semantics {
+                mnemonic: "NEG",
+                args: vec![Operand::Reg(*r)],
+                dest: Operand::Reg(*r),
+                sources: vec![Operand::Num(0), Operand::Reg(*r)],
+                op: Some(Operator::Sub),
+            },
+            Inst::Sat(r) => Semantics {
+                mnemonic: "SAT",
+                args: vec![Operand::Reg(*r)],
+                dest: Operand::Reg(*r),
+                sources: vec![Operand::Reg(*r), Operand::Overflow],
+                op: None,
+            },
+            Inst::Chk(r) => Semantics {
+                mnemonic: "CHK",
+                args: vec![Operand::Reg(*r)],
+                dest: Operand::Reg(*r),
+                sources: vec![Operand::Reg(*r), Operand::Overflow],
+                op: None,
+            },
+            Inst::Store(n, r) => copy(
+                "STORE",
+                vec![Operand::Num(*n), Operand::Reg(*r)],
+                Operand::Reg(*r),
+                Operand::Num(*n),
+            ),
+            Inst::Transfer(v, r) => copy(
+                "TRANSFER",
+                vec![Operand::Var(v.clone()), Operand::Reg(*r)],
+                Operand::Reg(*r),
+                Operand::Var(v.clone()),
+            ),
+            Inst::Mov(a, b) => copy(
+                "MOV",
+                vec![Operand::Reg(*a), Operand::Reg(*b)],
+                Operand::Reg(*b),
+                Operand::Reg(*a),
+            ),
+            Inst::Write(r, addr) => copy(
+                "WRITE",
+                vec![Operand::Reg(*r), Operand::Num(*addr as i32)],
+                Operand::Ram(*addr),
+                Operand::Reg(*r),
+            ),
+            Inst::Load(addr, r) => copy(
+                "LOAD",
+                vec![Operand::Num(*addr as i32), Operand::Reg(*r)],
+                Operand::Reg(*r),
+                Operand::Ram(*addr),
+            ),
+            Inst::Push(r) => copy(
+                "PUSH",
+                vec![Operand::Reg(*r)],
+                Operand::StackNext,
+                Operand::Reg(*r),
+            ),
+            Inst::Pop(r) => copy(
+                "POP",
+                vec![Operand::Reg(*r)],
+                Operand::Reg(*r),
+                Operand::StackTop,
+            ),
+            Inst::Result(r) => copy(
+                "RESULT",
+                vec![Operand::Reg(*r)],
+                Operand::Result,
+                Operand::Reg(*r),
+            ),
+            Inst::Halt => copy("HALT", vec![], Operand::Result, Operand::Nothing),
+        }
+    }
+}
+
+impl Semantics {
+    fn formula(&self, operands: &[String]) -> String {
+        match (self.op, operands) {
+            (Some(op), [l, r]) => format!("{l} {op} {r}"),
+            _ => operands.join(", "),
+        }
+    }
+
+    /// The instruction taken apart: reading the operands, the arithmetic and writing the result
+    /// back. Numbers are part of the instruction itself, so they aren't read, and a halt has
+    /// nothing to read.
+    pub fn micro_ops(&self) -> Vec<MicroOp> {
+        let reads = self
+            .sources
+            .iter()
+            .filter(|s| !matches!(s, Operand::Num(_) | Operand::Nothing))
+            .cloned()
+            .map(MicroOp::Read);
+        reads
+            .chain(self.op.map(MicroOp::Alu))
+            .chain([MicroOp::Write(self.dest.clone())])
+            .collect()
+    }
+
+    /// Explains the instruction with the current values, e.g. `ADD a b: b ← a + b = 5 + 3 = 8`.
+    pub fn explain(&self, interpreter: Option<&Interpreter>) -> String {
+        let head: Vec<_> = std::iter::once(self.mnemonic.to_string())
+            .chain(self.args.iter().map(Operand::to_string))
+            .collect();
+        let names: Vec<_> = self.sources.iter().map(Operand::to_string).collect();
+        let mut res = format!(
+            "{}: {} ← {}",
+            head.join(" "),
+            self.dest,
+            self.formula(&names)
+        );
+
+        let values: Option<Vec<i32>> = interpreter
+            .map(|i| self.sources.iter().map(|s| s.value(i)).collect())
+            .unwrap_or_default();
+        let (Some(values), Some(interpreter)) = (values, interpreter) else {
+            return res;
+        };
+        // a constant doesn't need "= 5" after it
+        if self.sources.iter().any(|s| !matches!(s, Operand::Num(_))) {
+            let shown: Vec<_> = values.iter().map(i32::to_string).collect();
+            res += &format!(" = {}", self.formula(&shown));
+        }
+        if let (Some(op), [l, r]) = (self.op, values.as_slice())
+            && let Some(v) = op.apply(*l, *r, interpreter.word_size())
+        {
+            res += &format!(" = {v}");
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gui::InterpreterOptions;
+    use crate::types::LpErr;
+
+    #[test]
+    fn explain_add_with_values() -> Result<(), LpErr> {
+        let hw = InterpreterOptions::default();
+        let mut interpreter = Interpreter::with_config(&hw)
+            .load_instructions(vec![
+                Inst::Store(5, Reg(0)),
+                Inst::Store(3, Reg(1)),
+                Inst::Add(Reg(0), Reg(1)),
+                Inst::Result(Reg(1)),
+            ])
+            .ready();
+        interpreter.step()?;
+        interpreter.step()?;
+
+        let add = Inst::Add(Reg(0), Reg(1)).semantics();
+        assert_eq!(add.explain(None), "ADD a b: b ← a + b");
+        assert_eq!(
+            add.explain(Some(&interpreter)),
+            "ADD a b: b ← a + b = 5 + 3 = 8"
+        );
+        assert_eq!(
+            Inst::Store(5, Reg(0))
+                .semantics()
+                .explain(Some(&interpreter)),
+            "STORE 5 a: a ← 5"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn instructions_take_micro_steps() {
+        let steps = |inst: Inst| inst.semantics().micro_ops();
+        assert_eq!(
+            steps(Inst::Add(Reg(0), Reg(1))),
+            [
+                MicroOp::Read(Operand::Reg(Reg(0))),
+                MicroOp::Read(Operand::Reg(Reg(1))),
+                MicroOp::Alu(Operator::Add),
+                MicroOp::Write(Operand::Reg(Reg(1))),
+            ]
+        );
+        assert_eq!(
+            steps(Inst::Store(5, Reg(0))),
+            [MicroOp::Write(Operand::Reg(Reg(0)))]
+        );
+        assert_eq!(
+            steps(Inst::Write(Reg(2), 3)),
+            [
+                MicroOp::Read(Operand::Reg(Reg(2))),
+                MicroOp::Write(Operand::Ram(3)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Short tones played while programs are animated, so you can *hear* the difference between fast
+//! and slow instructions.
+//!
+//! We don't ship an audio library, so the tones are synthesized into WAV files in the temporary
+//! directory and played with the command-line player of the operating system, one at a time on
+//! an audio thread of their own.
+
+use crate::types::Inst;
+use std::collections::HashSet;
+use std::f32::consts::TAU;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+
+const SAMPLE_RATE: u32 = 22050;
+
+/// Sounds waiting to be played. When the animation is faster than the sounds, the ones that
+/// don't fit are dropped instead of playing on long after the program finished.
+const QUEUE: usize = 4;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The audio thread, started with the first sound.
+static PLAYER: LazyLock<SyncSender<Sound>> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::sync_channel(QUEUE);
+    std::thread::spawn(move || play_queue(receiver));
+    sender
+});
+
+/// Whether sounds are played at all, off by default.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sound {
+    /// Add, subtract and shifts.
+    Arithmetic,
+    /// Multiply and divide.
+    MulDiv,
+    /// Loading constants, inputs and copies into registers.
+    Load,
+    /// RAM and stack accesses.
+    Memory,
+    /// The program finished.
+    Fanfare,
+}
+
+impl Sound {
+    pub fn of(inst: &Inst) -> Self {
+        match inst {
+            Inst::Add(_, _)
+            | Inst::Sub(_, _)
+            | Inst::Shl(_, _)
+            | Inst::Shr(_, _)
+            | Inst::Add3(..)
+            | Inst::Sub3(..)
+            | Inst::Shl3(..)
+            | Inst::Shr3(..)
+            | Inst::AddI(_, _)
+            | Inst::SubI(_, _)
+            | Inst::ShlI(_, _)
+            | Inst::ShrI(_, _)
+            | Inst::Neg(_)
+            | Inst::Min(_, _)
+            | Inst::Max(_, _)
+            | Inst::Min3(..)
+            | Inst::Max3(..) => Sound::Arithmetic,
+            Inst::Mul(_, _)
+            | Inst::Div(_, _)
+            | Inst::Mul3(..)
+            | Inst::Div3(..)
+            | Inst::MulI(_, _)
+            | Inst::DivI(_, _) => Sound::MulDiv,
+            Inst::Sat(_) | Inst::Chk(_) => Sound::Arithmetic,
+            Inst::Store(_, _)
+            | Inst::Transfer(_, _)
+            | Inst::Mov(_, _)
+            | Inst::Result(_)
+            | Inst::Halt => Sound::Load,
+            Inst::Write(_, _) | Inst::Load(_, _) | Inst::Push(_) | Inst::Pop(_) => Sound::Memory,
+        }
+    }
+
+    /// Frequencies (Hz) and durations (s) of the notes.
+    fn notes(self) -> &'static [(f32, f32)] {
+        match self {
+            Sound::Arithmetic => &[(880.0, 0.06)],
+            Sound::MulDiv => &[(660.0, 0.06), (990.0, 0.06)],
+            Sound::Load => &[(1320.0, 0.04)],
+            // low and long, RAM is slow
+            Sound::Memory => &[(220.0, 0.25)],
+            Sound::Fanfare => &[
+                (523.25, 0.12),
+                (659.25, 0.12),
+                (783.99, 0.12),
+                (1046.5, 0.4),
+            ],
+        }
+    }
+
+    /// Where the sound is written, a file per running app so they don't write each others'.
+    fn file(self) -> PathBuf {
+        let name = format!("lndw-{}-{self:?}.wav", std::process::id());
+        std::env::temp_dir().join(name.to_lowercase())
+    }
+}
+
+/// Sine tones with a short fade in and out, so they don't click.
+fn synthesize(notes: &[(f32, f32)]) -> Vec<i16> {
+    let mut samples = Vec::new();
+    for &(freq, secs) in notes {
+        let n = (secs * SAMPLE_RATE as f32) as usize;
+        let fade = (n / 10).max(1);
+        for i in 0..n {
+            let envelope = (i.min(n - i) as f32 / fade as f32).min(1.0);
+            let t = i as f32 / SAMPLE_RATE as f32;
+            samples.push(((TAU * freq * t).sin() * envelope * 0.3 * i16::MAX as f32) as i16);
+        }
+    }
+    samples
+}
+
+/// 16-bit mono PCM WAV file.
+fn wav(samples: &[i16]) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&1u16.to_le_bytes()); // mono
+    out.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    out.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for s in samples {
+        out.extend_from_slice(&s.to_le_bytes());
+    }
+    out
+}
+
+fn player(file: &PathBuf) -> Command {
+    if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("afplay");
+        cmd.arg(file);
+        cmd
+    } else if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("powershell");
+        cmd.arg("-c").arg(format!(
+            "(New-Object Media.SoundPlayer '{}').PlaySync()",
+            file.display()
+        ));
+        cmd
+    } else {
+        let mut cmd = Command::new("aplay");
+        cmd.arg("-q").arg(file);
+        cmd
+    }
+}
+
+/// Plays the sounds one after the other. Each file is written once, by this thread only, so a
+/// player never reads a file that's being written.
+fn play_queue(sounds: Receiver<Sound>) {
+    let mut written = HashSet::new();
+    for sound in sounds {
+        let file = sound.file();
+        if !written.contains(&sound) {
+            if std::fs::write(&file, wav(&synthesize(sound.notes()))).is_err() {
+                continue;
+            }
+            written.insert(sound);
+        }
+        let child = player(&file)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Ok(mut child) = child {
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Plays the sound in the background if sounds are enabled. Failures (e.g. no player installed)
+/// are ignored, sound is only a gimmick.
+pub fn play(sound: Sound) {
+    if enabled() {
+        let _ = PLAYER.try_send(sound);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wav_header() {
+        let samples = synthesize(Sound::Arithmetic.notes());
+        assert_eq!(samples.len(), (0.06 * SAMPLE_RATE as f32) as usize);
+
+        let wav = wav(&samples);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(wav.len(), 44 + samples.len() * 2);
+    }
+}
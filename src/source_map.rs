@@ -0,0 +1,94 @@
+use crate::types::Expr;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Byte range in the source code.
+pub type Span = Range<usize>;
+
+/// Debug info: where in the source code each (sub-)expression came from.
+///
+/// Like the register allocation, this is keyed by the expression itself, so equal
+/// sub-expressions share their spans (`x + x` maps `x` to both occurrences).
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap(HashMap<Expr, Vec<Span>>);
+
+impl SourceMap {
+    pub fn insert(&mut self, expr: Expr, span: Span) {
+        let spans = self.0.entry(expr).or_default();
+        if !spans.contains(&span) {
+            spans.push(span);
+        }
+    }
+
+    /// The source spans of an expression, empty if it doesn't appear in the source.
+    pub fn spans(&self, expr: &Expr) -> &[Span] {
+        self.0.get(expr).map_or(&[], Vec::as_slice)
+    }
+
+    /// Carries the spans over a pass that turned `before` into `after`. Where the trees still
+    /// have the same shape nodes keep their spans, nodes introduced by the pass get the spans of
+    /// the part of the tree they replaced.
+    pub fn carry(&mut self, before: &Expr, after: &Expr) {
+        let spans = self.spans(before).to_vec();
+        match (before, after) {
+            (Expr::UnaryOp(op1, b), Expr::UnaryOp(op2, a)) if op1 == op2 => {
+                self.inherit(after, &spans);
+                self.carry(&b.get(), &a.get());
+            }
+            (Expr::BinaryOp(bl, op1, br), Expr::BinaryOp(al, op2, ar)) if op1 == op2 => {
+                self.inherit(after, &spans);
+                self.carry(&bl.get(), &al.get());
+                self.carry(&br.get(), &ar.get());
+            }
+            _ => self.fill(after, &spans),
+        }
+    }
+
+    fn inherit(&mut self, expr: &Expr, spans: &[Span]) {
+        if !self.0.contains_key(expr) && !spans.is_empty() {
+            self.0.insert(*expr, spans.to_vec());
+        }
+    }
+
+    /// Gives `spans` to every node of `expr` that doesn't have a span yet.
+    fn fill(&mut self, expr: &Expr, spans: &[Span]) {
+        self.inherit(expr, spans);
+        let spans = self.spans(expr).to_vec();
+        match expr {
+            Expr::Num(_) | Expr::Var(_) => {}
+            Expr::UnaryOp(_, e) => self.fill(&e.get(), &spans),
+            Expr::BinaryOp(l, _, r) => {
+                self.fill(&l.get(), &spans);
+                self.fill(&r.get(), &spans);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod test {
+    use super::*;
+    use crate::gui::WordSize;
+    use crate::parser::{run_parser, run_parser_with_spans};
+    use crate::passes::{ConstantFold, TransformationLog};
+    use crate::types::LpErr;
+
+    #[test]
+    fn parser_spans() -> Result<(), LpErr> {
+        let (expr, map) = run_parser_with_spans("x + 2 * (y - 1)")?;
+        assert_eq!(map.spans(&expr), &[0..15]);
+        assert_eq!(map.spans(&Expr::Var("y".into())), &[9..10]);
+        assert_eq!(map.spans(&run_parser("2 * (y - 1)")?), &[4..15]);
+        Ok(())
+    }
+
+    #[test]
+    fn folded_constant_keeps_span() -> Result<(), LpErr> {
+        let (expr, mut map) = run_parser_with_spans("x + 2 * 3")?;
+        let folded = expr.run_constant_fold(WordSize::W32, &mut TransformationLog::default());
+        map.carry(&expr, &folded);
+        assert_eq!(map.spans(&Expr::Num(6)), &[4..9]);
+        Ok(())
+    }
+}
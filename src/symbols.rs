@@ -0,0 +1,126 @@
+use crate::eval::evaluate;
+use crate::gui::WordSize;
+use crate::parser::{ConstDecl, Program};
+use crate::source_map::SourceMap;
+use crate::types::{Expr, ExprId, LpErr};
+use rust_i18n::t;
+use std::collections::HashMap;
+
+/// Values of the named constants, in the order they were declared.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ConstTable(Vec<(String, i32)>);
+
+impl ConstTable {
+    /// Evaluates the declarations in order, each value may use the constants declared before it.
+    pub fn from_declarations(decls: &[ConstDecl]) -> Result<Self, LpErr> {
+        let mut table = ConstTable::default();
+        for decl in decls {
+            if table.get(&decl.name).is_some() {
+                return Err(LpErr::Parse(
+                    t!("symbols.redefined", name = decl.name).into(),
+                ));
+            }
+            let value = table.resolve(decl.value);
+            if !value.variables().is_empty() {
+                return Err(LpErr::Parse(
+                    t!("symbols.not_constant", name = decl.name).into(),
+                ));
+            }
+            let value = evaluate(&value, &HashMap::new(), WordSize::W32)?;
+            table.0.push((decl.name.clone(), value));
+        }
+        Ok(table)
+    }
+
+    /// Like [`ConstTable::from_declarations`], but leaves out the constants that can't be
+    /// computed instead of failing.
+    pub fn partial(decls: &[ConstDecl]) -> Self {
+        let mut table = ConstTable::default();
+        for decl in decls {
+            let value = table.resolve(decl.value);
+            if table.get(&decl.name).is_none()
+                && let Ok(value) = evaluate(&value, &HashMap::new(), WordSize::W32)
+            {
+                table.0.push((decl.name.clone(), value));
+            }
+        }
+        table
+    }
+
+    pub fn get(&self, name: &str) -> Option<i32> {
+        self.0.iter().find(|(n, _)| n == name).map(|&(_, v)| v)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i32)> {
+        self.0.iter().map(|(n, v)| (n.as_str(), *v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Replaces the constants in `expr` with their values.
+    pub fn resolve(&self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Var(name) => match self.get(&name) {
+                Some(value) => Expr::Num(value),
+                None => Expr::Var(name),
+            },
+            Expr::Num(_) => expr,
+            Expr::UnaryOp(op, e) => Expr::UnaryOp(op, ExprId::new(self.resolve(e.get()))),
+            Expr::BinaryOp(l, op, r) => Expr::BinaryOp(
+                ExprId::new(self.resolve(l.get())),
+                op,
+                ExprId::new(self.resolve(r.get())),
+            ),
+        }
+    }
+}
+
+impl Program {
+    /// The body with all constants replaced by their values, the spans of the constants' names
+    /// carry over to the values. This happens before any pass runs, so folding and the other
+    /// passes see the numbers, e.g. `x * K` with `K = 8` becomes a shift.
+    pub fn resolve(&self, map: &mut SourceMap) -> Result<(Expr, ConstTable), LpErr> {
+        let constants = ConstTable::from_declarations(&self.constants)?;
+        let expr = constants.resolve(self.body);
+        map.carry(&self.body, &expr);
+        Ok((expr, constants))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod test {
+    use super::*;
+    use crate::parser::{DEFAULT_MAX_NESTING, run_parser, run_program_parser};
+
+    #[test]
+    fn constants_are_replaced_by_their_values() -> Result<(), LpErr> {
+        let (program, mut map) = run_program_parser(
+            "const TAX = 19;\nconst TWICE = TAX * 2;\nprice * TWICE",
+            DEFAULT_MAX_NESTING,
+        )?;
+        let (expr, constants) = program.resolve(&mut map)?;
+        assert_eq!(expr, run_parser("price * 38")?);
+        assert_eq!(
+            constants.iter().collect::<Vec<_>>(),
+            [("TAX", 19), ("TWICE", 38)]
+        );
+        // the value is highlighted where the constant was used
+        assert_eq!(map.spans(&Expr::Num(38)), &[47..52]);
+        Ok(())
+    }
+
+    #[test]
+    fn constants_must_be_constant() {
+        assert!(run_parser("const A = 1; const A = 2; A").is_err());
+        assert!(run_parser("const A = x + 1; A").is_err());
+        assert!(run_parser("const A = B; const B = 1; A").is_err());
+        assert!(run_parser("const A = 1 / 0; A").is_err());
+    }
+}
@@ -1,9 +1,17 @@
+use crate::gui::WordSize;
+use crate::intern::{Arena, Node, Symbol};
 use rust_i18n::t;
-use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU8, Ordering};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LpErr {
     Parse(String),
+    Config(String),
     IR(String),
     Interpret(String),
 }
@@ -12,13 +20,14 @@ impl Display for LpErr {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             LpErr::Parse(e) => write!(f, "{e} (parse)"),
+            LpErr::Config(e) => write!(f, "{e} (config)"),
             LpErr::IR(e) => write!(f, "{e} (ir gen)"),
             LpErr::Interpret(e) => write!(f, "{e} (interpreter)"),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
 pub enum Operator {
     Add,
     Sub,
@@ -28,6 +37,23 @@ pub enum Operator {
     Shl,
     /// Right Shift, aka >>
     Shr,
+    /// `sat_add(a, b)`, addition that stops at the largest or smallest number instead of
+    /// wrapping around.
+    SatAdd,
+    /// `sat_sub(a, b)`
+    SatSub,
+    /// `checked_add(a, b)`, addition that stops the program if the result doesn't fit.
+    CheckedAdd,
+    /// `checked_sub(a, b)`
+    CheckedSub,
+    /// `checked_mul(a, b)`
+    CheckedMul,
+    /// `checked_div(a, b)`, only `MIN / -1` doesn't fit.
+    CheckedDiv,
+    /// `min(a, b)`, the smaller of the two.
+    Min,
+    /// `max(a, b)`
+    Max,
 }
 
 impl TryFrom<char> for Operator {
@@ -36,14 +62,144 @@ impl TryFrom<char> for Operator {
     fn try_from(value: char) -> Result<Self, Self::Error> {
         match value {
             '+' => Ok(Operator::Add),
-            '-' => Ok(Operator::Sub),
-            '*' => Ok(Operator::Mul),
-            '/' => Ok(Operator::Div),
+            // the unicode minus sign, as typed on many phones
+            '-' | '−' => Ok(Operator::Sub),
+            '*' | '·' | '×' => Ok(Operator::Mul),
+            '/' | '÷' => Ok(Operator::Div),
             _ => Err(value),
         }
     }
 }
 
+/// A function programs can call, see [`BUILTINS`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Builtin {
+    /// An operator written as a function, like `sat_add(a, b)`.
+    Operator(Operator),
+    /// `clamp(x, lo, hi)`, which is `min(max(x, lo), hi)`.
+    Clamp,
+}
+
+/// The functions programs can call, their names can't be used for variables.
+pub const BUILTINS: [Builtin; 9] = [
+    Builtin::Operator(Operator::SatAdd),
+    Builtin::Operator(Operator::SatSub),
+    Builtin::Operator(Operator::CheckedAdd),
+    Builtin::Operator(Operator::CheckedSub),
+    Builtin::Operator(Operator::CheckedMul),
+    Builtin::Operator(Operator::CheckedDiv),
+    Builtin::Operator(Operator::Min),
+    Builtin::Operator(Operator::Max),
+    Builtin::Clamp,
+];
+
+impl Builtin {
+    /// The builtin with this name.
+    pub fn named(name: &str) -> Option<Builtin> {
+        BUILTINS.into_iter().find(|b| b.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Operator(op) => op.function().expect("builtin operators have a name"),
+            Builtin::Clamp => "clamp",
+        }
+    }
+
+    /// How many values it's called with.
+    pub fn arity(self) -> usize {
+        match self {
+            Builtin::Operator(_) => 2,
+            Builtin::Clamp => 3,
+        }
+    }
+}
+
+impl Operator {
+    /// How strongly the operator binds, as in the parser: shifts bind weakest, function calls
+    /// like atoms.
+    pub fn precedence(self) -> u8 {
+        match self {
+            Operator::Shl | Operator::Shr => 1,
+            Operator::Add | Operator::Sub => 2,
+            Operator::Mul | Operator::Div => 3,
+            _ => 4,
+        }
+    }
+
+    /// Name of a builtin function, `None` for operators written between their operands.
+    pub fn function(self) -> Option<&'static str> {
+        Some(match self {
+            Operator::SatAdd => "sat_add",
+            Operator::SatSub => "sat_sub",
+            Operator::CheckedAdd => "checked_add",
+            Operator::CheckedSub => "checked_sub",
+            Operator::CheckedMul => "checked_mul",
+            Operator::CheckedDiv => "checked_div",
+            Operator::Min => "min",
+            Operator::Max => "max",
+            _ => return None,
+        })
+    }
+
+    /// The plain arithmetic a builtin does before looking at the overflow, the operator itself
+    /// otherwise.
+    pub fn base(self) -> Operator {
+        match self {
+            Operator::SatAdd | Operator::CheckedAdd => Operator::Add,
+            Operator::SatSub | Operator::CheckedSub => Operator::Sub,
+            Operator::CheckedMul => Operator::Mul,
+            Operator::CheckedDiv => Operator::Div,
+            op => op,
+        }
+    }
+
+    /// Whether the operation can stop the program: dividing by zero, or a checked operation
+    /// overflowing.
+    pub fn can_fail(self) -> bool {
+        matches!(
+            self,
+            Operator::Div
+                | Operator::CheckedAdd
+                | Operator::CheckedSub
+                | Operator::CheckedMul
+                | Operator::CheckedDiv
+        )
+    }
+
+    /// Applies the operator like the interpreter does with words of the given size: results
+    /// wrap, saturating operations saturate when the result doesn't fit. `None` when dividing
+    /// by zero or when a checked operation overflows, both of which fail when run.
+    pub fn apply(self, l: i32, r: i32, word: WordSize) -> Option<i32> {
+        let (l, r) = (word.wrap(l), word.wrap(r));
+        let (res, overflow) = match self.base() {
+            Operator::Add => l.overflowing_add(r),
+            Operator::Sub => l.overflowing_sub(r),
+            Operator::Mul => l.overflowing_mul(r),
+            Operator::Div if r == 0 => return None,
+            Operator::Div => l.overflowing_div(r),
+            Operator::Shl => (l.wrapping_shl(r as u32), false),
+            Operator::Shr => (l.wrapping_shr(r as u32), false),
+            Operator::Min => (l.min(r), false),
+            _ => (l.max(r), false),
+        };
+        let wrapped = word.wrap(res);
+        if !(overflow || wrapped != res) {
+            return Some(wrapped);
+        }
+        Some(match self {
+            // the wrapped result has the wrong sign, like for [`Inst::Sat`]
+            Operator::SatAdd | Operator::SatSub if wrapped < 0 => word.max(),
+            Operator::SatAdd | Operator::SatSub => word.min(),
+            Operator::CheckedAdd
+            | Operator::CheckedSub
+            | Operator::CheckedMul
+            | Operator::CheckedDiv => return None,
+            _ => wrapped,
+        })
+    }
+}
+
 impl Display for Operator {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -53,17 +209,110 @@ impl Display for Operator {
             Operator::Div => write!(f, "/"),
             Operator::Shl => write!(f, "<<"),
             Operator::Shr => write!(f, ">>"),
+            op => f.write_str(op.function().expect("all other operators are builtins")),
         }
     }
 }
 
 /// The main AST struct for representing the IR.
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+///
+/// Sub-expressions are [`ExprId`]s into an arena, so the whole tree is `Copy` and passes can keep
+/// parts of it without cloning them.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
 pub enum Expr {
     Num(i32),
-    Var(String),
-    UnaryOp(Operator, Box<Expr>),
-    BinaryOp(Box<Expr>, Operator, Box<Expr>),
+    Var(Symbol),
+    UnaryOp(Operator, ExprId),
+    BinaryOp(ExprId, Operator, ExprId),
+}
+
+impl Node for Expr {
+    fn map_indices(&mut self, mut f: impl FnMut(u32) -> u32) {
+        match self {
+            Expr::Num(_) | Expr::Var(_) => {}
+            Expr::UnaryOp(_, e) => e.0 = f(e.0),
+            Expr::BinaryOp(l, _, r) => {
+                l.0 = f(l.0);
+                r.0 = f(r.0);
+            }
+        }
+    }
+}
+
+/// Expressions in a generation of the arena, a few dozen megabytes. The largest programs that
+/// can be allowed need a fraction of it.
+const EXPRS_PER_GENERATION: usize = 1 << 20;
+
+static EXPRS: LazyLock<Arena<Expr>> = LazyLock::new(|| Arena::new(EXPRS_PER_GENERATION));
+
+/// Index of an [`Expr`] in the global arena.
+///
+/// Each distinct expression is stored only once, so equal ids mean equal trees and comparing or
+/// hashing whole trees doesn't have to walk them. Only ids from different generations of the
+/// arena can differ for equal trees, at worst a pass then misses a match.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(u32);
+
+impl ExprId {
+    pub fn new(expr: Expr) -> Self {
+        ExprId(EXPRS.intern(expr))
+    }
+
+    /// The expression, or `0` if the arena already forgot it.
+    pub fn get(self) -> Expr {
+        EXPRS.get(self.0).unwrap_or(Expr::Num(0))
+    }
+
+    /// Counts the generations of the arena. Expressions stored before are forgotten once it grew
+    /// by two, see [`Arena`].
+    pub fn generation() -> u32 {
+        EXPRS.generation()
+    }
+}
+
+impl From<Expr> for ExprId {
+    fn from(expr: Expr) -> Self {
+        ExprId::new(expr)
+    }
+}
+
+/// Serialized as the expression it stands for, the index only means something in this process.
+impl Serialize for ExprId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.get().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExprId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Expr::deserialize(deserializer).map(ExprId::new)
+    }
+}
+
+/// Shows the expression itself, the index is meaningless on its own.
+impl Debug for ExprId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.get(), f)
+    }
+}
+
+impl Display for ExprId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.get(), f)
+    }
+}
+
+/// Source-like representation, fully parenthesized so the structure of the tree is visible.
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{n}"),
+            Expr::Var(v) => write!(f, "{v}"),
+            Expr::UnaryOp(op, e) => write!(f, "{op}{e}"),
+            Expr::BinaryOp(l, op, r) if op.function().is_some() => write!(f, "{op}({l}, {r})"),
+            Expr::BinaryOp(l, op, r) => write!(f, "({l} {op} {r})"),
+        }
+    }
 }
 
 impl From<i32> for Expr {
@@ -72,52 +321,737 @@ impl From<i32> for Expr {
     }
 }
 
-pub type Reg = char;
+impl Expr {
+    /// Source code for the expression with as few parentheses as possible, e.g. `x << 3`.
+    ///
+    /// Parsing the result gives back the same expression, except that negative numbers come
+    /// back as negations.
+    pub fn pretty(&self) -> String {
+        match self {
+            // its negation is too large to be written as a number
+            Expr::Num(i32::MIN) => format!("({} - 1)", -i32::MAX),
+            Expr::Num(n) => n.to_string(),
+            Expr::Var(v) => v.to_string(),
+            Expr::UnaryOp(op, e) => match e.get() {
+                e @ Expr::BinaryOp(..) => format!("{op}({})", e.pretty()),
+                e => format!("{op}{}", e.pretty()),
+            },
+            Expr::BinaryOp(l, op, r) if op.function().is_some() => {
+                format!("{op}({}, {})", l.get().pretty(), r.get().pretty())
+            }
+            Expr::BinaryOp(l, op, r) => {
+                // all operators are left-associative, so only the right side needs parentheses
+                // for equal precedence
+                let wrap = |e: &Expr, right: bool| match e {
+                    Expr::BinaryOp(_, inner, _)
+                        if inner.precedence() < op.precedence()
+                            || (right && inner.precedence() == op.precedence()) =>
+                    {
+                        format!("({})", e.pretty())
+                    }
+                    _ => e.pretty(),
+                };
+                format!("{} {op} {}", wrap(&l.get(), false), wrap(&r.get(), true))
+            }
+        }
+    }
+
+    /// Rebuilds the tree from the leaves up, `f` gets each node once its operands were rebuilt.
+    ///
+    /// Uses an explicit stack instead of recursion, so long chains of operations can't overflow
+    /// the stack.
+    pub fn rebuild_bottom_up(self, mut f: impl FnMut(Expr) -> Expr) -> Expr {
+        // nodes to rebuild, and whether their operands are already done
+        let mut todo = vec![(self, false)];
+        let mut done = vec![];
+        while let Some((expr, operands_done)) = todo.pop() {
+            match (expr, operands_done) {
+                (Expr::UnaryOp(_, e), false) => {
+                    todo.push((expr, true));
+                    todo.push((e.get(), false));
+                }
+                (Expr::BinaryOp(l, _, r), false) => {
+                    todo.push((expr, true));
+                    todo.push((r.get(), false));
+                    todo.push((l.get(), false));
+                }
+                (Expr::UnaryOp(op, _), true) => {
+                    let e = done.pop().expect("operand was rebuilt");
+                    done.push(f(Expr::UnaryOp(op, ExprId::new(e))));
+                }
+                (Expr::BinaryOp(_, op, _), true) => {
+                    let r = done.pop().expect("operand was rebuilt");
+                    let l = done.pop().expect("operand was rebuilt");
+                    done.push(f(Expr::BinaryOp(ExprId::new(l), op, ExprId::new(r))));
+                }
+                (Expr::Num(_) | Expr::Var(_), _) => done.push(f(expr)),
+            }
+        }
+        done.pop().expect("the whole tree was rebuilt")
+    }
+
+    /// Variables anywhere in the right-hand side of a division, the inputs that could make the
+    /// program divide by zero.
+    pub fn divisor_variables(&self) -> HashSet<String> {
+        let mut divisors = HashSet::new();
+        self.collect_divisors(&mut divisors);
+        divisors
+    }
+
+    /// Input variables in the order they first occur, with how often each one occurs.
+    pub fn variable_occurrences(&self) -> Vec<(String, usize)> {
+        let mut occurrences = Vec::new();
+        self.count_variables(&mut occurrences);
+        occurrences
+    }
+
+    fn count_variables(&self, occurrences: &mut Vec<(String, usize)>) {
+        match self {
+            Expr::Num(_) => {}
+            Expr::Var(v) => match occurrences.iter_mut().find(|(name, _)| v == name) {
+                Some((_, n)) => *n += 1,
+                None => occurrences.push((v.to_string(), 1)),
+            },
+            Expr::UnaryOp(_, e) => e.get().count_variables(occurrences),
+            Expr::BinaryOp(l, _, r) => {
+                l.get().count_variables(occurrences);
+                r.get().count_variables(occurrences);
+            }
+        }
+    }
+
+    /// All input variables the expression reads.
+    pub fn variables(&self) -> HashSet<String> {
+        let mut vars = HashSet::new();
+        self.collect_variables(&mut vars);
+        vars
+    }
+
+    fn collect_divisors(&self, divisors: &mut HashSet<String>) {
+        match self {
+            Expr::Num(_) | Expr::Var(_) => {}
+            Expr::UnaryOp(_, e) => e.get().collect_divisors(divisors),
+            Expr::BinaryOp(l, op, r) => {
+                if op.base() == Operator::Div {
+                    r.get().collect_variables(divisors);
+                }
+                l.get().collect_divisors(divisors);
+                r.get().collect_divisors(divisors);
+            }
+        }
+    }
+
+    fn collect_variables(&self, vars: &mut HashSet<String>) {
+        match self {
+            Expr::Num(_) => {}
+            Expr::Var(v) => {
+                vars.insert(v.to_string());
+            }
+            Expr::UnaryOp(_, e) => e.get().collect_variables(vars),
+            Expr::BinaryOp(l, _, r) => {
+                l.get().collect_variables(vars);
+                r.get().collect_variables(vars);
+            }
+        }
+    }
+}
+
+/// A register, numbered from 0. How it's shown depends on the global [`RegNaming`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Reg(pub u8);
+
 pub type MemAddr = usize;
 
-#[derive(Debug, Clone)]
-pub enum Inst {
-    /// Add two values, storing the result in Register #2.
-    Add(Reg, Reg),
-    /// Subtract two values, storing the result in Register #2.
-    Sub(Reg, Reg),
-    /// Multiply two values, storing the result in Register #2.
-    Mul(Reg, Reg),
-    /// Divide two values, storing the result in Register #2.
-    Div(Reg, Reg),
-    /// Shift the value in register #2 to the left by the number of bits stated in register #1.
-    Shl(Reg, Reg),
-    /// Shift the value in register #2 to the right by the number of bits stated in register #1.
-    Shr(Reg, Reg),
-    /// Store a number in a register.
-    Store(i32, Reg),
-    /// Transfer a value into a register.
-    Transfer(String, Reg),
-    /// Return the value in the given register and terminate computation.
-    Result(Reg),
-
-    /// Write the contents of a register to main memory.
-    Write(Reg, MemAddr),
-    /// Load a piece of data from main memory into a register.
-    Load(MemAddr, Reg),
-}
-
-/// Localized user-friendly output, e.g. `t!("compiler.inst.add", ...)` becomes "add register a to
-/// register b" in English. Not to be used programmatically.
-impl Display for Inst {
+/// How registers are named in all output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum RegNaming {
+    /// `a`, `b`, .. `z`
+    #[default]
+    Letters,
+    /// `R0`, `R1`, .. `R31`
+    Numbered,
+    /// `x0`, `x1`, .. `x31`, like RISC-V
+    RiscV,
+}
+
+/// The naming scheme used by `Display for Reg`, stored like the global locale of `rust_i18n`.
+static NAMING: AtomicU8 = AtomicU8::new(0);
+
+impl RegNaming {
+    pub const ALL: [RegNaming; 3] = [RegNaming::Letters, RegNaming::Numbered, RegNaming::RiscV];
+
+    /// Number of registers that can be named with this scheme.
+    pub fn max_registers(&self) -> u8 {
+        match self {
+            RegNaming::Letters => 26,
+            RegNaming::Numbered | RegNaming::RiscV => 32,
+        }
+    }
+
+    pub fn name(&self, reg: Reg) -> String {
+        match self {
+            RegNaming::Letters if reg.0 < 26 => char::from(b'a' + reg.0).to_string(),
+            // there are no more letters, fall back to numbers
+            RegNaming::Letters | RegNaming::Numbered => format!("R{}", reg.0),
+            RegNaming::RiscV => format!("x{}", reg.0),
+        }
+    }
+
+    /// The scheme used for displaying registers.
+    pub fn global() -> Self {
+        match NAMING.load(Ordering::Relaxed) {
+            1 => RegNaming::Numbered,
+            2 => RegNaming::RiscV,
+            _ => RegNaming::Letters,
+        }
+    }
+
+    pub fn set_global(naming: RegNaming) {
+        NAMING.store(naming as u8, Ordering::Relaxed);
+    }
+}
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&RegNaming::global().name(*self))
+    }
+}
+
+/// Parses a register name in any naming scheme, e.g. `c`, `R2` or `x2`.
+impl FromStr for Reg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("`{s}` is not a register");
+        let reg = match s.as_bytes() {
+            [c] if c.is_ascii_alphabetic() => c.to_ascii_lowercase() - b'a',
+            [b'R' | b'r' | b'x', num @ ..] if !num.is_empty() => {
+                s[1..].parse::<u8>().map_err(|_| err())?
+            }
+            _ => return Err(err()),
+        };
+        if reg >= RegNaming::Numbered.max_registers() {
+            return Err(err());
+        }
+        Ok(Reg(reg))
+    }
+}
+
+/// Extra cycles of a RAM access that has to wait for the only RAM port, see [`Inst::stall`].
+pub const RAM_STALL_CYCLES: u32 = 100;
+
+/// The kind of value an operand of an instruction is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Reg,
+    Num,
+    /// Name of an input variable.
+    Var,
+    /// RAM address.
+    Addr,
+}
+
+/// The value of an operand, to handle all instructions the same way, see [`Inst::operands`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperandValue {
+    Reg(Reg),
+    Num(i32),
+    Var(String),
+    Addr(MemAddr),
+}
+
+impl Display for OperandValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Inst::Add(a, b) => f.write_str(&t!("compiler.inst.add", a = a, b = b)),
-            Inst::Sub(a, b) => f.write_str(&t!("compiler.inst.sub", a = a, b = b)),
-            Inst::Mul(a, b) => f.write_str(&t!("compiler.inst.mul", a = a, b = b)),
-            Inst::Div(a, b) => f.write_str(&t!("compiler.inst.div", a = a, b = b)),
-            Inst::Shl(a, b) => f.write_str(&t!("compiler.inst.shl", a = a, b = b)),
-            Inst::Shr(a, b) => f.write_str(&t!("compiler.inst.shr", a = a, b = b)),
-            Inst::Store(n, r) => f.write_str(&t!("compiler.inst.store", n = n, r = r)),
-            Inst::Transfer(v, r) => f.write_str(&t!("compiler.inst.transfer", v = v, r = r)),
-            Inst::Result(r) => f.write_str(&t!("compiler.inst.result", r = r)),
-            Inst::Write(r, addr) => f.write_str(&t!("compiler.inst.write", r = r, addr = addr)),
-            Inst::Load(addr, r) => f.write_str(&t!("compiler.inst.load", addr = addr, r = r)),
+            OperandValue::Reg(r) => write!(f, "{r}"),
+            OperandValue::Num(n) => write!(f, "{n}"),
+            OperandValue::Var(v) => write!(f, "{v}"),
+            OperandValue::Addr(addr) => write!(f, "{addr}"),
+        }
+    }
+}
+
+/// One operand of a kind of instruction, see [`InstDef`].
+pub struct OperandDef {
+    pub kind: Operand,
+    pub read: bool,
+    pub written: bool,
+}
+
+/// Arithmetic of an ALU instruction and whether it overflowed, see [`Unit::Alu`].
+pub type AluOp = fn(i32, i32) -> Option<(i32, bool)>;
+
+/// Which part of the processor runs an instruction.
+pub enum Unit {
+    /// Arithmetic computing `op(a, b)` of the two operands in order, `None` if that's undefined
+    /// (division by zero). The result goes to the `mut` operand, the overflow flag is set if it
+    /// didn't fit. The symbol is shown while stepping through the program, e.g. `a + b`. With a
+    /// single operand it's `op(0, a)`.
+    Alu {
+        op: AluOp,
+        symbol: &'static str,
+    },
+    Ram,
+    /// Moving values into and out of the registers, and acting on the overflow flag.
+    Control,
+}
+
+/// Everything about one kind of instruction, see [`ISA`].
+pub struct InstDef {
+    /// Short name, the same as in assembly text.
+    pub name: &'static str,
+    /// In the order of the assembly text and the machine code.
+    pub operands: &'static [OperandDef],
+    /// First byte of the machine code, see [`crate::encoding`].
+    pub opcode: u8,
+    /// Simulated duration in cycles (animation frames).
+    pub cycles: u32,
+    pub unit: Unit,
+}
+
+impl InstDef {
+    /// An instruction of this kind, to show what it looks like.
+    pub fn example(&self) -> Inst {
+        let mut registers = (0..).map(Reg);
+        let values = self.operands.iter().map(|operand| match operand.kind {
+            Operand::Reg => OperandValue::Reg(registers.next().unwrap()),
+            Operand::Num => OperandValue::Num(42),
+            Operand::Var => OperandValue::Var("x".into()),
+            Operand::Addr => OperandValue::Addr(3),
+        });
+        Inst::from_operands(self, values).expect("the operands have the right kinds")
+    }
+}
+
+/// Pieces of [`instructions!`] for each operand kind and access.
+macro_rules! operand {
+    (type Reg) => {
+        Reg
+    };
+    (type Num) => {
+        i32
+    };
+    (type Var) => {
+        String
+    };
+    (type Addr) => {
+        MemAddr
+    };
+    (value $kind:ident, $field:expr) => {
+        OperandValue::$kind($field.to_owned())
+    };
+    (take $kind:ident, $values:expr) => {
+        match $values.next()? {
+            OperandValue::$kind(value) => value,
+            _ => return None,
+        }
+    };
+    (read in) => {
+        true
+    };
+    (read out) => {
+        false
+    };
+    (read mut) => {
+        true
+    };
+    (written in) => {
+        false
+    };
+    (written out) => {
+        true
+    };
+    (written mut) => {
+        true
+    };
+}
+
+/// Defines [`Inst`] and everything about each instruction in one place, see [`InstDef`].
+///
+/// Each operand is `in` (read), `out` (written) or `mut` (both). Adding an instruction only
+/// takes an entry here and its `compiler.inst.<name>` translation, the interpreter, the
+/// assembler and the encoder handle all instructions the same way. Only the real-world targets,
+/// and the interpreter for anything but arithmetic, need to know what it does.
+macro_rules! instructions {
+    (
+        $(#[$meta:meta])*
+        pub enum Inst {
+            $(
+                $(#[$attr:meta])*
+                $variant:ident $(($($field:ident: $access:tt $kind:ident),*))? =
+                    $name:literal, $opcode:literal, $cycles:literal, $unit:expr;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Serialize, Deserialize)]
+        pub enum Inst {
+            $($(#[$attr])* $variant $(($(operand!(type $kind)),*))?,)*
         }
+
+        /// Positions in [`ISA`].
+        enum Index {
+            $($variant,)*
+        }
+
+        /// All instructions of our ISA, in the order of the [`Inst`] variants. Arithmetic is
+        /// fast, multiplication and division less so, and going to RAM is by far the slowest.
+        pub static ISA: &[InstDef] = &[
+            $(InstDef {
+                name: $name,
+                operands: &[$($(OperandDef {
+                    kind: Operand::$kind,
+                    read: operand!(read $access),
+                    written: operand!(written $access),
+                }),*)?],
+                opcode: $opcode,
+                cycles: $cycles,
+                unit: $unit,
+            },)*
+        ];
+
+        impl Inst {
+            /// The kind of instruction in the [`ISA`].
+            pub fn def(&self) -> &'static InstDef {
+                match self {
+                    $(Inst::$variant { .. } => &ISA[Index::$variant as usize],)*
+                }
+            }
+
+            /// The operands in the order of [`InstDef::operands`].
+            pub fn operands(&self) -> Vec<OperandValue> {
+                match self {
+                    $(Inst::$variant $(($($field),*))? => {
+                        vec![$($(operand!(value $kind, $field)),*)?]
+                    })*
+                }
+            }
+
+            /// The instruction of this kind with these operands, if they are the right kinds.
+            pub fn from_operands(
+                def: &InstDef,
+                values: impl IntoIterator<Item = OperandValue>,
+            ) -> Option<Inst> {
+                let mut values = values.into_iter();
+                let inst = match def.opcode {
+                    $($opcode => Inst::$variant $(($(operand!(take $kind, values)),*))?,)*
+                    _ => return None,
+                };
+                values.next().is_none().then_some(inst)
+            }
+        }
+
+        /// Localized user-friendly output, e.g. `t!("compiler.inst.add", ...)` becomes "add
+        /// register a to register b" in English. Not to be used programmatically.
+        impl Display for Inst {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(Inst::$variant $(($($field),*))? => f.write_str(&t!(
+                        concat!("compiler.inst.", $name)
+                        $($(, $field = $field)*)?
+                    )),)*
+                }
+            }
+        }
+    };
+}
+
+instructions! {
+    /// An instruction of our custom ISA.
+    ///
+    /// Programs are straight-line code: there are no jumps or branches, so every instruction
+    /// runs exactly once and in order. The cost model, the timeline and the interpreter's
+    /// [`Stats`] rely on that, e.g. there's nothing for a branch predictor to predict.
+    ///
+    /// [`Stats`]: crate::interpreter::Stats
+    pub enum Inst {
+        /// Add two values, storing the result in Register #2.
+        Add(a: in Reg, b: mut Reg) = "add", 0x01, 30, Unit::Alu {
+            op: |a, b| Some(a.overflowing_add(b)),
+            symbol: "+",
+        };
+        /// Subtract two values, storing the result in Register #2.
+        Sub(a: in Reg, b: mut Reg) = "sub", 0x02, 30, Unit::Alu {
+            op: |a, b| Some(a.overflowing_sub(b)),
+            symbol: "-",
+        };
+        /// Multiply two values, storing the result in Register #2.
+        Mul(a: in Reg, b: mut Reg) = "mul", 0x03, 60, Unit::Alu {
+            op: |a, b| Some(a.overflowing_mul(b)),
+            symbol: "*",
+        };
+        /// Divide two values, storing the result in Register #2.
+        Div(a: in Reg, b: mut Reg) = "div", 0x04, 120, Unit::Alu {
+            op: |a, b| (b != 0).then(|| a.overflowing_div(b)),
+            symbol: "/",
+        };
+        /// Shift the value in register #2 to the left by the number of bits stated in register #1.
+        Shl(a: in Reg, b: mut Reg) = "shl", 0x05, 30, Unit::Alu {
+            op: |a, b| Some((a.wrapping_shl(b as u32), false)),
+            symbol: "<<",
+        };
+        /// Shift the value in register #2 to the right by the number of bits stated in register #1.
+        Shr(a: in Reg, b: mut Reg) = "shr", 0x06, 30, Unit::Alu {
+            op: |a, b| Some((a.wrapping_shr(b as u32), false)),
+            symbol: ">>",
+        };
+        /// The smaller of two values, storing it in Register #2.
+        Min(a: in Reg, b: mut Reg) = "min", 0x0e, 30, Unit::Alu {
+            op: |a, b| Some((a.min(b), false)),
+            symbol: "min",
+        };
+        /// The larger of two values, storing it in Register #2.
+        Max(a: in Reg, b: mut Reg) = "max", 0x0f, 30, Unit::Alu {
+            op: |a, b| Some((a.max(b), false)),
+            symbol: "max",
+        };
+        /// Add the values of two registers, storing the result in a third one.
+        Add3(a: in Reg, b: in Reg, c: out Reg) = "add3", 0x07, 30, Unit::Alu {
+            op: |a, b| Some(a.overflowing_add(b)),
+            symbol: "+",
+        };
+        /// Subtract the values of two registers, storing the result in a third one.
+        Sub3(a: in Reg, b: in Reg, c: out Reg) = "sub3", 0x08, 30, Unit::Alu {
+            op: |a, b| Some(a.overflowing_sub(b)),
+            symbol: "-",
+        };
+        /// Multiply the values of two registers, storing the result in a third one.
+        Mul3(a: in Reg, b: in Reg, c: out Reg) = "mul3", 0x09, 60, Unit::Alu {
+            op: |a, b| Some(a.overflowing_mul(b)),
+            symbol: "*",
+        };
+        /// Divide the values of two registers, storing the result in a third one.
+        Div3(a: in Reg, b: in Reg, c: out Reg) = "div3", 0x0a, 120, Unit::Alu {
+            op: |a, b| (b != 0).then(|| a.overflowing_div(b)),
+            symbol: "/",
+        };
+        /// Shift the value of register #1 left by register #2, storing the result in a third one.
+        Shl3(a: in Reg, b: in Reg, c: out Reg) = "shl3", 0x0b, 30, Unit::Alu {
+            op: |a, b| Some((a.wrapping_shl(b as u32), false)),
+            symbol: "<<",
+        };
+        /// Shift the value of register #1 right by register #2, storing the result in a third one.
+        Shr3(a: in Reg, b: in Reg, c: out Reg) = "shr3", 0x0c, 30, Unit::Alu {
+            op: |a, b| Some((a.wrapping_shr(b as u32), false)),
+            symbol: ">>",
+        };
+        /// The smaller of the values of two registers, storing it in a third one.
+        Min3(a: in Reg, b: in Reg, c: out Reg) = "min3", 0x16, 30, Unit::Alu {
+            op: |a, b| Some((a.min(b), false)),
+            symbol: "min",
+        };
+        /// The larger of the values of two registers, storing it in a third one.
+        Max3(a: in Reg, b: in Reg, c: out Reg) = "max3", 0x17, 30, Unit::Alu {
+            op: |a, b| Some((a.max(b), false)),
+            symbol: "max",
+        };
+        /// Negate the value in the register.
+        Neg(r: mut Reg) = "neg", 0x0d, 30, Unit::Alu {
+            op: |a, b| Some(a.overflowing_sub(b)),
+            symbol: "-",
+        };
+        /// Add a number to the value in the register, without storing it in a register first.
+        AddI(r: mut Reg, n: in Num) = "addi", 0x41, 30, Unit::Alu {
+            op: |a, b| Some(a.overflowing_add(b)),
+            symbol: "+",
+        };
+        /// Subtract a number from the value in the register.
+        SubI(r: mut Reg, n: in Num) = "subi", 0x42, 30, Unit::Alu {
+            op: |a, b| Some(a.overflowing_sub(b)),
+            symbol: "-",
+        };
+        /// Multiply the value in the register by a number.
+        MulI(r: mut Reg, n: in Num) = "muli", 0x43, 60, Unit::Alu {
+            op: |a, b| Some(a.overflowing_mul(b)),
+            symbol: "*",
+        };
+        /// Divide the value in the register by a number.
+        DivI(r: mut Reg, n: in Num) = "divi", 0x44, 120, Unit::Alu {
+            op: |a, b| (b != 0).then(|| a.overflowing_div(b)),
+            symbol: "/",
+        };
+        /// Shift the value in the register to the left by a number of bits.
+        ShlI(r: mut Reg, n: in Num) = "shli", 0x45, 30, Unit::Alu {
+            op: |a, b| Some((a.wrapping_shl(b as u32), false)),
+            symbol: "<<",
+        };
+        /// Shift the value in the register to the right by a number of bits.
+        ShrI(r: mut Reg, n: in Num) = "shri", 0x46, 30, Unit::Alu {
+            op: |a, b| Some((a.wrapping_shr(b as u32), false)),
+            symbol: ">>",
+        };
+        /// Store a number in a register.
+        Store(n: in Num, r: out Reg) = "store", 0x10, 15, Unit::Control;
+        /// Transfer a value into a register.
+        Transfer(v: in Var, r: out Reg) = "transfer", 0x11, 15, Unit::Control;
+        /// Return the value in the given register and terminate computation.
+        Result(r: in Reg) = "result", 0x12, 15, Unit::Control;
+        /// Terminate computation without a result, the program fails. Every program ends with
+        /// this or [`Inst::Result`], instead of running past its last instruction.
+        Halt = "halt", 0x14, 15, Unit::Control;
+        /// Copy the value of register #1 into register #2.
+        Mov(a: in Reg, b: out Reg) = "mov", 0x13, 15, Unit::Control;
+        /// If the last arithmetic overflowed, replace its result with the largest or smallest
+        /// number. The sign of the wrapped result tells which, for additions and subtractions.
+        Sat(r: mut Reg) = "sat", 0x50, 15, Unit::Control;
+        /// Stop the program with an error if computing the value in the register overflowed.
+        Chk(r: in Reg) = "chk", 0x51, 15, Unit::Control;
+
+        /// Write the contents of a register to main memory.
+        Write(r: in Reg, addr: out Addr) = "write", 0x20, 300, Unit::Ram;
+        /// Load a piece of data from main memory into a register.
+        Load(addr: in Addr, r: out Reg) = "load", 0x21, 300, Unit::Ram;
+
+        /// Push a register onto the stack at the end of main memory.
+        Push(r: in Reg) = "push", 0x30, 300, Unit::Ram;
+        /// Pop the top of the stack into a register.
+        Pop(r: out Reg) = "pop", 0x31, 300, Unit::Ram;
+    }
+}
+
+impl Inst {
+    /// Simulated duration of the instruction in cycles, see [`ISA`].
+    pub fn cycles(&self) -> u32 {
+        self.def().cycles
+    }
+
+    /// Whether the instruction runs on an ALU, the part of the processor that computes.
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(self.def().unit, Unit::Alu { .. })
+    }
+
+    /// Whether the program ends with the instruction, nothing after it runs.
+    pub fn terminates(&self) -> bool {
+        matches!(self, Inst::Result(_) | Inst::Halt)
+    }
+
+    /// Whether the instruction goes to RAM, including the stack.
+    pub fn uses_ram(&self) -> bool {
+        matches!(self.def().unit, Unit::Ram)
+    }
+
+    /// Cycles the instruction waits before it can run after `previous`. With a single RAM port,
+    /// a RAM access right after another one has to wait until the port is free again.
+    pub fn stall(&self, previous: Option<&Inst>, ram_ports: u8) -> u32 {
+        let contended = ram_ports <= 1 && self.uses_ram() && previous.is_some_and(Inst::uses_ram);
+        if contended { RAM_STALL_CYCLES } else { 0 }
+    }
+
+    /// Short name of the kind of instruction, the same as in assembly text.
+    pub fn name(&self) -> &'static str {
+        self.def().name
+    }
+
+    /// Whether the instruction reads the value of the register.
+    pub fn reads_register(&self, r: Reg) -> bool {
+        self.accesses(r, |operand| operand.read)
+    }
+
+    /// Whether the instruction puts a new value into the register.
+    pub fn writes_register(&self, r: Reg) -> bool {
+        self.accesses(r, |operand| operand.written)
+    }
+
+    fn accesses(&self, r: Reg, access: fn(&OperandDef) -> bool) -> bool {
+        let operands = self.def().operands.iter().zip(self.operands());
+        operands
+            .filter(|(operand, _)| access(operand))
+            .any(|(_, value)| value == OperandValue::Reg(r))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn isa_table_matches_the_instructions() {
+        for (i, def) in ISA.iter().enumerate() {
+            let example = def.example();
+            assert!(std::ptr::eq(example.def(), &ISA[i]), "{}", def.name);
+            assert!(ISA[..i].iter().all(|other| other.opcode != def.opcode));
+
+            let rebuilt = Inst::from_operands(def, example.operands());
+            assert_eq!(
+                rebuilt.map(|inst| inst.to_string()),
+                Some(example.to_string())
+            );
+        }
+        // operands of the wrong kind or number
+        assert!(
+            Inst::from_operands(&ISA[0], [OperandValue::Num(1), OperandValue::Num(2)]).is_none()
+        );
+        assert!(Inst::from_operands(Inst::Result(Reg(0)).def(), []).is_none());
+    }
+
+    #[test]
+    fn pretty_print_minimal_parentheses() -> Result<(), LpErr> {
+        for source in [
+            "a + b * c",
+            "(a + b) * c",
+            "a - (b - c)",
+            "a - b - c",
+            "a << 2 + b",
+            "(a << 2) + b",
+            "-(a + b) * -c",
+            "a / (b * c)",
+        ] {
+            let expr = crate::parser::run_parser(source)?;
+            assert_eq!(expr.pretty(), source);
+        }
+
+        let min = Expr::BinaryOp(
+            ExprId::new(Expr::Var("a".into())),
+            Operator::Mul,
+            ExprId::new(i32::MIN.into()),
+        );
+        assert_eq!(min.pretty(), "a * (-2147483647 - 1)");
+        crate::parser::run_parser(&min.pretty())?;
+        Ok(())
+    }
+
+    #[test]
+    fn equal_subtrees_are_stored_once() -> Result<(), LpErr> {
+        let Expr::BinaryOp(l, Operator::Mul, r) = crate::parser::run_parser("(a + 1) * (a + 1)")?
+        else {
+            panic!("expected a multiplication");
+        };
+        assert_eq!(l.0, r.0);
+        assert_eq!(
+            l.get(),
+            Expr::BinaryOp(
+                ExprId::new(Expr::Var("a".into())),
+                Operator::Add,
+                ExprId::new(1.into())
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn variables_in_order_of_occurrence() -> Result<(), LpErr> {
+        let ast = crate::parser::run_parser("z * a + (b - z) / z + a")?;
+        assert_eq!(
+            ast.variable_occurrences(),
+            [("z".into(), 3), ("a".into(), 2), ("b".into(), 1)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn find_divisor_variables() -> Result<(), LpErr> {
+        let ast = crate::parser::run_parser("a / (b - c) + d * (e / 2) / f")?;
+        let expected: HashSet<String> = ["b", "c", "f"].map(String::from).into();
+        assert_eq!(ast.divisor_variables(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_register_names() {
+        assert_eq!("c".parse(), Ok(Reg(2)));
+        assert_eq!("R12".parse(), Ok(Reg(12)));
+        assert_eq!("x31".parse(), Ok(Reg(31)));
+        assert!("x32".parse::<Reg>().is_err());
+        assert!("ab".parse::<Reg>().is_err());
     }
 }